@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
+    os::fd::AsRawFd,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    epoll_loop::{DuplexChannel, EventLoopMessages},
+    error::CommonError,
+    event_loop::{CallBack, Token},
+};
+
+/// Interval at which a server closes the connections that outlived their deadline.
+pub(crate) const SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Limits of the connections of the `MetricsServer` and of the `ManagementServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Longest time a connection may stay open, to send its request and read the response
+    pub idle_timeout: Duration,
+    /// Largest number of connections open at once, the connections beyond it are closed as
+    /// soon as they are accepted
+    pub max_connections: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(10),
+            max_connections: 64,
+        }
+    }
+}
+
+/// Open connections of a server, with the deadlines of those whose token is known.
+///
+/// The table decides who closes a connection: the first of the connection and of the sweep
+/// of the expired connections to remove its deadline, so the connection is closed only once.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionTable {
+    limits: ConnectionLimits,
+    state: Arc<Mutex<TableState>>,
+}
+
+#[derive(Debug, Default)]
+struct TableState {
+    open: usize,
+    deadlines: HashMap<Token, Instant>,
+}
+
+impl ConnectionTable {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            limits,
+            state: Arc::default(),
+        }
+    }
+
+    /// Takes a slot for an accepted connection, or returns `None` when the server already has
+    /// as many connections as it allows.
+    pub fn open(&self) -> Option<ConnectionSlot> {
+        let mut state = self.lock();
+        if state.open >= self.limits.max_connections {
+            return None;
+        }
+        state.open += 1;
+        Some(ConnectionSlot {
+            table: self.clone(),
+            deadline: Instant::now() + self.limits.idle_timeout,
+            token: None,
+        })
+    }
+
+    /// Removes the connections past their deadline and returns their tokens, for the caller to
+    /// close them.
+    pub fn expired(&self) -> Vec<Token> {
+        let now = Instant::now();
+        let mut state = self.lock();
+        let expired: Vec<Token> = state
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(token, _)| *token)
+            .collect();
+        expired.iter().for_each(|token| {
+            state.deadlines.remove(token);
+        });
+        expired
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TableState> {
+        // The state stays consistent whatever panicked while holding the lock
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Slot of an open connection, given back when it is dropped along with the callback of the
+/// connection.
+#[derive(Debug)]
+pub(crate) struct ConnectionSlot {
+    table: ConnectionTable,
+    deadline: Instant,
+    token: Option<Token>,
+}
+
+impl ConnectionSlot {
+    /// Starts the deadline of the connection on its first event, when its token is known.
+    /// Returns whether the connection is still open, as the sweep may have closed it.
+    pub fn watch(&mut self, token: Token) -> bool {
+        let mut state = self.table.lock();
+        match self.token {
+            Some(token) => state.deadlines.contains_key(&token),
+            None => {
+                self.token = Some(token);
+                state.deadlines.insert(token, self.deadline);
+                true
+            }
+        }
+    }
+
+    /// Stops the deadline of the connection. Returns whether the caller is to close the
+    /// connection, which is not the case once the sweep closed it.
+    pub fn close(&mut self) -> bool {
+        self.token
+            .is_some_and(|token| self.table.lock().deadlines.remove(&token).is_some())
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut state = self.table.lock();
+        state.open -= 1;
+        if let Some(token) = self.token {
+            state.deadlines.remove(&token);
+        }
+    }
+}
+
+/// Closes the connections of a server that outlived their deadline.
+pub(crate) fn create_sweep_callback<T: AsRawFd + Send + 'static>(
+    connections: ConnectionTable,
+    event_sender: Arc<Mutex<DuplexChannel<T>>>,
+) -> CallBack<T> {
+    Box::new(move |_listener: &mut T, _token| {
+        for token in connections.expired() {
+            log::debug!("Closing the idle connection {:?}", token);
+            // A failed sweep is tried again on the next one
+            let sent = event_sender
+                .try_lock()
+                .map_err(CommonError::from)
+                .and_then(|channel| channel.send(EventLoopMessages::Unregister(token)));
+            if let Err(e) = sent {
+                log::error!("Failed to close the idle connection: {}", e);
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Single request and response over a non-blocking connection.
+///
+/// The request is read until it is complete, then the response is written as far as the
+/// connection accepts it, and the rest on the next writable events.
+#[derive(Debug)]
+pub(crate) enum Exchange {
+    Reading(Vec<u8>),
+    Writing(Vec<u8>, usize),
+    Done,
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Exchange::Reading(Vec::new())
+    }
+}
+
+impl Exchange {
+    /// Moves the exchange forward with whatever the connection can read or write without
+    /// blocking, and returns whether it is over.
+    ///
+    /// `respond` gets the bytes received so far and whether the peer stopped sending, and
+    /// returns the response once the request is complete. An incomplete request is dropped
+    /// without a response when the peer stops sending.
+    pub fn advance<S: Read + Write>(
+        &mut self,
+        stream: &mut S,
+        respond: impl FnOnce(&[u8], bool) -> Option<Vec<u8>>,
+    ) -> bool {
+        if let Exchange::Reading(request) = self {
+            let end_of_stream = read_available(stream, request);
+            *self = match respond(request, end_of_stream) {
+                Some(response) => Exchange::Writing(response, 0),
+                None if end_of_stream => Exchange::Done,
+                None => return false,
+            };
+        }
+        if let Exchange::Writing(response, written) = self {
+            while *written < response.len() {
+                match stream.write(&response[*written..]) {
+                    Ok(0) => break,
+                    Ok(length) => *written += length,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return false,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        log::debug!("Failed to send the response: {}", e);
+                        break;
+                    }
+                }
+            }
+            *self = Exchange::Done;
+        }
+        true
+    }
+}
+
+/// Appends the available bytes of `stream` to `buffer` and returns whether the peer stopped
+/// sending.
+fn read_available<S: Read>(stream: &mut S, buffer: &mut Vec<u8>) -> bool {
+    let mut chunk = [0u8; 4096];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => return true,
+            Ok(length) => buffer.extend_from_slice(&chunk[..length]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return false,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                log::debug!("Failed to read the request: {}", e);
+                return true;
+            }
+        }
+    }
+}
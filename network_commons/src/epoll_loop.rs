@@ -10,6 +10,7 @@ use std::{
         mpsc, Arc, Mutex, RwLock,
     },
     thread::Thread,
+    time::Duration,
 };
 
 use crate::{
@@ -18,12 +19,18 @@ use crate::{
         itimerspec_to_libc, CallBack, EventLoopTrait, Itimerspec, Source, TimedSource, Token,
     },
     libc_call,
+    stop_handle::StopHandle,
 };
 
 pub enum EventLoopMessages<T: Send, U: Send> {
     AddDuration(Itimerspec),
     RegisterTimed((Itimerspec, Token, U)),
     Register(Source<T>),
+    /// Registers a source whose callback is also called when it becomes writable, see
+    /// `LinuxEventLoop::register_read_write_source`
+    RegisterReadWrite(Source<T>),
+    /// Registers a source and sends its token through the sender, see `register_from_thread`
+    RegisterWithReply((Source<T>, mpsc::Sender<Token>)),
     Unregister(Token),
     Clean,
     TimedCleanup {
@@ -56,6 +63,8 @@ pub struct LinuxEventLoop<T: AsRawFd + Send> {
     overtime_exceptions: Vec<Token>,
     cleanup: Option<Itimerspec>,
     cleanup_token: Option<Token>,
+    stop_handle: Option<StopHandle>,
+    signal_token: Option<Token>,
 }
 
 impl<T: AsRawFd + Send> LinuxEventLoop<T> {
@@ -82,6 +91,75 @@ impl<T: AsRawFd + Send> LinuxEventLoop<T> {
     }
 }
 
+impl<T: AsRawFd + Send + 'static> LinuxEventLoop<T> {
+    /// Registers a `StopHandle` with the event loop.
+    ///
+    /// Stopping the handle has the same effect as reaching the deadline of the loop:
+    /// the overtime period starts, and the loop ends once it is over.
+    /// If the handle listens to signals, its signalfd is registered as well.
+    ///
+    /// # Parameters
+    ///
+    /// * `stop_handle`: The `StopHandle` to register.
+    pub fn register_stop_handle(&mut self, stop_handle: &StopHandle) -> Result<(), CommonError> {
+        let event_fd = stop_handle.as_raw_fd();
+        let stop_token = self.generate_token();
+        self.poll.registry().register(
+            &mut SourceFd(&event_fd),
+            mio::Token(stop_token.0),
+            Interest::READABLE,
+        )?;
+        if let Some(signal_fd) = stop_handle.signal_fd() {
+            let signal_token = self.generate_token();
+            self.poll.registry().register(
+                &mut SourceFd(&signal_fd),
+                mio::Token(signal_token.0),
+                Interest::READABLE,
+            )?;
+            self.signal_token = Some(signal_token);
+        }
+        self.stop_handle = Some(stop_handle.clone());
+        Ok(())
+    }
+
+    /// Registers an I/O source whose callback is called when it is readable and when it becomes
+    /// writable, so a connection can finish writing a response without blocking the loop.
+    ///
+    /// As a connected socket is writable at once, the callback is first called right after the
+    /// registration, which tells it its token.
+    pub fn register_read_write_source(
+        &self,
+        event_source: T,
+        callback: CallBack<T>,
+    ) -> Result<Token, CommonError> {
+        self.register_source(
+            event_source,
+            callback,
+            Interest::READABLE | Interest::WRITABLE,
+        )
+    }
+
+    fn register_source(
+        &self,
+        event_source: T,
+        callback: CallBack<T>,
+        interest: Interest,
+    ) -> Result<Token, CommonError> {
+        let binding = &event_source.as_raw_fd();
+        let mut source = SourceFd(binding);
+        let generate_token = self.generate_token();
+        let token = mio::Token(generate_token.0);
+        self.poll
+            .registry()
+            .register(&mut source, token, interest)?;
+        self.sources
+            .try_write()
+            .unwrap()
+            .insert(generate_token, (event_source, Box::new(callback)));
+        Ok(generate_token)
+    }
+}
+
 impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
     fn new(event_capacity: usize) -> Result<Self, CommonError> {
         // Create the poll
@@ -105,6 +183,8 @@ impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
             overtime_exceptions: Vec::new(),
             cleanup: None,
             cleanup_token: None,
+            stop_handle: None,
+            signal_token: None,
         })
     }
 
@@ -123,18 +203,34 @@ impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
                 match message {
                     EventLoopMessages::Register((event_source, callback)) => {
                         let token = self.register_event_source(event_source, callback)?;
-                        self.registration_sender.try_lock()?.set_token(token.0);
+                        self.registration_sender.lock()?.set_token(token.0);
                         log::debug!("Registering event source with token {}", token.0);
                     }
+                    EventLoopMessages::RegisterReadWrite((event_source, callback)) => {
+                        let token = self.register_read_write_source(event_source, callback)?;
+                        self.registration_sender.lock()?.set_token(token.0);
+                        log::debug!("Registering read write source with token {}", token.0);
+                    }
+                    EventLoopMessages::RegisterWithReply(((event_source, callback), reply)) => {
+                        // The sender is dropped on failure, which tells the waiting thread
+                        match self.register_event_source(event_source, callback) {
+                            Ok(token) => {
+                                log::debug!("Registering event source with token {}", token.0);
+                                let _ = reply.send(token);
+                            }
+                            Err(e) => log::error!("Failed to register event source: {}", e),
+                        }
+                    }
                     EventLoopMessages::Unregister(token) => {
-                        self.unregister_event_source(token)?;
+                        // A source whose descriptor was closed is already unregistered
+                        if let Err(e) = self.unregister_event_source(token) {
+                            log::debug!("{}", e);
+                        }
                     }
                     EventLoopMessages::RegisterTimed((time_spec, token, callback)) => {
                         log::debug!("Registering timedevent source");
                         let timer_token = self.register_timer(&time_spec, &token, callback)?;
-                        self.registration_sender
-                            .try_lock()?
-                            .set_token(timer_token.0);
+                        self.registration_sender.lock()?.set_token(timer_token.0);
                     }
                     EventLoopMessages::Clean => {
                         // Unregister all event sources, we do this by closing all file descriptors from the sources and timedsources
@@ -148,12 +244,12 @@ impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
                     }
                     EventLoopMessages::AddDuration(time_spec) => {
                         let token = self.add_duration(&time_spec)?;
-                        self.registration_sender.try_lock()?.set_token(token.0);
+                        self.registration_sender.lock()?.set_token(token.0);
                     }
                     EventLoopMessages::TimedCleanup { timer_spec, thread } => {
                         log::debug!("Adding cleanup timer");
                         let token = self.add_cleanup(&timer_spec)?;
-                        self.registration_sender.try_lock()?.set_token(token.0);
+                        self.registration_sender.lock()?.set_token(token.0);
                         thread.unpark();
                     }
                 }
@@ -164,10 +260,18 @@ impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
                 Some(std::time::Duration::from_millis(100)),
             )?;
             for event in self.events.iter() {
-                if event.is_readable() {
+                // Only the sources registered for it get writable events
+                if event.is_readable() || event.is_writable() {
                     let token = event.token();
                     log::trace!("Event token {:?}", token);
                     let generate_token = Token(token.0);
+                    if Some(generate_token) == self.signal_token {
+                        // Stopping the handle wakes up every loop it is registered with
+                        if let Some(stop_handle) = &self.stop_handle {
+                            stop_handle.handle_signal()?;
+                        }
+                        continue;
+                    }
                     if let Ok(mut sources) = self.sources.try_write() {
                         if let Ok(mut timed_sources) = self.timed_sources.try_write() {
                             if let Some((source, callback)) = sources.get_mut(&generate_token) {
@@ -191,7 +295,8 @@ impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
                                     reset_timer(timer_source)?;
                                 }
                             } else {
-                                // else only triggers on ungeristered timed events such as TimedCleanup, Overtime and Duration
+                                // else only triggers on ungeristered timed events such as TimedCleanup, Overtime and Duration,
+                                // and on the stop handle
                                 if self.overtime.is_none() {
                                     log::debug!("No overtime");
                                     if self.cleanup.is_none() {
@@ -205,8 +310,7 @@ impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
                                     }
                                 }
 
-                                let tokens: Vec<Token> =
-                                    timed_sources.iter().map(|(token, _)| *token).collect();
+                                let tokens: Vec<Token> = timed_sources.keys().copied().collect();
                                 drop(timed_sources);
                                 // Unregister all timed events
                                 tokens.iter().for_each(|token| {
@@ -296,18 +400,7 @@ impl<T: AsRawFd + Send + 'static> EventLoopTrait<T> for LinuxEventLoop<T> {
         event_source: T,
         callback: CallBack<T>,
     ) -> Result<Token, CommonError> {
-        let binding = &event_source.as_raw_fd();
-        let mut source = SourceFd(binding);
-        let generate_token = self.generate_token();
-        let token = mio::Token(generate_token.0);
-        self.poll
-            .registry()
-            .register(&mut source, token, Interest::READABLE)?;
-        self.sources
-            .try_write()
-            .unwrap()
-            .insert(generate_token, (event_source, Box::new(callback)));
-        Ok(generate_token)
+        self.register_source(event_source, callback, Interest::READABLE)
     }
 
     fn unregister_event_source(&self, token: Token) -> Result<(), CommonError> {
@@ -433,6 +526,35 @@ pub fn create_non_blocking_unix_datagram() -> Result<UnixDatagram, CommonError>
     Ok(unsafe { UnixDatagram::from_raw_fd(socket_fd) })
 }
 
+/// Longest time `register_from_thread` waits for the event loop to register a source.
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Registers an event source with an event loop running on another thread, through the
+/// communication channel of the loop, and returns its token once the loop registered it.
+///
+/// The token comes back through a channel of its own, so any number of threads, and the
+/// callbacks of other loops, may register sources at the same time.
+/// It must not be called from the thread of the loop itself, as the loop only registers the
+/// source once the call returned: it would fail after `REGISTRATION_TIMEOUT`.
+pub fn register_from_thread<T>(
+    channel: &Arc<Mutex<DuplexChannel<T>>>,
+    event_source: T,
+    callback: CallBack<T>,
+) -> Result<Token, CommonError>
+where
+    T: Send,
+    CallBack<T>: Send,
+{
+    let (reply, token) = mpsc::channel();
+    channel.lock()?.send(EventLoopMessages::RegisterWithReply((
+        (event_source, callback),
+        reply,
+    )))?;
+    token
+        .recv_timeout(REGISTRATION_TIMEOUT)
+        .map_err(|_| CommonError::Generic("The event loop didn't register the source".to_string()))
+}
+
 /// Checks if file descriptor is open or closed returning a boolean value
 fn is_fd_open<T: AsRawFd>(file: &T) -> bool {
     let fd = file.as_raw_fd();
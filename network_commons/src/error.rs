@@ -2,7 +2,7 @@
 /// `CommonError` is an enum containing error variants which are likely to be used across different parts of the codebase.
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use std::sync::{PoisonError, RwLockWriteGuard, TryLockError};
+use std::sync::{PoisonError, TryLockError};
 
 /// A handy Result type specific to the common error set defined by `CommonError`.
 pub type Result<T> = std::result::Result<T, CommonError>;
@@ -29,6 +29,8 @@ pub enum CommonError {
     SocketAcceptFailed(std::io::Error),
     SocketGetPeerName(std::io::Error),
     UnknownAddressFamily,
+    ThresholdViolation(String),
+    Regression(String),
 }
 
 impl Display for CommonError {
@@ -71,6 +73,8 @@ impl Display for CommonError {
                 write!(f, "Failed to get peer socket address: {}", e)
             }
             CommonError::UnknownAddressFamily => write!(f, "Failed to match address family"),
+            CommonError::ThresholdViolation(e) => write!(f, "Thresholds violated: {}", e),
+            CommonError::Regression(e) => write!(f, "Regressed from the baseline: {}", e),
         }
     }
 }
@@ -101,8 +105,8 @@ impl From<std::convert::Infallible> for CommonError {
     }
 }
 
-impl<T> From<PoisonError<RwLockWriteGuard<'_, Vec<T>>>> for CommonError {
-    fn from(_: PoisonError<RwLockWriteGuard<'_, Vec<T>>>) -> Self {
+impl<T> From<PoisonError<T>> for CommonError {
+    fn from(_: PoisonError<T>) -> Self {
         CommonError::Lock
     }
 }
@@ -12,6 +12,7 @@
 //! ```
 
 use error::CommonError;
+pub use sink::ResultSink;
 
 pub mod error;
 
@@ -19,8 +20,17 @@ pub mod epoll_loop;
 pub mod event_loop;
 pub mod socket;
 
+pub mod connection;
 pub mod interval;
+pub mod management;
+pub mod metrics;
+pub mod pcap;
+pub mod schedule;
+pub mod scheduler;
+pub mod signal;
+pub mod sink;
 pub mod stats;
+pub mod stop_handle;
 pub mod tcp_socket;
 pub mod time;
 pub mod udp_socket;
@@ -41,6 +51,10 @@ pub trait Strategy<R: TestResult, E: std::error::Error> {
     ///
     /// A `Result` that contains the result of the Test test, or an error if the test failed.
     fn execute(&mut self) -> std::result::Result<R, E>;
+
+    /// Sets the `StopHandle` used to stop the test before it ends on its own.
+    /// Strategies that can't be stopped ignore the handle.
+    fn set_stop_handle(&mut self, _stop_handle: stop_handle::StopHandle) {}
 }
 
 pub trait TestResult: Send {
@@ -65,11 +79,12 @@ macro_rules! assert_approx_eq {
 
 #[macro_export]
 macro_rules! libc_call {
-    ($name:ident($($arg_name:expr), *)) => (unsafe {
-        let result = libc::$name($($arg_name),*) ;
+    ($name:ident($($arg_name:expr), *)) => ({
+        #[allow(clippy::macro_metavars_in_unsafe)]
+        let result = unsafe { libc::$name($($arg_name),*) };
         if result == -1 {
             let err = std::io::Error::last_os_error();
-            let err_msg = std::ffi::CStr::from_ptr(libc::strerror(err.raw_os_error().ok_or("Error retrieving os error")?));
+            let err_msg = unsafe { std::ffi::CStr::from_ptr(libc::strerror(err.raw_os_error().ok_or("Error retrieving os error")?)) };
             return Err(std::io::Error::new(err.kind(), err_msg.to_string_lossy().into_owned()).into());
         }
         std::result::Result::Ok(result)
@@ -0,0 +1,346 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+#[cfg(target_os = "linux")]
+use crate::epoll_loop::LinuxEventLoop as EventLoop;
+use crate::{
+    connection::{
+        create_sweep_callback, ConnectionLimits, ConnectionSlot, ConnectionTable, Exchange,
+        SWEEP_INTERVAL,
+    },
+    epoll_loop::{DuplexChannel, EventLoopMessages},
+    error::CommonError,
+    event_loop::{CallBack, EventLoopTrait, Itimerspec},
+    stop_handle::StopHandle,
+};
+
+/// Largest request, headers and body, a `ManagementServer` reads before answering with a 413.
+const MAX_REQUEST_SIZE: usize = 1 << 20;
+
+/// Answers the requests of a `ManagementServer`.
+pub type RequestHandler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// HTTP request received by a `ManagementServer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// Path of the request, without its query
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Non-empty segments of the path, so that `/jobs/daily/` gives `["jobs", "daily"]`.
+    pub fn segments(&self) -> Vec<&str> {
+        self.path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    }
+}
+
+/// JSON response of a `ManagementServer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    /// Response with `value` serialized as its body.
+    pub fn json<T: Serialize + ?Sized>(status: u16, value: &T) -> Self {
+        match serde_json::to_string_pretty(value) {
+            Ok(body) => Self {
+                status,
+                body: body + "\n",
+            },
+            Err(e) => Self::error(500, &format!("Failed to serialize the response: {}", e)),
+        }
+    }
+
+    /// Response with a `{"error": message}` body.
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message }))
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+/// Local HTTP server answering JSON requests on a Unix domain socket.
+///
+/// As the `MetricsServer`, the server runs its own event loop on a separate thread, every
+/// connection serves a single request and the connections are bounded by `ConnectionLimits`.
+/// The socket is only accessible to its owner, and it is removed when the server stops.
+pub struct ManagementServer {
+    path: PathBuf,
+    stop_handle: StopHandle,
+    thread: Option<JoinHandle<Result<(), CommonError>>>,
+}
+
+impl ManagementServer {
+    /// Binds to the socket at `path` and starts answering requests with `handler`, with the
+    /// default `ConnectionLimits`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if another server listens on `path` or if the socket
+    /// can't be bound.
+    pub fn spawn(path: &Path, handler: RequestHandler) -> Result<Self, CommonError> {
+        Self::spawn_with_limits(path, handler, ConnectionLimits::default())
+    }
+
+    /// Binds to the socket at `path` and starts answering requests with `handler`, with the
+    /// given limits on the connections.
+    ///
+    /// A socket left over at `path` by a server that is gone is replaced.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if another server listens on `path` or if the socket
+    /// can't be bound.
+    pub fn spawn_with_limits(
+        path: &Path,
+        handler: RequestHandler,
+        limits: ConnectionLimits,
+    ) -> Result<Self, CommonError> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(CommonError::Generic(format!(
+                    "Another server already listens on {}",
+                    path.display()
+                )));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+        let stop_handle = StopHandle::new()?;
+        let loop_stop_handle = stop_handle.clone();
+        let thread = std::thread::Builder::new()
+            .name("management".to_string())
+            .spawn(move || -> Result<(), CommonError> {
+                let mut event_loop: EventLoop<LocalSocket> = EventLoop::new(64)?;
+                event_loop.set_overtime(Itimerspec {
+                    it_interval: Duration::ZERO,
+                    it_value: Duration::from_millis(1),
+                });
+                event_loop.register_stop_handle(&loop_stop_handle)?;
+                let event_sender = event_loop.get_communication_channel();
+                let connections = ConnectionTable::new(limits);
+                let accepted_connections = connections.clone();
+                let listener_token = event_loop.register_event_source(
+                    LocalSocket::Listener(listener),
+                    Box::new(move |listener: &mut LocalSocket, _token| {
+                        let LocalSocket::Listener(listener) = listener else {
+                            return Ok(0);
+                        };
+                        // Failing to accept a connection must not close the listener
+                        loop {
+                            match listener.accept() {
+                                Ok((connection, _)) => {
+                                    let Some(slot) = accepted_connections.open() else {
+                                        log::debug!(
+                                            "Too many management connections, closing a new one"
+                                        );
+                                        continue;
+                                    };
+                                    if let Err(e) = connection.set_nonblocking(true) {
+                                        log::debug!(
+                                            "Failed to set up management connection: {}",
+                                            e
+                                        );
+                                        continue;
+                                    }
+                                    let callback = create_connection_callback(
+                                        handler.clone(),
+                                        slot,
+                                        event_sender.clone(),
+                                    );
+                                    event_sender.try_lock()?.send(
+                                        EventLoopMessages::RegisterReadWrite((
+                                            LocalSocket::Connection(connection),
+                                            callback,
+                                        )),
+                                    )?;
+                                }
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                                Err(e) => {
+                                    log::debug!("Failed to accept management connection: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                        Ok(0)
+                    }),
+                )?;
+                event_loop.register_timer(
+                    &Itimerspec {
+                        it_interval: SWEEP_INTERVAL,
+                        it_value: SWEEP_INTERVAL,
+                    },
+                    &listener_token,
+                    create_sweep_callback(connections, event_loop.get_communication_channel()),
+                )?;
+                event_loop.run()
+            })?;
+        log::info!("Serving the management API on {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            stop_handle,
+            thread: Some(thread),
+        })
+    }
+
+    /// Path of the socket the server listens on.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops the server, waits for its thread to end and removes its socket.
+    pub fn stop(mut self) -> Result<(), CommonError> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), CommonError> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        self.stop_handle.stop()?;
+        let result = thread
+            .join()
+            .map_err(|_| CommonError::Generic("Management server thread panicked".to_string()))?;
+        let _ = fs::remove_file(&self.path);
+        result
+    }
+}
+
+impl Drop for ManagementServer {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            log::error!("Failed to stop the management server: {}", e);
+        }
+    }
+}
+
+/// Sockets of the event loop of a `ManagementServer`.
+enum LocalSocket {
+    Listener(UnixListener),
+    Connection(UnixStream),
+}
+
+impl AsRawFd for LocalSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            LocalSocket::Listener(listener) => listener.as_raw_fd(),
+            LocalSocket::Connection(connection) => connection.as_raw_fd(),
+        }
+    }
+}
+
+/// Reads a request from a connection until its body is complete, writes the response as
+/// the connection accepts it and asks the event loop to close the connection.
+fn create_connection_callback(
+    handler: RequestHandler,
+    mut slot: ConnectionSlot,
+    event_sender: Arc<Mutex<DuplexChannel<LocalSocket>>>,
+) -> CallBack<LocalSocket> {
+    let mut exchange = Exchange::default();
+    Box::new(move |connection: &mut LocalSocket, token| {
+        let LocalSocket::Connection(connection) = connection else {
+            return Ok(0);
+        };
+        if !slot.watch(token) {
+            return Ok(0);
+        }
+        let done = exchange.advance(connection, |request, _| {
+            let response = match parse(request) {
+                Ok(Some(request)) => handler(&request),
+                Ok(None) if request.len() > MAX_REQUEST_SIZE => {
+                    Response::error(413, "Request too large")
+                }
+                Ok(None) => return None,
+                Err(message) => Response::error(400, &message),
+            };
+            Some(response.encode().into_bytes())
+        });
+        if done && slot.close() {
+            event_sender
+                .try_lock()?
+                .send(EventLoopMessages::Unregister(token))?;
+        }
+        Ok(0)
+    })
+}
+
+/// Parses a request, or returns `None` while it is incomplete.
+fn parse(request: &[u8]) -> Result<Option<Request>, String> {
+    let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = String::from_utf8_lossy(&request[..end]);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err("Invalid request".to_string());
+    };
+    let mut content_length = 0;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| "Invalid Content-Length".to_string())?;
+            }
+        }
+    }
+    let body = &request[end + 4..];
+    if body.len() < content_length {
+        return Ok(None);
+    }
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or_default().to_string(),
+        body: body[..content_length].to_vec(),
+    }))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ if status >= 500 => "Internal Server Error",
+        _ => "",
+    }
+}
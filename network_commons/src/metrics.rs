@@ -0,0 +1,402 @@
+use std::{
+    fmt::Write,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::fd::{AsRawFd, RawFd},
+    sync::{Arc, Mutex, RwLock},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+#[cfg(target_os = "linux")]
+use crate::epoll_loop::LinuxEventLoop as EventLoop;
+use crate::{
+    connection::{
+        create_sweep_callback, ConnectionLimits, ConnectionSlot, ConnectionTable, Exchange,
+        SWEEP_INTERVAL,
+    },
+    epoll_loop::{DuplexChannel, EventLoopMessages},
+    error::CommonError,
+    event_loop::{CallBack, EventLoopTrait, Itimerspec},
+    stats::streaming::Histogram,
+    stop_handle::StopHandle,
+};
+
+/// Content type of the responses of a `MetricsServer`.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Largest request a `MetricsServer` reads before answering with a 400.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Type of a family of metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Histogram => "histogram",
+        }
+    }
+}
+
+/// Writes metrics in the OpenMetrics text format.
+///
+/// Every family is started with `family`, followed by its samples. Counter samples get the
+/// `_total` suffix, so the family name must not have it.
+///
+/// # References
+///
+/// * [OpenMetrics 1.0](https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md)
+#[derive(Debug, Default)]
+pub struct OpenMetricsEncoder {
+    output: String,
+}
+
+impl OpenMetricsEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a family of metrics.
+    pub fn family(&mut self, name: &str, metric_type: MetricType, help: &str) {
+        let help = help.replace('\\', "\\\\").replace('\n', "\\n");
+        let _ = writeln!(self.output, "# TYPE {} {}", name, metric_type.as_str());
+        let _ = writeln!(self.output, "# HELP {} {}", name, help);
+    }
+
+    pub fn counter(&mut self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let _ = writeln!(
+            self.output,
+            "{}_total{} {}",
+            name,
+            format_labels(labels, None),
+            value
+        );
+    }
+
+    pub fn gauge(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let _ = writeln!(
+            self.output,
+            "{}{} {}",
+            name,
+            format_labels(labels, None),
+            format_value(value)
+        );
+    }
+
+    /// Writes the buckets, count and sum of a histogram, with its bounds and sum divided by
+    /// `unit`, as when converting nanoseconds to seconds.
+    /// The sum is left out when it is negative, since OpenMetrics requires it to be a counter.
+    pub fn histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        histogram: &Histogram,
+        unit: f64,
+    ) {
+        for (bound, count) in histogram.cumulative_counts() {
+            let bound = format_value(bound / unit);
+            let _ = writeln!(
+                self.output,
+                "{}_bucket{} {}",
+                name,
+                format_labels(labels, Some(&bound)),
+                count
+            );
+        }
+        let labels = format_labels(labels, None);
+        let _ = writeln!(
+            self.output,
+            "{}_count{} {}",
+            name,
+            labels,
+            histogram.count()
+        );
+        if histogram.sum() >= 0.0 {
+            let _ = writeln!(
+                self.output,
+                "{}_sum{} {}",
+                name,
+                labels,
+                format_value(histogram.sum() / unit)
+            );
+        }
+    }
+
+    /// Returns the encoded metrics, terminated by the `# EOF` line.
+    pub fn finish(mut self) -> String {
+        self.output.push_str("# EOF\n");
+        self.output
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)], le: Option<&str>) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .copied()
+        .chain(le.map(|le| ("le", le)))
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Minimal HTTP server exposing a snapshot of metrics on `GET /metrics`.
+///
+/// The server runs its own event loop on a separate thread and only reads the snapshot, so
+/// the test that renders it never waits on a scrape. Every connection serves a single
+/// request and is closed after the response, or once it outlived the idle timeout of its
+/// `ConnectionLimits`.
+#[derive(Debug)]
+pub struct MetricsServer {
+    local_address: SocketAddr,
+    stop_handle: StopHandle,
+    thread: Option<JoinHandle<Result<(), CommonError>>>,
+}
+
+impl MetricsServer {
+    /// Binds to `address` and starts serving the `snapshot`, with the default
+    /// `ConnectionLimits`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the address can't be bound.
+    pub fn spawn(address: SocketAddr, snapshot: Arc<RwLock<String>>) -> Result<Self, CommonError> {
+        Self::spawn_with_limits(address, snapshot, ConnectionLimits::default())
+    }
+
+    /// Binds to `address` and starts serving the `snapshot`, with the given limits on the
+    /// connections.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the address can't be bound.
+    pub fn spawn_with_limits(
+        address: SocketAddr,
+        snapshot: Arc<RwLock<String>>,
+        limits: ConnectionLimits,
+    ) -> Result<Self, CommonError> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let local_address = listener.local_addr()?;
+        let stop_handle = StopHandle::new()?;
+        let loop_stop_handle = stop_handle.clone();
+        let thread = std::thread::Builder::new()
+            .name("metrics".to_string())
+            .spawn(move || -> Result<(), CommonError> {
+                let mut event_loop: EventLoop<MetricsSocket> = EventLoop::new(64)?;
+                event_loop.set_overtime(Itimerspec {
+                    it_interval: Duration::ZERO,
+                    it_value: Duration::from_millis(1),
+                });
+                event_loop.register_stop_handle(&loop_stop_handle)?;
+                let event_sender = event_loop.get_communication_channel();
+                let connections = ConnectionTable::new(limits);
+                let accepted_connections = connections.clone();
+                let listener_token = event_loop.register_event_source(
+                    MetricsSocket::Listener(listener),
+                    Box::new(move |listener: &mut MetricsSocket, _token| {
+                        let MetricsSocket::Listener(listener) = listener else {
+                            return Ok(0);
+                        };
+                        // Failing to accept a connection must not close the listener
+                        loop {
+                            match listener.accept() {
+                                Ok((connection, peer_address)) => {
+                                    let Some(slot) = accepted_connections.open() else {
+                                        log::debug!(
+                                            "Too many metrics connections, closing the one of {}",
+                                            peer_address
+                                        );
+                                        continue;
+                                    };
+                                    log::debug!(
+                                        "Accepted metrics connection from {}",
+                                        peer_address
+                                    );
+                                    if let Err(e) = connection.set_nonblocking(true) {
+                                        log::debug!("Failed to set up metrics connection: {}", e);
+                                        continue;
+                                    }
+                                    let callback = create_connection_callback(
+                                        snapshot.clone(),
+                                        slot,
+                                        event_sender.clone(),
+                                    );
+                                    event_sender.try_lock()?.send(
+                                        EventLoopMessages::RegisterReadWrite((
+                                            MetricsSocket::Connection(connection),
+                                            callback,
+                                        )),
+                                    )?;
+                                }
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                                Err(e) => {
+                                    log::debug!("Failed to accept metrics connection: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                        Ok(0)
+                    }),
+                )?;
+                event_loop.register_timer(
+                    &Itimerspec {
+                        it_interval: SWEEP_INTERVAL,
+                        it_value: SWEEP_INTERVAL,
+                    },
+                    &listener_token,
+                    create_sweep_callback(connections, event_loop.get_communication_channel()),
+                )?;
+                event_loop.run()
+            })?;
+        log::info!("Serving metrics on http://{}/metrics", local_address);
+        Ok(Self {
+            local_address,
+            stop_handle,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server is bound to.
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+
+    /// Stops the server and waits for its thread to end.
+    pub fn stop(mut self) -> Result<(), CommonError> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), CommonError> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        self.stop_handle.stop()?;
+        thread
+            .join()
+            .map_err(|_| CommonError::Generic("Metrics server thread panicked".to_string()))?
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            log::error!("Failed to stop the metrics server: {}", e);
+        }
+    }
+}
+
+/// Sockets of the event loop of a `MetricsServer`.
+enum MetricsSocket {
+    Listener(TcpListener),
+    Connection(TcpStream),
+}
+
+impl AsRawFd for MetricsSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            MetricsSocket::Listener(listener) => listener.as_raw_fd(),
+            MetricsSocket::Connection(connection) => connection.as_raw_fd(),
+        }
+    }
+}
+
+/// Reads a request from a connection until its headers are complete, writes the answer as
+/// the connection accepts it and asks the event loop to close the connection.
+fn create_connection_callback(
+    snapshot: Arc<RwLock<String>>,
+    mut slot: ConnectionSlot,
+    event_sender: Arc<Mutex<DuplexChannel<MetricsSocket>>>,
+) -> CallBack<MetricsSocket> {
+    let mut exchange = Exchange::default();
+    Box::new(move |connection: &mut MetricsSocket, token| {
+        let MetricsSocket::Connection(connection) = connection else {
+            return Ok(0);
+        };
+        if !slot.watch(token) {
+            return Ok(0);
+        }
+        let done = exchange.advance(connection, |request, _| {
+            let response = match request.windows(4).position(|window| window == b"\r\n\r\n") {
+                Some(end) => respond(&request[..end], &snapshot).unwrap_or_else(|e| {
+                    response(
+                        500,
+                        "Internal Server Error",
+                        "text/plain",
+                        &format!("{}\n", e),
+                    )
+                }),
+                None if request.len() > MAX_REQUEST_SIZE => {
+                    response(400, "Bad Request", "text/plain", "Request too large\n")
+                }
+                None => return None,
+            };
+            Some(response.into_bytes())
+        });
+        if done && slot.close() {
+            event_sender
+                .try_lock()?
+                .send(EventLoopMessages::Unregister(token))?;
+        }
+        Ok(0)
+    })
+}
+
+fn respond(head: &[u8], snapshot: &RwLock<String>) -> Result<String, CommonError> {
+    let head = String::from_utf8_lossy(head);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (method, target) = (request_line.next(), request_line.next());
+    let path = target.map(|target| target.split('?').next().unwrap_or_default());
+    Ok(match (method, path) {
+        (Some("GET"), Some("/metrics")) => {
+            response(200, "OK", OPENMETRICS_CONTENT_TYPE, &snapshot.read()?)
+        }
+        (Some("GET"), Some(_)) => response(404, "Not Found", "text/plain", "Not found\n"),
+        (Some(_), Some(_)) => response(
+            405,
+            "Method Not Allowed",
+            "text/plain",
+            "Only GET is supported\n",
+        ),
+        _ => response(400, "Bad Request", "text/plain", "Invalid request\n"),
+    })
+}
+
+fn response(status: u16, reason: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    )
+}
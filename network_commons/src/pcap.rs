@@ -0,0 +1,615 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{error::CommonError, time::DateTime};
+
+/// Link type of the captured packets, raw IPv4 or IPv6 packets without a link layer header.
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+/// Largest packet length stored in the capture files.
+const SNAPLEN: u32 = 65535;
+/// Hop limit written in the synthesized IP headers, the actual one isn't known.
+const DEFAULT_TTL: u8 = 64;
+const UDP_PROTOCOL: u8 = 17;
+
+/// Format of the capture files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CaptureFormat {
+    /// Classic pcap file with nanosecond timestamps
+    Pcap,
+    /// pcapng file, where every packet also carries its direction
+    #[default]
+    Pcapng,
+}
+
+/// Settings of the capture of the test packets.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CaptureConfiguration {
+    /// File the packets are written to, replaced if it exists
+    pub path: PathBuf,
+    #[serde(default)]
+    pub format: CaptureFormat,
+}
+
+/// Direction of a captured packet, from the point of view of the capturing host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// A UDP datagram to write to a capture file.
+#[derive(Debug, Clone, Copy)]
+pub struct Datagram<'a> {
+    pub timestamp: DateTime,
+    pub direction: Direction,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    /// Traffic class of the packet, the DSCP value shifted by two bits
+    pub tos: u8,
+    /// UDP payload
+    pub payload: &'a [u8],
+}
+
+/// Writes UDP datagrams to a pcap or pcapng file.
+///
+/// Sockets only give access to the UDP payloads, so every datagram gets synthesized IP and
+/// UDP headers built from its addresses, and the file uses the raw IP link type.
+///
+/// # References
+///
+/// * [PCAP Capture File Format](https://datatracker.ietf.org/doc/draft-ietf-opsawg-pcap/)
+/// * [PCAP Next Generation Dump File Format](https://datatracker.ietf.org/doc/draft-ietf-opsawg-pcapng/)
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    format: CaptureFormat,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// Creates the capture file of the configuration.
+    pub fn create(configuration: &CaptureConfiguration) -> Result<Self, CommonError> {
+        Self::new(
+            BufWriter::new(File::create(&configuration.path)?),
+            configuration.format,
+        )
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the file header to `writer`.
+    pub fn new(mut writer: W, format: CaptureFormat) -> Result<Self, CommonError> {
+        match format {
+            CaptureFormat::Pcap => {
+                // The magic number of the nanosecond resolution files
+                writer.write_all(&0xa1b2_3c4d_u32.to_le_bytes())?;
+                writer.write_all(&2u16.to_le_bytes())?;
+                writer.write_all(&4u16.to_le_bytes())?;
+                writer.write_all(&[0u8; 8])?;
+                writer.write_all(&SNAPLEN.to_le_bytes())?;
+                writer.write_all(&u32::from(LINKTYPE_RAW).to_le_bytes())?;
+            }
+            CaptureFormat::Pcapng => {
+                let mut section_header = Vec::new();
+                section_header.extend_from_slice(&0x1a2b_3c4d_u32.to_le_bytes());
+                section_header.extend_from_slice(&1u16.to_le_bytes());
+                section_header.extend_from_slice(&0u16.to_le_bytes());
+                // Unknown section length
+                section_header.extend_from_slice(&(-1i64).to_le_bytes());
+                write_block(&mut writer, 0x0a0d_0d0a, &section_header)?;
+
+                let mut interface = Vec::new();
+                interface.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+                interface.extend_from_slice(&0u16.to_le_bytes());
+                interface.extend_from_slice(&SNAPLEN.to_le_bytes());
+                // if_tsresol, timestamps are in nanoseconds
+                push_option(&mut interface, 9, &[9]);
+                push_option(&mut interface, 0, &[]);
+                write_block(&mut writer, 1, &interface)?;
+            }
+        }
+        Ok(Self { writer, format })
+    }
+
+    /// Writes a datagram, with its synthesized IP and UDP headers.
+    pub fn write(&mut self, datagram: &Datagram) -> Result<(), CommonError> {
+        let packet = ip_udp_packet(datagram)?;
+        let length = packet.len().min(SNAPLEN as usize);
+        let timestamp = datagram.timestamp.to_nanos();
+        match self.format {
+            CaptureFormat::Pcap => {
+                self.writer
+                    .write_all(&((timestamp / 1_000_000_000) as u32).to_le_bytes())?;
+                self.writer
+                    .write_all(&((timestamp % 1_000_000_000) as u32).to_le_bytes())?;
+                self.writer.write_all(&(length as u32).to_le_bytes())?;
+                self.writer
+                    .write_all(&(packet.len() as u32).to_le_bytes())?;
+                self.writer.write_all(&packet[..length])?;
+            }
+            CaptureFormat::Pcapng => {
+                let mut body = Vec::with_capacity(length + 32);
+                body.extend_from_slice(&0u32.to_le_bytes());
+                body.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+                body.extend_from_slice(&(timestamp as u32).to_le_bytes());
+                body.extend_from_slice(&(length as u32).to_le_bytes());
+                body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+                body.extend_from_slice(&packet[..length]);
+                body.resize(body.len().next_multiple_of(4), 0);
+                // epb_flags, with the direction in the two lowest bits
+                let direction: u32 = match datagram.direction {
+                    Direction::Inbound => 1,
+                    Direction::Outbound => 2,
+                };
+                push_option(&mut body, 2, &direction.to_le_bytes());
+                push_option(&mut body, 0, &[]);
+                write_block(&mut self.writer, 6, &body)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), CommonError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes a pcapng block around `body`, whose length must be a multiple of 4.
+fn write_block(writer: &mut impl Write, block_type: u32, body: &[u8]) -> Result<(), CommonError> {
+    let length = (body.len() + 12) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&length.to_le_bytes())?;
+    Ok(())
+}
+
+/// Appends a pcapng option, padded to 32 bits.
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+/// Builds the IP packet carrying a datagram, with its IPv4 or IPv6 header and UDP header.
+///
+/// # Errors
+///
+/// This function returns an error if the source and destination are not of the same family.
+pub fn ip_udp_packet(datagram: &Datagram) -> Result<Vec<u8>, CommonError> {
+    let udp_length = datagram.payload.len() + 8;
+    let mut udp = Vec::with_capacity(udp_length);
+    udp.extend_from_slice(&datagram.source.port().to_be_bytes());
+    udp.extend_from_slice(&datagram.destination.port().to_be_bytes());
+    udp.extend_from_slice(&(udp_length as u16).to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(datagram.payload);
+
+    let mut packet = match (datagram.source.ip(), datagram.destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let mut pseudo_header = Vec::with_capacity(12);
+            pseudo_header.extend_from_slice(&source.octets());
+            pseudo_header.extend_from_slice(&destination.octets());
+            pseudo_header.extend_from_slice(&[0, UDP_PROTOCOL]);
+            pseudo_header.extend_from_slice(&(udp_length as u16).to_be_bytes());
+            let checksum = udp_checksum(&pseudo_header, &udp);
+            udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+            let mut header = Vec::with_capacity(20 + udp_length);
+            header.extend_from_slice(&[0x45, datagram.tos]);
+            header.extend_from_slice(&((20 + udp_length) as u16).to_be_bytes());
+            header.extend_from_slice(&[0, 0, 0, 0, DEFAULT_TTL, UDP_PROTOCOL, 0, 0]);
+            header.extend_from_slice(&source.octets());
+            header.extend_from_slice(&destination.octets());
+            let checksum = !ones_complement_sum(0, &header);
+            header[10..12].copy_from_slice(&checksum.to_be_bytes());
+            header
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            let mut pseudo_header = Vec::with_capacity(40);
+            pseudo_header.extend_from_slice(&source.octets());
+            pseudo_header.extend_from_slice(&destination.octets());
+            pseudo_header.extend_from_slice(&(udp_length as u32).to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, UDP_PROTOCOL]);
+            let checksum = udp_checksum(&pseudo_header, &udp);
+            udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+            let mut header = Vec::with_capacity(40 + udp_length);
+            let version_class_flow = (6u32 << 28) | (u32::from(datagram.tos) << 20);
+            header.extend_from_slice(&version_class_flow.to_be_bytes());
+            header.extend_from_slice(&(udp_length as u16).to_be_bytes());
+            header.extend_from_slice(&[UDP_PROTOCOL, DEFAULT_TTL]);
+            header.extend_from_slice(&source.octets());
+            header.extend_from_slice(&destination.octets());
+            header
+        }
+        _ => {
+            return Err(CommonError::Generic(format!(
+                "Can't capture a datagram from {} to {}",
+                datagram.source, datagram.destination
+            )))
+        }
+    };
+    packet.extend_from_slice(&udp);
+    Ok(packet)
+}
+
+/// UDP checksum of a datagram, zero being sent as all ones.
+///
+/// # References
+///
+/// * [RFC 768](https://datatracker.ietf.org/doc/html/rfc768)
+fn udp_checksum(pseudo_header: &[u8], udp: &[u8]) -> u16 {
+    match !ones_complement_sum(ones_complement_sum(0, pseudo_header), udp) {
+        0 => 0xffff,
+        checksum => checksum,
+    }
+}
+
+/// Adds the 16 bits words of `bytes` to `sum` in ones' complement, an odd byte being padded
+/// with zero.
+fn ones_complement_sum(sum: u16, bytes: &[u8]) -> u16 {
+    let mut sum = u32::from(sum);
+    for chunk in bytes.chunks(2) {
+        sum += u32::from(u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// A UDP datagram read from a capture file.
+#[derive(Debug, Clone)]
+pub struct CapturedDatagram {
+    /// Time at which the packet was captured
+    pub timestamp: DateTime,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    /// TTL or hop limit of the IP header
+    pub ttl: u8,
+    /// UDP payload, truncated if the capture didn't keep the whole packet
+    pub payload: Vec<u8>,
+}
+
+/// Reads the UDP datagrams of a pcap or pcapng file, in the order of the file.
+///
+/// Packets are read from Ethernet (with VLAN tags), Linux cooked, BSD loopback and raw IP
+/// links. Other packets, including IP fragments, are skipped. The file is read record by
+/// record, so only its datagrams are held in memory.
+///
+/// # Errors
+///
+/// This function returns an error if the file can't be read or isn't a capture file.
+pub fn read_udp_datagrams(path: &Path) -> Result<Vec<CapturedDatagram>, CommonError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut datagrams = Vec::new();
+    let mut collect = |timestamp: u64, link_type: u16, frame: &[u8]| {
+        let Some((ttl, source, destination, payload)) =
+            ip_packet(link_type, frame).and_then(parse_udp)
+        else {
+            return;
+        };
+        datagrams.push(CapturedDatagram {
+            timestamp: DateTime::from_nanos(timestamp),
+            source,
+            destination,
+            ttl,
+            payload: payload.to_vec(),
+        });
+    };
+    let magic =
+        read_u32(reader.fill_buf()?, 0, true).ok_or_else(|| invalid_capture("empty file"))?;
+    if magic == PCAPNG_SECTION_HEADER {
+        read_pcapng(&mut reader, &mut collect)?;
+    } else {
+        read_pcap(&mut reader, &mut collect)?;
+    }
+    Ok(datagrams)
+}
+
+/// Type of the block starting every section of a pcapng file, the same in both byte orders.
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+/// Largest record read from a capture file, beyond which the file is considered corrupt.
+const MAX_RECORD_LENGTH: usize = 1 << 24;
+
+fn invalid_capture(reason: &str) -> CommonError {
+    CommonError::Generic(format!("Invalid capture file: {}", reason))
+}
+
+/// Reads a `u16` or `u32` in the byte order of the file.
+fn read_u16(data: &[u8], offset: usize, little_endian: bool) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if little_endian {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    })
+}
+
+/// Appends the next `length` bytes of `reader` to `buffer`, and returns whether the file
+/// held them all, as the last record of a file being written may be truncated.
+fn read_record<R: Read>(
+    reader: &mut R,
+    length: usize,
+    buffer: &mut Vec<u8>,
+) -> Result<bool, CommonError> {
+    if length > MAX_RECORD_LENGTH {
+        return Err(invalid_capture("record too large"));
+    }
+    let read = reader.take(length as u64).read_to_end(buffer)?;
+    Ok(read == length)
+}
+
+/// Reads the frames of a pcap file, and hands each of them to `frame` with its timestamp in
+/// nanoseconds and its link type.
+fn read_pcap<R: Read>(
+    reader: &mut R,
+    frame: &mut impl FnMut(u64, u16, &[u8]),
+) -> Result<(), CommonError> {
+    let mut header = Vec::new();
+    if !read_record(reader, 24, &mut header)? {
+        return Err(invalid_capture("truncated header"));
+    }
+    let (little_endian, nanoseconds) = match read_u32(&header, 0, true).unwrap_or_default() {
+        0xa1b2_c3d4 => (true, false),
+        0xa1b2_3c4d => (true, true),
+        0xd4c3_b2a1 => (false, false),
+        0x4d3c_b2a1 => (false, true),
+        _ => return Err(invalid_capture("unknown magic number")),
+    };
+    let link_type = read_u32(&header, 20, little_endian).unwrap_or_default() as u16;
+    let mut record = Vec::new();
+    loop {
+        record.clear();
+        if !read_record(reader, 16, &mut record)? {
+            break;
+        }
+        let seconds = u64::from(read_u32(&record, 0, little_endian).unwrap_or_default());
+        let fraction = u64::from(read_u32(&record, 4, little_endian).unwrap_or_default());
+        let length = read_u32(&record, 8, little_endian).unwrap_or_default() as usize;
+        record.clear();
+        if !read_record(reader, length, &mut record)? {
+            break;
+        }
+        let fraction = if nanoseconds {
+            fraction
+        } else {
+            fraction * 1000
+        };
+        frame(seconds * 1_000_000_000 + fraction, link_type, &record);
+    }
+    Ok(())
+}
+
+/// Interface of a pcapng section.
+struct Interface {
+    link_type: u16,
+    /// Timestamp resolution, as the `if_tsresol` option
+    resolution: u8,
+    /// Offset in seconds added to the timestamps
+    offset: i64,
+}
+
+impl Interface {
+    /// Finest timestamp resolution, in negative powers of 10, whose scale fits in a `u128`.
+    const MAX_DECIMAL_RESOLUTION: u8 = 38;
+
+    fn nanoseconds(&self, timestamp: u64) -> u64 {
+        let timestamp = u128::from(timestamp);
+        let nanoseconds = if self.resolution & 0x80 == 0 {
+            let exponent = u32::from(self.resolution);
+            if exponent <= 9 {
+                timestamp * 10u128.pow(9 - exponent)
+            } else {
+                timestamp / 10u128.pow(exponent - 9)
+            }
+        } else {
+            (timestamp * 1_000_000_000) >> (self.resolution & 0x7f)
+        };
+        (nanoseconds as i128 + i128::from(self.offset) * 1_000_000_000) as u64
+    }
+}
+
+/// Reads the frames of a pcapng file, and hands each of them to `frame` with its timestamp
+/// in nanoseconds and its link type.
+fn read_pcapng<R: Read>(
+    reader: &mut R,
+    frame: &mut impl FnMut(u64, u16, &[u8]),
+) -> Result<(), CommonError> {
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut little_endian = true;
+    let mut block = Vec::new();
+    loop {
+        block.clear();
+        if !read_record(reader, 8, &mut block)? {
+            break;
+        }
+        if read_u32(&block, 0, true) == Some(PCAPNG_SECTION_HEADER) {
+            // Every section sets its own byte order and interfaces
+            if !read_record(reader, 4, &mut block)? {
+                break;
+            }
+            little_endian = match read_u32(&block, 8, true) {
+                Some(0x1a2b_3c4d) => true,
+                Some(0x4d3c_2b1a) => false,
+                _ => return Err(invalid_capture("unknown byte order magic")),
+            };
+            interfaces.clear();
+        }
+        let block_type = read_u32(&block, 0, little_endian).unwrap_or_default();
+        let length = read_u32(&block, 4, little_endian).unwrap_or_default() as usize;
+        if length < 12 || length < block.len() {
+            break;
+        }
+        if !read_record(reader, length - block.len(), &mut block)? {
+            break;
+        }
+        let body = &block[8..length - 4];
+        match block_type {
+            // Interface description
+            1 => {
+                let mut interface = Interface {
+                    link_type: read_u16(body, 0, little_endian).unwrap_or_default(),
+                    resolution: 6,
+                    offset: 0,
+                };
+                let mut option = 8;
+                while let (Some(code), Some(option_length)) = (
+                    read_u16(body, option, little_endian),
+                    read_u16(body, option + 2, little_endian),
+                ) {
+                    let option_length = usize::from(option_length);
+                    let value = body.get(option + 4..option + 4 + option_length);
+                    match (code, value) {
+                        (0, _) | (_, None) => break,
+                        (9, Some([resolution, ..])) => {
+                            if resolution & 0x80 == 0
+                                && *resolution > Interface::MAX_DECIMAL_RESOLUTION
+                            {
+                                return Err(invalid_capture("timestamp resolution out of range"));
+                            }
+                            interface.resolution = *resolution
+                        }
+                        (14, Some(value)) if option_length == 8 => {
+                            let bytes = value.try_into().unwrap_or_default();
+                            interface.offset = if little_endian {
+                                i64::from_le_bytes(bytes)
+                            } else {
+                                i64::from_be_bytes(bytes)
+                            };
+                        }
+                        _ => {}
+                    }
+                    option += 4 + option_length.next_multiple_of(4);
+                }
+                interfaces.push(interface);
+            }
+            // Enhanced packet
+            6 => {
+                let interface = read_u32(body, 0, little_endian)
+                    .and_then(|id| interfaces.get(id as usize))
+                    .ok_or_else(|| invalid_capture("packet of an unknown interface"))?;
+                let high = u64::from(read_u32(body, 4, little_endian).unwrap_or_default());
+                let low = u64::from(read_u32(body, 8, little_endian).unwrap_or_default());
+                let length = read_u32(body, 12, little_endian).unwrap_or_default() as usize;
+                if let Some(data) = body.get(20..20 + length) {
+                    frame(
+                        interface.nanoseconds((high << 32) | low),
+                        interface.link_type,
+                        data,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Returns the IP packet carried by a frame.
+fn ip_packet(link_type: u16, frame: &[u8]) -> Option<&[u8]> {
+    match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(frame),
+        // The address family is in the byte order of the capturing host
+        LINKTYPE_NULL => frame.get(4..),
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ether_type =
+                u16::from_be_bytes(frame.get(offset..offset + 2)?.try_into().ok()?);
+            // VLAN tags
+            while ether_type == 0x8100 || ether_type == 0x88a8 {
+                offset += 4;
+                ether_type = u16::from_be_bytes(frame.get(offset..offset + 2)?.try_into().ok()?);
+            }
+            frame.get(offset + 2..)
+        }
+        LINKTYPE_LINUX_SLL => frame.get(16..),
+        LINKTYPE_LINUX_SLL2 => frame.get(20..),
+        _ => None,
+    }
+}
+
+/// Returns the TTL, addresses and payload of a UDP datagram carried by an IP packet.
+fn parse_udp(packet: &[u8]) -> Option<(u8, SocketAddr, SocketAddr, &[u8])> {
+    let (ttl, source, destination, mut udp) = match packet.first()? >> 4 {
+        4 => {
+            let header_length = usize::from(packet[0] & 0x0f) * 4;
+            // The header has at least the 20 bytes of its fixed fields
+            if header_length < 20 {
+                return None;
+            }
+            let fragment = u16::from_be_bytes(packet.get(6..8)?.try_into().ok()?);
+            // Only whole datagrams are read, fragments are skipped
+            if fragment & 0x3fff != 0 || *packet.get(9)? != UDP_PROTOCOL {
+                return None;
+            }
+            let total_length = usize::from(u16::from_be_bytes(packet.get(2..4)?.try_into().ok()?));
+            let source: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            (
+                packet[8],
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                packet.get(header_length..total_length.min(packet.len()))?,
+            )
+        }
+        6 => {
+            let source: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            let mut next_header = *packet.get(6)?;
+            let mut offset = 40;
+            // Hop-by-hop, routing and destination options extension headers
+            while matches!(next_header, 0 | 43 | 60) {
+                next_header = *packet.get(offset)?;
+                offset += (usize::from(*packet.get(offset + 1)?) + 1) * 8;
+            }
+            if next_header != UDP_PROTOCOL {
+                return None;
+            }
+            (
+                packet[7],
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                packet.get(offset..)?,
+            )
+        }
+        _ => return None,
+    };
+    let source_port = u16::from_be_bytes(udp.get(0..2)?.try_into().ok()?);
+    let destination_port = u16::from_be_bytes(udp.get(2..4)?.try_into().ok()?);
+    let length = usize::from(u16::from_be_bytes(udp.get(4..6)?.try_into().ok()?));
+    if length >= 8 && length <= udp.len() {
+        udp = &udp[..length];
+    }
+    Some((
+        ttl,
+        SocketAddr::new(source, source_port),
+        SocketAddr::new(destination, destination_port),
+        udp.get(8..)?,
+    ))
+}
@@ -0,0 +1,276 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::time::DateTime;
+
+const SECONDS_PER_DAY: i64 = 86400;
+/// A cron expression matches at least once in every 8 years, or never.
+const MAX_SEARCHED_DAYS: i64 = 8 * 366;
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const DAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// When a recurring test runs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    /// Runs every given number of seconds, starting right away
+    Every(u64),
+    /// Runs at the minutes matched by a cron expression, in UTC
+    Cron(CronExpression),
+}
+
+impl Schedule {
+    /// Time of the first run of a schedule started at `now`.
+    pub fn first(&self, now: DateTime) -> Option<DateTime> {
+        match self {
+            Schedule::Every(_) => Some(now),
+            Schedule::Cron(expression) => expression.next_after(now),
+        }
+    }
+
+    /// Time of the run that follows the run planned at `previous`, skipping the runs that
+    /// would already be late at `now`.
+    pub fn next(&self, previous: DateTime, now: DateTime) -> Option<DateTime> {
+        match self {
+            Schedule::Every(period) => {
+                // Runs are whole periods apart, down to the fraction of a second of the first
+                let period = (*period).max(1) * 1_000_000_000;
+                let previous = previous.to_nanos();
+                let late = now.to_nanos().saturating_sub(previous) / period;
+                Some(DateTime::from_nanos(previous + (late + 1) * period))
+            }
+            Schedule::Cron(expression) => {
+                let after = if previous.get_sec() > now.get_sec() {
+                    previous
+                } else {
+                    now
+                };
+                expression.next_after(after)
+            }
+        }
+    }
+}
+
+/// Periods must be at least one second long.
+impl Validate for Schedule {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        if let Schedule::Every(0) = self {
+            let mut errors = ValidationErrors::new();
+            errors.add(
+                "every",
+                ValidationError::new("every must be at least 1 second"),
+            );
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+/// Cron expression with the five usual fields: minute, hour, day of the month, month and day
+/// of the week.
+///
+/// Fields hold `*`, values, ranges such as `1-5`, steps such as `*/15` or `0-30/10`, and lists
+/// of those separated by commas. Months and days of the week can also be named, as `JAN` or
+/// `MON`, and Sunday is either 0 or 7. As with cron, a time matches when either day field
+/// matches if both are restricted. The `@yearly`, `@monthly`, `@weekly`, `@daily` and
+/// `@hourly` shortcuts are accepted as well.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct CronExpression {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month and the day of the week are both restricted
+    either_day: bool,
+}
+
+impl CronExpression {
+    /// First minute matched by the expression strictly after `after`, or `None` if the
+    /// expression never matches, such as on February 30th.
+    pub fn next_after(&self, after: DateTime) -> Option<DateTime> {
+        let start = (after.get_sec() as i64 / 60 + 1) * 60;
+        let first_day = start.div_euclid(SECONDS_PER_DAY);
+        for day in first_day..first_day + MAX_SEARCHED_DAYS {
+            if !self.matches_day(day) {
+                continue;
+            }
+            let first_minute = if day == first_day {
+                start.rem_euclid(SECONDS_PER_DAY) / 60
+            } else {
+                0
+            };
+            let minute = (first_minute..24 * 60).find(|minute| {
+                contains(self.hours, minute / 60) && contains(self.minutes, minute % 60)
+            });
+            if let Some(minute) = minute {
+                return Some(from_seconds(day * SECONDS_PER_DAY + minute * 60));
+            }
+        }
+        None
+    }
+
+    /// Whether the expression matches the day, counted from the Unix epoch.
+    fn matches_day(&self, day: i64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        // The epoch was a Thursday
+        let weekday = (day + 4).rem_euclid(7);
+        let day_of_month = contains(self.days, day_of_month);
+        let weekday = contains(self.weekdays, weekday);
+        let day = if self.either_day {
+            day_of_month || weekday
+        } else {
+            day_of_month && weekday
+        };
+        contains(self.months, month) && day
+    }
+}
+
+impl FromStr for CronExpression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            fields => fields,
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "invalid cron expression '{}': expected 5 fields",
+                s
+            ));
+        };
+        let field = |field: &str, min: i64, max: i64, names: &[&str]| {
+            parse_field(field, min, max, names)
+                .map_err(|e| format!("invalid cron expression '{}': {}", s, e))
+        };
+        let (days, restricted_days) = field(days, 1, 31, &[])?;
+        let (weekdays, restricted_weekdays) = field(weekdays, 0, 7, &DAY_NAMES)?;
+        Ok(Self {
+            expression: s.to_string(),
+            minutes: field(minutes, 0, 59, &[])?.0,
+            hours: field(hours, 0, 23, &[])?.0,
+            days,
+            months: field(months, 1, 12, &MONTH_NAMES)?.0,
+            // Sunday is both 0 and 7
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day: restricted_days && restricted_weekdays,
+        })
+    }
+}
+
+impl TryFrom<String> for CronExpression {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<CronExpression> for String {
+    fn from(expression: CronExpression) -> Self {
+        expression.expression
+    }
+}
+
+impl fmt::Display for CronExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// Parses a field into the mask of the values it matches, along with whether it restricts
+/// them. Names stand for the values from `min` on.
+fn parse_field(field: &str, min: i64, max: i64, names: &[&str]) -> Result<(u64, bool), String> {
+    let value = |value: &str| {
+        let number = match names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(value))
+        {
+            Some(position) => position as i64 + min,
+            None => value
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", value))?,
+        };
+        if !(min..=max).contains(&number) {
+            return Err(format!(
+                "value {} is not between {} and {}",
+                number, min, max
+            ));
+        }
+        Ok(number)
+    };
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<i64>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step '{}'", step)),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((first, last)) => (value(first)?, value(last)?),
+                // A single value with a step runs up to the maximum
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if first > last {
+            return Err(format!("invalid range '{}'", range));
+        }
+        for number in (first..=last).step_by(step as usize) {
+            mask |= 1 << number;
+        }
+    }
+    // As with cron, a field starting with a star doesn't restrict the days
+    Ok((mask, !field.starts_with('*')))
+}
+
+fn contains(mask: u64, value: i64) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn from_seconds(seconds: i64) -> DateTime {
+    DateTime {
+        sec: seconds as u32,
+        nanos: 0,
+    }
+}
+
+/// Converts a number of days since the Unix epoch to a year, a month and a day.
+///
+/// # References
+///
+/// * H. Hinnant. "chrono-Compatible Low-Level Date Algorithms".
+///   <https://howardhinnant.github.io/date_algorithms.html>
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,641 @@
+use std::{
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    error::CommonError, libc_call, schedule::Schedule, stats::offset_estimator::LcgRng,
+    stop_handle::StopHandle, time::DateTime, Strategy, TestResult,
+};
+
+/// Creates the strategy of every run of a job.
+pub type StrategyFactory<R> =
+    Arc<dyn Fn() -> Result<Box<dyn Strategy<R, CommonError>>, CommonError> + Send + Sync>;
+
+/// Receives the name of the job and the result of every run.
+pub type ResultHandler<R> = Arc<dyn Fn(&str, &Result<R, CommonError>) + Send + Sync>;
+
+/// A test run again and again on a schedule, or on demand without one.
+pub struct Job<R> {
+    name: String,
+    schedule: Option<Schedule>,
+    jitter: Duration,
+    factory: StrategyFactory<R>,
+}
+
+impl<R: TestResult> Job<R> {
+    /// Creates a job whose runs execute the strategies created by `factory`.
+    /// The factory attaches the result sinks of the job to the strategies it creates.
+    ///
+    /// A job without a schedule runs once when it is added to the scheduler, and then
+    /// whenever `SchedulerHandle::run_now` asks for it.
+    pub fn new<F>(name: impl Into<String>, schedule: Option<Schedule>, factory: F) -> Self
+    where
+        F: Fn() -> Result<Box<dyn Strategy<R, CommonError>>, CommonError> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            schedule,
+            jitter: Duration::ZERO,
+            factory: Arc::new(factory),
+        }
+    }
+
+    /// Delays every scheduled run by a random duration up to `jitter`, so jobs sharing a
+    /// schedule don't all start at once.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schedule(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+}
+
+/// State of a job, as listed by `SchedulerHandle::jobs`.
+#[derive(Serialize, Debug, Clone)]
+pub struct JobStatus {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    /// Start of the run that is going, if any
+    pub running_since: Option<DateTime>,
+    /// Start of the next scheduled run
+    pub next_run: Option<DateTime>,
+    /// Number of finished runs
+    pub runs: u64,
+    /// Start of the last finished run
+    pub last_run: Option<DateTime>,
+    /// `passed`, or why the last finished run didn't pass
+    pub last_status: Option<String>,
+}
+
+/// Last finished run of a job.
+#[derive(Debug, Clone)]
+pub struct RunRecord<R> {
+    pub started: DateTime,
+    pub finished: DateTime,
+    /// Result of the run, or why it failed
+    pub result: Result<R, String>,
+}
+
+impl<R: TestResult> RunRecord<R> {
+    /// `passed`, or why the run didn't pass.
+    pub fn status(&self) -> String {
+        match &self.result {
+            Ok(result) => match result.status() {
+                Ok(()) => "passed".to_string(),
+                Err(e) => e.to_string(),
+            },
+            Err(e) => e.clone(),
+        }
+    }
+}
+
+/// Runs jobs on their schedules, each run on a thread of the process.
+///
+/// At most `max_concurrent_jobs` runs of scheduled jobs go on at the same time. A run that
+/// is due while no slot is free waits for one, and the runs it misses meanwhile are merged
+/// into it. A run that is due while the previous run of the same job is still going is
+/// skipped. The runs of jobs without a schedule, which may last as long as the scheduler,
+/// don't take a slot.
+///
+/// Every run gets its own `StopHandle`, stopped along with the scheduler, so the runs that
+/// are going still deliver their partial results. Jobs are managed while the scheduler runs
+/// through a `SchedulerHandle`.
+pub struct Scheduler<R> {
+    shared: Arc<Shared<R>>,
+    stop_handle: Option<StopHandle>,
+    result_handler: Option<ResultHandler<R>>,
+}
+
+/// Manages the jobs of a running `Scheduler` from any thread.
+pub struct SchedulerHandle<R> {
+    shared: Arc<Shared<R>>,
+}
+
+impl<R> Clone for SchedulerHandle<R> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+struct Shared<R> {
+    state: Mutex<State<R>>,
+    /// Wakes the scheduler up when a run ends or the jobs change
+    wakeup: Notifier,
+}
+
+struct State<R> {
+    entries: Vec<Entry<R>>,
+    max_concurrent_jobs: usize,
+    /// Number of runs of scheduled jobs going on
+    active: usize,
+    next_id: u64,
+    rng: LcgRng,
+}
+
+/// Planning and history of a job.
+struct Entry<R> {
+    /// Identifies the job across replacements, for the runs to report to it
+    id: u64,
+    job: Arc<Job<R>>,
+    /// Time of the next scheduled run, before the jitter
+    planned: Option<DateTime>,
+    /// Time the next scheduled run starts at
+    due: Option<DateTime>,
+    /// Whether a run was asked for outside of the schedule
+    requested: bool,
+    run: Option<(DateTime, StopHandle)>,
+    runs: u64,
+    last: Option<RunRecord<R>>,
+}
+
+impl<R: TestResult> Entry<R> {
+    /// Plans the first run of a job.
+    fn plan(&mut self, rng: &mut LcgRng, now: DateTime) {
+        self.planned = self
+            .job
+            .schedule
+            .as_ref()
+            .and_then(|schedule| schedule.first(now));
+        self.due = self
+            .planned
+            .map(|planned| planned + jitter(rng, self.job.jitter));
+        self.requested = self.job.schedule.is_none();
+    }
+
+    /// Plans the run that follows the one that was due.
+    fn advance(&mut self, rng: &mut LcgRng, now: DateTime) {
+        self.planned = match (&self.job.schedule, self.planned) {
+            (Some(schedule), Some(planned)) => schedule.next(planned, now),
+            _ => None,
+        };
+        self.due = self
+            .planned
+            .map(|planned| planned + jitter(rng, self.job.jitter));
+        match self.due {
+            Some(due) => log::debug!("Next run of {} at {}", self.job.name, due),
+            None => log::warn!("{} will not run again on its schedule", self.job.name),
+        }
+    }
+
+    fn status(&self) -> JobStatus {
+        JobStatus {
+            name: self.job.name.clone(),
+            schedule: self.job.schedule.clone(),
+            running_since: self.run.as_ref().map(|(started, _)| *started),
+            next_run: self.due,
+            runs: self.runs,
+            last_run: self.last.as_ref().map(|last| last.started),
+            last_status: self.last.as_ref().map(RunRecord::status),
+        }
+    }
+}
+
+impl<R: TestResult + 'static> Scheduler<R> {
+    pub fn new(max_concurrent_jobs: usize) -> Result<Self, CommonError> {
+        let state = State {
+            entries: Vec::new(),
+            max_concurrent_jobs: max_concurrent_jobs.max(1),
+            active: 0,
+            next_id: 0,
+            rng: LcgRng::new(DateTime::utc_now().to_nanos()),
+        };
+        Ok(Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                wakeup: Notifier::new()?,
+            }),
+            stop_handle: None,
+            result_handler: None,
+        })
+    }
+
+    /// Adds a job, replacing the job with the same name if there is one.
+    pub fn add_job(&mut self, job: Job<R>) -> Result<(), CommonError> {
+        self.handle().add_job(job)
+    }
+
+    /// Handle that manages the jobs while the scheduler runs.
+    pub fn handle(&self) -> SchedulerHandle<R> {
+        SchedulerHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Sets the `StopHandle` that stops the scheduler and the runs that are going.
+    pub fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+
+    /// Sets the handler called with the result of every run, from the thread of the run.
+    pub fn set_result_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&str, &Result<R, CommonError>) + Send + Sync + 'static,
+    {
+        self.result_handler = Some(Arc::new(handler));
+    }
+
+    /// Runs the jobs until the scheduler is stopped, then waits for the runs that are going.
+    pub fn run(&self) -> Result<(), CommonError> {
+        let stop_handle = match &self.stop_handle {
+            Some(stop_handle) => stop_handle.clone(),
+            None => StopHandle::new()?,
+        };
+        let mut workers: Vec<JoinHandle<()>> = Vec::new();
+        log::info!(
+            "Scheduling {} jobs",
+            self.shared.state.lock()?.entries.len()
+        );
+
+        while !stop_handle.is_stopped() {
+            let now = DateTime::utc_now();
+            let timeout = {
+                let mut state = self.shared.state.lock()?;
+                let State {
+                    entries,
+                    max_concurrent_jobs,
+                    active,
+                    rng,
+                    ..
+                } = &mut *state;
+                for entry in entries.iter_mut() {
+                    let scheduled = entry
+                        .due
+                        .is_some_and(|due| due.to_nanos() <= now.to_nanos());
+                    if !scheduled && !entry.requested {
+                        continue;
+                    }
+                    if entry.run.is_some() {
+                        // Requested runs wait for the end of the run that is going
+                        if scheduled {
+                            log::warn!(
+                                "Skipping a run of {}, its previous run is still going",
+                                entry.job.name
+                            );
+                            entry.advance(rng, now);
+                        }
+                        continue;
+                    }
+                    let limited = entry.job.schedule.is_some();
+                    if limited && *active >= *max_concurrent_jobs {
+                        // Waits for a run to finish
+                        continue;
+                    }
+                    let run_stop_handle = StopHandle::new()?;
+                    entry.run = Some((now, run_stop_handle.clone()));
+                    entry.requested = false;
+                    if limited {
+                        *active += 1;
+                    }
+                    workers.push(self.start(entry.id, entry.job.clone(), limited, run_stop_handle));
+                    if scheduled {
+                        entry.advance(rng, now);
+                    }
+                }
+                // Runs waiting for a slot are woken up by the end of another run
+                entries
+                    .iter()
+                    .filter_map(|entry| entry.due)
+                    .filter(|due| due.to_nanos() > now.to_nanos())
+                    .map(|due| Duration::from_nanos(due.to_nanos() - now.to_nanos()))
+                    .min()
+            };
+            workers.retain(|worker| !worker.is_finished());
+            wait(&stop_handle, &self.shared.wakeup, timeout)?;
+        }
+
+        let state = self.shared.state.lock()?;
+        let runs: Vec<&StopHandle> = state
+            .entries
+            .iter()
+            .filter_map(|entry| entry.run.as_ref())
+            .map(|(_, run_stop_handle)| run_stop_handle)
+            .collect();
+        log::info!("Scheduler stopped, waiting for {} runs", runs.len());
+        for run_stop_handle in runs {
+            run_stop_handle.stop()?;
+        }
+        drop(state);
+        for worker in workers {
+            let _ = worker.join();
+        }
+        Ok(())
+    }
+
+    /// Starts a run of a job, which gives its slot back when it ends if it is `limited`.
+    fn start(
+        &self,
+        id: u64,
+        job: Arc<Job<R>>,
+        limited: bool,
+        stop_handle: StopHandle,
+    ) -> JoinHandle<()> {
+        let shared = self.shared.clone();
+        let result_handler = self.result_handler.clone();
+        thread::spawn(move || {
+            let name = &job.name;
+            log::info!("Starting a run of {}", name);
+            let started = DateTime::utc_now();
+            let result = (job.factory)().and_then(|mut strategy| {
+                strategy.set_stop_handle(stop_handle);
+                strategy.execute()
+            });
+            match &result {
+                Ok(result) => match result.status() {
+                    Ok(()) => log::info!("Run of {} passed", name),
+                    Err(e) => log::warn!("Run of {}: {}", name, e),
+                },
+                Err(e) => log::error!("Run of {} failed: {}", name, e),
+            }
+            if let Some(result_handler) = result_handler {
+                result_handler(name, &result);
+            }
+            let record = RunRecord {
+                started,
+                finished: DateTime::utc_now(),
+                result: result.map_err(|e| e.to_string()),
+            };
+            if let Err(e) = shared.finish(id, limited, record) {
+                log::error!("Failed to record the end of a run of {}: {}", name, e);
+            }
+        })
+    }
+}
+
+impl<R: TestResult> Shared<R> {
+    /// Records the end of a run of the job with the `id`, if the job is still there, and gives
+    /// its slot back if it took one.
+    fn finish(&self, id: u64, limited: bool, record: RunRecord<R>) -> Result<(), CommonError> {
+        let mut state = self.state.lock()?;
+        if limited {
+            state.active -= 1;
+        }
+        if let Some(entry) = state.entries.iter_mut().find(|entry| entry.id == id) {
+            entry.run = None;
+            entry.runs += 1;
+            entry.last = Some(record);
+        }
+        drop(state);
+        self.wakeup.notify()
+    }
+}
+
+impl<R: TestResult> SchedulerHandle<R> {
+    /// Adds a job, replacing the job with the same name if there is one. The run of the
+    /// replaced job that is going, if any, goes on, and the job is planned again.
+    pub fn add_job(&self, job: Job<R>) -> Result<(), CommonError> {
+        let mut state = self.shared.state.lock()?;
+        let State {
+            entries,
+            next_id,
+            rng,
+            ..
+        } = &mut *state;
+        let job = Arc::new(job);
+        match entries.iter_mut().find(|entry| entry.job.name == job.name) {
+            Some(entry) => {
+                log::info!("Replacing job {}", job.name);
+                entry.job = job;
+                entry.plan(rng, DateTime::utc_now());
+            }
+            None => {
+                log::info!("Adding job {}", job.name);
+                let mut entry = Entry {
+                    id: *next_id,
+                    job,
+                    planned: None,
+                    due: None,
+                    requested: false,
+                    run: None,
+                    runs: 0,
+                    last: None,
+                };
+                *next_id += 1;
+                entry.plan(rng, DateTime::utc_now());
+                entries.push(entry);
+            }
+        }
+        drop(state);
+        self.shared.wakeup.notify()
+    }
+
+    /// Replaces the job with the same name without planning it again, so that only its next
+    /// runs change. Returns whether there was a job with that name.
+    pub fn update_job(&self, job: Job<R>) -> Result<bool, CommonError> {
+        let mut state = self.shared.state.lock()?;
+        let Some(entry) = state
+            .entries
+            .iter_mut()
+            .find(|entry| entry.job.name == job.name)
+        else {
+            return Ok(false);
+        };
+        log::info!("Updating job {}", job.name);
+        entry.job = Arc::new(job);
+        Ok(true)
+    }
+
+    /// Removes a job and stops its run that is going.
+    /// Returns whether there was a job with that name.
+    pub fn remove_job(&self, name: &str) -> Result<bool, CommonError> {
+        let mut state = self.shared.state.lock()?;
+        let Some(position) = state
+            .entries
+            .iter()
+            .position(|entry| entry.job.name == name)
+        else {
+            return Ok(false);
+        };
+        let entry = state.entries.remove(position);
+        log::info!("Removing job {}", name);
+        if let Some((_, run_stop_handle)) = entry.run {
+            run_stop_handle.stop()?;
+        }
+        Ok(true)
+    }
+
+    /// Runs a job as soon as a slot is free, or once its run that is going ends.
+    /// Returns whether there is a job with that name.
+    pub fn run_now(&self, name: &str) -> Result<bool, CommonError> {
+        let mut state = self.shared.state.lock()?;
+        let Some(entry) = state
+            .entries
+            .iter_mut()
+            .find(|entry| entry.job.name == name)
+        else {
+            return Ok(false);
+        };
+        entry.requested = true;
+        drop(state);
+        self.shared.wakeup.notify()?;
+        Ok(true)
+    }
+
+    /// Stops the run of a job that is going, which still delivers its partial results.
+    /// Returns whether a run was going.
+    pub fn stop_run(&self, name: &str) -> Result<bool, CommonError> {
+        let state = self.shared.state.lock()?;
+        let run = state
+            .entries
+            .iter()
+            .find(|entry| entry.job.name == name)
+            .and_then(|entry| entry.run.as_ref());
+        match run {
+            Some((_, run_stop_handle)) => {
+                log::info!("Stopping the run of {}", name);
+                run_stop_handle.stop()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn jobs(&self) -> Result<Vec<JobStatus>, CommonError> {
+        Ok(self
+            .shared
+            .state
+            .lock()?
+            .entries
+            .iter()
+            .map(Entry::status)
+            .collect())
+    }
+
+    pub fn job(&self, name: &str) -> Result<Option<JobStatus>, CommonError> {
+        Ok(self
+            .shared
+            .state
+            .lock()?
+            .entries
+            .iter()
+            .find(|entry| entry.job.name == name)
+            .map(Entry::status))
+    }
+}
+
+impl<R: TestResult + Clone> SchedulerHandle<R> {
+    /// Last finished run of a job, if it ran already.
+    pub fn last_run(&self, name: &str) -> Result<Option<RunRecord<R>>, CommonError> {
+        Ok(self
+            .shared
+            .state
+            .lock()?
+            .entries
+            .iter()
+            .find(|entry| entry.job.name == name)
+            .and_then(|entry| entry.last.clone()))
+    }
+}
+
+/// Random delay up to `jitter`.
+fn jitter(rng: &mut LcgRng, jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(rng.gen_range(0.0..jitter.as_secs_f64()))
+}
+
+/// Waits until the timeout elapses, the scheduler is stopped, a run ends or the jobs change.
+fn wait(
+    stop_handle: &StopHandle,
+    wakeup: &Notifier,
+    timeout: Option<Duration>,
+) -> Result<(), CommonError> {
+    let mut poll_fds = vec![
+        libc::pollfd {
+            fd: stop_handle.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: wakeup.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    if let Some(signal_fd) = stop_handle.signal_fd() {
+        poll_fds.push(libc::pollfd {
+            fd: signal_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+    }
+    // Rounded up, so a run isn't woken up just before it is due
+    let timeout = timeout.map_or(-1, |timeout| {
+        timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+    });
+    let result = unsafe {
+        libc::poll(
+            poll_fds.as_mut_ptr(),
+            poll_fds.len() as libc::nfds_t,
+            timeout,
+        )
+    };
+    if result < 0 {
+        let error = std::io::Error::last_os_error();
+        if error.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(());
+        }
+        return Err(CommonError::Io(error));
+    }
+    if poll_fds[1].revents & libc::POLLIN != 0 {
+        wakeup.clear();
+    }
+    if poll_fds
+        .get(2)
+        .is_some_and(|fd| fd.revents & libc::POLLIN != 0)
+    {
+        stop_handle.handle_signal()?;
+    }
+    Ok(())
+}
+
+/// Eventfd through which the scheduler is woken up.
+struct Notifier {
+    fd: OwnedFd,
+}
+
+impl Notifier {
+    fn new() -> Result<Self, CommonError> {
+        let fd = libc_call!(eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC))
+            .map_err(CommonError::Io)?;
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    fn notify(&self) -> Result<(), CommonError> {
+        let value: u64 = 1;
+        libc_call!(write(
+            self.fd.as_raw_fd(),
+            &value as *const u64 as *const libc::c_void,
+            std::mem::size_of::<u64>()
+        ))
+        .map_err(CommonError::Io)?;
+        Ok(())
+    }
+
+    fn clear(&self) {
+        let mut value: u64 = 0;
+        unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut value as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+    }
+}
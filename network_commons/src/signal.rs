@@ -0,0 +1,99 @@
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use crate::{error::CommonError, stop_handle::StopHandle};
+
+/// Receives signals through a signalfd instead of their default action, such as SIGHUP
+/// asking a reflector to reload its configuration.
+#[derive(Debug)]
+pub struct SignalListener {
+    signal_fd: OwnedFd,
+}
+
+impl SignalListener {
+    /// Listens to the provided signals.
+    ///
+    /// As with `StopHandle::with_signals`, the signals are blocked for the calling thread and
+    /// the threads it spawns afterwards, so this should be called from the main thread before
+    /// any other thread is spawned.
+    pub fn new(signals: &[i32]) -> Result<Self, CommonError> {
+        Ok(Self {
+            signal_fd: block_signals(signals, 0)?,
+        })
+    }
+
+    /// Listens to SIGHUP, the usual request to reload a configuration.
+    pub fn hangup() -> Result<Self, CommonError> {
+        Self::new(&[libc::SIGHUP])
+    }
+
+    /// Waits for one of the signals and returns its number, or `None` once the `stop_handle`
+    /// is stopped.
+    pub fn wait(&self, stop_handle: &StopHandle) -> Result<Option<u32>, CommonError> {
+        let mut poll_fds = vec![
+            libc::pollfd {
+                fd: self.signal_fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: stop_handle.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        if let Some(signal_fd) = stop_handle.signal_fd() {
+            poll_fds.push(libc::pollfd {
+                fd: signal_fd,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+        }
+        loop {
+            let result =
+                unsafe { libc::poll(poll_fds.as_mut_ptr(), poll_fds.len() as libc::nfds_t, -1) };
+            if result < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(CommonError::Io(error));
+            }
+            if poll_fds[1..].iter().any(|poll_fd| poll_fd.revents != 0) {
+                stop_handle.handle_signal()?;
+                return Ok(None);
+            }
+            let mut siginfo: libc::signalfd_siginfo = unsafe { std::mem::zeroed() };
+            let size = std::mem::size_of::<libc::signalfd_siginfo>();
+            let result = unsafe {
+                libc::read(
+                    self.signal_fd.as_raw_fd(),
+                    &mut siginfo as *mut libc::signalfd_siginfo as *mut libc::c_void,
+                    size,
+                )
+            };
+            if result == size as isize {
+                return Ok(Some(siginfo.ssi_signo));
+            }
+        }
+    }
+}
+
+/// Blocks the signals for the calling thread and returns a signalfd receiving them.
+pub(crate) fn block_signals(signals: &[i32], flags: i32) -> Result<OwnedFd, CommonError> {
+    let signal_fd = unsafe {
+        let mut mask: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        for signal in signals {
+            libc::sigaddset(&mut mask, *signal);
+        }
+        let result = libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut());
+        if result != 0 {
+            return Err(CommonError::Io(std::io::Error::from_raw_os_error(result)));
+        }
+        libc::signalfd(-1, &mask, flags | libc::SFD_CLOEXEC)
+    };
+    if signal_fd < 0 {
+        return Err(CommonError::Io(std::io::Error::last_os_error()));
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(signal_fd) })
+}
@@ -0,0 +1,311 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Stdout, Write},
+    path::Path,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::error::CommonError;
+
+/// Destination of the outputs of a test.
+///
+/// A test calls `on_start` before it starts, `on_interval` with every periodic report,
+/// `on_packet` with every packet once it is complete or lost, and `on_complete` with the
+/// final result. Reports are only produced when one of the sinks of the test `wants_intervals`,
+/// and packets when one of them `wants_packets`.
+/// `flush` is called after every batch of events.
+///
+/// # Type Parameters
+///
+/// - `R`: The type of the final result of the test.
+/// - `I`: The type of the periodic reports of the test.
+/// - `P`: The type of the packets of the test.
+pub trait ResultSink<R, I, P>: Send {
+    fn on_start(&mut self) -> Result<(), CommonError> {
+        Ok(())
+    }
+
+    /// Whether the sink receives the periodic reports of the test.
+    fn wants_intervals(&self) -> bool {
+        true
+    }
+
+    fn on_interval(&mut self, _interval: &I) -> Result<(), CommonError> {
+        Ok(())
+    }
+
+    /// Whether the sink receives every packet of the test.
+    fn wants_packets(&self) -> bool {
+        false
+    }
+
+    fn on_packet(&mut self, _packet: &P) -> Result<(), CommonError> {
+        Ok(())
+    }
+
+    fn on_complete(&mut self, _result: &R) -> Result<(), CommonError> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), CommonError> {
+        Ok(())
+    }
+}
+
+/// A sink shared between a test and its callbacks.
+pub type SharedSink<R, I, P> = Arc<Mutex<dyn ResultSink<R, I, P>>>;
+
+/// An output of a test, as written by a `JsonLinesSink` and sent by a `ChannelSink`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum SinkEvent<R, I, P> {
+    Start,
+    Interval(I),
+    Packet(P),
+    Complete(R),
+}
+
+/// Writes every event as a line of JSON.
+#[derive(Debug)]
+pub struct JsonLinesSink<W: Write + Send> {
+    writer: W,
+    packets: bool,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    /// Creates a sink writing to `writer`, with every packet if `packets` is set.
+    pub fn new(writer: W, packets: bool) -> Self {
+        Self { writer, packets }
+    }
+
+    fn write<T: Serialize>(&mut self, event: &T) -> Result<(), CommonError> {
+        let line = serde_json::to_string(event).map_err(|e| CommonError::Generic(e.to_string()))?;
+        writeln!(self.writer, "{}", line)?;
+        Ok(())
+    }
+}
+
+impl JsonLinesSink<Stdout> {
+    pub fn stdout(packets: bool) -> Self {
+        Self::new(std::io::stdout(), packets)
+    }
+}
+
+impl JsonLinesSink<BufWriter<File>> {
+    /// Creates a sink writing to the file at `path`, replaced if it exists.
+    pub fn create(path: &Path, packets: bool) -> Result<Self, CommonError> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), packets))
+    }
+
+    /// Creates a sink writing to the end of the file at `path`, created if it doesn't exist.
+    pub fn append(path: &Path, packets: bool) -> Result<Self, CommonError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file), packets))
+    }
+}
+
+impl<R, I, P, W> ResultSink<R, I, P> for JsonLinesSink<W>
+where
+    R: Serialize,
+    I: Serialize,
+    P: Serialize,
+    W: Write + Send,
+{
+    fn on_start(&mut self) -> Result<(), CommonError> {
+        self.write(&SinkEvent::<(), (), ()>::Start)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn on_interval(&mut self, interval: &I) -> Result<(), CommonError> {
+        self.write(&SinkEvent::<(), &I, ()>::Interval(interval))
+    }
+
+    fn wants_packets(&self) -> bool {
+        self.packets
+    }
+
+    fn on_packet(&mut self, packet: &P) -> Result<(), CommonError> {
+        self.write(&SinkEvent::<(), (), &P>::Packet(packet))
+    }
+
+    fn on_complete(&mut self, result: &R) -> Result<(), CommonError> {
+        self.write(&SinkEvent::<&R, (), ()>::Complete(result))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), CommonError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Sends every event through a channel.
+/// A receiver that went away doesn't fail the test, the events are dropped.
+#[derive(Debug)]
+pub struct ChannelSink<R, I, P> {
+    sender: Sender<SinkEvent<R, I, P>>,
+    packets: bool,
+}
+
+impl<R, I, P> ChannelSink<R, I, P> {
+    /// Creates a sink sending to `sender`, with every packet if `packets` is set.
+    pub fn new(sender: Sender<SinkEvent<R, I, P>>, packets: bool) -> Self {
+        Self { sender, packets }
+    }
+
+    fn send(&self, event: SinkEvent<R, I, P>) {
+        if self.sender.send(event).is_err() {
+            log::debug!("Result sink receiver is gone");
+        }
+    }
+}
+
+impl<R, I, P> ResultSink<R, I, P> for ChannelSink<R, I, P>
+where
+    R: Clone + Send,
+    I: Clone + Send,
+    P: Clone + Send,
+{
+    fn on_start(&mut self) -> Result<(), CommonError> {
+        self.send(SinkEvent::Start);
+        Ok(())
+    }
+
+    fn on_interval(&mut self, interval: &I) -> Result<(), CommonError> {
+        self.send(SinkEvent::Interval(interval.clone()));
+        Ok(())
+    }
+
+    fn wants_packets(&self) -> bool {
+        self.packets
+    }
+
+    fn on_packet(&mut self, packet: &P) -> Result<(), CommonError> {
+        self.send(SinkEvent::Packet(packet.clone()));
+        Ok(())
+    }
+
+    fn on_complete(&mut self, result: &R) -> Result<(), CommonError> {
+        self.send(SinkEvent::Complete(result.clone()));
+        Ok(())
+    }
+}
+
+/// The sinks of a test.
+///
+/// Only a failure to start is returned, so a test doesn't start with a broken sink. Later
+/// failures are logged, so a sink never stops a running test.
+pub struct Sinks<R, I, P> {
+    sinks: Vec<SharedSink<R, I, P>>,
+}
+
+impl<R, I, P> Default for Sinks<R, I, P> {
+    fn default() -> Self {
+        Self { sinks: Vec::new() }
+    }
+}
+
+impl<R, I, P> Clone for Sinks<R, I, P> {
+    fn clone(&self) -> Self {
+        Self {
+            sinks: self.sinks.clone(),
+        }
+    }
+}
+
+impl<R, I, P> std::fmt::Debug for Sinks<R, I, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sinks")
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl<R, I, P> Sinks<R, I, P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, sink: SharedSink<R, I, P>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Whether any of the sinks receives the periodic reports.
+    pub fn wants_intervals(&self) -> bool {
+        self.sinks.iter().any(|sink| {
+            sink.lock()
+                .map(|sink| sink.wants_intervals())
+                .unwrap_or(false)
+        })
+    }
+
+    /// Whether any of the sinks receives every packet.
+    pub fn wants_packets(&self) -> bool {
+        self.sinks.iter().any(|sink| {
+            sink.lock()
+                .map(|sink| sink.wants_packets())
+                .unwrap_or(false)
+        })
+    }
+
+    pub fn start(&self) -> Result<(), CommonError> {
+        self.sinks
+            .iter()
+            .try_for_each(|sink| sink.lock()?.on_start())
+    }
+
+    /// Delivers a periodic report to the sinks that want them.
+    pub fn interval(&self, interval: &I) {
+        self.for_each("interval", |sink| {
+            if sink.wants_intervals() {
+                sink.on_interval(interval)?;
+                sink.flush()?;
+            }
+            Ok(())
+        });
+    }
+
+    /// Delivers a batch of packets to the sinks that want them.
+    pub fn packets(&self, packets: &[P]) {
+        self.for_each("packets", |sink| {
+            if sink.wants_packets() {
+                packets
+                    .iter()
+                    .try_for_each(|packet| sink.on_packet(packet))?;
+                sink.flush()?;
+            }
+            Ok(())
+        });
+    }
+
+    pub fn complete(&self, result: &R) {
+        self.for_each("result", |sink| {
+            sink.on_complete(result)?;
+            sink.flush()
+        });
+    }
+
+    fn for_each(
+        &self,
+        event: &str,
+        mut deliver: impl FnMut(&mut dyn ResultSink<R, I, P>) -> Result<(), CommonError>,
+    ) {
+        for sink in &self.sinks {
+            let delivered = sink
+                .lock()
+                .map_err(CommonError::from)
+                .and_then(|mut sink| deliver(&mut *sink));
+            if let Err(e) = delivered {
+                log::error!("Failed to deliver the {} to a result sink: {}", event, e);
+            }
+        }
+    }
+}
@@ -109,12 +109,22 @@ pub trait Socket<T: AsRawFd>: Sized + AsRawFd {
             | libc::SOF_TIMESTAMPING_TX_SOFTWARE;
         self.set_socket_options(libc::SOL_SOCKET, libc::SO_TIMESTAMPING, Some(value as i32))
     }
+
+    /// Enables the receive timestamps only.
+    /// Transmit timestamps are queued on the error queue and count against the receive
+    /// buffer, so sockets that never drain it must not request them.
+    fn set_rx_timestamping_options(&mut self) -> Result<i32, CommonError> {
+        let value = libc::SOF_TIMESTAMPING_SOFTWARE | libc::SOF_TIMESTAMPING_RX_SOFTWARE;
+        self.set_socket_options(libc::SOL_SOCKET, libc::SO_TIMESTAMPING, Some(value as i32))
+    }
 }
 
-pub fn socketaddr_to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr, u32) {
+/// Converts an address to the socket address of its family, along with the length of that
+/// socket address.
+pub fn socketaddr_to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
     let mut storage: libc::sockaddr_storage = unsafe { core::mem::zeroed() };
     log::debug!("addr: {}", addr.to_string());
-    let (sock_addr, sock_addr_len) = match addr {
+    let sock_addr_len = match addr {
         SocketAddr::V4(a) => {
             let sockaddr_in: *mut libc::sockaddr_in =
                 &mut storage as *mut _ as *mut libc::sockaddr_in;
@@ -123,10 +133,7 @@ pub fn socketaddr_to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr, u32) {
                 (*sockaddr_in).sin_port = a.port().to_be();
                 (*sockaddr_in).sin_addr.s_addr = u32::from_ne_bytes(a.ip().octets());
             }
-            (
-                sockaddr_in as *const libc::sockaddr,
-                core::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
-            )
+            core::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t
         }
         SocketAddr::V6(a) => {
             let sockaddr_in6: *mut libc::sockaddr_in6 =
@@ -141,13 +148,10 @@ pub fn socketaddr_to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr, u32) {
                 (*sockaddr_in6).sin6_flowinfo = a.flowinfo();
                 (*sockaddr_in6).sin6_scope_id = a.scope_id();
             }
-            (
-                sockaddr_in6 as *const libc::sockaddr,
-                core::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
-            )
+            core::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t
         }
     };
-    (unsafe { sock_addr.read() }, sock_addr_len)
+    (storage, sock_addr_len)
 }
 
 pub fn storage_to_socket_addr(
@@ -168,7 +172,7 @@ pub fn storage_to_socket_addr(
             )
         }
         libc::AF_INET6 => {
-            let sockaddr: &libc::sockaddr_in6 = unsafe { core::mem::transmute(&addr_storage) };
+            let sockaddr: &libc::sockaddr_in6 = unsafe { core::mem::transmute(addr_storage) };
             SocketAddr::new(
                 IpAddr::V6(Ipv6Addr::from(sockaddr.sin6_addr.s6_addr)),
                 sockaddr.sin6_port.to_be(),
@@ -184,11 +188,11 @@ pub fn to_msghdr(bytes: &mut [u8], address: &mut SocketAddr) -> libc::msghdr {
         iov_base: bytes.as_mut_ptr() as *mut libc::c_void,
         iov_len: bytes.len(),
     };
-    let (mut sockaddr, _) = socketaddr_to_sockaddr(address);
+    let (mut sockaddr, sockaddr_len) = socketaddr_to_sockaddr(address);
 
     libc::msghdr {
         msg_name: &mut sockaddr as *mut _ as *mut libc::c_void,
-        msg_namelen: core::mem::size_of_val(&sockaddr) as u32,
+        msg_namelen: sockaddr_len,
         msg_iov: &msg_iov as *const _ as *mut _,
         msg_iovlen: core::mem::size_of_val(&msg_iov),
         msg_control: [0; CMSG_SPACE_SIZE].as_mut_ptr() as *mut libc::c_void,
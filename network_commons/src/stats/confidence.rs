@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::{distribution::normal_quantile, offset_estimator::LcgRng};
+
+/// Confidence level of a default `ConfidenceConfiguration`
+pub const DEFAULT_CONFIDENCE_LEVEL: f64 = 0.95;
+/// Number of resamples of a default `ConfidenceConfiguration`
+pub const DEFAULT_RESAMPLES: usize = 1000;
+/// Seed of the random generator of a default `ConfidenceConfiguration`
+pub const DEFAULT_SEED: u64 = 0xB007_57A9;
+/// Largest sample, or number of trials, resampled by the `Bootstrap` method of an
+/// `IntervalEstimator`. Larger samples use the analytic intervals, which they match closely,
+/// so the cost of an interval stays bounded by `resamples` times this size.
+pub const MAX_BOOTSTRAP_SAMPLE: usize = 10_000;
+
+/// Bounds of a confidence interval.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ConfidenceInterval {
+    pub fn contains(&self, value: f64) -> bool {
+        self.lower <= value && value <= self.upper
+    }
+}
+
+/// Method used to calculate confidence intervals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IntervalMethod {
+    /// Closed form intervals, see `analytic_mean_interval`, `analytic_median_interval`
+    /// and `wilson_interval`
+    #[default]
+    Analytic,
+    /// Percentile intervals of the statistics of resampled values, see `Bootstrap`
+    Bootstrap,
+}
+
+/// Settings of the confidence intervals of a test.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConfidenceConfiguration {
+    #[validate(range(min = 0.5, max = 0.999))]
+    pub level: f64,
+    pub method: IntervalMethod,
+    /// Number of resamples of the `Bootstrap` method
+    #[validate(range(min = 10, max = 100000))]
+    pub resamples: usize,
+    /// Seed of the random generator of the `Bootstrap` method
+    pub seed: u64,
+}
+
+impl Default for ConfidenceConfiguration {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_CONFIDENCE_LEVEL,
+            method: IntervalMethod::default(),
+            resamples: DEFAULT_RESAMPLES,
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+/// Calculates the confidence intervals of a test with the configured method.
+///
+/// The `Bootstrap` method falls back to the analytic intervals for samples larger than
+/// `MAX_BOOTSTRAP_SAMPLE`.
+pub struct IntervalEstimator {
+    configuration: ConfidenceConfiguration,
+    bootstrap: Bootstrap,
+}
+
+impl IntervalEstimator {
+    pub fn new(configuration: ConfidenceConfiguration) -> Self {
+        let bootstrap = Bootstrap::new(configuration.resamples, configuration.seed);
+        Self {
+            configuration,
+            bootstrap,
+        }
+    }
+
+    pub fn configuration(&self) -> &ConfidenceConfiguration {
+        &self.configuration
+    }
+
+    /// Confidence interval of the mean of the values.
+    pub fn mean(&mut self, values: &[f64]) -> Option<ConfidenceInterval> {
+        match self.method(values.len()) {
+            IntervalMethod::Analytic => analytic_mean_interval(values, self.configuration.level),
+            IntervalMethod::Bootstrap => {
+                self.bootstrap
+                    .interval(values, self.configuration.level, mean)
+            }
+        }
+    }
+
+    /// Confidence interval of the median of the values.
+    pub fn median(&mut self, values: &[f64]) -> Option<ConfidenceInterval> {
+        match self.method(values.len()) {
+            IntervalMethod::Analytic => analytic_median_interval(values, self.configuration.level),
+            IntervalMethod::Bootstrap => {
+                self.bootstrap
+                    .interval(values, self.configuration.level, median)
+            }
+        }
+    }
+
+    /// Confidence interval of the ratio of `successes` out of `trials`.
+    pub fn proportion(&mut self, successes: usize, trials: usize) -> Option<ConfidenceInterval> {
+        match self.method(trials) {
+            IntervalMethod::Analytic => {
+                wilson_interval(successes, trials, self.configuration.level)
+            }
+            IntervalMethod::Bootstrap => {
+                self.bootstrap
+                    .proportion_interval(successes, trials, self.configuration.level)
+            }
+        }
+    }
+
+    /// Method used for a sample of `size` values.
+    fn method(&self, size: usize) -> IntervalMethod {
+        match self.configuration.method {
+            IntervalMethod::Bootstrap if size > MAX_BOOTSTRAP_SAMPLE => IntervalMethod::Analytic,
+            method => method,
+        }
+    }
+}
+
+/// Confidence interval of the mean of the values, from the normal approximation of the
+/// distribution of the sample mean.
+/// Returns `None` with fewer than two values.
+pub fn analytic_mean_interval(values: &[f64], level: f64) -> Option<ConfidenceInterval> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = mean(values);
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    let margin = z_value(level) * (variance / n).sqrt();
+    Some(ConfidenceInterval {
+        lower: mean - margin,
+        upper: mean + margin,
+    })
+}
+
+/// Distribution free confidence interval of the median of the values.
+///
+/// The bounds are the order statistics whose ranks bracket the median with the requested
+/// probability, from the normal approximation of the binomial distribution of the ranks:
+/// the ranks `n / 2 - z * sqrt(n) / 2`, rounded down, and `1 + n / 2 + z * sqrt(n) / 2`,
+/// rounded up, counting from 1.
+/// Returns `None` with fewer than two values.
+///
+/// # References
+///
+/// * W. J. Conover. "Practical Nonparametric Statistics", 3rd edition, Section 3.2.
+pub fn analytic_median_interval(values: &[f64], level: f64) -> Option<ConfidenceInterval> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mut sorted = values.to_owned();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len() as f64;
+    let half_width = z_value(level) * n.sqrt() / 2.0;
+    // Ranks counted from 1
+    let lower_rank = ((n / 2.0 - half_width).floor() as usize).max(1);
+    let upper_rank = ((1.0 + n / 2.0 + half_width).ceil() as usize).min(sorted.len());
+    Some(ConfidenceInterval {
+        lower: sorted[lower_rank - 1],
+        upper: sorted[upper_rank - 1],
+    })
+}
+
+/// Wilson score interval of the ratio of `successes` out of `trials`.
+/// Unlike the normal approximation interval, it stays within [0, 1] and does not collapse
+/// when there are no successes, as is usual for packet loss.
+/// Returns `None` without trials.
+///
+/// # References
+///
+/// * Edwin B. Wilson. "Probable Inference, the Law of Succession, and Statistical Inference".
+///   Journal of the American Statistical Association, Vol. 22, No. 158 (1927), pp. 209-212.
+pub fn wilson_interval(successes: usize, trials: usize, level: f64) -> Option<ConfidenceInterval> {
+    if trials == 0 {
+        return None;
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let z = z_value(level);
+    let z2 = z * z;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let margin = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    Some(ConfidenceInterval {
+        lower: (center - margin).max(0.0),
+        upper: (center + margin).min(1.0),
+    })
+}
+
+/// Percentile bootstrap of the statistics of a sample.
+///
+/// The sample is resampled with replacement `resamples` times, and the interval is made of the
+/// quantiles of the statistic over the resamples.
+/// The random generator is seeded, so intervals are reproducible.
+///
+/// # References
+///
+/// * Bradley Efron, Robert J. Tibshirani. "An Introduction to the Bootstrap".
+///   Chapman & Hall, 1993, Chapter 13.
+pub struct Bootstrap {
+    resamples: usize,
+    rng: LcgRng,
+}
+
+impl Bootstrap {
+    pub fn new(resamples: usize, seed: u64) -> Self {
+        Self {
+            resamples,
+            rng: LcgRng::new(seed),
+        }
+    }
+
+    /// Confidence interval of `statistic` over the values.
+    /// Returns `None` with fewer than two values.
+    pub fn interval<F>(
+        &mut self,
+        values: &[f64],
+        level: f64,
+        statistic: F,
+    ) -> Option<ConfidenceInterval>
+    where
+        F: Fn(&[f64]) -> f64,
+    {
+        if values.len() < 2 || self.resamples == 0 {
+            return None;
+        }
+        let mut resample = vec![0.0; values.len()];
+        let mut statistics: Vec<f64> = (0..self.resamples)
+            .map(|_| {
+                resample
+                    .iter_mut()
+                    .for_each(|value| *value = values[self.rng.gen_index(values.len())]);
+                statistic(&resample)
+            })
+            .collect();
+        statistics.sort_by(|a, b| a.total_cmp(b));
+        Some(percentile_interval(&statistics, level))
+    }
+
+    /// Confidence interval of the ratio of `successes` out of `trials`.
+    /// The interval collapses to a single point when there are no successes or only
+    /// successes, where `wilson_interval` still gives a useful upper bound.
+    /// Returns `None` without trials.
+    pub fn proportion_interval(
+        &mut self,
+        successes: usize,
+        trials: usize,
+        level: f64,
+    ) -> Option<ConfidenceInterval> {
+        if trials == 0 || self.resamples == 0 {
+            return None;
+        }
+        let p = successes as f64 / trials as f64;
+        // Resampling the outcomes amounts to drawing binomial counts
+        let mut ratios: Vec<f64> = (0..self.resamples)
+            .map(|_| {
+                let count = (0..trials)
+                    .filter(|_| self.rng.gen_range(0.0..1.0) < p)
+                    .count();
+                count as f64 / trials as f64
+            })
+            .collect();
+        ratios.sort_by(|a, b| a.total_cmp(b));
+        Some(percentile_interval(&ratios, level))
+    }
+}
+
+/// Interval between the quantiles of the sorted values that leave `(1 - level) / 2` out on
+/// each side.
+fn percentile_interval(sorted: &[f64], level: f64) -> ConfidenceInterval {
+    let last = sorted.len() - 1;
+    let tail = (1.0 - level) / 2.0;
+    let rank = |p: f64| ((p * last as f64).round() as usize).min(last);
+    ConfidenceInterval {
+        lower: sorted[rank(tail)],
+        upper: sorted[rank(1.0 - tail)],
+    }
+}
+
+/// Two-sided standard normal critical value of a confidence level.
+fn z_value(level: f64) -> f64 {
+    normal_quantile(0.5 + level / 2.0)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut values = values.to_owned();
+    let len = values.len();
+    let (below, upper, _) = values.select_nth_unstable_by(len / 2, |a, b| a.total_cmp(b));
+    if len.is_multiple_of(2) {
+        let lower = below.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        (lower + *upper) / 2.0
+    } else {
+        *upper
+    }
+}
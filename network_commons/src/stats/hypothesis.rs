@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+use super::distribution::normal_cdf;
+
+/// Outcome of a one-sided test of whether a current sample is larger than a baseline sample.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TestOutcome {
+    /// Value of the statistic of the test
+    pub statistic: f64,
+    /// Standardized statistic, positive when the current sample is larger
+    pub z: f64,
+    /// Probability of a `z` at least as large if both samples came from the same distribution
+    pub p_value: f64,
+}
+
+impl TestOutcome {
+    fn new(statistic: f64, z: f64) -> Self {
+        Self {
+            statistic,
+            z,
+            p_value: 1.0 - normal_cdf(z),
+        }
+    }
+
+    /// Whether the current sample is significantly larger at the `significance_level`.
+    pub fn is_significant(&self, significance_level: f64) -> bool {
+        self.p_value < significance_level
+    }
+}
+
+/// Mann-Whitney U test of whether the `current` values tend to be larger than the
+/// `baseline` values.
+///
+/// The statistic is the U of the current sample, the number of pairs in which the current
+/// value is the larger one, ties counting for half. Its p-value comes from the normal
+/// approximation with the tie correction and a continuity correction, which is accurate
+/// for the sample sizes of a test session.
+/// Returns `None` when a sample is empty or when all the values are equal.
+///
+/// # References
+///
+/// * H. B. Mann, D. R. Whitney. "On a Test of Whether one of Two Random Variables is
+///   Stochastically Larger than the Other". The Annals of Mathematical Statistics, Vol. 18,
+///   No. 1 (1947), pp. 50-60.
+pub fn mann_whitney_u(baseline: &[f64], current: &[f64]) -> Option<TestOutcome> {
+    if baseline.is_empty() || current.is_empty() {
+        return None;
+    }
+    let (n1, n2) = (baseline.len() as f64, current.len() as f64);
+    let mut values: Vec<(f64, bool)> = baseline
+        .iter()
+        .map(|value| (*value, false))
+        .chain(current.iter().map(|value| (*value, true)))
+        .collect();
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Tied values share the average of their ranks
+    let (mut current_rank_sum, mut tie_correction) = (0.0, 0.0);
+    let mut start = 0;
+    while start < values.len() {
+        let end = start
+            + values[start..]
+                .iter()
+                .take_while(|(value, _)| *value == values[start].0)
+                .count();
+        let ties = (end - start) as f64;
+        let rank = (start + end + 1) as f64 / 2.0;
+        let current_ties = values[start..end]
+            .iter()
+            .filter(|(_, is_current)| *is_current)
+            .count();
+        current_rank_sum += rank * current_ties as f64;
+        tie_correction += ties.powi(3) - ties;
+        start = end;
+    }
+
+    let n = n1 + n2;
+    let u = current_rank_sum - n2 * (n2 + 1.0) / 2.0;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return None;
+    }
+    let z = (u - mean - 0.5) / variance.sqrt();
+    Some(TestOutcome::new(u, z))
+}
+
+/// Two-proportion z-test of whether the ratio of `current_successes` out of `current_trials`
+/// is larger than the ratio of `baseline_successes` out of `baseline_trials`.
+///
+/// The statistic is the difference of the ratios, and its standard error comes from the pooled
+/// ratio of both samples.
+/// Returns `None` without trials, or when the pooled ratio is 0 or 1 since both samples then
+/// agree.
+pub fn two_proportion_test(
+    baseline_successes: usize,
+    baseline_trials: usize,
+    current_successes: usize,
+    current_trials: usize,
+) -> Option<TestOutcome> {
+    if baseline_trials == 0 || current_trials == 0 {
+        return None;
+    }
+    let (n1, n2) = (baseline_trials as f64, current_trials as f64);
+    let (p1, p2) = (
+        baseline_successes as f64 / n1,
+        current_successes as f64 / n2,
+    );
+    let pooled = (baseline_successes + current_successes) as f64 / (n1 + n2);
+    let variance = pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n2);
+    if variance <= 0.0 {
+        return None;
+    }
+    Some(TestOutcome::new(p2 - p1, (p2 - p1) / variance.sqrt()))
+}
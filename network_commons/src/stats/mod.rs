@@ -1 +1,7 @@
+pub mod confidence;
+pub mod distribution;
+pub mod hypothesis;
 pub mod offset_estimator;
+pub mod skew_estimator;
+pub mod streaming;
+pub mod voice_quality;
@@ -1,4 +1,147 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::error::CommonError;
+
+use super::distribution::normal_quantile;
+
+/// Seed of the random generator of a default `GamlrConfiguration`
+pub const DEFAULT_SEED: u64 = 0x5EED_0FF5E7;
+/// Number of delays estimated together by a default `GamlrConfiguration`
+pub const DEFAULT_CHUNK_SIZE: usize = 5;
+/// Confidence level of the interval of a default `GamlrConfiguration`
+pub const DEFAULT_CONFIDENCE_LEVEL: f64 = 0.95;
+
+/// Central moment used to fit the Gamma distribution to the delays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MomentEstimation {
+    #[default]
+    Median,
+    Mean,
+}
+
+/// Settings of a `GamlrEstimator`.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct GamlrConfiguration {
+    /// Seed of the random generator, so the same delays always give the same estimate
+    pub seed: u64,
+    /// Number of consecutive delays estimated together
+    #[validate(range(min = 2, max = 10000))]
+    pub chunk_size: usize,
+    /// Number of Gamma values generated for every chunk, the size of the chunk when not set
+    #[validate(range(min = 2, max = 100000))]
+    pub num_samples: Option<usize>,
+    pub moment: MomentEstimation,
+    /// Confidence level of the interval around the estimate
+    #[validate(range(min = 0.5, max = 0.999))]
+    pub confidence_level: f64,
+}
+
+impl Default for GamlrConfiguration {
+    fn default() -> Self {
+        Self {
+            seed: DEFAULT_SEED,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            num_samples: None,
+            moment: MomentEstimation::default(),
+            confidence_level: DEFAULT_CONFIDENCE_LEVEL,
+        }
+    }
+}
+
+/// Offset estimated by a `GamlrEstimator`, in the units of the delays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffsetEstimate {
+    pub offset: f64,
+    /// Standard error of the offset, when more than one chunk was estimated
+    pub standard_error: Option<f64>,
+    /// Bounds of the confidence interval, when the standard error is known
+    pub lower_bound: Option<f64>,
+    pub upper_bound: Option<f64>,
+    /// Number of chunks the offset is the mean of
+    pub chunks: usize,
+}
+
+impl OffsetEstimate {
+    /// Builds an estimate from its offset and standard error, with the confidence interval
+    /// of the normal approximation at `confidence_level`.
+    pub fn new(
+        offset: f64,
+        standard_error: Option<f64>,
+        confidence_level: f64,
+        chunks: usize,
+    ) -> Self {
+        let margin =
+            standard_error.map(|error| error * normal_quantile(0.5 + confidence_level / 2.0));
+        Self {
+            offset,
+            standard_error,
+            lower_bound: margin.map(|margin| offset - margin),
+            upper_bound: margin.map(|margin| offset + margin),
+            chunks,
+        }
+    }
+}
+
+/// GAMLR offset estimator of a series of one-way delays.
+///
+/// The delays are split in chunks of `chunk_size` consecutive values and every complete chunk is
+/// estimated with the method of `estimate`. The offset is the mean of the chunk estimates, and its
+/// confidence interval follows from their spread.
+/// The random generator is seeded from the configuration, so estimates are reproducible.
+pub struct GamlrEstimator {
+    configuration: GamlrConfiguration,
+    rng: LcgRng,
+}
+
+impl GamlrEstimator {
+    /// Fails when the configuration is invalid, as a chunk of no delay can't be estimated.
+    pub fn new(configuration: GamlrConfiguration) -> Result<Self, CommonError> {
+        configuration
+            .validate()
+            .map_err(CommonError::ValidationError)?;
+        let rng = LcgRng::new(configuration.seed);
+        Ok(Self { configuration, rng })
+    }
+
+    pub fn configuration(&self) -> &GamlrConfiguration {
+        &self.configuration
+    }
+
+    /// Estimates the offset of the delays.
+    /// Returns `None` when there is not a single complete chunk.
+    pub fn estimate(&mut self, owd: &[f64]) -> Option<OffsetEstimate> {
+        let offsets = self.chunk_offsets(owd);
+        if offsets.is_empty() {
+            return None;
+        }
+        let n = offsets.len() as f64;
+        let mean = offsets.iter().sum::<f64>() / n;
+        let standard_error = (offsets.len() > 1).then(|| {
+            let variance = offsets
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1.0);
+            (variance / n).sqrt()
+        });
+        Some(OffsetEstimate::new(
+            mean,
+            standard_error,
+            self.configuration.confidence_level,
+            offsets.len(),
+        ))
+    }
+
+    /// Estimates the offset of every complete chunk of the delays.
+    pub fn chunk_offsets(&mut self, owd: &[f64]) -> Vec<f64> {
+        owd.chunks_exact(self.configuration.chunk_size)
+            .map(|chunk| estimate_chunk(chunk, &self.configuration, &mut self.rng))
+            .collect()
+    }
+}
 
 /// A simple Linear Congruential Generator (LCG) for generating pseudorandom numbers.
 ///
@@ -38,51 +181,41 @@ impl LcgRng {
         range.start + random_f64 * (range.end - range.start)
     }
 
+    /// Generates a random index into a collection of `len` elements.
+    pub fn gen_index(&mut self, len: usize) -> usize {
+        ((self.gen_range(0.0..1.0) * len as f64) as usize).min(len.saturating_sub(1))
+    }
+
     fn next_u64(&mut self) -> u64 {
         self.state = (self.a.wrapping_mul(self.state).wrapping_add(self.c)) % self.m;
         self.state
     }
 }
 
-// /// Estimates the alpha and beta parameters for the Gamma distribution based on the sample data provided.
-// fn estimate_alpha_beta_from_owd<'a, I>(x: I) -> (f64, f64)
-// where
-//     I: IntoIterator<Item = &'a f64>,
-//     I::IntoIter: ExactSizeIterator + Clone,
-// {
-//     let x_vec: Vec<_> = x.into_iter().cloned().collect();
-//     let n = x_vec.len() as f64;
-//     let sum_x = x_vec.iter().sum::<f64>();
-//     let mean_x = sum_x / n;
-//     let sum_sq_diff = x_vec.iter().map(|&xi| (xi - mean_x).powi(2)).sum::<f64>();
-//     let var_x = sum_sq_diff / (n - 1.0);
-//     let alpha = mean_x.powi(2) / var_x;
-//     let beta = var_x / mean_x;
-//     (alpha, beta)
-// }
 /// Estimates the alpha and beta parameters for the Gamma distribution based on the sample data provided,
-/// using the median instead of the mean.
-fn estimate_alpha_beta_from_owd<'a, I>(x: I) -> (f64, f64)
-where
-    I: IntoIterator<Item = &'a f64>,
-    I::IntoIter: ExactSizeIterator + Clone,
-{
-    let mut x_vec: Vec<_> = x.into_iter().cloned().collect();
-    let n = x_vec.len() as f64;
-    // Sort the sample data to find the median
-    x_vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    let median_x = if x_vec.len() % 2 == 0 {
-        let mid = x_vec.len() / 2;
-        (x_vec[mid - 1] + x_vec[mid]) / 2.0
-    } else {
-        x_vec[x_vec.len() / 2]
+/// with the method of moments.
+/// The `Median` moment estimation uses the median in place of the mean, which is less sensitive
+/// to the few delays inflated by queuing.
+fn estimate_alpha_beta_from_owd(x: &[f64], moment: MomentEstimation) -> (f64, f64) {
+    let n = x.len() as f64;
+    let center = match moment {
+        MomentEstimation::Mean => x.iter().sum::<f64>() / n,
+        MomentEstimation::Median => {
+            // Sort the sample data to find the median
+            let x_vec = sort_values(x);
+            if x_vec.len() % 2 == 0 {
+                let mid = x_vec.len() / 2;
+                (x_vec[mid - 1] + x_vec[mid]) / 2.0
+            } else {
+                x_vec[x_vec.len() / 2]
+            }
+        }
     };
 
-    // Use the median in place of the mean to estimate alpha and beta
-    let sum_sq_diff = x_vec.iter().map(|&xi| (xi - median_x).powi(2)).sum::<f64>();
+    let sum_sq_diff = x.iter().map(|&xi| (xi - center).powi(2)).sum::<f64>();
     let var_x = sum_sq_diff / (n - 1.0);
-    let alpha = median_x.powi(2) / var_x;
-    let beta = var_x / median_x;
+    let alpha = center.powi(2) / var_x;
+    let beta = var_x / center;
     (alpha, beta)
 }
 
@@ -93,7 +226,7 @@ where
     I::IntoIter: ExactSizeIterator + Clone,
 {
     let mut sorted_values: Vec<_> = values.into_iter().cloned().collect();
-    sorted_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    sorted_values.sort_by(|a, b| a.total_cmp(b));
     sorted_values
 }
 
@@ -120,15 +253,19 @@ fn marsaglia_polar_sample(rng: &mut LcgRng) -> f64 {
 ///
 /// George Marsaglia, Wai Wan Tsang. "A Simple Method for Generating Gamma Variables".
 /// ACM Transactions on Mathematical Software, Vol. 26, No. 3, September 2000, Pages 363-372.
-fn generate_random_gamma_values(alpha: f64, beta: f64, num_samples: usize, seed: u64) -> Vec<f64> {
-    let mut rng = LcgRng::new(seed);
+fn generate_random_gamma_values(
+    alpha: f64,
+    beta: f64,
+    num_samples: usize,
+    rng: &mut LcgRng,
+) -> Vec<f64> {
     (0..num_samples)
         .map(|_| {
             let d = alpha - 1.0 / 3.0;
             let c = (1.0 / 3.0) / d.sqrt();
 
             loop {
-                let x = marsaglia_polar_sample(&mut rng);
+                let x = marsaglia_polar_sample(rng);
                 let v = 1.0 + c * x;
                 if v <= 0.0 {
                     continue;
@@ -153,26 +290,49 @@ fn generate_random_gamma_values(alpha: f64, beta: f64, num_samples: usize, seed:
 ///
 /// Edmar Mota-Garcia and Rogelio Hasimoto-Beltran: "A new model-based clock-offset approximation over IP networks"
 /// Computer Communications, Volume 53, 2014, Pages 26-36, ISSN 0140-3664, https://doi.org/10.1016/j.comcom.2014.07.006.
+///
+/// The values are estimated as a single chunk with the default `GamlrConfiguration`.
 pub fn estimate<I>(time_values: I) -> f64
 where
     I: IntoIterator<Item = f64>,
 {
     let time_values_vec: Vec<f64> = time_values.into_iter().collect();
-    let n = time_values_vec.len();
-    let (mut alpha, beta) = estimate_alpha_beta_from_owd(&time_values_vec);
-    if alpha > 4.0 {
-        alpha = 4.0;
-    } else if alpha < 1.0 {
-        alpha = 1.0;
+    let configuration = GamlrConfiguration::default();
+    let mut rng = LcgRng::new(configuration.seed);
+    estimate_chunk(&time_values_vec, &configuration, &mut rng)
+}
+
+/// Estimates the offset of a single chunk of delays.
+fn estimate_chunk(
+    time_values: &[f64],
+    configuration: &GamlrConfiguration,
+    rng: &mut LcgRng,
+) -> f64 {
+    let n = time_values.len();
+    // The delays are fitted above their minimum, so the estimate follows any shift of the
+    // delays, including the negative delays measured when the offset exceeds the path delay
+    let minimum = time_values.iter().copied().fold(f64::INFINITY, f64::min);
+    let excess: Vec<f64> = time_values.iter().map(|value| value - minimum).collect();
+    let (alpha, beta) = estimate_alpha_beta_from_owd(&excess, configuration.moment);
+    // Delays without spread have no distribution to fit
+    if !(alpha.is_finite() && beta.is_finite() && beta > 0.0) {
+        return minimum;
     }
-    let random_values = generate_random_gamma_values(alpha, beta, n, get_time_based_seed());
-    // sort random values
-    let sorted = sort_values(&time_values_vec);
-    let mut random_sorted = random_values;
+    let alpha = alpha.clamp(1.0, 4.0);
+    let num_samples = configuration.num_samples.unwrap_or(n);
+    let mut random_sorted = generate_random_gamma_values(alpha, beta, num_samples, rng);
     // sort in increasing order
-    random_sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    random_sorted.sort_by(|a, b| a.total_cmp(b));
+    // Quantiles of the generated values matching the plotting positions of the time values
+    let quantiles: Vec<f64> = (0..n)
+        .map(|i| {
+            let p_value = (i as f64 + 0.5) / n as f64;
+            random_sorted[((p_value * num_samples as f64) as usize).min(num_samples - 1)]
+        })
+        .collect();
+    let sorted = sort_values(time_values);
 
-    estimate_offset(&sorted, &random_sorted)
+    estimate_offset(&sorted, &quantiles)
 }
 
 /// Calculates the offset between the generated gamma values and the sorted time values.
@@ -217,9 +377,3 @@ fn estimate_offset(x_sort: &[f64], y: &[f64]) -> f64 {
     // Return the estimated offset (x_cross).
     -gamma / beta
 }
-
-fn get_time_based_seed() -> u64 {
-    let now = SystemTime::now();
-    let duration = now.duration_since(UNIX_EPOCH).expect("Time went backwards");
-    duration.as_secs() ^ duration.subsec_nanos() as u64
-}
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+
+/// Line fitted under a series of one-way delays.
+///
+/// `skew` is the drift of the delays per unit of time, and `intercept` the delay at time 0,
+/// both in the units of the series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkewEstimate {
+    pub skew: f64,
+    pub intercept: f64,
+}
+
+impl SkewEstimate {
+    /// Removes the drift from the delays, keeping the delay of the first point unchanged.
+    pub fn detrend(&self, points: &[(f64, f64)]) -> Vec<f64> {
+        let Some((start, _)) = points.first() else {
+            return Vec::new();
+        };
+        points
+            .iter()
+            .map(|(time, delay)| delay - self.skew * (time - start))
+            .collect()
+    }
+}
+
+/// Method used to estimate the skew of a series of one-way delays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SkewEstimator {
+    /// Linear programming fit of the lower hull of the delays, see `estimate_linear_programming`
+    LinearProgramming,
+    /// Median slope of the segment minima of the delays, a simplification of Paxson's
+    /// algorithm, see `estimate_paxson`
+    Paxson,
+}
+
+impl SkewEstimator {
+    /// Estimates the skew of `points`, a series of `(time, delay)` pairs.
+    pub fn estimate(&self, points: &[(f64, f64)]) -> Option<SkewEstimate> {
+        match self {
+            SkewEstimator::LinearProgramming => estimate_linear_programming(points),
+            SkewEstimator::Paxson => estimate_paxson(points),
+        }
+    }
+}
+
+/// Estimates the skew of a series of `(time, delay)` pairs with the linear programming
+/// algorithm described in:
+///
+/// Sue B. Moon, Paul Skelly, Don Towsley. "Estimation and Removal of Clock Skew from Network
+/// Delay Measurements". Proceedings of IEEE INFOCOM '99, Vol. 1, pp. 227-234.
+///
+/// The fitted line lies under every delay and minimizes the sum of the distances to the delays.
+/// That line supports the lower convex hull of the points at their mean time, so it is found
+/// on the hull edge that spans the mean time instead of with a general LP solver.
+/// Returns `None` when the points do not span more than one instant.
+pub fn estimate_linear_programming(points: &[(f64, f64)]) -> Option<SkewEstimate> {
+    let hull = lower_hull(points);
+    let mean_time = points.iter().map(|(time, _)| time).sum::<f64>() / points.len() as f64;
+    let edge = hull
+        .windows(2)
+        .find(|edge| edge[1].0 >= mean_time)
+        .or_else(|| hull.windows(2).last())?;
+    let (start, end) = (edge[0], edge[1]);
+    let skew = (end.1 - start.1) / (end.0 - start.0);
+    Some(SkewEstimate {
+        skew,
+        intercept: start.1 - skew * start.0,
+    })
+}
+
+/// Estimates the skew of a series of `(time, delay)` pairs with a simplification of the
+/// method described in:
+///
+/// Vern Paxson. "On Calibrating Measurements of Packet Transit Times".
+/// Proceedings of ACM SIGMETRICS '98, pp. 11-21.
+///
+/// Only the median line fit of the segment minima is kept: unlike the paper, the forward and
+/// backward skews are not checked against each other, and clock adjustments within the series
+/// are neither detected nor corrected.
+/// The series is split into about the square root of its length segments, and the skew is
+/// the median of the slopes between the minimum delays of every pair of segments.
+/// Using the median makes the estimate robust to segments whose minimum is inflated by queuing.
+/// Returns `None` when there are fewer than two segments with distinct times.
+pub fn estimate_paxson(points: &[(f64, f64)]) -> Option<SkewEstimate> {
+    let mut sorted = points.to_owned();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let segment_size = ((sorted.len() as f64).sqrt().ceil() as usize).max(1);
+    let minima: Vec<(f64, f64)> = sorted
+        .chunks(segment_size)
+        .filter_map(|segment| segment.iter().min_by(|a, b| a.1.total_cmp(&b.1)).copied())
+        .collect();
+
+    let mut slopes: Vec<f64> = minima
+        .iter()
+        .enumerate()
+        .flat_map(|(index, first)| {
+            minima[index + 1..]
+                .iter()
+                .filter(|second| second.0 > first.0)
+                .map(move |second| (second.1 - first.1) / (second.0 - first.0))
+        })
+        .collect();
+    let skew = median(&mut slopes)?;
+    let mut intercepts: Vec<f64> = minima
+        .iter()
+        .map(|(time, delay)| delay - skew * time)
+        .collect();
+    Some(SkewEstimate {
+        skew,
+        intercept: median(&mut intercepts)?,
+    })
+}
+
+/// Lower convex hull of the points, ordered by time, with Andrew's monotone chain algorithm.
+fn lower_hull(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut sorted = points.to_owned();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    // Only the smallest delay of every instant can be on the lower hull
+    sorted.dedup_by(|next, previous| next.0 == previous.0);
+
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(sorted.len());
+    for point in sorted {
+        while hull.len() >= 2 {
+            let (a, b) = (hull[hull.len() - 2], hull[hull.len() - 1]);
+            let cross = (b.0 - a.0) * (point.1 - a.1) - (b.1 - a.1) * (point.0 - a.0);
+            if cross > 0.0 {
+                break;
+            }
+            hull.pop();
+        }
+        hull.push(point);
+    }
+    hull
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
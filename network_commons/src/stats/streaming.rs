@@ -0,0 +1,373 @@
+/// Quantiles tracked by a `StreamingSummary` created through `Default`.
+pub const DEFAULT_QUANTILES: [f64; 3] = [0.25, 0.5, 0.75];
+
+/// Smoothing factor used by a `StreamingSummary` created through `Default`.
+///
+/// This is the 1/16 gain used for the interarrival jitter estimator of
+/// [RFC3550 Section 6.4.1](https://www.rfc-editor.org/rfc/rfc3550#section-6.4.1).
+pub const DEFAULT_EWMA_ALPHA: f64 = 1.0 / 16.0;
+
+/// Running count, mean, variance, minimum and maximum of a stream of values.
+///
+/// The mean and variance are updated with Welford's online algorithm, so every
+/// sample is processed in constant time and memory.
+///
+/// # References
+///
+/// * B. P. Welford. "Note on a Method for Calculating Corrected Sums of Squares and Products".
+///   Technometrics, Vol. 4, No. 3 (Aug., 1962), pp. 419-420.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl RunningStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sample to the running statistics.
+    pub fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Population variance of the samples seen so far.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.count as f64)
+    }
+
+    /// Population standard deviation of the samples seen so far.
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+/// Estimates a single quantile of a stream of values without storing the samples.
+///
+/// Five markers are kept and adjusted with a piecewise-parabolic formula as the
+/// samples arrive. Until five samples have been seen the quantile is computed
+/// exactly from the stored samples.
+///
+/// # References
+///
+/// * Raj Jain, Imrich Chlamtac. "The P² Algorithm for Dynamic Calculation of Quantiles and
+///   Histograms Without Storing Observations". Communications of the ACM, Vol. 28, No. 10
+///   (Oct., 1985), pp. 1076-1085.
+#[derive(Debug, Clone, PartialEq)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    /// Marker heights
+    heights: [f64; 5],
+    /// Actual marker positions
+    positions: [f64; 5],
+    /// Desired marker positions
+    desired: [f64; 5],
+    /// Increments of the desired marker positions
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    /// Creates an estimator for the quantile `p`, which must be in the `[0, 1]` range.
+    pub fn new(p: f64) -> Self {
+        let p = p.clamp(0.0, 1.0);
+        Self {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired: [0.0, 2.0 * p, 4.0 * p, 2.0 + 2.0 * p, 4.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    /// The quantile tracked by this estimator.
+    pub fn p(&self) -> f64 {
+        self.p
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Adds a sample to the estimator.
+    pub fn push(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(|a, b| a.total_cmp(b));
+            }
+            return;
+        }
+        self.count += 1;
+
+        // Find the cell the sample falls into, extending the extreme markers if needed
+        let cell = if value < self.heights[0] {
+            self.heights[0] = value;
+            0
+        } else if value >= self.heights[4] {
+            self.heights[4] = value;
+            3
+        } else {
+            (1..5)
+                .find(|&i| value < self.heights[i])
+                .map(|i| i - 1)
+                .unwrap_or(3)
+        };
+
+        self.positions
+            .iter_mut()
+            .skip(cell + 1)
+            .for_each(|position| *position += 1.0);
+        self.desired
+            .iter_mut()
+            .zip(self.increments.iter())
+            .for_each(|(desired, increment)| *desired += increment);
+
+        // Adjust the heights of the middle markers if they are off their desired position
+        for i in 1..4 {
+            let delta = self.desired[i] - self.positions[i];
+            if (delta >= 1.0 && self.positions[i + 1] - self.positions[i] > 1.0)
+                || (delta <= -1.0 && self.positions[i - 1] - self.positions[i] < -1.0)
+            {
+                let direction = delta.signum();
+                let parabolic = self.parabolic(i, direction);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, direction)
+                    };
+                self.positions[i] += direction;
+            }
+        }
+    }
+
+    /// Current estimate of the quantile, or `None` if no samples were added.
+    pub fn quantile(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            count if count < 5 => {
+                let mut samples = self.heights[..count].to_vec();
+                samples.sort_by(|a, b| a.total_cmp(b));
+                let index = (self.p * (count - 1) as f64).round() as usize;
+                Some(samples[index])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+
+    fn parabolic(&self, i: usize, direction: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + direction / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + direction) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - direction) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, direction: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        let neighbour = if direction > 0.0 { i + 1 } else { i - 1 };
+        q[i] + direction * (q[neighbour] - q[i]) / (n[neighbour] - n[i])
+    }
+}
+
+/// Exponentially weighted moving average of a stream of values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ewma {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ewma {
+    /// Creates a new average where `alpha` is the weight given to every new sample.
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            value: None,
+        }
+    }
+
+    /// Adds a sample to the average. The first sample initializes the average.
+    pub fn push(&mut self, value: f64) {
+        self.value = Some(match self.value {
+            Some(average) => average + self.alpha * (value - average),
+            None => value,
+        });
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+impl Default for Ewma {
+    fn default() -> Self {
+        Self::new(DEFAULT_EWMA_ALPHA)
+    }
+}
+
+/// Constant memory summary of a stream of values.
+///
+/// Combines the `RunningStats`, a `P2Quantile` estimator for every tracked quantile
+/// and an `Ewma` so a snapshot of the distribution can be taken at any time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamingSummary {
+    stats: RunningStats,
+    quantiles: Vec<P2Quantile>,
+    ewma: Ewma,
+}
+
+impl StreamingSummary {
+    pub fn new(quantiles: &[f64], ewma_alpha: f64) -> Self {
+        Self {
+            stats: RunningStats::new(),
+            quantiles: quantiles.iter().map(|p| P2Quantile::new(*p)).collect(),
+            ewma: Ewma::new(ewma_alpha),
+        }
+    }
+
+    /// Adds a sample to every estimator of the summary.
+    pub fn push(&mut self, value: f64) {
+        self.stats.push(value);
+        self.quantiles
+            .iter_mut()
+            .for_each(|estimator| estimator.push(value));
+        self.ewma.push(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.stats.count()
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        self.stats.mean()
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        self.stats.std_dev()
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.stats.min()
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.stats.max()
+    }
+
+    pub fn ewma(&self) -> Option<f64> {
+        self.ewma.value()
+    }
+
+    /// Estimate of the quantile `p`, or `None` if `p` is not tracked or no samples were added.
+    pub fn quantile(&self, p: f64) -> Option<f64> {
+        self.quantiles
+            .iter()
+            .find(|estimator| (estimator.p() - p).abs() < f64::EPSILON)
+            .and_then(P2Quantile::quantile)
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+}
+
+impl Default for StreamingSummary {
+    fn default() -> Self {
+        Self::new(&DEFAULT_QUANTILES, DEFAULT_EWMA_ALPHA)
+    }
+}
+
+/// Upper bounds in nanoseconds of the buckets of a `Histogram` created through `Default`,
+/// from 100 µs to 1 s.
+pub const DEFAULT_HISTOGRAM_BOUNDS: [f64; 13] = [
+    1e5, 2.5e5, 5e5, 1e6, 2.5e6, 5e6, 1e7, 2.5e7, 5e7, 1e8, 2.5e8, 5e8, 1e9,
+];
+
+/// Counts of a stream of values in buckets with fixed upper bounds, along with their sum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    /// Count of every bucket, the last one holds the values above every bound
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Histogram {
+    /// Creates a histogram with the provided upper bounds, which are sorted.
+    pub fn new(bounds: &[f64]) -> Self {
+        let mut bounds = bounds.to_owned();
+        bounds.sort_by(|a, b| a.total_cmp(b));
+        Self {
+            counts: vec![0; bounds.len() + 1],
+            bounds,
+            sum: 0.0,
+        }
+    }
+
+    /// Adds a sample to the bucket of the lowest bound that is not below it.
+    pub fn push(&mut self, value: f64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket] += 1;
+        self.sum += value;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Returns every upper bound with the count of the values that are not above it,
+    /// ending with an infinite bound that counts every value.
+    pub fn cumulative_counts(&self) -> Vec<(f64, u64)> {
+        self.bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(self.counts.iter().scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            }))
+            .collect()
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(&DEFAULT_HISTOGRAM_BOUNDS)
+    }
+}
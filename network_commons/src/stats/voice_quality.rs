@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+/// Basic signal-to-noise ratio minus the simultaneous impairment factor, with the default
+/// values of ITU-T G.107
+const DEFAULT_R0_MINUS_IS: f64 = 93.2;
+/// One-way delay in milliseconds above which the delay impairment grows faster
+const DELAY_KNEE: f64 = 177.3;
+
+/// Voice codec whose quality is estimated over a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Codec {
+    /// G.711 with packet loss concealment, 20 ms packets
+    G711,
+    /// G.729A, 20 ms packets
+    G729,
+    /// Opus in narrowband VoIP mode, 20 ms packets, rated as G.711
+    Opus,
+}
+
+impl Codec {
+    /// Equipment impairment factor `Ie`.
+    ///
+    /// The G.711 and G.729 values come from ITU-T G.113 Appendix I. G.113 has no value for Opus,
+    /// so Opus is rated as G.711, without the gain of its loss concealment and redundancy.
+    pub fn equipment_impairment(&self) -> f64 {
+        match self {
+            Codec::G711 => 0.0,
+            Codec::G729 => 11.0,
+            Codec::Opus => 0.0,
+        }
+    }
+
+    /// Packet loss robustness factor `Bpl`, from ITU-T G.113 Appendix I.
+    /// As for `Ie`, Opus takes the value of G.711.
+    pub fn packet_loss_robustness(&self) -> f64 {
+        match self {
+            Codec::G711 => 25.1,
+            Codec::G729 => 19.0,
+            Codec::Opus => 25.1,
+        }
+    }
+
+    /// Delay added by the codec in milliseconds: the packetization delay plus the look-ahead.
+    /// The Opus value is the algorithmic delay of 20 ms frames given by RFC 6716.
+    pub fn delay(&self) -> f64 {
+        match self {
+            Codec::G711 => 20.0,
+            Codec::G729 => 25.0,
+            Codec::Opus => 26.5,
+        }
+    }
+}
+
+/// Impairments of a path that affect the quality of a voice call.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PathImpairments {
+    /// Mean one-way network delay in milliseconds
+    pub one_way_delay: f64,
+    /// Mean jitter in milliseconds, buffered by a jitter buffer of twice its size
+    pub jitter: f64,
+    /// Ratio of lost packets, between 0 and 1
+    pub loss_ratio: f64,
+    /// Ratio of the mean length of the loss bursts to the one of random losses, see `burst_ratio`
+    pub burst_ratio: f64,
+}
+
+/// Calculates the burst ratio `BurstR` of ITU-T G.107 from the loss pattern of a stream, where
+/// every item tells whether a packet was lost. See `BurstCounter`.
+pub fn burst_ratio<I>(losses: I) -> f64
+where
+    I: IntoIterator<Item = bool>,
+{
+    let mut counter = BurstCounter::default();
+    losses.into_iter().for_each(|lost| counter.push(lost));
+    counter.burst_ratio()
+}
+
+/// Streaming counter of the transitions between received and lost packets, from which the burst
+/// ratio `BurstR` of ITU-T G.107 is calculated.
+///
+/// The losses are modeled as a two state Markov chain, with `p` the probability of losing a packet
+/// after a received one and `q` the probability of receiving a packet after a lost one.
+/// Then `BurstR = 1 / (p + q)`, which is 1 for random losses and above 1 for bursty losses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BurstCounter {
+    received: u64,
+    received_to_lost: u64,
+    lost: u64,
+    lost_to_received: u64,
+    previous: Option<bool>,
+}
+
+impl BurstCounter {
+    /// Adds the next packet of the stream, in sending order.
+    pub fn push(&mut self, lost_packet: bool) {
+        match self.previous {
+            Some(false) => {
+                self.received += 1;
+                self.received_to_lost += u64::from(lost_packet);
+            }
+            Some(true) => {
+                self.lost += 1;
+                self.lost_to_received += u64::from(!lost_packet);
+            }
+            None => {}
+        }
+        self.previous = Some(lost_packet);
+    }
+
+    /// Returns the burst ratio, 1 when the stream has no losses or only losses.
+    pub fn burst_ratio(&self) -> f64 {
+        if self.received == 0 || self.lost == 0 || self.received_to_lost == 0 {
+            return 1.0;
+        }
+        let p = self.received_to_lost as f64 / self.received as f64;
+        let q = self.lost_to_received as f64 / self.lost as f64;
+        (1.0 / (p + q)).max(1.0)
+    }
+}
+
+/// Calculates the transmission rating factor `R` of the E-model of ITU-T G.107 for a codec
+/// over a path.
+///
+/// Only the delay and equipment impairments depend on the path; the other factors take the
+/// default values of G.107. The delay impairment uses the simplification of:
+///
+/// R. G. Cole, J. H. Rosenbluth. "Voice over IP Performance Monitoring".
+/// ACM SIGCOMM Computer Communication Review, Vol. 31, No. 2 (April 2001), pp. 9-24.
+pub fn r_factor(codec: Codec, impairments: &PathImpairments) -> f64 {
+    let mouth_to_ear_delay = impairments.one_way_delay + 2.0 * impairments.jitter + codec.delay();
+    let delay_impairment =
+        0.024 * mouth_to_ear_delay + 0.11 * (mouth_to_ear_delay - DELAY_KNEE).max(0.0);
+
+    let ie = codec.equipment_impairment();
+    let loss_percent = 100.0 * impairments.loss_ratio.clamp(0.0, 1.0);
+    let burst_ratio = impairments.burst_ratio.max(1.0);
+    let effective_equipment_impairment = ie
+        + (95.0 - ie) * loss_percent
+            / (loss_percent / burst_ratio + codec.packet_loss_robustness());
+
+    (DEFAULT_R0_MINUS_IS - delay_impairment - effective_equipment_impairment).clamp(0.0, 100.0)
+}
+
+/// Estimates the conversational Mean Opinion Score of a rating factor, with the conversion of
+/// ITU-T G.107 Annex B.
+pub fn mos(r_factor: f64) -> f64 {
+    if r_factor <= 0.0 {
+        1.0
+    } else if r_factor >= 100.0 {
+        4.5
+    } else {
+        // The polynomial dips slightly below 1 for the lowest ratings
+        (1.0 + 0.035 * r_factor + r_factor * (r_factor - 60.0) * (100.0 - r_factor) * 7e-6).max(1.0)
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::Arc,
+};
+
+use crate::{error::CommonError, libc_call, signal::block_signals};
+
+#[derive(Debug)]
+struct Inner {
+    event_fd: OwnedFd,
+    signal_fd: Option<OwnedFd>,
+}
+
+/// Handle used to stop a running test from any thread.
+///
+/// The handle is backed by an eventfd that event loops register through
+/// `LinuxEventLoop::register_stop_handle`. Once `stop` is called, every event loop
+/// sharing the handle reacts as if its deadline was reached, so the test still
+/// goes through its overtime period and returns its partial results.
+///
+/// A handle created with `with_signals` also owns a signalfd, so the listed signals
+/// stop the test instead of terminating the process.
+#[derive(Debug, Clone)]
+pub struct StopHandle {
+    inner: Arc<Inner>,
+}
+
+impl StopHandle {
+    /// Creates a new stop handle.
+    pub fn new() -> Result<Self, CommonError> {
+        let event_fd = libc_call!(eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC))
+            .map_err(CommonError::Io)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                event_fd: unsafe { OwnedFd::from_raw_fd(event_fd) },
+                signal_fd: None,
+            }),
+        })
+    }
+
+    /// Creates a new stop handle that is also triggered by the provided signals.
+    ///
+    /// The signals are blocked for the calling thread, and every thread it spawns afterwards
+    /// inherits that mask. To make sure the signals are only delivered through the signalfd,
+    /// this should be called from the main thread before any other thread is spawned.
+    pub fn with_signals(signals: &[i32]) -> Result<Self, CommonError> {
+        let mut handle = Self::new()?;
+        let signal_fd = block_signals(signals, libc::SFD_NONBLOCK)?;
+        // The handle was just created, so there is no other reference to it yet
+        if let Some(inner) = Arc::get_mut(&mut handle.inner) {
+            inner.signal_fd = Some(signal_fd);
+        }
+        Ok(handle)
+    }
+
+    /// Creates a new stop handle that is triggered by SIGINT and SIGTERM.
+    pub fn with_termination_signals() -> Result<Self, CommonError> {
+        Self::with_signals(&[libc::SIGINT, libc::SIGTERM])
+    }
+
+    /// Stops every event loop this handle is registered with.
+    pub fn stop(&self) -> Result<(), CommonError> {
+        let value: u64 = 1;
+        libc_call!(write(
+            self.inner.event_fd.as_raw_fd(),
+            &value as *const u64 as *const libc::c_void,
+            std::mem::size_of::<u64>()
+        ))
+        .map_err(CommonError::Io)?;
+        Ok(())
+    }
+
+    /// Returns whether `stop` was called on this handle or any of its clones.
+    pub fn is_stopped(&self) -> bool {
+        let mut poll_fd = libc::pollfd {
+            fd: self.inner.event_fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut poll_fd, 1, 0) > 0 }
+    }
+
+    /// Raw file descriptor of the signalfd, if the handle listens to signals.
+    pub fn signal_fd(&self) -> Option<RawFd> {
+        self.inner.signal_fd.as_ref().map(|fd| fd.as_raw_fd())
+    }
+
+    /// Consumes a pending signal from the signalfd and stops the test.
+    /// Returns the number of the received signal, if there was one pending.
+    pub fn handle_signal(&self) -> Result<Option<u32>, CommonError> {
+        let Some(signal_fd) = self.signal_fd() else {
+            return Ok(None);
+        };
+        let mut siginfo: libc::signalfd_siginfo = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<libc::signalfd_siginfo>();
+        let result = unsafe {
+            libc::read(
+                signal_fd,
+                &mut siginfo as *mut libc::signalfd_siginfo as *mut libc::c_void,
+                size,
+            )
+        };
+        // Another event loop sharing this handle may have consumed the signal already
+        if result != size as isize {
+            return Ok(None);
+        }
+        log::info!("Received signal {}, stopping", siginfo.ssi_signo);
+        self.stop()?;
+        Ok(Some(siginfo.ssi_signo))
+    }
+}
+
+/// Two handles are equal when they are clones of the same handle.
+impl PartialEq for StopHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl AsRawFd for StopHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.event_fd.as_raw_fd()
+    }
+}
@@ -76,7 +76,7 @@ impl TimestampedTcpSocket {
                 socket.as_raw_fd(),
                 libc::SOL_SOCKET,
                 libc::SO_REUSEADDR,
-                1 as *const _,
+                &1i32 as *const i32 as *const libc::c_void,
                 std::mem::size_of::<i32>() as u32,
             );
         }
@@ -102,7 +102,7 @@ impl TimestampedTcpSocket {
             return Err(CommonError::SocketCreateFailed(io::Error::last_os_error()));
         }
         let (sock_addr, sock_addr_len) = socketaddr_to_sockaddr(addr);
-        let sock_addr_ptr = &sock_addr as *const _;
+        let sock_addr_ptr = &sock_addr as *const _ as *const libc::sockaddr;
 
         if unsafe { libc::bind(socket_fd, sock_addr_ptr, sock_addr_len) } < 0 {
             return Err(CommonError::SocketBindFailed(io::Error::last_os_error()));
@@ -175,7 +175,7 @@ impl TimestampedTcpSocket {
             return Err(CommonError::SocketCreateFailed(io::Error::last_os_error()));
         }
         let (sock_addr, sock_addr_len) = socketaddr_to_sockaddr(&addr);
-        let sock_addr_ptr = &sock_addr as *const _;
+        let sock_addr_ptr = &sock_addr as *const _ as *const libc::sockaddr;
         let result = unsafe { libc::connect(socket_fd, sock_addr_ptr, sock_addr_len) };
         log::debug!("Connect result: {}", result);
         if result < 0 {
@@ -186,6 +186,34 @@ impl TimestampedTcpSocket {
 
         Ok(result)
     }
+
+    /// Sends every byte of the buffer, retrying until the socket accepted all of them.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the connection fails before the whole buffer is sent.
+    pub fn send_all(&self, bytes: &[u8]) -> Result<usize, CommonError> {
+        let mut sent = 0;
+        while sent < bytes.len() {
+            let result = unsafe {
+                libc::send(
+                    self.inner,
+                    bytes[sent..].as_ptr() as *const libc::c_void,
+                    bytes.len() - sent,
+                    MSG_NOSIGNAL,
+                )
+            };
+            if result < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(CommonError::from(error));
+            }
+            sent += result as usize;
+        }
+        Ok(sent)
+    }
 }
 
 impl Socket<TimestampedTcpSocket> for TimestampedTcpSocket {
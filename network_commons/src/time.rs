@@ -119,6 +119,11 @@ impl DateTime {
         }
     }
 
+    /// Nanoseconds since the Unix epoch.
+    pub fn to_nanos(&self) -> u64 {
+        self.sec as u64 * 1_000_000_000 + self.nanos as u64
+    }
+
     pub fn from_timespec(ts: timespec) -> DateTime {
         DateTime {
             sec: ts.tv_sec as u32,
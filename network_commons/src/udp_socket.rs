@@ -5,7 +5,11 @@ use std::io::{self, IoSliceMut};
 
 use std::os::fd::{AsRawFd, RawFd};
 use std::ptr;
-use std::{io::IoSlice, net::SocketAddr, ops::Deref};
+use std::{
+    io::IoSlice,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    ops::Deref,
+};
 
 use crate::error::CommonError;
 use crate::libc_call;
@@ -84,7 +88,7 @@ impl TimestampedUdpSocket {
         }
 
         let (sock_addr, sock_addr_len) = socketaddr_to_sockaddr(addr);
-        let sock_addr_ptr = &sock_addr as *const _;
+        let sock_addr_ptr = &sock_addr as *const _ as *const libc::sockaddr;
         if unsafe { libc::bind(socket_fd, sock_addr_ptr, sock_addr_len) } < 0 {
             return Err(CommonError::SocketBindFailed(io::Error::last_os_error()));
         }
@@ -103,6 +107,61 @@ impl TimestampedUdpSocket {
         Ok(res)
     }
 
+    /// Returns the address the socket is bound to.
+    pub fn local_address(&self) -> Result<SocketAddr, CommonError> {
+        let mut addr_storage: sockaddr_storage = unsafe { core::mem::zeroed() };
+        let mut len = core::mem::size_of_val(&addr_storage) as libc::socklen_t;
+        libc_call!(getsockname(
+            self.inner,
+            &mut addr_storage as *mut _ as *mut libc::sockaddr,
+            &mut len
+        ))
+        .map_err(CommonError::Io)?;
+        storage_to_socket_addr(&addr_storage)
+    }
+
+    /// Sends a message like `send_to`, with the IPv4 type of service or IPv6 traffic class
+    /// given as ancillary data, so packets to different destinations can carry different
+    /// classes without changing the options of the socket.
+    pub fn send_to_with_traffic_class(
+        &self,
+        address: &SocketAddr,
+        message: impl BeBytes,
+        traffic_class: i32,
+    ) -> Result<(isize, DateTime), CommonError> {
+        let bytes = message.to_be_bytes();
+        let iov = [IoSlice::new(&bytes)];
+        let (mut sock_addr, sock_addr_len) = socketaddr_to_sockaddr(address);
+        let (level, kind) = match address {
+            SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_TOS),
+            SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_TCLASS),
+        };
+        let mut cmsg_space = [0u8; CMSG_SPACE_SIZE];
+        let data_len = core::mem::size_of::<i32>() as u32;
+        let mut msg: msghdr = unsafe { core::mem::zeroed() };
+        msg.msg_name = &mut sock_addr as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = sock_addr_len;
+        msg.msg_iov = iov.as_ptr() as *mut iovec;
+        msg.msg_iovlen = iov.len();
+        msg.msg_control = cmsg_space.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(data_len) } as usize;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = level;
+            (*cmsg).cmsg_type = kind;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as usize;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut i32, traffic_class);
+        }
+        log::trace!(
+            "Sending to {} with traffic class {}",
+            address,
+            traffic_class
+        );
+        let utc_now = DateTime::utc_now();
+        let result = unsafe { sendmsg(self.as_raw_fd(), &msg, 0) };
+        Ok((result, utc_now))
+    }
+
     pub fn receive_from_multiple(
         &self,
         buffers: &mut [[u8; DEFAULT_BUFFER_SIZE]],
@@ -196,6 +255,143 @@ impl TimestampedUdpSocket {
         }
     }
 
+    /// Enables the packet information of the received packets, so `receive_from_to` returns
+    /// the local address each of them was sent to. This is the only way to know it when the
+    /// socket is bound to an unspecified address.
+    pub fn set_packet_info_options(&mut self) -> Result<i32, CommonError> {
+        match self.local_address()? {
+            SocketAddr::V4(_) => {
+                self.set_socket_options(libc::IPPROTO_IP, libc::IP_PKTINFO, Some(1))
+            }
+            SocketAddr::V6(_) => {
+                self.set_socket_options(libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, Some(1))
+            }
+        }
+    }
+
+    /// Receives a packet like `receive_from`, along with the local address it was sent to,
+    /// which is only known once `set_packet_info_options` was called.
+    pub fn receive_from_to(
+        &self,
+        buffer: &mut [u8],
+    ) -> Result<(isize, SocketAddr, Option<IpAddr>, DateTime), CommonError> {
+        let fd = self.as_raw_fd();
+        let mut addr_storage: sockaddr_storage = unsafe { core::mem::zeroed() };
+
+        let iov = [IoSliceMut::new(buffer)];
+        let mut msg: msghdr = unsafe { core::mem::zeroed() };
+        msg.msg_name = &mut addr_storage as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = core::mem::size_of_val(&addr_storage) as u32;
+        msg.msg_iov = iov.as_ptr() as *mut iovec;
+        msg.msg_iovlen = iov.len();
+        let mut cmsg_space = [0u8; CMSG_SPACE_SIZE];
+        msg.msg_control = cmsg_space.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = cmsg_space.len();
+
+        // Getting the backup timestamp right before the recvmsg call
+        let mut timestamp = DateTime::utc_now();
+
+        let n = unsafe { recvmsg(fd, &mut msg, 0) };
+        if n < 0 {
+            return Err(CommonError::Io(std::io::Error::last_os_error()));
+        }
+
+        let socket_addr =
+            storage_to_socket_addr(unsafe { &*(msg.msg_name as *const libc::sockaddr_storage) })?;
+        if let Ok(date_time) = retrieve_data_from_header(&msg) {
+            timestamp = date_time;
+        };
+
+        Ok((n, socket_addr, retrieve_destination(&msg), timestamp))
+    }
+
+    /// Enables the timestamping options along with `SOF_TIMESTAMPING_OPT_ID`, so every
+    /// transmit timestamp carries the identifier of the packet it belongs to.
+    /// Identifiers start at 0 and are incremented for every datagram sent afterwards.
+    pub fn set_identified_timestamping_options(&mut self) -> Result<i32, CommonError> {
+        let value = libc::SOF_TIMESTAMPING_SOFTWARE
+            | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+            | libc::SOF_TIMESTAMPING_TX_SOFTWARE
+            | libc::SOF_TIMESTAMPING_OPT_ID;
+        self.set_socket_options(libc::SOL_SOCKET, libc::SO_TIMESTAMPING, Some(value as i32))
+    }
+
+    /// Drains the error queue of the socket and returns the transmit timestamps it held,
+    /// along with the identifier of the packet each of them belongs to.
+    ///
+    /// Identifiers are only meaningful if the socket was configured through
+    /// `set_identified_timestamping_options`. Error messages that are not transmit
+    /// timestamps, like ICMP errors, are discarded.
+    pub fn retrieve_identified_tx_timestamps(
+        &mut self,
+    ) -> Result<Vec<(u32, DateTime)>, CommonError> {
+        let mut timestamps = Vec::new();
+        self.drain_error_queue(|msg, error| {
+            if error.ee_errno == libc::ENOMSG as u32
+                && error.ee_origin == libc::SO_EE_ORIGIN_TIMESTAMPING
+            {
+                if let Ok(date_time) = retrieve_data_from_header(msg) {
+                    timestamps.push((error.ee_data, date_time));
+                }
+            }
+        })?;
+        Ok(timestamps)
+    }
+
+    /// Drains the error queue of the socket and returns the destination of every packet that
+    /// was too big for its path, along with the MTU reported for that path.
+    ///
+    /// The MTU comes either from the local interface or from an ICMP fragmentation needed
+    /// or packet too big message, so the socket must have `IP_RECVERR` or `IPV6_RECVERR` set.
+    /// Other error messages are discarded.
+    pub fn retrieve_path_mtu_errors(&mut self) -> Result<Vec<(SocketAddr, u32)>, CommonError> {
+        let mut errors = Vec::new();
+        self.drain_error_queue(|msg, error| {
+            if error.ee_errno != libc::EMSGSIZE as u32 {
+                return;
+            }
+            if let Ok(destination) =
+                storage_to_socket_addr(unsafe { &*(msg.msg_name as *const libc::sockaddr_storage) })
+            {
+                errors.push((destination, error.ee_info));
+            }
+        })?;
+        Ok(errors)
+    }
+
+    /// Reads every message of the error queue and hands the ones carrying an extended error
+    /// to `handler`.
+    fn drain_error_queue(
+        &mut self,
+        mut handler: impl FnMut(&msghdr, &libc::sock_extended_err),
+    ) -> Result<(), CommonError> {
+        let mut buffer = [0u8; DEFAULT_BUFFER_SIZE];
+        let mut cmsg_space = [0u8; CMSG_SPACE_SIZE * MAX_MSG];
+        loop {
+            let mut addr_storage: sockaddr_storage = unsafe { core::mem::zeroed() };
+            let iov = [IoSliceMut::new(&mut buffer)];
+            let mut msg: msghdr = unsafe { core::mem::zeroed() };
+            msg.msg_name = &mut addr_storage as *mut _ as *mut libc::c_void;
+            msg.msg_namelen = core::mem::size_of_val(&addr_storage) as u32;
+            msg.msg_iov = iov.as_ptr() as *mut iovec;
+            msg.msg_iovlen = iov.len();
+            msg.msg_control = cmsg_space.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = cmsg_space.len();
+
+            let n = unsafe { recvmsg(self.as_raw_fd(), &mut msg, libc::MSG_ERRQUEUE) };
+            if n < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(());
+                }
+                return Err(CommonError::Io(error));
+            }
+            if let Some(error) = retrieve_extended_error(&msg) {
+                handler(&msg, &error);
+            }
+        }
+    }
+
     /// Attempts to receive a single timestamped error message from the socket.
     ///
     /// Returns a tuple containing the size of the received message,
@@ -223,6 +419,50 @@ impl TimestampedUdpSocket {
     }
 }
 
+/// Returns the extended error of a message read from the error queue.
+fn retrieve_extended_error(msg_hdr: &msghdr) -> Option<libc::sock_extended_err> {
+    let mut cmsg_ptr = unsafe { libc::CMSG_FIRSTHDR(msg_hdr as *const msghdr) };
+    while !cmsg_ptr.is_null() {
+        unsafe {
+            let is_extended_error = ((*cmsg_ptr).cmsg_level == libc::SOL_IP
+                && (*cmsg_ptr).cmsg_type == libc::IP_RECVERR)
+                || ((*cmsg_ptr).cmsg_level == libc::SOL_IPV6
+                    && (*cmsg_ptr).cmsg_type == libc::IPV6_RECVERR);
+            if is_extended_error {
+                return Some(ptr::read_unaligned(
+                    libc::CMSG_DATA(cmsg_ptr) as *const libc::sock_extended_err
+                ));
+            }
+            cmsg_ptr = libc::CMSG_NXTHDR(msg_hdr as *const msghdr, cmsg_ptr);
+        }
+    }
+    None
+}
+
+/// Returns the local address a received packet was sent to, from its packet information.
+fn retrieve_destination(msg_hdr: &msghdr) -> Option<IpAddr> {
+    let mut cmsg_ptr = unsafe { libc::CMSG_FIRSTHDR(msg_hdr as *const msghdr) };
+    while !cmsg_ptr.is_null() {
+        unsafe {
+            let (level, kind) = ((*cmsg_ptr).cmsg_level, (*cmsg_ptr).cmsg_type);
+            if level == libc::IPPROTO_IP && kind == libc::IP_PKTINFO {
+                let info =
+                    ptr::read_unaligned(libc::CMSG_DATA(cmsg_ptr) as *const libc::in_pktinfo);
+                return Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                    info.ipi_addr.s_addr,
+                ))));
+            }
+            if level == libc::IPPROTO_IPV6 && kind == libc::IPV6_PKTINFO {
+                let info =
+                    ptr::read_unaligned(libc::CMSG_DATA(cmsg_ptr) as *const libc::in6_pktinfo);
+                return Some(IpAddr::V6(Ipv6Addr::from(info.ipi6_addr.s6_addr)));
+            }
+            cmsg_ptr = libc::CMSG_NXTHDR(msg_hdr as *const msghdr, cmsg_ptr);
+        }
+    }
+    None
+}
+
 fn recvmmsg_timestamped(
     fd: i32,
     msg_hdrs: &mut [mmsghdr],
@@ -275,11 +515,11 @@ impl Socket<TimestampedUdpSocket> for TimestampedUdpSocket {
         let bytes = message.to_be_bytes();
         let iov = [IoSlice::new(&bytes)];
 
-        let (mut sock_addr, _len) = socketaddr_to_sockaddr(address);
-        log::trace!("Sending to {:?}", sock_addr.sa_data);
+        let (mut sock_addr, sock_addr_len) = socketaddr_to_sockaddr(address);
+        log::trace!("Sending to {}", address);
         let msg = msghdr {
             msg_name: &mut sock_addr as *mut _ as *mut libc::c_void,
-            msg_namelen: core::mem::size_of_val(&sock_addr) as u32,
+            msg_namelen: sock_addr_len,
             msg_iov: iov.as_ptr() as *mut libc::iovec,
             msg_iovlen: iov.len(),
             msg_control: std::ptr::null_mut(),
@@ -299,43 +539,12 @@ impl Socket<TimestampedUdpSocket> for TimestampedUdpSocket {
         &self,
         buffer: &mut [u8],
     ) -> Result<(isize, SocketAddr, DateTime), CommonError> {
-        let fd = self.as_raw_fd();
-        let mut addr_storage: sockaddr_storage = unsafe { core::mem::zeroed() };
-
-        let iov = [IoSliceMut::new(buffer)];
-        let mut msg: msghdr = unsafe { core::mem::zeroed() };
-        msg.msg_name = &mut addr_storage as *mut _ as *mut libc::c_void;
-        msg.msg_namelen = core::mem::size_of_val(&addr_storage) as u32;
-        msg.msg_iov = iov.as_ptr() as *mut iovec;
-        msg.msg_iovlen = iov.len();
-        const SPACE_SIZE: usize = unsafe {
-            libc::CMSG_SPACE(core::mem::size_of::<libc::timeval>() as u32) as usize * MAX_MSG
-        };
-        let mut cmsg_space: [u8; SPACE_SIZE] = unsafe { core::mem::zeroed() };
-        msg.msg_control = cmsg_space.as_mut_ptr() as *mut libc::c_void;
-        msg.msg_controllen = cmsg_space.len();
-
-        // Getting the backup timestamp right before the recvmsg call
-        let mut timestamp = DateTime::utc_now();
-
-        let n = unsafe { recvmsg(fd, &mut msg, 0) };
-        if n < 0 {
-            return Err(CommonError::Io(std::io::Error::last_os_error()));
-        }
-
-        let socket_addr =
-            storage_to_socket_addr(unsafe { &*(msg.msg_name as *const libc::sockaddr_storage) })?;
-        log::debug!("Socket address: {:?}", socket_addr);
-        if let Ok(date_time) = retrieve_data_from_header(&msg) {
-            timestamp = date_time;
-            log::debug!("Timestamp: {:?}", timestamp);
-        };
-
+        let (n, socket_addr, _, timestamp) = self.receive_from_to(buffer)?;
+        log::debug!(
+            "Socket address: {:?}, timestamp: {:?}",
+            socket_addr,
+            timestamp
+        );
         Ok((n, socket_addr, timestamp))
     }
 }
-
-#[derive(BeBytes, PartialEq, Debug, Clone)]
-struct Message {
-    pub data: Vec<u8>,
-}
@@ -0,0 +1,80 @@
+use network_commons::{
+    assert_approx_eq,
+    stats::confidence::{
+        analytic_mean_interval, analytic_median_interval, wilson_interval, Bootstrap,
+        ConfidenceConfiguration, IntervalEstimator, IntervalMethod, MAX_BOOTSTRAP_SAMPLE,
+    },
+};
+
+#[test]
+fn wilson_interval_matches_newcombe() {
+    // Examples of R. G. Newcombe, "Two-sided confidence intervals for the single proportion",
+    // Statistics in Medicine, 1998
+    let interval = wilson_interval(81, 263, 0.95).unwrap();
+    assert_approx_eq!(interval.lower, 0.2553, 1e-4);
+    assert_approx_eq!(interval.upper, 0.3662, 1e-4);
+    let interval = wilson_interval(0, 10, 0.95).unwrap();
+    assert_approx_eq!(interval.lower, 0.0, 1e-12);
+    assert_approx_eq!(interval.upper, 0.2775, 1e-4);
+    assert_eq!(wilson_interval(0, 0, 0.95), None);
+}
+
+#[test]
+fn analytic_mean_interval_uses_the_sample_variance() {
+    let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+    // 5 +/- 1.96 * sqrt(32 / 7 / 8)
+    let interval = analytic_mean_interval(&values, 0.95).unwrap();
+    assert_approx_eq!(interval.lower, 3.5184, 1e-4);
+    assert_approx_eq!(interval.upper, 6.4816, 1e-4);
+    assert_eq!(analytic_mean_interval(&[1.0], 0.95), None);
+}
+
+#[test]
+fn analytic_median_interval_matches_the_binomial_ranks() {
+    // The 95% interval of the median of 100 values lies between the 40th and 61st values
+    let values: Vec<f64> = (1..=100).rev().map(f64::from).collect();
+    let interval = analytic_median_interval(&values, 0.95).unwrap();
+    assert_eq!((interval.lower, interval.upper), (40.0, 61.0));
+}
+
+#[test]
+fn bootstrap_is_reproducible_and_close_to_the_analytic_interval() {
+    let values: Vec<f64> = (0..500).map(|i| f64::from((i * 37) % 100)).collect();
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let first = Bootstrap::new(2000, 7)
+        .interval(&values, 0.95, mean)
+        .unwrap();
+    let second = Bootstrap::new(2000, 7)
+        .interval(&values, 0.95, mean)
+        .unwrap();
+    assert_eq!(first, second);
+
+    let analytic = analytic_mean_interval(&values, 0.95).unwrap();
+    assert_approx_eq!(first.lower, analytic.lower, 0.5);
+    assert_approx_eq!(first.upper, analytic.upper, 0.5);
+
+    let proportion = Bootstrap::new(2000, 7)
+        .proportion_interval(50, 1000, 0.95)
+        .unwrap();
+    let wilson = wilson_interval(50, 1000, 0.95).unwrap();
+    assert_approx_eq!(proportion.lower, wilson.lower, 0.005);
+    assert_approx_eq!(proportion.upper, wilson.upper, 0.005);
+}
+
+#[test]
+fn large_samples_use_the_analytic_intervals() {
+    let mut estimator = IntervalEstimator::new(ConfidenceConfiguration {
+        method: IntervalMethod::Bootstrap,
+        ..Default::default()
+    });
+    let trials = MAX_BOOTSTRAP_SAMPLE + 1;
+    assert_eq!(
+        estimator.proportion(10, trials),
+        wilson_interval(10, trials, 0.95)
+    );
+    let values: Vec<f64> = (0..trials).map(|i| (i % 7) as f64).collect();
+    assert_eq!(
+        estimator.mean(&values),
+        analytic_mean_interval(&values, 0.95)
+    );
+}
@@ -0,0 +1,68 @@
+use network_commons::{
+    assert_approx_eq,
+    stats::hypothesis::{mann_whitney_u, two_proportion_test},
+};
+
+#[test]
+fn mann_whitney_counts_the_pairs_won_by_the_current_sample() {
+    // Race of 6 tortoises and 6 hares: a tortoise finishes first, the hares 2nd to 6th, the
+    // other tortoises 7th to 11th and the last hare 12th, so the hares win 11 of 36 pairs
+    let tortoises = [1.0, 7.0, 8.0, 9.0, 10.0, 11.0];
+    let hares = [2.0, 3.0, 4.0, 5.0, 6.0, 12.0];
+    let outcome = mann_whitney_u(&tortoises, &hares).unwrap();
+    assert_eq!(outcome.statistic, 11.0);
+    // (11 - 18 - 0.5) / sqrt(6 * 6 * 13 / 12)
+    assert_approx_eq!(outcome.z, -1.200961, 1e-6);
+    assert_approx_eq!(outcome.p_value, 0.885117, 1e-6);
+    assert!(!outcome.is_significant(0.05));
+
+    let outcome = mann_whitney_u(&tortoises, &tortoises).unwrap();
+    assert_eq!(outcome.statistic, 18.0);
+}
+
+#[test]
+fn mann_whitney_detects_a_shifted_sample() {
+    let outcome = mann_whitney_u(&[1.0, 2.0, 3.0, 4.0, 5.0], &[6.0, 7.0, 8.0, 9.0, 10.0]).unwrap();
+    assert_eq!(outcome.statistic, 25.0);
+    assert_approx_eq!(outcome.z, 2.506718, 1e-6);
+    assert_approx_eq!(outcome.p_value, 0.006093, 1e-6);
+    assert!(outcome.is_significant(0.05));
+}
+
+#[test]
+fn mann_whitney_shares_the_ranks_of_ties() {
+    let outcome = mann_whitney_u(&[1.0, 2.0, 2.0, 3.0], &[2.0, 3.0, 3.0, 4.0]).unwrap();
+    assert_eq!(outcome.statistic, 13.0);
+    // The variance is reduced by the ties of 2 and 3
+    assert_approx_eq!(outcome.z, 1.365698, 1e-6);
+    assert_approx_eq!(outcome.p_value, 0.086017, 1e-6);
+}
+
+#[test]
+fn mann_whitney_needs_different_values() {
+    assert!(mann_whitney_u(&[], &[1.0]).is_none());
+    assert!(mann_whitney_u(&[1.0], &[]).is_none());
+    assert!(mann_whitney_u(&[2.0, 2.0], &[2.0, 2.0, 2.0]).is_none());
+}
+
+#[test]
+fn two_proportion_test_pools_the_ratios() {
+    // 5% of 200 against 10% of 250
+    let outcome = two_proportion_test(10, 200, 25, 250).unwrap();
+    assert_approx_eq!(outcome.statistic, 0.05, 1e-12);
+    assert_approx_eq!(outcome.z, 1.967901, 1e-6);
+    assert_approx_eq!(outcome.p_value, 0.024540, 1e-6);
+    assert!(outcome.is_significant(0.05));
+    assert!(!outcome.is_significant(0.01));
+
+    let outcome = two_proportion_test(25, 250, 10, 200).unwrap();
+    assert_approx_eq!(outcome.z, -1.967901, 1e-6);
+    assert!(!outcome.is_significant(0.05));
+}
+
+#[test]
+fn two_proportion_test_needs_disagreeing_samples() {
+    assert!(two_proportion_test(0, 0, 1, 10).is_none());
+    assert!(two_proportion_test(0, 100, 0, 100).is_none());
+    assert!(two_proportion_test(100, 100, 50, 50).is_none());
+}
@@ -0,0 +1,128 @@
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use network_commons::{
+    connection::ConnectionLimits,
+    management::{ManagementServer, Request, Response},
+};
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("management-{}-{}.sock", std::process::id(), name))
+}
+
+/// Serves the method, path and body of every request back, padded with `padding` bytes.
+fn spawn(name: &str, padding: usize, limits: ConnectionLimits) -> ManagementServer {
+    let handler = Arc::new(move |request: &Request| {
+        Response::json(
+            200,
+            &serde_json::json!({
+                "method": request.method,
+                "path": request.path,
+                "body": String::from_utf8_lossy(&request.body),
+                "padding": "x".repeat(padding),
+            }),
+        )
+    });
+    ManagementServer::spawn_with_limits(&socket_path(name), handler, limits).unwrap()
+}
+
+fn connect(path: &Path) -> UnixStream {
+    let stream = UnixStream::connect(path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Sends a request on `stream` and returns the whole response.
+fn send(mut stream: UnixStream, request: &str) -> String {
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn answers_requests_with_their_body() {
+    let server = spawn("answers", 0, ConnectionLimits::default());
+    let response = send(
+        connect(server.path()),
+        "PUT /jobs/daily?force HTTP/1.1\r\nContent-Length: 7\r\n\r\n{\"a\":1}",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    let body: serde_json::Value =
+        serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    assert_eq!(body["method"], "PUT");
+    assert_eq!(body["path"], "/jobs/daily");
+    assert_eq!(body["body"], "{\"a\":1}");
+
+    let response = send(connect(server.path()), "\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    server.stop().unwrap();
+}
+
+#[test]
+fn clients_that_dont_read_dont_block_the_others() {
+    let server = spawn("slow", 4 << 20, ConnectionLimits::default());
+    // The response is much larger than the socket buffer
+    let mut slow = connect(server.path());
+    slow.write_all(b"GET /jobs HTTP/1.1\r\n\r\n").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    let response = send(connect(server.path()), "GET /jobs HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    // The slow client still gets its whole response
+    let mut response = String::new();
+    slow.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("\"\n}\n"));
+    server.stop().unwrap();
+}
+
+#[test]
+fn closes_idle_connections() {
+    let server = spawn(
+        "idle",
+        0,
+        ConnectionLimits {
+            idle_timeout: Duration::from_millis(200),
+            ..Default::default()
+        },
+    );
+    let mut idle = connect(server.path());
+    let start = Instant::now();
+    let mut buffer = [0u8; 16];
+    assert_eq!(idle.read(&mut buffer).unwrap(), 0);
+    assert!(start.elapsed() < Duration::from_secs(2));
+    server.stop().unwrap();
+}
+
+#[test]
+fn rejects_connections_beyond_the_limit() {
+    let server = spawn(
+        "limit",
+        0,
+        ConnectionLimits {
+            max_connections: 1,
+            ..Default::default()
+        },
+    );
+    let first = connect(server.path());
+    let mut second = connect(server.path());
+    let mut buffer = [0u8; 16];
+    // The second connection is closed without a response
+    assert!(second.read(&mut buffer).map_or(true, |length| length == 0));
+    assert!(send(first, "GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200 OK\r\n"));
+
+    // The socket is removed along with the server
+    let path = server.path().to_path_buf();
+    server.stop().unwrap();
+    assert!(!path.exists());
+}
@@ -0,0 +1,92 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use network_commons::{connection::ConnectionLimits, metrics::MetricsServer};
+
+fn spawn(snapshot: &str, limits: ConnectionLimits) -> MetricsServer {
+    let snapshot = Arc::new(RwLock::new(snapshot.to_string()));
+    MetricsServer::spawn_with_limits("127.0.0.1:0".parse().unwrap(), snapshot, limits).unwrap()
+}
+
+fn connect(address: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Sends a scrape on `stream` and returns the whole response.
+fn scrape(mut stream: TcpStream) -> String {
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn serves_every_pending_connection() {
+    let server = spawn("up 1\n# EOF\n", ConnectionLimits::default());
+    // The connections are all pending before the first one is accepted
+    let streams: Vec<TcpStream> = (0..8).map(|_| connect(server.local_address())).collect();
+    for stream in streams {
+        let response = scrape(stream);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("up 1\n# EOF\n"));
+    }
+    server.stop().unwrap();
+}
+
+#[test]
+fn writes_large_snapshots_entirely() {
+    let snapshot = "metric 1\n".repeat(200_000);
+    let server = spawn(&snapshot, ConnectionLimits::default());
+    let response = scrape(connect(server.local_address()));
+    assert!(response.ends_with(&snapshot));
+    server.stop().unwrap();
+}
+
+#[test]
+fn closes_idle_connections() {
+    let server = spawn(
+        "",
+        ConnectionLimits {
+            idle_timeout: Duration::from_millis(200),
+            ..Default::default()
+        },
+    );
+    let mut idle = connect(server.local_address());
+    let start = Instant::now();
+    let mut buffer = [0u8; 16];
+    assert_eq!(idle.read(&mut buffer).unwrap(), 0);
+    assert!(start.elapsed() < Duration::from_secs(2));
+    server.stop().unwrap();
+}
+
+#[test]
+fn rejects_connections_beyond_the_limit() {
+    let server = spawn(
+        "up 1\n",
+        ConnectionLimits {
+            max_connections: 1,
+            ..Default::default()
+        },
+    );
+    let first = connect(server.local_address());
+    let mut second = connect(server.local_address());
+    let mut buffer = [0u8; 16];
+    // The second connection is closed without a response
+    assert!(second.read(&mut buffer).map_or(true, |length| length == 0));
+    assert!(scrape(first).ends_with("up 1\n"));
+
+    // The slot of the first connection is free again
+    let response = scrape(connect(server.local_address()));
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    server.stop().unwrap();
+}
@@ -0,0 +1,210 @@
+use network_commons::{
+    error::CommonError,
+    stats::{
+        distribution::{normal_cdf, normal_quantile},
+        offset_estimator::{
+            estimate, GamlrConfiguration, GamlrEstimator, LcgRng, MomentEstimation, OffsetEstimate,
+        },
+    },
+};
+use validator::Validate;
+
+/// Minimum delay of the simulated path in nanoseconds
+const PROPAGATION_DELAY: f64 = 1_000_000.0;
+/// Scale of the Gamma distributed queuing delay in nanoseconds
+const QUEUING_SCALE: f64 = 50_000.0;
+const QUEUING_SHAPE: u32 = 2;
+const PACKETS: usize = 1000;
+const RUNS: u64 = 20;
+
+/// Draws a Gamma distributed queuing delay, as the sum of `QUEUING_SHAPE` exponential delays.
+fn queuing_delay(rng: &mut LcgRng) -> f64 {
+    (0..QUEUING_SHAPE)
+        .map(|_| -(1.0 - rng.gen_range(0.0..1.0)).ln() * QUEUING_SCALE)
+        .sum()
+}
+
+/// Simulates the forward and backward one-way delays measured over a symmetric path,
+/// with the reflector clock `offset` nanoseconds ahead of the sender clock.
+fn simulate(offset: f64, seed: u64) -> (Vec<f64>, Vec<f64>) {
+    let mut rng = LcgRng::new(seed);
+    let forward = (0..PACKETS)
+        .map(|_| PROPAGATION_DELAY + queuing_delay(&mut rng) + offset)
+        .collect();
+    let backward = (0..PACKETS)
+        .map(|_| PROPAGATION_DELAY + queuing_delay(&mut rng) - offset)
+        .collect();
+    (forward, backward)
+}
+
+/// Estimates the clock offset and its standard error from both directions.
+fn estimate_offset(configuration: &GamlrConfiguration, offset: f64, seed: u64) -> (f64, f64) {
+    let (forward, backward) = simulate(offset, seed);
+    let mut estimator = GamlrEstimator::new(configuration.clone()).unwrap();
+    let forward = estimator.estimate(&forward).unwrap();
+    let backward = estimator.estimate(&backward).unwrap();
+    let standard_error =
+        (forward.standard_error.unwrap().powi(2) + backward.standard_error.unwrap().powi(2)).sqrt()
+            / 2.0;
+    ((forward.offset - backward.offset) / 2.0, standard_error)
+}
+
+/// Asserts that the offset is recovered within 10% of the mean queuing delay on every run.
+fn assert_recovers_offsets(configuration: &GamlrConfiguration) {
+    let tolerance = 0.1 * QUEUING_SCALE * f64::from(QUEUING_SHAPE) + 1_000.0;
+    for offset in [-2_000_000.0, -250_000.0, 0.0, 250_000.0, 2_000_000.0] {
+        for seed in 0..RUNS {
+            let (estimate, _) = estimate_offset(configuration, offset, seed);
+            assert!(
+                (estimate - offset).abs() < tolerance,
+                "offset {} estimated as {} with seed {} and {:?}",
+                offset,
+                estimate,
+                seed,
+                configuration
+            );
+        }
+    }
+}
+
+#[test]
+fn default_configuration_recovers_offsets() {
+    assert_recovers_offsets(&GamlrConfiguration::default());
+}
+
+#[test]
+fn large_chunks_recover_offsets() {
+    for moment in [MomentEstimation::Median, MomentEstimation::Mean] {
+        assert_recovers_offsets(&GamlrConfiguration {
+            chunk_size: 50,
+            moment,
+            ..Default::default()
+        });
+    }
+}
+
+#[test]
+fn more_samples_than_delays_recover_offsets() {
+    assert_recovers_offsets(&GamlrConfiguration {
+        chunk_size: 20,
+        num_samples: Some(200),
+        ..Default::default()
+    });
+}
+
+#[test]
+fn estimates_are_reproducible() {
+    let (forward, _) = simulate(250_000.0, 1);
+    let configuration = GamlrConfiguration::default();
+    let first = GamlrEstimator::new(configuration.clone())
+        .unwrap()
+        .estimate(&forward);
+    let second = GamlrEstimator::new(configuration.clone())
+        .unwrap()
+        .estimate(&forward);
+    assert_eq!(first, second);
+
+    let reseeded = GamlrEstimator::new(GamlrConfiguration {
+        seed: configuration.seed + 1,
+        ..configuration
+    })
+    .unwrap()
+    .estimate(&forward);
+    assert_ne!(first, reseeded);
+
+    assert_eq!(
+        estimate(forward[..5].to_vec()),
+        estimate(forward[..5].to_vec())
+    );
+}
+
+#[test]
+fn confidence_interval_covers_offsets() {
+    let configuration = GamlrConfiguration::default();
+    let z = normal_quantile(0.5 + configuration.confidence_level / 2.0);
+    let covered = (0..RUNS)
+        .filter(|seed| {
+            let (estimate, standard_error) = estimate_offset(&configuration, 250_000.0, *seed);
+            (estimate - 250_000.0).abs() <= z * standard_error
+        })
+        .count();
+    // A 95% interval misses about one run in twenty
+    assert!(covered >= 17, "only {} of {} runs covered", covered, RUNS);
+}
+
+#[test]
+fn confidence_interval_is_centered_on_offset() {
+    let (forward, _) = simulate(0.0, 3);
+    let estimate = GamlrEstimator::new(GamlrConfiguration::default())
+        .unwrap()
+        .estimate(&forward)
+        .unwrap();
+    let (lower, upper) = (estimate.lower_bound.unwrap(), estimate.upper_bound.unwrap());
+    assert!(lower < estimate.offset && estimate.offset < upper);
+    assert!(((upper - estimate.offset) - (estimate.offset - lower)).abs() < 1e-6);
+    assert_eq!(estimate.chunks, PACKETS / 5);
+
+    let narrow = OffsetEstimate::new(0.0, Some(1.0), 0.5, 2);
+    let wide = OffsetEstimate::new(0.0, Some(1.0), 0.99, 2);
+    assert!(narrow.upper_bound.unwrap() < wide.upper_bound.unwrap());
+}
+
+#[test]
+fn incomplete_chunks_are_not_estimated() {
+    let mut estimator = GamlrEstimator::new(GamlrConfiguration::default()).unwrap();
+    assert_eq!(estimator.estimate(&[1.0, 2.0, 3.0, 4.0]), None);
+
+    let single = estimator.estimate(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+    assert_eq!(single.chunks, 1);
+    assert_eq!(single.standard_error, None);
+    assert_eq!(single.lower_bound, None);
+
+    let constant = estimator.estimate(&[7.0; 10]).unwrap();
+    assert_eq!(constant.offset, 7.0);
+}
+
+#[test]
+fn invalid_configurations_are_rejected() {
+    assert!(GamlrConfiguration::default().validate().is_ok());
+    for configuration in [
+        GamlrConfiguration {
+            chunk_size: 1,
+            ..Default::default()
+        },
+        GamlrConfiguration {
+            num_samples: Some(1),
+            ..Default::default()
+        },
+        GamlrConfiguration {
+            confidence_level: 1.0,
+            ..Default::default()
+        },
+    ] {
+        assert!(configuration.validate().is_err(), "{:?}", configuration);
+    }
+}
+
+#[test]
+fn estimators_reject_empty_chunks() {
+    let configuration = GamlrConfiguration {
+        chunk_size: 0,
+        ..Default::default()
+    };
+    assert!(matches!(
+        GamlrEstimator::new(configuration),
+        Err(CommonError::ValidationError(_))
+    ));
+}
+
+#[test]
+fn normal_quantiles_match_tables() {
+    for (p, z) in [
+        (0.5, 0.0),
+        (0.975, 1.959964),
+        (0.995, 2.575829),
+        (0.01, -2.326348),
+    ] {
+        assert!((normal_quantile(p) - z).abs() < 1e-6, "quantile of {}", p);
+        assert!((normal_cdf(z) - p).abs() < 1e-6, "cdf of {}", z);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use network_commons::{
+    pcap::{
+        ip_udp_packet, read_udp_datagrams, CaptureConfiguration, CaptureFormat, Datagram,
+        Direction, PcapWriter,
+    },
+    time::DateTime,
+};
+
+fn capture_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pcap-{}-{}", std::process::id(), name))
+}
+
+fn datagram<'a>(source: &str, destination: &str, nanos: u64, payload: &'a [u8]) -> Datagram<'a> {
+    Datagram {
+        timestamp: DateTime::from_nanos(nanos),
+        direction: Direction::Outbound,
+        source: source.parse().unwrap(),
+        destination: destination.parse().unwrap(),
+        tos: 0xb8,
+        payload,
+    }
+}
+
+/// Builds a pcapng block around `body`, in little endian.
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let length = (body.len() + 12) as u32;
+    let mut block = Vec::new();
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&length.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&length.to_le_bytes());
+    block
+}
+
+/// Builds a pcapng file of raw IP packets, whose interface has the `if_tsresol` option
+/// `resolution`.
+fn pcapng(resolution: u8, packets: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut section_header = vec![0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0];
+    section_header.extend_from_slice(&(-1i64).to_le_bytes());
+    let mut file = block(0x0a0d_0d0a, &section_header);
+    // Raw IP link, snap length and the if_tsresol option
+    let mut interface = vec![101, 0, 0, 0, 0xff, 0xff, 0, 0];
+    interface.extend_from_slice(&[9, 0, 1, 0, resolution, 0, 0, 0]);
+    interface.extend_from_slice(&[0, 0, 0, 0]);
+    file.extend(block(1, &interface));
+    for (timestamp, packet) in packets {
+        let mut body = 0u32.to_le_bytes().to_vec();
+        body.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(*timestamp as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(packet);
+        body.resize(body.len().next_multiple_of(4), 0);
+        file.extend(block(6, &body));
+    }
+    file
+}
+
+#[test]
+fn written_datagrams_read_back() {
+    let datagrams = [
+        datagram(
+            "192.0.2.1:862",
+            "198.51.100.2:40000",
+            1_700_000_000_123_456_789,
+            b"ipv4",
+        ),
+        datagram(
+            "[2001:db8::1]:862",
+            "[2001:db8::2]:40001",
+            1_700_000_001_000_000_001,
+            b"ipv6",
+        ),
+    ];
+    for format in [CaptureFormat::Pcap, CaptureFormat::Pcapng] {
+        let path = capture_path(&format!("{:?}", format));
+        let configuration = CaptureConfiguration {
+            path: path.clone(),
+            format,
+        };
+        let mut writer = PcapWriter::create(&configuration).unwrap();
+        datagrams
+            .iter()
+            .for_each(|datagram| writer.write(datagram).unwrap());
+        writer.flush().unwrap();
+        drop(writer);
+
+        let read = read_udp_datagrams(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), datagrams.len());
+        for (read, written) in read.iter().zip(&datagrams) {
+            assert_eq!(read.timestamp.to_nanos(), written.timestamp.to_nanos());
+            assert_eq!(read.source, written.source);
+            assert_eq!(read.destination, written.destination);
+            assert_eq!(read.ttl, 64);
+            assert_eq!(read.payload, written.payload);
+        }
+    }
+}
+
+#[test]
+fn truncated_records_end_the_file() {
+    let path = capture_path("truncated");
+    let configuration = CaptureConfiguration {
+        path: path.clone(),
+        format: CaptureFormat::Pcap,
+    };
+    let mut writer = PcapWriter::create(&configuration).unwrap();
+    for payload in [b"first", b"other"] {
+        writer
+            .write(&datagram("192.0.2.1:862", "192.0.2.2:862", 1_000, payload))
+            .unwrap();
+    }
+    writer.flush().unwrap();
+    drop(writer);
+    // The file of a capture still being written ends in the middle of a packet
+    let data = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &data[..data.len() - 3]).unwrap();
+
+    let read = read_udp_datagrams(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].payload, b"first");
+}
+
+#[test]
+fn timestamp_resolutions_are_converted_to_nanoseconds() {
+    let packet = ip_udp_packet(&datagram("192.0.2.1:862", "192.0.2.2:862", 0, b"x")).unwrap();
+    // Microseconds, 2^-10 seconds and 10^-38 seconds
+    for (resolution, timestamp, nanos) in [
+        (6, 1_500_000, 1_500_000_000),
+        (0x8a, 3 * 1024, 3_000_000_000),
+        (38, u64::MAX, 0),
+    ] {
+        let path = capture_path(&format!("resolution-{}", resolution));
+        std::fs::write(&path, pcapng(resolution, &[(timestamp, packet.clone())])).unwrap();
+        let read = read_udp_datagrams(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read[0].timestamp.to_nanos(), nanos);
+    }
+}
+
+#[test]
+fn timestamp_resolutions_beyond_the_range_are_rejected() {
+    let path = capture_path("resolution-39");
+    std::fs::write(&path, pcapng(39, &[])).unwrap();
+    let read = read_udp_datagrams(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(read.is_err());
+}
+
+#[test]
+fn ipv4_headers_shorter_than_their_fixed_fields_are_skipped() {
+    let source: SocketAddr = "192.0.2.1:862".parse().unwrap();
+    let valid = ip_udp_packet(&datagram("192.0.2.1:862", "192.0.2.2:862", 0, b"x")).unwrap();
+    let mut invalid = valid.clone();
+    // A header length of 16 bytes
+    invalid[0] = 0x44;
+    let path = capture_path("short-header");
+    std::fs::write(&path, pcapng(9, &[(1, invalid), (2, valid)])).unwrap();
+    let read = read_udp_datagrams(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.len(), 1);
+    assert_eq!(read[0].source, source);
+}
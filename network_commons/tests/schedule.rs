@@ -0,0 +1,100 @@
+use network_commons::{
+    schedule::{CronExpression, Schedule},
+    time::DateTime,
+};
+
+fn at(sec: u32) -> DateTime {
+    DateTime { sec, nanos: 0 }
+}
+
+fn next(expression: &str, after: u32) -> Option<u32> {
+    let expression: CronExpression = expression.parse().unwrap();
+    expression.next_after(at(after)).map(|next| next.get_sec())
+}
+
+/// 2024-03-01T00:00:00Z, a Friday
+const MARCH_1_2024: u32 = 1709251200;
+const DAY: u32 = 86400;
+
+#[test]
+fn cron_matches_the_next_minute() {
+    // 2024-02-28T23:59:30Z to the leap day
+    assert_eq!(next("*/15 * * * *", 1709164770), Some(1709164800));
+    // Matches are strictly after the given time
+    assert_eq!(
+        next("15 0 * * *", MARCH_1_2024 + 900),
+        Some(MARCH_1_2024 + DAY + 900)
+    );
+    assert_eq!(next("@yearly", MARCH_1_2024), Some(1735689600));
+}
+
+#[test]
+fn cron_matches_days() {
+    // Monday 2024-03-04T09:00:00Z
+    assert_eq!(next("0 9 * * MON", MARCH_1_2024), Some(1709542800));
+    // Sunday is both 0 and 7
+    assert_eq!(
+        next("0 0 * * 7", MARCH_1_2024),
+        Some(MARCH_1_2024 + 2 * DAY)
+    );
+    // Either restricted day field matches
+    assert_eq!(
+        next("0 0 15 * 1", MARCH_1_2024),
+        Some(MARCH_1_2024 + 3 * DAY)
+    );
+    // Next leap day, 2028-02-29T00:00:00Z
+    assert_eq!(next("0 0 29 FEB *", MARCH_1_2024), Some(1835395200));
+    assert_eq!(next("0 0 30 2 *", MARCH_1_2024), None);
+}
+
+#[test]
+fn cron_rejects_invalid_expressions() {
+    for expression in [
+        "60 * * * *",
+        "* * * *",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "* * * JUN-X *",
+    ] {
+        assert!(
+            expression.parse::<CronExpression>().is_err(),
+            "{}",
+            expression
+        );
+    }
+}
+
+#[test]
+fn periods_skip_late_runs() {
+    let schedule = Schedule::Every(60);
+    assert_eq!(
+        schedule.first(at(1000)).map(|first| first.get_sec()),
+        Some(1000)
+    );
+    assert_eq!(
+        schedule.next(at(1000), at(1010)).map(|next| next.get_sec()),
+        Some(1060)
+    );
+    assert_eq!(
+        schedule.next(at(1000), at(1130)).map(|next| next.get_sec()),
+        Some(1180)
+    );
+    // The first run starts right away, and the next ones a whole period later
+    let first = DateTime {
+        sec: 1000,
+        nanos: 900_000_000,
+    };
+    assert_eq!(
+        schedule.next(first, first).map(|next| next.to_nanos()),
+        Some(1_060_900_000_000)
+    );
+}
+
+#[test]
+fn schedules_are_read_from_configuration_files() {
+    let schedule: Schedule = serde_json::from_str(r#"{"cron": "0 9 * * MON"}"#).unwrap();
+    assert_eq!(schedule, Schedule::Cron("0 9 * * MON".parse().unwrap()));
+    let schedule: Schedule = serde_json::from_str(r#"{"every": 300}"#).unwrap();
+    assert_eq!(schedule, Schedule::Every(300));
+    assert!(serde_json::from_str::<Schedule>(r#"{"cron": "0 25 * * *"}"#).is_err());
+}
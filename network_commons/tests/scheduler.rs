@@ -0,0 +1,299 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use network_commons::{
+    error::CommonError,
+    schedule::Schedule,
+    scheduler::{Job, Scheduler},
+    stop_handle::StopHandle,
+    time::DateTime,
+    Strategy, TestResult,
+};
+
+/// Result of a fake run: whether it was stopped before its end.
+#[derive(Debug, Clone, PartialEq)]
+struct FakeResult {
+    stopped: bool,
+}
+
+impl TestResult for FakeResult {}
+
+/// A run of a fake job, from its start to its end.
+#[derive(Debug, Clone)]
+struct Run {
+    job: String,
+    started: Instant,
+    ended: Instant,
+    stopped: bool,
+}
+
+/// Strategy lasting `duration` unless it is stopped first, reporting its start to `started`
+/// and its run to `runs`.
+struct FakeStrategy {
+    job: String,
+    duration: Duration,
+    started: mpsc::Sender<String>,
+    runs: Arc<Mutex<Vec<Run>>>,
+    stop_handle: Option<StopHandle>,
+}
+
+impl Strategy<FakeResult, CommonError> for FakeStrategy {
+    fn execute(&mut self) -> Result<FakeResult, CommonError> {
+        let started = Instant::now();
+        let _ = self.started.send(self.job.clone());
+        let stopped = loop {
+            if self
+                .stop_handle
+                .as_ref()
+                .is_some_and(StopHandle::is_stopped)
+            {
+                break true;
+            }
+            if started.elapsed() >= self.duration {
+                break false;
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+        self.runs.lock().unwrap().push(Run {
+            job: self.job.clone(),
+            started,
+            ended: Instant::now(),
+            stopped,
+        });
+        Ok(FakeResult { stopped })
+    }
+
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+}
+
+/// Creates fake jobs and records their runs.
+struct FakeJobs {
+    started: mpsc::Sender<String>,
+    runs: Arc<Mutex<Vec<Run>>>,
+}
+
+impl FakeJobs {
+    fn new() -> (Self, mpsc::Receiver<String>) {
+        let (started, receiver) = mpsc::channel();
+        let jobs = Self {
+            started,
+            runs: Arc::new(Mutex::new(Vec::new())),
+        };
+        (jobs, receiver)
+    }
+
+    fn job(&self, name: &str, schedule: Option<Schedule>, duration: Duration) -> Job<FakeResult> {
+        let job = name.to_string();
+        let started = self.started.clone();
+        let runs = self.runs.clone();
+        Job::new(name, schedule, move || {
+            Ok(Box::new(FakeStrategy {
+                job: job.clone(),
+                duration,
+                started: started.clone(),
+                runs: runs.clone(),
+                stop_handle: None,
+            }) as Box<dyn Strategy<FakeResult, CommonError>>)
+        })
+    }
+
+    fn runs(&self) -> Vec<Run> {
+        self.runs.lock().unwrap().clone()
+    }
+}
+
+/// Runs the scheduler on its own thread until the returned handle is stopped.
+fn start(
+    mut scheduler: Scheduler<FakeResult>,
+) -> (StopHandle, JoinHandle<Result<(), CommonError>>) {
+    let stop_handle = StopHandle::new().unwrap();
+    scheduler.set_stop_handle(stop_handle.clone());
+    (stop_handle, thread::spawn(move || scheduler.run()))
+}
+
+#[test]
+fn runs_due_while_the_previous_run_is_going_are_skipped() {
+    let (jobs, started) = FakeJobs::new();
+    let mut scheduler = Scheduler::new(4).unwrap();
+    scheduler
+        .add_job(jobs.job(
+            "slow",
+            Some(Schedule::Every(1)),
+            Duration::from_millis(2200),
+        ))
+        .unwrap();
+    let (stop_handle, thread) = start(scheduler);
+    // The runs due after 1 and 2 seconds are skipped, the one after 3 seconds starts
+    started.recv_timeout(Duration::from_secs(1)).unwrap();
+    started.recv_timeout(Duration::from_secs(4)).unwrap();
+    stop_handle.stop().unwrap();
+    thread.join().unwrap().unwrap();
+
+    let runs = jobs.runs();
+    assert_eq!(runs.len(), 2);
+    assert!(!runs[0].stopped);
+    assert!(runs[1].stopped);
+    // A run that waited for the previous one would start as soon as it ended
+    let gap = runs[1].started - runs[0].ended;
+    assert!(gap >= Duration::from_millis(400), "{:?}", gap);
+}
+
+#[test]
+fn runs_due_while_no_slot_is_free_wait_for_one() {
+    let (jobs, started) = FakeJobs::new();
+    let mut scheduler = Scheduler::new(1).unwrap();
+    for name in ["first", "second"] {
+        scheduler
+            .add_job(jobs.job(name, Some(Schedule::Every(60)), Duration::from_millis(300)))
+            .unwrap();
+    }
+    let (stop_handle, thread) = start(scheduler);
+    started.recv_timeout(Duration::from_secs(1)).unwrap();
+    started.recv_timeout(Duration::from_secs(1)).unwrap();
+    stop_handle.stop().unwrap();
+    thread.join().unwrap().unwrap();
+
+    let runs = jobs.runs();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0].job, "first");
+    assert!(!runs[0].stopped);
+    // The second job starts once the first one leaves the slot
+    assert_eq!(runs[1].job, "second");
+    assert!(runs[1].started >= runs[0].ended);
+}
+
+#[test]
+fn jitter_delays_runs_up_to_its_bound() {
+    let (jobs, _started) = FakeJobs::new();
+    let mut scheduler = Scheduler::new(1).unwrap();
+    let before = DateTime::utc_now().to_nanos();
+    for i in 0..50 {
+        let job = jobs.job(&i.to_string(), Some(Schedule::Every(60)), Duration::ZERO);
+        scheduler
+            .add_job(job.with_jitter(Duration::from_secs(10)))
+            .unwrap();
+    }
+    scheduler
+        .add_job(jobs.job("punctual", Some(Schedule::Every(60)), Duration::ZERO))
+        .unwrap();
+    let after = DateTime::utc_now().to_nanos();
+
+    let next_runs: Vec<u64> = scheduler
+        .handle()
+        .jobs()
+        .unwrap()
+        .iter()
+        .map(|job| job.next_run.unwrap().to_nanos())
+        .collect();
+    let (jittered, punctual) = next_runs.split_at(50);
+    for next_run in jittered {
+        assert!(*next_run >= before && *next_run <= after + 10_000_000_000);
+    }
+    // The delays are random, and spread over the bound
+    let spread = jittered.iter().max().unwrap() - jittered.iter().min().unwrap();
+    assert!(spread > 5_000_000_000, "{}", spread);
+    assert!(punctual[0] >= before && punctual[0] <= after);
+}
+
+#[test]
+fn stopping_the_scheduler_stops_the_runs_that_are_going() {
+    let (jobs, started) = FakeJobs::new();
+    let mut scheduler = Scheduler::new(4).unwrap();
+    for name in ["first", "second"] {
+        scheduler
+            .add_job(jobs.job(name, Some(Schedule::Every(60)), Duration::from_secs(60)))
+            .unwrap();
+    }
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let handler_results = results.clone();
+    scheduler.set_result_handler(move |name, result: &Result<FakeResult, CommonError>| {
+        handler_results
+            .lock()
+            .unwrap()
+            .push((name.to_string(), result.as_ref().unwrap().clone()));
+    });
+    let handle = scheduler.handle();
+    let (stop_handle, thread) = start(scheduler);
+    started.recv_timeout(Duration::from_secs(1)).unwrap();
+    started.recv_timeout(Duration::from_secs(1)).unwrap();
+
+    // A single run is stopped through the handle
+    assert!(handle.stop_run("first").unwrap());
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while handle
+        .job("first")
+        .unwrap()
+        .unwrap()
+        .running_since
+        .is_some()
+    {
+        assert!(
+            Instant::now() < deadline,
+            "the run of first was not stopped"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(handle
+        .job("second")
+        .unwrap()
+        .unwrap()
+        .running_since
+        .is_some());
+
+    // The scheduler waits for the runs it stops, which deliver their results
+    stop_handle.stop().unwrap();
+    thread.join().unwrap().unwrap();
+    let mut results = results.lock().unwrap().clone();
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        results,
+        [
+            ("first".to_string(), FakeResult { stopped: true }),
+            ("second".to_string(), FakeResult { stopped: true }),
+        ]
+    );
+    assert!(jobs.runs().iter().all(|run| run.stopped));
+    let status = handle.job("first").unwrap().unwrap();
+    assert_eq!(status.runs, 1);
+    assert_eq!(status.last_status.as_deref(), Some("passed"));
+}
+
+#[test]
+fn jobs_without_a_schedule_dont_take_a_slot() {
+    let (jobs, started) = FakeJobs::new();
+    let mut scheduler = Scheduler::new(1).unwrap();
+    scheduler
+        .add_job(jobs.job("reflector", None, Duration::from_secs(60)))
+        .unwrap();
+    scheduler
+        .add_job(jobs.job(
+            "test",
+            Some(Schedule::Every(60)),
+            Duration::from_millis(100),
+        ))
+        .unwrap();
+    let handle = scheduler.handle();
+    let (stop_handle, thread) = start(scheduler);
+    started.recv_timeout(Duration::from_secs(1)).unwrap();
+    // The scheduled job runs next to the job that runs until the scheduler stops
+    started.recv_timeout(Duration::from_secs(1)).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while handle.job("test").unwrap().unwrap().runs == 0 {
+        assert!(Instant::now() < deadline, "the scheduled run didn't end");
+        thread::sleep(Duration::from_millis(10));
+    }
+    stop_handle.stop().unwrap();
+    thread.join().unwrap().unwrap();
+
+    let runs = jobs.runs();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0].job, "test");
+    assert!(!runs[0].stopped);
+    assert_eq!(runs[1].job, "reflector");
+    assert!(runs[1].stopped);
+}
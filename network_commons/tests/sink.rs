@@ -0,0 +1,169 @@
+use std::sync::{mpsc, Arc, Mutex};
+
+use network_commons::{
+    error::CommonError,
+    sink::{ChannelSink, JsonLinesSink, ResultSink, SinkEvent, Sinks},
+};
+
+type TestSinks = Sinks<String, u32, u8>;
+
+/// Records the events it receives, failing all of them when `failing` is set.
+#[derive(Debug, Default)]
+struct RecordingSink {
+    intervals: bool,
+    packets: bool,
+    failing: bool,
+    events: Vec<String>,
+}
+
+impl RecordingSink {
+    fn shared(intervals: bool, packets: bool, failing: bool) -> Arc<Mutex<RecordingSink>> {
+        Arc::new(Mutex::new(RecordingSink {
+            intervals,
+            packets,
+            failing,
+            events: Vec::new(),
+        }))
+    }
+
+    fn record(&mut self, event: String) -> Result<(), CommonError> {
+        if self.failing {
+            return Err(CommonError::Generic(format!("failed {}", event)));
+        }
+        self.events.push(event);
+        Ok(())
+    }
+}
+
+impl ResultSink<String, u32, u8> for RecordingSink {
+    fn on_start(&mut self) -> Result<(), CommonError> {
+        self.record("start".to_string())
+    }
+
+    fn wants_intervals(&self) -> bool {
+        self.intervals
+    }
+
+    fn on_interval(&mut self, interval: &u32) -> Result<(), CommonError> {
+        self.record(format!("interval {}", interval))
+    }
+
+    fn wants_packets(&self) -> bool {
+        self.packets
+    }
+
+    fn on_packet(&mut self, packet: &u8) -> Result<(), CommonError> {
+        self.record(format!("packet {}", packet))
+    }
+
+    fn on_complete(&mut self, result: &String) -> Result<(), CommonError> {
+        self.record(format!("complete {}", result))
+    }
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("sink-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn sinks_deliver_the_events_each_sink_wants() {
+    let everything = RecordingSink::shared(true, true, false);
+    let results_only = RecordingSink::shared(false, false, false);
+    let mut sinks = TestSinks::new();
+    assert!(!sinks.wants_intervals());
+    sinks.add(results_only.clone());
+    assert!(!sinks.wants_intervals());
+    assert!(!sinks.wants_packets());
+    sinks.add(everything.clone());
+    assert!(sinks.wants_intervals());
+    assert!(sinks.wants_packets());
+
+    sinks.start().unwrap();
+    sinks.interval(&1);
+    sinks.packets(&[7, 8]);
+    sinks.complete(&"done".to_string());
+
+    assert_eq!(
+        everything.lock().unwrap().events,
+        [
+            "start",
+            "interval 1",
+            "packet 7",
+            "packet 8",
+            "complete done"
+        ]
+    );
+    assert_eq!(
+        results_only.lock().unwrap().events,
+        ["start", "complete done"]
+    );
+}
+
+#[test]
+fn sinks_only_fail_to_start() {
+    let failing = RecordingSink::shared(true, true, true);
+    let working = RecordingSink::shared(true, true, false);
+    let mut sinks = TestSinks::new();
+    sinks.add(failing);
+    sinks.add(working.clone());
+
+    assert!(sinks.start().is_err());
+    // A failing sink doesn't keep the others from the events
+    sinks.interval(&1);
+    sinks.complete(&"done".to_string());
+    assert_eq!(
+        working.lock().unwrap().events,
+        ["interval 1", "complete done"]
+    );
+}
+
+#[test]
+fn json_lines_sink_writes_one_event_per_line() {
+    let path = temp_path("json-lines");
+    std::fs::write(&path, "previous test\n").unwrap();
+    let mut sink = JsonLinesSink::create(&path, false).unwrap();
+    ResultSink::<String, u32, u8>::on_start(&mut sink).unwrap();
+    ResultSink::<String, u32, u8>::on_interval(&mut sink, &3).unwrap();
+    assert!(!ResultSink::<String, u32, u8>::wants_packets(&sink));
+    ResultSink::<String, u32, u8>::on_complete(&mut sink, &"done".to_string()).unwrap();
+    drop(sink);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "{\"event\":\"start\"}\n\
+         {\"event\":\"interval\",\"data\":3}\n\
+         {\"event\":\"complete\",\"data\":\"done\"}\n"
+    );
+
+    let mut sink = JsonLinesSink::append(&path, true).unwrap();
+    assert!(ResultSink::<String, u32, u8>::wants_packets(&sink));
+    ResultSink::<String, u32, u8>::on_packet(&mut sink, &9).unwrap();
+    ResultSink::<String, u32, u8>::flush(&mut sink).unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(written.ends_with("\"done\"}\n{\"event\":\"packet\",\"data\":9}\n"));
+}
+
+#[test]
+fn channel_sink_sends_every_event() {
+    let (sender, receiver) = mpsc::channel();
+    let sink = Arc::new(Mutex::new(ChannelSink::new(sender, true)));
+    let mut sinks = TestSinks::new();
+    sinks.add(sink.clone());
+    sinks.start().unwrap();
+    sinks.interval(&2);
+    sinks.packets(&[5]);
+    sinks.complete(&"done".to_string());
+    assert_eq!(
+        receiver.try_iter().collect::<Vec<_>>(),
+        [
+            SinkEvent::Start,
+            SinkEvent::Interval(2),
+            SinkEvent::Packet(5),
+            SinkEvent::Complete("done".to_string()),
+        ]
+    );
+
+    // A receiver that went away doesn't fail the test
+    drop(receiver);
+    assert!(sink.lock().unwrap().on_interval(&3).is_ok());
+}
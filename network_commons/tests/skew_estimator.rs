@@ -0,0 +1,156 @@
+use network_commons::stats::{
+    offset_estimator::LcgRng,
+    skew_estimator::{estimate_linear_programming, estimate_paxson, SkewEstimate, SkewEstimator},
+};
+
+/// Minimum delay of the simulated path in nanoseconds
+const PROPAGATION_DELAY: f64 = 1_000_000.0;
+/// Mean of the exponentially distributed queuing delay in nanoseconds
+const QUEUING_SCALE: f64 = 100_000.0;
+/// Interval between the simulated packets in nanoseconds
+const PACKET_INTERVAL: f64 = 10_000_000.0;
+const PACKETS: usize = 1000;
+/// Largest error allowed on a skew, in nanoseconds per nanosecond
+const TOLERANCE: f64 = 2e-6;
+
+/// Simulates the `(time, delay)` pairs measured by a clock drifting `skew` nanoseconds per
+/// nanosecond, with positive queuing jitter on top of the propagation delay.
+fn simulate(skew: f64, seed: u64) -> Vec<(f64, f64)> {
+    let mut rng = LcgRng::new(seed);
+    (0..PACKETS)
+        .map(|index| {
+            let time = index as f64 * PACKET_INTERVAL;
+            let queuing = -(1.0 - rng.gen_range(0.0..1.0)).ln() * QUEUING_SCALE;
+            (time, PROPAGATION_DELAY + skew * time + queuing)
+        })
+        .collect()
+}
+
+fn assert_recovers_skew(estimator: SkewEstimator) {
+    for skew in [-50e-6, -5e-6, 0.0, 5e-6, 50e-6] {
+        for seed in 0..10 {
+            let estimate = estimator.estimate(&simulate(skew, seed)).unwrap();
+            assert!(
+                (estimate.skew - skew).abs() < TOLERANCE,
+                "skew {} estimated as {} with seed {} and {:?}",
+                skew,
+                estimate.skew,
+                seed,
+                estimator
+            );
+            // The line starts at the floor of the delays
+            assert!(
+                (estimate.intercept - PROPAGATION_DELAY).abs() < 0.1 * QUEUING_SCALE,
+                "intercept {} with seed {} and {:?}",
+                estimate.intercept,
+                seed,
+                estimator
+            );
+        }
+    }
+}
+
+#[test]
+fn linear_programming_recovers_the_skew() {
+    assert_recovers_skew(SkewEstimator::LinearProgramming);
+}
+
+#[test]
+fn paxson_recovers_the_skew() {
+    assert_recovers_skew(SkewEstimator::Paxson);
+}
+
+#[test]
+fn estimators_dispatch_to_their_method() {
+    let points = simulate(20e-6, 3);
+    assert_eq!(
+        SkewEstimator::LinearProgramming.estimate(&points),
+        estimate_linear_programming(&points)
+    );
+    assert_eq!(
+        SkewEstimator::Paxson.estimate(&points),
+        estimate_paxson(&points)
+    );
+}
+
+#[test]
+fn linear_programming_line_lies_under_every_delay() {
+    let points = simulate(-30e-6, 5);
+    let estimate = estimate_linear_programming(&points).unwrap();
+    for (time, delay) in &points {
+        let line = estimate.intercept + estimate.skew * time;
+        assert!(
+            *delay >= line - 1e-6 * line.abs(),
+            "delay {} at {} under the line at {}",
+            delay,
+            time,
+            line
+        );
+    }
+}
+
+#[test]
+fn linear_programming_ignores_the_order_and_duplicate_times_of_the_points() {
+    let mut points = simulate(10e-6, 9);
+    let expected = estimate_linear_programming(&points).unwrap();
+    // A delay larger than another one of the same instant cannot be on the lower hull
+    points.push((points[500].0, points[500].1 + QUEUING_SCALE));
+    points.reverse();
+    let estimate = estimate_linear_programming(&points).unwrap();
+    assert!((estimate.skew - expected.skew).abs() < 1e-12);
+    assert!((estimate.intercept - expected.intercept).abs() < 1e-3);
+}
+
+#[test]
+fn detrended_delays_have_no_skew_left() {
+    let skew = 50e-6;
+    let points = simulate(skew, 1);
+    for estimator in [SkewEstimator::LinearProgramming, SkewEstimator::Paxson] {
+        let estimate = estimator.estimate(&points).unwrap();
+        let detrended = estimate.detrend(&points);
+        assert_eq!(detrended.len(), points.len());
+        assert_eq!(detrended[0], points[0].1);
+
+        let detrended_points: Vec<(f64, f64)> = points
+            .iter()
+            .zip(&detrended)
+            .map(|((time, _), delay)| (*time, *delay))
+            .collect();
+        let residual = estimator.estimate(&detrended_points).unwrap();
+        assert!(
+            residual.skew.abs() < TOLERANCE,
+            "skew {} left by {:?}",
+            residual.skew,
+            estimator
+        );
+        // The drift of the last packet is gone
+        let last = detrended.len() - 1;
+        let drift = skew * points[last].0;
+        assert!(
+            (points[last].1 - detrended[last] - drift).abs() < TOLERANCE * points[last].0,
+            "{} removed from the last delay instead of {}",
+            points[last].1 - detrended[last],
+            drift
+        );
+    }
+}
+
+#[test]
+fn detrend_keeps_the_delays_of_a_null_skew() {
+    let points = simulate(0.0, 2);
+    let estimate = SkewEstimate {
+        skew: 0.0,
+        intercept: PROPAGATION_DELAY,
+    };
+    let delays: Vec<f64> = points.iter().map(|(_, delay)| *delay).collect();
+    assert_eq!(estimate.detrend(&points), delays);
+    assert!(estimate.detrend(&[]).is_empty());
+}
+
+#[test]
+fn series_without_a_time_span_have_no_skew() {
+    assert_eq!(estimate_linear_programming(&[]), None);
+    assert_eq!(estimate_linear_programming(&[(0.0, 1.0), (0.0, 2.0)]), None);
+    assert_eq!(estimate_paxson(&[]), None);
+    assert_eq!(estimate_paxson(&[(0.0, 1.0)]), None);
+}
@@ -0,0 +1,109 @@
+use network_commons::{
+    assert_approx_eq,
+    stats::streaming::{Ewma, Histogram, P2Quantile, RunningStats, StreamingSummary},
+};
+
+/// Observations of the example of Jain and Chlamtac. The paper reports a median of 4.44 as it
+/// rounds the markers to two decimals at every step; unrounded markers give 4.2462.
+const P2_EXAMPLE: [f64; 20] = [
+    0.02, 0.5, 0.74, 3.39, 0.83, 22.37, 10.15, 15.43, 38.62, 15.92, 34.60, 10.28, 1.47, 0.40, 0.05,
+    11.39, 0.27, 0.42, 0.09, 11.37,
+];
+
+#[test]
+fn running_stats_match_the_exact_moments() {
+    let mut stats = RunningStats::new();
+    assert_eq!(stats.mean(), None);
+    [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+        .into_iter()
+        .for_each(|value| stats.push(value));
+    assert_eq!(stats.count(), 8);
+    assert_approx_eq!(stats.mean().unwrap(), 5.0, 1e-12);
+    assert_approx_eq!(stats.variance().unwrap(), 4.0, 1e-12);
+    assert_approx_eq!(stats.std_dev().unwrap(), 2.0, 1e-12);
+    assert_eq!(stats.min(), Some(2.0));
+    assert_eq!(stats.max(), Some(9.0));
+
+    let mut stats = RunningStats::new();
+    P2_EXAMPLE.into_iter().for_each(|value| stats.push(value));
+    assert_approx_eq!(stats.mean().unwrap(), 8.9155, 1e-9);
+    assert_approx_eq!(stats.variance().unwrap(), 128.70797475, 1e-6);
+}
+
+#[test]
+fn p2_quantile_is_exact_below_five_samples() {
+    let mut median = P2Quantile::new(0.5);
+    assert_eq!(median.quantile(), None);
+    [3.0, 1.0, 2.0]
+        .into_iter()
+        .for_each(|value| median.push(value));
+    assert_eq!(median.quantile(), Some(2.0));
+
+    let mut high = P2Quantile::new(0.75);
+    [4.0, 1.0, 3.0, 2.0]
+        .into_iter()
+        .for_each(|value| high.push(value));
+    // Nearest rank of 0.75 over 4 samples
+    assert_eq!(high.quantile(), Some(3.0));
+}
+
+#[test]
+fn p2_quantile_follows_the_published_example() {
+    let mut median = P2Quantile::new(0.5);
+    P2_EXAMPLE.into_iter().for_each(|value| median.push(value));
+    assert_eq!(median.count(), 20);
+    assert_approx_eq!(median.quantile().unwrap(), 4.2462, 1e-4);
+}
+
+#[test]
+fn p2_quantile_converges_on_uniform_values() {
+    let mut quantiles = [0.25, 0.5, 0.99].map(P2Quantile::new);
+    // Visits 0..10000 in a scrambled order
+    for i in 0..10_000u64 {
+        let value = ((i * 7919) % 10_000) as f64;
+        quantiles.iter_mut().for_each(|q| q.push(value));
+    }
+    assert_approx_eq!(quantiles[0].quantile().unwrap(), 2500.0, 50.0);
+    assert_approx_eq!(quantiles[1].quantile().unwrap(), 5000.0, 50.0);
+    assert_approx_eq!(quantiles[2].quantile().unwrap(), 9900.0, 50.0);
+}
+
+#[test]
+fn ewma_weights_every_new_sample() {
+    let mut ewma = Ewma::new(0.5);
+    assert_eq!(ewma.value(), None);
+    ewma.push(8.0);
+    assert_eq!(ewma.value(), Some(8.0));
+    ewma.push(4.0);
+    assert_eq!(ewma.value(), Some(6.0));
+    ewma.push(0.0);
+    assert_eq!(ewma.value(), Some(3.0));
+}
+
+#[test]
+fn summary_combines_its_estimators() {
+    let mut summary = StreamingSummary::new(&[0.5], 1.0);
+    [1.0, 2.0, 3.0]
+        .into_iter()
+        .for_each(|value| summary.push(value));
+    assert_eq!(summary.count(), 3);
+    assert_eq!(summary.mean(), Some(2.0));
+    assert_eq!(summary.median(), Some(2.0));
+    assert_eq!(summary.quantile(0.75), None);
+    // An alpha of 1 keeps the last sample
+    assert_eq!(summary.ewma(), Some(3.0));
+}
+
+#[test]
+fn histogram_counts_values_below_every_bound() {
+    let mut histogram = Histogram::new(&[10.0, 1.0]);
+    [0.5, 1.0, 5.0, 20.0]
+        .into_iter()
+        .for_each(|value| histogram.push(value));
+    assert_eq!(histogram.count(), 4);
+    assert_eq!(histogram.sum(), 26.5);
+    assert_eq!(
+        histogram.cumulative_counts(),
+        vec![(1.0, 2), (10.0, 3), (f64::INFINITY, 4)]
+    );
+}
@@ -0,0 +1,77 @@
+use network_commons::{
+    assert_approx_eq,
+    stats::voice_quality::{burst_ratio, mos, r_factor, BurstCounter, Codec, PathImpairments},
+};
+
+#[test]
+fn default_rating_gives_the_g107_mos() {
+    // G.107 gives a MOS of about 4.41 for its default rating of 93.2
+    assert_approx_eq!(mos(93.2), 4.4093, 1e-4);
+    assert_eq!(mos(0.0), 1.0);
+    assert_eq!(mos(-5.0), 1.0);
+    assert_eq!(mos(100.0), 4.5);
+    assert_eq!(mos(120.0), 4.5);
+}
+
+#[test]
+fn codecs_are_impaired_by_their_delay_and_equipment() {
+    let path = PathImpairments::default();
+    // Only the 20 ms of packetization delay impair G.711
+    assert_approx_eq!(r_factor(Codec::G711, &path), 92.72, 1e-9);
+    // 25 ms of delay and an Ie of 11 for G.729
+    assert_approx_eq!(r_factor(Codec::G729, &path), 81.6, 1e-9);
+    // Opus is rated as G.711, with its own delay
+    assert_approx_eq!(r_factor(Codec::Opus, &path), 93.2 - 0.024 * 26.5, 1e-9);
+}
+
+#[test]
+fn delay_impairment_grows_above_the_knee() {
+    let path = PathImpairments {
+        one_way_delay: 190.0,
+        jitter: 5.0,
+        ..Default::default()
+    };
+    // 220 ms mouth to ear: 0.024 * 220 + 0.11 * (220 - 177.3)
+    assert_approx_eq!(r_factor(Codec::G711, &path), 93.2 - 5.28 - 4.697, 1e-9);
+}
+
+#[test]
+fn losses_impair_according_to_their_burstiness() {
+    let random = PathImpairments {
+        loss_ratio: 0.01,
+        burst_ratio: 1.0,
+        ..Default::default()
+    };
+    // Ie-eff = 95 * 1 / (1 + 25.1)
+    assert_approx_eq!(r_factor(Codec::G711, &random), 92.72 - 95.0 / 26.1, 1e-9);
+
+    let bursty = PathImpairments {
+        burst_ratio: 2.0,
+        ..random
+    };
+    assert_approx_eq!(r_factor(Codec::G711, &bursty), 92.72 - 95.0 / 25.6, 1e-9);
+
+    let lost = PathImpairments {
+        loss_ratio: 1.0,
+        ..random
+    };
+    assert_approx_eq!(r_factor(Codec::G711, &lost), 92.72 - 9500.0 / 125.1, 1e-9);
+}
+
+#[test]
+fn burst_ratio_follows_the_loss_transitions() {
+    assert_eq!(burst_ratio([false, false, false]), 1.0);
+    assert_eq!(burst_ratio([true, true]), 1.0);
+    // Alternating losses are less bursty than random ones, which is clamped to 1
+    assert_eq!(burst_ratio([false, true, false, true, false, true]), 1.0);
+
+    // p = 1/5 from received to lost, q = 1/4 from lost to received
+    let pattern = [
+        false, false, false, false, true, true, true, true, false, false,
+    ];
+    assert_approx_eq!(burst_ratio(pattern), 1.0 / 0.45, 1e-12);
+
+    let mut counter = BurstCounter::default();
+    pattern.into_iter().for_each(|lost| counter.push(lost));
+    assert_eq!(counter.burst_ratio(), burst_ratio(pattern));
+}
@@ -0,0 +1,307 @@
+use std::{net::SocketAddr, path::PathBuf, str::FromStr};
+
+use log::LevelFilter;
+use serde_json::{json, Value};
+
+use crate::output::OutputFormat;
+
+pub const USAGE: &str = "\
+Usage: network-tests twamp <COMMAND> [OPTIONS]
+
+Commands:
+  send                       Runs a TWAMP Light sender (LIGHT_SENDER)
+  reflect                    Runs a TWAMP Light reflector (LIGHT_REFLECTOR)
+  server                     Runs a TWAMP server (FULL_REFLECTOR)
+  client                     Runs a TWAMP control client and sender (FULL_SENDER)
+  pmtu                       Finds the path MTU towards the targets (LIGHT_PMTU)
+  replay <CAPTURE>           Calculates the results of the sessions of a pcap or pcapng file
+  schedule                   Runs the jobs of the configuration file on their schedules until
+                             stopped. Options apply to the configuration of every job, and
+                             results go to the sinks of the jobs
+
+Options:
+  -c, --config <FILE>        Configuration file, read as YAML for .yaml and .yml files
+                             and as JSON otherwise. The command sets its mode
+  -t, --target <ADDR>        Adds a host to test_session_hosts
+  -s, --source <ADDR>        Sets source_ip_address
+      --control <ADDR>       Sets control_host
+  -d, --duration <SECONDS>   Sets collection_period
+  -i, --interval <MILLIS>    Sets packet_interval
+      --probe-timeout <MILLIS>
+                             Sets probe_timeout of 'pmtu'
+      --padding <BYTES>      Sets padding
+      --timeout <SECONDS>    Sets last_message_timeout
+      --ref-wait <SECONDS>   Sets ref_wait
+      --report-interval <SECONDS>
+                             Sets report_interval
+      --continuous           Sets continuous
+      --sla <METRIC=MAX>     Adds an SLA threshold, such as P95_RTT=20
+      --metrics <ADDR>       Sets metrics_address
+      --capture <FILE>       Captures the test packets, in pcap format for .pcap files
+                             and in pcapng format otherwise
+      --set <FIELD=VALUE>    Sets any field of the configuration, with a JSON value or a
+                             string
+  -o, --output <FORMAT>      Format of the reports and results: json (default), yaml or
+                             text
+      --log-level <LEVEL>    off, error, warn, info (default), debug or trace
+      --api <SOCKET>         Serves the management API of 'schedule' on a Unix domain
+                             socket
+  -h, --help                 Prints this help
+  -V, --version              Prints the version
+
+Options are applied in order on top of the configuration file. On SIGHUP, 'reflect',
+'server' and 'schedule' read the configuration file again and apply it without dropping
+their sessions.
+
+Exit status:
+  0  The test passed
+  1  The test failed to run
+  2  The command line or the configuration is invalid
+  3  A session violated its SLA thresholds
+  4  A session regressed from a baseline set to fail_on_regression";
+
+/// Action run by the command line.
+#[derive(Debug)]
+pub enum Command {
+    Help,
+    Version,
+    Twamp(TwampCommand),
+}
+
+/// TWAMP command, with the changes made by the options to the configuration.
+#[derive(Debug)]
+pub struct TwampCommand {
+    pub action: TwampAction,
+    pub config: Option<PathBuf>,
+    /// Capture file of the `replay` action
+    pub capture: Option<PathBuf>,
+    /// Socket of the management API of the `schedule` action
+    pub api: Option<PathBuf>,
+    pub overrides: Vec<Override>,
+    pub output: OutputFormat,
+    pub log_level: LevelFilter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TwampAction {
+    Send,
+    Reflect,
+    Server,
+    Client,
+    Pmtu,
+    Replay,
+    Schedule,
+}
+
+impl TwampAction {
+    /// Mode of the configuration of the action. Captures are analyzed with the settings of
+    /// a sender, and scheduled jobs keep the modes of their configurations.
+    pub fn mode(&self) -> Option<&'static str> {
+        match self {
+            TwampAction::Send | TwampAction::Replay => Some("LIGHT_SENDER"),
+            TwampAction::Reflect => Some("LIGHT_REFLECTOR"),
+            TwampAction::Server => Some("FULL_REFLECTOR"),
+            TwampAction::Client => Some("FULL_SENDER"),
+            TwampAction::Pmtu => Some("LIGHT_PMTU"),
+            TwampAction::Schedule => None,
+        }
+    }
+
+    /// Whether the action reloads its configuration file on SIGHUP.
+    pub fn reloads(&self) -> bool {
+        matches!(
+            self,
+            TwampAction::Reflect | TwampAction::Server | TwampAction::Schedule
+        )
+    }
+}
+
+/// Change made by an option to a field of the configuration.
+#[derive(Debug, PartialEq)]
+pub enum Override {
+    /// Replaces the value of the field
+    Set(String, Value),
+    /// Adds a value to the list of the field
+    Append(String, Value),
+}
+
+impl Override {
+    /// Applies the change to a configuration, as a JSON object.
+    pub fn apply(&self, configuration: &mut serde_json::Map<String, Value>) {
+        match self {
+            Override::Set(field, value) => {
+                configuration.insert(field.clone(), value.clone());
+            }
+            Override::Append(field, value) => {
+                let list = configuration.entry(field.clone()).or_insert(Value::Null);
+                match list {
+                    Value::Array(values) => values.push(value.clone()),
+                    _ => *list = Value::Array(vec![value.clone()]),
+                }
+            }
+        }
+    }
+}
+
+/// Parses the arguments, without the program name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        None | Some("-h" | "--help" | "help") => return Ok(Command::Help),
+        Some("-V" | "--version") => return Ok(Command::Version),
+        Some("twamp") => {}
+        Some(test) => return Err(format!("unknown test '{}'", test)),
+    }
+    let action = match args.next().as_deref() {
+        None | Some("-h" | "--help" | "help") => return Ok(Command::Help),
+        Some("send") => TwampAction::Send,
+        Some("reflect") => TwampAction::Reflect,
+        Some("server") => TwampAction::Server,
+        Some("client") => TwampAction::Client,
+        Some("pmtu") => TwampAction::Pmtu,
+        Some("replay") => TwampAction::Replay,
+        Some("schedule") => TwampAction::Schedule,
+        Some(action) => return Err(format!("unknown twamp command '{}'", action)),
+    };
+    let mut command = TwampCommand {
+        action,
+        config: None,
+        capture: None,
+        api: None,
+        overrides: Vec::new(),
+        output: OutputFormat::Json,
+        log_level: LevelFilter::Info,
+    };
+
+    while let Some(arg) = args.next() {
+        // Values are given either as the next argument or after an equal sign
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => {
+                (option.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for '{}'", option))
+        };
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-c" | "--config" => command.config = Some(PathBuf::from(value()?)),
+            "-t" | "--target" => command.overrides.push(Override::Append(
+                "test_session_hosts".to_string(),
+                json!(parse_value::<SocketAddr>(&option, &value()?)?.to_string()),
+            )),
+            "-s" | "--source" => command.overrides.push(Override::Set(
+                "source_ip_address".to_string(),
+                json!(parse_value::<SocketAddr>(&option, &value()?)?.to_string()),
+            )),
+            "--control" => command.overrides.push(Override::Set(
+                "control_host".to_string(),
+                json!(parse_value::<SocketAddr>(&option, &value()?)?.to_string()),
+            )),
+            "-d" | "--duration" => command.overrides.push(Override::Set(
+                "collection_period".to_string(),
+                json!(parse_value::<u64>(&option, &value()?)?),
+            )),
+            "-i" | "--interval" if action == TwampAction::Pmtu => {
+                return Err(format!(
+                    "'{}' doesn't apply to 'pmtu', use '--probe-timeout'",
+                    option
+                ))
+            }
+            "-i" | "--interval" => command.overrides.push(Override::Set(
+                "packet_interval".to_string(),
+                json!(parse_value::<u64>(&option, &value()?)?),
+            )),
+            "--probe-timeout" if action == TwampAction::Pmtu => {
+                command.overrides.push(Override::Set(
+                    "probe_timeout".to_string(),
+                    json!(parse_value::<u64>(&option, &value()?)?),
+                ))
+            }
+            "--padding" => command.overrides.push(Override::Set(
+                "padding".to_string(),
+                json!(parse_value::<usize>(&option, &value()?)?),
+            )),
+            "--timeout" => command.overrides.push(Override::Set(
+                "last_message_timeout".to_string(),
+                json!(parse_value::<u64>(&option, &value()?)?),
+            )),
+            "--ref-wait" => command.overrides.push(Override::Set(
+                "ref_wait".to_string(),
+                json!(parse_value::<u64>(&option, &value()?)?),
+            )),
+            "--report-interval" => command.overrides.push(Override::Set(
+                "report_interval".to_string(),
+                json!(parse_value::<u64>(&option, &value()?)?),
+            )),
+            "--continuous" => command
+                .overrides
+                .push(Override::Set("continuous".to_string(), json!(true))),
+            "--sla" => {
+                let threshold = value()?;
+                let (metric, max) = threshold
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid value '{}' for '--sla'", threshold))?;
+                command.overrides.push(Override::Append(
+                    "sla".to_string(),
+                    json!({ "metric": metric, "max": parse_value::<f64>(&option, max)? }),
+                ));
+            }
+            "--metrics" => command.overrides.push(Override::Set(
+                "metrics_address".to_string(),
+                json!(parse_value::<SocketAddr>(&option, &value()?)?.to_string()),
+            )),
+            "--capture" => {
+                let path = PathBuf::from(value()?);
+                let format = match path.extension().and_then(|extension| extension.to_str()) {
+                    Some("pcap") => "PCAP",
+                    _ => "PCAPNG",
+                };
+                command.overrides.push(Override::Set(
+                    "capture".to_string(),
+                    json!({ "path": path, "format": format }),
+                ));
+            }
+            "--set" => {
+                let assignment = value()?;
+                let (field, field_value) = assignment
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid value '{}' for '--set'", assignment))?;
+                let field_value = serde_json::from_str(field_value)
+                    .unwrap_or_else(|_| Value::String(field_value.to_string()));
+                command
+                    .overrides
+                    .push(Override::Set(field.to_string(), field_value));
+            }
+            "-o" | "--output" => command.output = parse_value(&option, &value()?)?,
+            "--log-level" => command.log_level = parse_value(&option, &value()?)?,
+            "--api" if action == TwampAction::Schedule => {
+                command.api = Some(PathBuf::from(value()?))
+            }
+            _ if option.starts_with('-') => return Err(format!("unknown option '{}'", option)),
+            _ if action == TwampAction::Replay && command.capture.is_none() => {
+                command.capture = Some(PathBuf::from(arg))
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    if action == TwampAction::Replay && command.capture.is_none() {
+        return Err("missing capture file for 'replay'".to_string());
+    }
+    // Jobs are either read from the configuration file or added through the API
+    if action == TwampAction::Schedule && command.config.is_none() && command.api.is_none() {
+        return Err("missing configuration file or '--api' for 'schedule'".to_string());
+    }
+    Ok(Command::Twamp(command))
+}
+
+fn parse_value<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, option))
+}
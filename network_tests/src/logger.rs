@@ -0,0 +1,40 @@
+use std::io::Write;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use network_commons::time::DateTime;
+
+/// Writes the log records to the standard error, so the standard output only carries the
+/// results.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = writeln!(
+            std::io::stderr().lock(),
+            "{} {:<5} {}",
+            DateTime::utc_now(),
+            record.level(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Installs the standard error logger with the records of `level` and above.
+pub fn init(level: LevelFilter) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
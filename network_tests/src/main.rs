@@ -0,0 +1,67 @@
+//! Command-line tool running the network tests.
+//!
+//! The results are written to the standard output and the logs to the standard error. Run
+//! `network-tests --help` for the commands and options.
+
+use std::{env, process};
+
+use network_commons::{signal::SignalListener, stop_handle::StopHandle};
+
+use cli::Command;
+
+mod cli;
+mod logger;
+mod output;
+mod twamp_command;
+
+/// The test passed.
+pub const EXIT_SUCCESS: i32 = 0;
+/// The test failed to run.
+pub const EXIT_FAILURE: i32 = 1;
+/// The command line or the configuration is invalid.
+pub const EXIT_INVALID: i32 = 2;
+/// A session violated its SLA thresholds.
+pub const EXIT_SLA_VIOLATED: i32 = 3;
+/// A session regressed from its baseline.
+pub const EXIT_REGRESSION: i32 = 4;
+
+fn main() {
+    // SIGINT and SIGTERM must be blocked before any thread is spawned
+    let stop_handle = match StopHandle::with_termination_signals() {
+        Ok(stop_handle) => stop_handle,
+        Err(e) => {
+            eprintln!("error: failed to install the signal handler: {}", e);
+            process::exit(EXIT_FAILURE);
+        }
+    };
+
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("network-tests {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Ok(Command::Twamp(command)) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\nRun 'network-tests --help' for usage.", e);
+            process::exit(EXIT_INVALID);
+        }
+    };
+    // SIGHUP must be blocked before any thread is spawned as well
+    let reload_signal = match command.action.reloads() {
+        true => match SignalListener::hangup() {
+            Ok(reload_signal) => Some(reload_signal),
+            Err(e) => {
+                eprintln!("error: failed to install the signal handler: {}", e);
+                process::exit(EXIT_FAILURE);
+            }
+        },
+        false => None,
+    };
+    logger::init(command.log_level);
+
+    process::exit(twamp_command::run(&command, stop_handle, reload_signal));
+}
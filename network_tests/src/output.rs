@@ -0,0 +1,135 @@
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use serde::Serialize;
+use twamp::{IntervalReport, SessionResult, TwampResult};
+
+const NANOS_PER_MILLI: f64 = 1e6;
+
+/// Format in which the reports and the result of a test are written to the standard output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// One JSON document per line
+    Json,
+    /// YAML documents, each starting with `---`
+    Yaml,
+    /// A summary of every session meant to be read by people
+    Text,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "text" => Ok(OutputFormat::Text),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn write_report(&self, report: &IntervalReport) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        match self {
+            OutputFormat::Text => {
+                writeln!(
+                    stdout,
+                    "Report from {} to {}",
+                    report.interval_start, report.interval_end
+                )?;
+                for session_result in &report.session_results {
+                    writeln!(stdout, "  {}", summary(session_result))?;
+                    if let Some(violations) = session_result.sla_violations() {
+                        writeln!(stdout, "    SLA violated: {}", violations)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => self.write_document(&mut stdout, report),
+        }
+    }
+
+    pub fn write_result(&self, result: &TwampResult) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        match self {
+            OutputFormat::Text => {
+                writeln!(stdout, "Result")?;
+                for session_result in &result.session_results {
+                    writeln!(stdout, "  {}", summary(session_result))?;
+                    if let Some(violations) = session_result.sla_violations() {
+                        writeln!(stdout, "    SLA violated: {}", violations)?;
+                    }
+                    if let Some(regressions) = session_result.regressions() {
+                        writeln!(stdout, "    Regressed: {}", regressions)?;
+                    }
+                }
+                if let Some(error) = &result.error {
+                    writeln!(stdout, "  Error: {}", error)?;
+                }
+                Ok(())
+            }
+            _ => self.write_document(&mut stdout, result),
+        }
+    }
+
+    fn write_document(&self, writer: &mut impl Write, document: &impl Serialize) -> io::Result<()> {
+        match self {
+            OutputFormat::Yaml => {
+                let yaml = serde_yaml::to_string(document)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                write!(writer, "---\n{}", yaml)?;
+            }
+            _ => {
+                serde_json::to_writer(&mut *writer, document)?;
+                writeln!(writer)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// Describes the main statistics of a session on one line.
+fn summary(session_result: &SessionResult) -> String {
+    let mut summary = format!(
+        "{} {}",
+        session_result.target(),
+        session_result.status.as_deref().unwrap_or_default()
+    );
+    if let Some(path_mtu) = session_result.path_mtu {
+        summary.push_str(&format!(" path MTU {}", path_mtu));
+    }
+    let Some(statistics) = &session_result.network_statistics else {
+        return summary;
+    };
+    if let (Some(avg), Some(min), Some(max)) =
+        (statistics.avg_rtt, statistics.min_rtt, statistics.max_rtt)
+    {
+        summary.push_str(&format!(
+            " rtt avg/min/max {:.3}/{:.3}/{:.3} ms",
+            avg / NANOS_PER_MILLI,
+            min / NANOS_PER_MILLI,
+            max / NANOS_PER_MILLI
+        ));
+    }
+    if let (Some(forward), Some(backward)) = (
+        statistics.avg_forward_jitter,
+        statistics.avg_backward_jitter,
+    ) {
+        summary.push_str(&format!(
+            " jitter forward/backward {:.3}/{:.3} ms",
+            forward / NANOS_PER_MILLI,
+            backward / NANOS_PER_MILLI
+        ));
+    }
+    summary.push_str(&format!(
+        " lost {} of {} packets",
+        statistics.total_loss,
+        statistics.total_packets + statistics.total_loss as usize
+    ));
+    summary
+}
@@ -0,0 +1,331 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{mpsc, Arc, RwLock},
+};
+
+use network_commons::{
+    error::CommonError, signal::SignalListener, stop_handle::StopHandle, TestResult,
+};
+use serde_json::{json, Map, Value};
+use twamp::{
+    calculate_session_results, load_capture_sessions, Agent, IntervalReport,
+    LightSenderConfiguration, ReloadHandle, ResultAnalysis, SchedulerConfiguration, Twamp,
+    TwampConfiguration, TwampMode, TwampResult,
+};
+use validator::Validate;
+
+use crate::{
+    cli::{TwampAction, TwampCommand},
+    EXIT_FAILURE, EXIT_INVALID, EXIT_REGRESSION, EXIT_SLA_VIOLATED, EXIT_SUCCESS,
+};
+
+/// Runs a TWAMP command and returns the exit status of the program.
+pub fn run(
+    command: &TwampCommand,
+    stop_handle: StopHandle,
+    reload_signal: Option<SignalListener>,
+) -> i32 {
+    if command.action == TwampAction::Schedule {
+        return schedule(command, stop_handle, reload_signal);
+    }
+    let configuration = match configuration(command) {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            log::error!("{}", e);
+            return EXIT_INVALID;
+        }
+    };
+    log::debug!("{:?}", configuration);
+
+    let result = match (&configuration.mode, &command.capture) {
+        (TwampMode::LightSender(configuration), Some(capture)) => replay(capture, configuration),
+        _ => test(command, configuration, stop_handle, reload_signal),
+    };
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            log::error!("{}", e);
+            return match e {
+                CommonError::ValidationError(_) => EXIT_INVALID,
+                _ => EXIT_FAILURE,
+            };
+        }
+    };
+    if let Err(e) = command.output.write_result(&result) {
+        log::error!("Failed to write the result: {}", e);
+    }
+
+    if let Some(error) = &result.error {
+        log::error!("{}", error);
+        return EXIT_FAILURE;
+    }
+    match result.status() {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            log::warn!("{}", e);
+            match e {
+                CommonError::ThresholdViolation(_) => EXIT_SLA_VIOLATED,
+                CommonError::Regression(_) => EXIT_REGRESSION,
+                _ => EXIT_FAILURE,
+            }
+        }
+    }
+}
+
+/// Reads the configuration file of the command, if any, and applies the options and the mode
+/// of the command to it.
+fn configuration(command: &TwampCommand) -> Result<TwampConfiguration, String> {
+    let mut configuration = match &command.config {
+        Some(path) => read_configuration(path)?,
+        None => Map::new(),
+    };
+    for change in &command.overrides {
+        change.apply(&mut configuration);
+    }
+    if let Some(mode) = command.action.mode() {
+        configuration.insert("mode".to_string(), json!(mode));
+    }
+    let configuration: TwampConfiguration = serde_json::from_value(Value::Object(configuration))
+        .map_err(|e| format!("Invalid configuration: {}", e))?;
+    // A replay sends nothing, its analysis settings are checked once the capture is read
+    if command.action != TwampAction::Replay {
+        configuration
+            .validate()
+            .map_err(|e| format!("Invalid configuration: {}", e))?;
+    }
+    Ok(configuration)
+}
+
+/// Runs the jobs of the configuration file until the program is stopped, serving the
+/// management API if asked to.
+fn schedule(
+    command: &TwampCommand,
+    stop_handle: StopHandle,
+    reload_signal: Option<SignalListener>,
+) -> i32 {
+    let agent = scheduler_configuration(command).and_then(|configuration| {
+        Agent::new(&configuration).map_err(|e| format!("Invalid configuration: {}", e))
+    });
+    let (agent, mut scheduler) = match agent {
+        Ok(agent) => agent,
+        Err(e) => {
+            log::error!("{}", e);
+            return EXIT_INVALID;
+        }
+    };
+    let _api = match command
+        .api
+        .as_deref()
+        .map(|path| agent.serve(path))
+        .transpose()
+    {
+        Ok(api) => api,
+        Err(e) => {
+            log::error!("Failed to serve the management API: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    scheduler.set_stop_handle(stop_handle.clone());
+    let result = std::thread::scope(|scope| {
+        if let Some(reload_signal) = &reload_signal {
+            scope.spawn(|| reload_jobs_on_signal(command, reload_signal, &stop_handle, &agent));
+        }
+        let result = scheduler.run();
+        // Ends the wait for signals
+        if let Err(e) = stop_handle.stop() {
+            log::error!("Failed to stop the signal handler: {}", e);
+        }
+        result
+    });
+    match result {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Reads the configuration file again on every signal and applies its jobs, until the
+/// program is stopped.
+fn reload_jobs_on_signal(
+    command: &TwampCommand,
+    reload_signal: &SignalListener,
+    stop_handle: &StopHandle,
+    agent: &Agent,
+) {
+    loop {
+        match reload_signal.wait(stop_handle) {
+            Ok(Some(_)) => (),
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Failed to wait for signals: {}", e);
+                return;
+            }
+        }
+        // Without a configuration file, the jobs are only managed through the API
+        if command.config.is_none() {
+            continue;
+        }
+        log::info!("Reloading the configuration");
+        let reloaded = scheduler_configuration(command)
+            .and_then(|configuration| agent.reload(&configuration).map_err(|e| e.to_string()));
+        if let Err(e) = reloaded {
+            log::error!("Failed to reload the configuration: {}", e);
+        }
+    }
+}
+
+/// Reads the configuration file of the scheduled jobs and applies the options of the command
+/// to the configuration of every job.
+fn scheduler_configuration(command: &TwampCommand) -> Result<SchedulerConfiguration, String> {
+    let mut configuration = match &command.config {
+        Some(path) => read_configuration(path)?,
+        None => Map::new(),
+    };
+    if let Some(Value::Array(jobs)) = configuration.get_mut("jobs") {
+        for job in jobs.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(Value::Object(job_configuration)) = job.get_mut("configuration") {
+                for change in &command.overrides {
+                    change.apply(job_configuration);
+                }
+            }
+        }
+    }
+    serde_json::from_value(Value::Object(configuration))
+        .map_err(|e| format!("Invalid configuration: {}", e))
+}
+
+/// Reads a configuration file as YAML when its extension is `yaml` or `yml`, and as JSON
+/// otherwise.
+fn read_configuration(path: &Path) -> Result<Map<String, Value>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let configuration = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+        _ => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    match configuration {
+        Value::Object(configuration) => Ok(configuration),
+        _ => Err(format!(
+            "{} doesn't hold a configuration object",
+            path.display()
+        )),
+    }
+}
+
+/// Runs the test of the configuration, writing its interval reports as they come.
+/// Fails only when the test can't be created.
+fn test(
+    command: &TwampCommand,
+    configuration: TwampConfiguration,
+    stop_handle: StopHandle,
+    reload_signal: Option<SignalListener>,
+) -> Result<TwampResult, CommonError> {
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_stop_handle(stop_handle.clone());
+    let reload_handle = ReloadHandle::default();
+    twamp.set_reload_handle(reload_handle.clone());
+    // Reports are only produced by senders with a report_interval
+    let (report_sender, report_receiver) = mpsc::channel::<IntervalReport>();
+    twamp.set_report_channel(report_sender);
+    let output = command.output;
+    let report_handle = std::thread::spawn(move || {
+        for report in report_receiver {
+            if let Err(e) = output.write_report(&report) {
+                log::error!("Failed to write a report: {}", e);
+            }
+        }
+    });
+    let mut strategy = twamp.generate()?;
+    let result = std::thread::scope(|scope| {
+        if let Some(reload_signal) = &reload_signal {
+            scope.spawn(|| reload_on_signal(command, reload_signal, &stop_handle, &reload_handle));
+        }
+        let result = strategy.execute();
+        // Ends the wait for signals
+        if let Err(e) = stop_handle.stop() {
+            log::error!("Failed to stop the signal handler: {}", e);
+        }
+        result
+    })
+    .unwrap_or_else(|e| TwampResult {
+        session_results: vec![],
+        error: Some(e.to_string()),
+    });
+    // Wait for the remaining reports once every sender is gone
+    drop(strategy);
+    drop(twamp);
+    let _ = report_handle.join();
+    Ok(result)
+}
+
+/// Reads the configuration file again on every signal and reloads the running reflector with
+/// it, until the program is stopped.
+fn reload_on_signal(
+    command: &TwampCommand,
+    reload_signal: &SignalListener,
+    stop_handle: &StopHandle,
+    reload_handle: &ReloadHandle,
+) {
+    loop {
+        match reload_signal.wait(stop_handle) {
+            Ok(Some(_)) => (),
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Failed to wait for signals: {}", e);
+                return;
+            }
+        }
+        log::info!("Reloading the configuration");
+        let reloaded = configuration(command).and_then(|configuration| {
+            reload_handle
+                .reload(&configuration.mode)
+                .map_err(|e| e.to_string())
+        });
+        if let Err(e) = reloaded {
+            log::error!("Failed to reload the configuration: {}", e);
+        }
+    }
+}
+
+/// Calculates the results of the sessions of a capture with the analysis settings and the SLA
+/// thresholds of the configuration.
+fn replay(
+    capture: &Path,
+    configuration: &LightSenderConfiguration,
+) -> Result<TwampResult, CommonError> {
+    for threshold in &configuration.sla {
+        threshold.validate().map_err(CommonError::ValidationError)?;
+    }
+    configuration
+        .offset_estimation
+        .validate()
+        .map_err(CommonError::ValidationError)?;
+    if let Some(confidence) = &configuration.confidence {
+        confidence
+            .validate()
+            .map_err(CommonError::ValidationError)?;
+    }
+    let mut sessions = load_capture_sessions(capture)?;
+    for session in &mut sessions {
+        session.sla = configuration.sla.clone();
+    }
+    // The capture holds every packet, so the quantiles are exact even for a continuous test
+    let analysis = ResultAnalysis {
+        continuous: false,
+        ..ResultAnalysis::new(configuration)
+    };
+    let baseline = analysis.load_baseline()?;
+    let session_results = calculate_session_results(
+        Arc::new(RwLock::new(sessions)),
+        &analysis,
+        baseline.as_ref(),
+    )?;
+    Ok(TwampResult {
+        session_results,
+        error: None,
+    })
+}
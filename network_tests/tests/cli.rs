@@ -0,0 +1,203 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    path::PathBuf,
+    process::{Command, Output},
+    time::Duration,
+};
+
+use network_commons::stop_handle::StopHandle;
+use serde_json::{json, Value};
+use twamp::{Twamp, TwampConfiguration};
+
+fn network_tests(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_network-tests"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("network-tests-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn help_and_version_succeed() {
+    let output = network_tests(&["--help"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: network-tests"));
+    let output = network_tests(&["twamp", "send", "--log-level", "off", "-V"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("network-tests "));
+}
+
+#[test]
+fn invalid_command_lines_exit_with_2() {
+    for (args, error) in [
+        (&["ping"][..], "unknown test 'ping'"),
+        (&["twamp", "bounce"], "unknown twamp command 'bounce'"),
+        (&["twamp", "send", "--bogus"], "unknown option '--bogus'"),
+        (
+            &["twamp", "send", "--duration"],
+            "missing value for '--duration'",
+        ),
+        (
+            &["twamp", "send", "-d", "soon"],
+            "invalid value 'soon' for '-d'",
+        ),
+        (
+            &["twamp", "send", "--target=nowhere"],
+            "invalid value 'nowhere' for '--target'",
+        ),
+        (
+            &["twamp", "send", "--sla", "P95_RTT"],
+            "invalid value 'P95_RTT' for '--sla'",
+        ),
+        (
+            &["twamp", "send", "--set", "padding"],
+            "invalid value 'padding' for '--set'",
+        ),
+        (
+            &["twamp", "pmtu", "-i", "100"],
+            "'-i' doesn't apply to 'pmtu', use '--probe-timeout'",
+        ),
+        (
+            &["twamp", "send", "--probe-timeout", "100"],
+            "unknown option '--probe-timeout'",
+        ),
+        (&["twamp", "send", "extra"], "unexpected argument 'extra'"),
+        (&["twamp", "replay"], "missing capture file for 'replay'"),
+        (
+            &["twamp", "schedule"],
+            "missing configuration file or '--api'",
+        ),
+    ] {
+        let output = network_tests(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&output).contains(error), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn invalid_configurations_exit_with_2() {
+    let path = temp_path("invalid.json");
+    std::fs::write(&path, "[]").unwrap();
+    let output = network_tests(&["twamp", "send", "-c", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("doesn't hold a configuration object"));
+
+    // The options are applied to the configuration before it is validated
+    let output = network_tests(&[
+        "twamp",
+        "send",
+        "-t",
+        "127.0.0.1:862",
+        "--set",
+        "padding=\"x\"",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Invalid configuration"));
+
+    // The probe timeout of a path MTU discovery can't be null
+    let output = network_tests(&[
+        "twamp",
+        "pmtu",
+        "-t",
+        "127.0.0.1:862",
+        "--probe-timeout",
+        "0",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr(&output).contains("probe_timeout"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn runtime_errors_exit_with_1() {
+    let missing = temp_path("missing.pcapng");
+    let output = network_tests(&["twamp", "replay", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+}
+
+/// Starts a reflector on a free port, running until the returned handle is stopped.
+fn start_reflector() -> (SocketAddr, StopHandle, std::thread::JoinHandle<()>) {
+    let address = UdpSocket::bind("127.0.0.1:0")
+        .and_then(|socket| socket.local_addr())
+        .unwrap();
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_REFLECTOR",
+        "source_ip_address": address.to_string(),
+        "ref_wait": 5,
+    }))
+    .unwrap();
+    let stop_handle = StopHandle::new().unwrap();
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_stop_handle(stop_handle.clone());
+    let thread = std::thread::spawn(move || {
+        twamp.generate().unwrap().execute().unwrap();
+    });
+    // Waits for a test packet to be reflected
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let mut buffer = [0u8; 2048];
+    for _ in 0..250 {
+        socket.send_to(&[0u8; 41], address).unwrap();
+        if socket.recv(&mut buffer).is_ok() {
+            return (address, stop_handle, thread);
+        }
+    }
+    panic!("{} never reflected a packet", address);
+}
+
+#[test]
+fn options_are_applied_on_top_of_the_configuration_file() {
+    let (reflector, stop_handle, thread) = start_reflector();
+    let (other_reflector, other_stop_handle, other_thread) = start_reflector();
+    let path = temp_path("send.json");
+    std::fs::write(
+        &path,
+        json!({
+            "source_ip_address": "127.0.0.1:0",
+            "test_session_hosts": [reflector.to_string()],
+            "collection_period": 30,
+            "packet_interval": 100,
+            "last_message_timeout": 1,
+        })
+        .to_string(),
+    )
+    .unwrap();
+    // The duration is replaced and the target added to those of the file
+    let output = network_tests(&[
+        "twamp",
+        "send",
+        "-c",
+        path.to_str().unwrap(),
+        "-d",
+        "1",
+        "-t",
+        &other_reflector.to_string(),
+        "--set",
+        "padding=50",
+        "--log-level",
+        "off",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+    stop_handle.stop().unwrap();
+    thread.join().unwrap();
+    other_stop_handle.stop().unwrap();
+    other_thread.join().unwrap();
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(result["session_results"].as_array().unwrap().len(), 2);
+}
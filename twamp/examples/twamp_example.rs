@@ -3,24 +3,42 @@
 use std::env;
 use std::fs::File;
 use std::io::Read;
+use std::sync::mpsc;
 
 use network_commons::error::CommonError;
-use twamp::{Twamp, TwampConfiguration, TwampResult};
+use network_commons::stop_handle::StopHandle;
+use network_commons::TestResult;
+use twamp::{IntervalReport, Twamp, TwampConfiguration, TwampResult};
 use validator::Validate;
 
 #[derive(Debug)]
 struct App {
     config: TwampConfiguration,
+    stop_handle: StopHandle,
 }
 
 impl App {
-    fn new(config: TwampConfiguration) -> Self {
-        Self { config }
+    fn new(config: TwampConfiguration, stop_handle: StopHandle) -> Self {
+        Self {
+            config,
+            stop_handle,
+        }
     }
 
     fn run(&self) -> Result<(), CommonError> {
         log::debug!("{:?}", self.config);
-        let twamp = Twamp::new(self.config.clone());
+        let mut twamp = Twamp::new(self.config.clone());
+        twamp.set_stop_handle(self.stop_handle.clone());
+        // Reports are only produced by senders with a report_interval
+        let (report_sender, report_receiver) = mpsc::channel::<IntervalReport>();
+        twamp.set_report_channel(report_sender);
+        let report_handle = std::thread::spawn(move || {
+            for report in report_receiver {
+                if let Ok(json_report) = serde_json::to_string(&report) {
+                    log::info!("Report {}", json_report);
+                }
+            }
+        });
         let result = match twamp.generate() {
             Ok(mut strategy) => match strategy.execute() {
                 Ok(result) => result,
@@ -34,14 +52,20 @@ impl App {
                 error: Some(e.to_string()),
             },
         };
+        // Wait for the remaining reports once every sender is gone
+        drop(twamp);
+        let _ = report_handle.join();
 
         log::info!("Result {:#}", serde_json::to_string(&result).unwrap());
 
-        Ok(())
+        result.status()
     }
 }
 
 fn main() {
+    // SIGINT and SIGTERM must be blocked before any thread is spawned
+    let stop_handle =
+        StopHandle::with_termination_signals().expect("failed to install the signal handler");
     let _ = log4rs::init_file("twamp/log_config.yml", Default::default());
 
     let args: Vec<String> = env::args().collect();
@@ -61,6 +85,9 @@ fn main() {
 
     config.validate().expect("invalid configuration");
 
-    let app = App::new(config);
-    let _ = app.run();
+    let app = App::new(config, stop_handle);
+    if let Err(e) = app.run() {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
 }
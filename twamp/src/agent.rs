@@ -0,0 +1,306 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use network_commons::{
+    error::CommonError,
+    management::{ManagementServer, Request, Response},
+    scheduler::{JobStatus, Scheduler, SchedulerHandle},
+    time::DateTime,
+};
+use serde::Serialize;
+use validator::Validate;
+
+use crate::{
+    configuration::TwampConfiguration,
+    twamp_common::{monitor::SessionMonitor, reload::ReloadHandle},
+    ActiveSession, JobConfiguration, SchedulerConfiguration, TwampResult,
+};
+
+/// Manages the jobs of a running scheduler through a local HTTP/JSON API.
+///
+/// | Request                     | Action                                                  |
+/// |-----------------------------|---------------------------------------------------------|
+/// | `GET /jobs`                 | Lists the jobs                                          |
+/// | `GET /jobs/{name}`          | Shows a job along with its configuration                |
+/// | `PUT /jobs/{name}`          | Adds or replaces a job, whose configuration is the body |
+/// | `DELETE /jobs/{name}`       | Removes a job and stops its run                         |
+/// | `POST /jobs/{name}/run`     | Runs a job now                                          |
+/// | `POST /jobs/{name}/stop`    | Stops the run of a job                                  |
+/// | `GET /jobs/{name}/result`   | Shows the `TwampResult` of the last run of a job        |
+/// | `GET /jobs/{name}/sessions` | Lists the sessions of a reflector job                   |
+#[derive(Clone)]
+pub struct Agent {
+    scheduler: SchedulerHandle<TwampResult>,
+    jobs: Arc<Mutex<BTreeMap<String, AgentJob>>>,
+}
+
+struct AgentJob {
+    configuration: JobConfiguration,
+    monitor: SessionMonitor,
+    reload_handle: ReloadHandle,
+}
+
+/// Job shown by `GET /jobs/{name}`.
+#[derive(Serialize)]
+struct JobDetails<'a> {
+    #[serde(flatten)]
+    status: JobStatus,
+    jitter: u64,
+    configuration: &'a TwampConfiguration,
+}
+
+/// Last finished run shown by `GET /jobs/{name}/result`.
+#[derive(Serialize)]
+struct RunDetails {
+    started: DateTime,
+    finished: DateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<TwampResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Agent {
+    /// Validates the jobs and makes them a scheduler, along with the agent managing it.
+    pub fn new(
+        configuration: &SchedulerConfiguration,
+    ) -> Result<(Self, Scheduler<TwampResult>), CommonError> {
+        configuration
+            .validate()
+            .map_err(CommonError::ValidationError)?;
+        let scheduler = Scheduler::new(configuration.max_concurrent_jobs)?;
+        let agent = Self {
+            scheduler: scheduler.handle(),
+            jobs: Arc::new(Mutex::new(BTreeMap::new())),
+        };
+        for job in &configuration.jobs {
+            agent.add_job(job.clone())?;
+        }
+        Ok((agent, scheduler))
+    }
+
+    /// Adds a job, replacing the job with the same name if there is one.
+    /// Returns whether the job replaced another.
+    ///
+    /// The run of a replaced job that is going goes on, unless the job is unscheduled. A
+    /// running reflector then reloads the new configuration and keeps its sessions, and other
+    /// runs are stopped to start again with it.
+    pub fn add_job(&self, configuration: JobConfiguration) -> Result<bool, CommonError> {
+        configuration
+            .validate()
+            .map_err(CommonError::ValidationError)?;
+        let mut jobs = self.jobs.lock()?;
+        let name = configuration.name.clone();
+        let (monitor, reload_handle) = match jobs.get(&name) {
+            Some(job) => (job.monitor.clone(), job.reload_handle.clone()),
+            None => (SessionMonitor::default(), ReloadHandle::default()),
+        };
+        let replaced = jobs.contains_key(&name);
+        let job = configuration.managed_job(monitor.clone(), reload_handle.clone());
+        let reloaded = replaced
+            && configuration.schedule().is_none()
+            && match reload_handle.reload(&configuration.configuration.mode) {
+                Ok(reloaded) => reloaded,
+                Err(e) => {
+                    log::info!("Restarting job {}: {}", name, e);
+                    false
+                }
+            };
+        if reloaded {
+            self.scheduler.update_job(job)?;
+        } else {
+            if replaced && configuration.schedule().is_none() {
+                self.scheduler.stop_run(&name)?;
+            }
+            self.scheduler.add_job(job)?;
+        }
+        jobs.insert(
+            name,
+            AgentJob {
+                configuration,
+                monitor,
+                reload_handle,
+            },
+        );
+        Ok(replaced)
+    }
+
+    /// Applies the jobs of a configuration reloaded from its file: the jobs that changed are
+    /// replaced as with `add_job`, the new ones are added and the others removed.
+    pub fn reload(&self, configuration: &SchedulerConfiguration) -> Result<(), CommonError> {
+        configuration
+            .validate()
+            .map_err(CommonError::ValidationError)?;
+        let removed: Vec<String> = self
+            .jobs
+            .lock()?
+            .keys()
+            .filter(|name| !configuration.jobs.iter().any(|job| &job.name == *name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.remove_job(&name)?;
+        }
+        for job in &configuration.jobs {
+            let unchanged = self
+                .jobs
+                .lock()?
+                .get(&job.name)
+                .is_some_and(|current| &current.configuration == job);
+            if !unchanged {
+                self.add_job(job.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a job and stops its run that is going.
+    /// Returns whether there was a job with that name.
+    pub fn remove_job(&self, name: &str) -> Result<bool, CommonError> {
+        let mut jobs = self.jobs.lock()?;
+        jobs.remove(name);
+        self.scheduler.remove_job(name)
+    }
+
+    /// Sessions of the reflector run by a job, or `None` if there is no job with that name.
+    pub fn sessions(&self, name: &str) -> Result<Option<Vec<ActiveSession>>, CommonError> {
+        Ok(self
+            .jobs
+            .lock()?
+            .get(name)
+            .map(|job| job.monitor.sessions()))
+    }
+
+    /// Serves the API on the Unix domain socket at `path` until the returned server is
+    /// stopped or dropped.
+    pub fn serve(&self, path: &Path) -> Result<ManagementServer, CommonError> {
+        let agent = self.clone();
+        ManagementServer::spawn(path, Arc::new(move |request| agent.handle(request)))
+    }
+
+    /// Answers a request of the API.
+    pub fn handle(&self, request: &Request) -> Response {
+        log::debug!("{} {}", request.method, request.path);
+        match self.route(request) {
+            Ok(response) => response,
+            Err(e) => Response::error(500, &e.to_string()),
+        }
+    }
+
+    fn route(&self, request: &Request) -> Result<Response, CommonError> {
+        let method = request.method.as_str();
+        Ok(match request.segments()[..] {
+            ["jobs"] => match method {
+                "GET" => Response::json(200, &self.scheduler.jobs()?),
+                _ => method_not_allowed(),
+            },
+            ["jobs", name] => match method {
+                "GET" => self.job(name)?,
+                "PUT" => self.put_job(name, &request.body)?,
+                "DELETE" => match self.remove_job(name)? {
+                    true => Response::json(200, &serde_json::json!({ "removed": name })),
+                    false => job_not_found(name),
+                },
+                _ => method_not_allowed(),
+            },
+            ["jobs", name, "run"] => match method {
+                "POST" => match self.scheduler.run_now(name)? {
+                    true => Response::json(202, &serde_json::json!({ "requested": name })),
+                    false => job_not_found(name),
+                },
+                _ => method_not_allowed(),
+            },
+            ["jobs", name, "stop"] => match method {
+                "POST" => match self.scheduler.stop_run(name)? {
+                    true => Response::json(202, &serde_json::json!({ "stopping": name })),
+                    false if self.scheduler.job(name)?.is_some() => {
+                        Response::error(409, &format!("Job {} is not running", name))
+                    }
+                    false => job_not_found(name),
+                },
+                _ => method_not_allowed(),
+            },
+            ["jobs", name, "result"] => match method {
+                "GET" => self.result(name)?,
+                _ => method_not_allowed(),
+            },
+            ["jobs", name, "sessions"] => match method {
+                "GET" => match self.sessions(name)? {
+                    Some(sessions) => Response::json(200, &sessions),
+                    None => job_not_found(name),
+                },
+                _ => method_not_allowed(),
+            },
+            _ => Response::error(404, &format!("No route for {}", request.path)),
+        })
+    }
+
+    fn job(&self, name: &str) -> Result<Response, CommonError> {
+        let jobs = self.jobs.lock()?;
+        let (Some(job), Some(status)) = (jobs.get(name), self.scheduler.job(name)?) else {
+            return Ok(job_not_found(name));
+        };
+        Ok(Response::json(
+            200,
+            &JobDetails {
+                status,
+                jitter: job.configuration.jitter,
+                configuration: &job.configuration.configuration,
+            },
+        ))
+    }
+
+    /// Adds the job of the body, named after the path.
+    fn put_job(&self, name: &str, body: &[u8]) -> Result<Response, CommonError> {
+        let mut configuration: JobConfiguration = match serde_json::from_slice(body) {
+            Ok(configuration) => configuration,
+            Err(e) => return Ok(Response::error(400, &format!("Invalid job: {}", e))),
+        };
+        configuration.name = name.to_string();
+        let replaced = match self.add_job(configuration) {
+            Ok(replaced) => replaced,
+            Err(CommonError::ValidationError(e)) => {
+                return Ok(Response::error(422, &format!("Invalid job: {}", e)))
+            }
+            Err(e) => return Err(e),
+        };
+        let status = if replaced { 200 } else { 201 };
+        Ok(Response::json(status, &self.scheduler.job(name)?))
+    }
+
+    fn result(&self, name: &str) -> Result<Response, CommonError> {
+        if self.scheduler.job(name)?.is_none() {
+            return Ok(job_not_found(name));
+        }
+        let Some(record) = self.scheduler.last_run(name)? else {
+            return Ok(Response::error(
+                404,
+                &format!("Job {} has not finished a run yet", name),
+            ));
+        };
+        let (result, error) = match record.result {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e)),
+        };
+        Ok(Response::json(
+            200,
+            &RunDetails {
+                started: record.started,
+                finished: record.finished,
+                result,
+                error,
+            },
+        ))
+    }
+}
+
+fn job_not_found(name: &str) -> Response {
+    Response::error(404, &format!("No job named {}", name))
+}
+
+fn method_not_allowed() -> Response {
+    Response::error(405, "Method not allowed")
+}
@@ -0,0 +1,341 @@
+use std::net::SocketAddr;
+
+use network_commons::{
+    error::CommonError,
+    pcap::CaptureConfiguration,
+    stats::{
+        confidence::ConfidenceConfiguration, offset_estimator::GamlrConfiguration,
+        skew_estimator::SkewEstimator, voice_quality::Codec,
+    },
+};
+use validator::Validate;
+
+use crate::{
+    configuration::{TwampConfiguration, TwampMode},
+    twamp_common::access::Subnet,
+    twamp_control::{ControlConfiguration, FullSenderConfiguration},
+    twamp_light_reflector::Configuration as LightReflectorConfiguration,
+    twamp_light_sender::{
+        baseline::BaselineConfiguration, packet_export::PacketExportConfiguration,
+        push::PushConfiguration, size_profile::SizeProfile, sla::SlaThreshold,
+        Configuration as LightSenderConfiguration, TargetConfiguration,
+    },
+    twamp_pmtu::Configuration as PmtuConfiguration,
+};
+
+impl TwampConfiguration {
+    /// Starts the configuration of a `LIGHT_SENDER` test.
+    pub fn light_sender() -> SenderBuilder {
+        SenderBuilder {
+            configuration: LightSenderConfiguration::default(),
+            control_host: None,
+        }
+    }
+
+    /// Starts the configuration of a `FULL_SENDER` test, whose sessions are requested from
+    /// the server at `control_host`.
+    pub fn full_sender(control_host: SocketAddr) -> SenderBuilder {
+        SenderBuilder {
+            configuration: LightSenderConfiguration::default(),
+            control_host: Some(control_host),
+        }
+    }
+
+    /// Starts the configuration of a `LIGHT_REFLECTOR`.
+    pub fn light_reflector() -> ReflectorBuilder {
+        ReflectorBuilder {
+            configuration: LightReflectorConfiguration::default(),
+        }
+    }
+
+    /// Starts the configuration of a `FULL_REFLECTOR`.
+    pub fn full_reflector() -> ServerBuilder {
+        ServerBuilder {
+            configuration: ControlConfiguration::default(),
+        }
+    }
+
+    /// Starts the configuration of a `LIGHT_PMTU` discovery.
+    pub fn light_pmtu() -> PmtuBuilder {
+        PmtuBuilder {
+            configuration: PmtuConfiguration::default(),
+        }
+    }
+}
+
+/// Validates the settings of a mode and makes them a configuration.
+fn build(mode: TwampMode) -> Result<TwampConfiguration, CommonError> {
+    mode.validate().map_err(CommonError::ValidationError)?;
+    Ok(TwampConfiguration::new(mode))
+}
+
+/// Builder of the configuration of a `LIGHT_SENDER` or `FULL_SENDER` test.
+/// Settings that are not set take the default values of the configuration files.
+#[derive(Debug, Clone)]
+pub struct SenderBuilder {
+    configuration: LightSenderConfiguration,
+    control_host: Option<SocketAddr>,
+}
+
+impl SenderBuilder {
+    /// Adds a target tested with the settings of the test.
+    pub fn host(mut self, host: SocketAddr) -> Self {
+        self.configuration.hosts.push(host);
+        self
+    }
+
+    /// Adds a target with its own settings.
+    pub fn target(mut self, target: TargetConfiguration) -> Self {
+        self.configuration.targets.push(target);
+        self
+    }
+
+    pub fn source_ip_address(mut self, source_ip_address: SocketAddr) -> Self {
+        self.configuration.source_ip_address = source_ip_address;
+        self
+    }
+
+    /// Sets the duration of the test in seconds.
+    pub fn collection_period(mut self, seconds: u64) -> Self {
+        self.configuration.duration = seconds;
+        self
+    }
+
+    /// Sets the interval at which packets are sent, in milliseconds.
+    pub fn packet_interval(mut self, milliseconds: u64) -> Self {
+        self.configuration.packet_interval = milliseconds;
+        self
+    }
+
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.configuration.padding = padding;
+        self
+    }
+
+    pub fn size_profile(mut self, size_profile: SizeProfile) -> Self {
+        self.configuration.size_profile = Some(size_profile);
+        self
+    }
+
+    /// Sets the time during which reflected packets are still awaited once the last packet
+    /// is sent, in seconds.
+    pub fn last_message_timeout(mut self, seconds: u64) -> Self {
+        self.configuration.last_message_timeout = seconds;
+        self
+    }
+
+    /// Sets the interval at which reports of the running test are produced, in seconds.
+    pub fn report_interval(mut self, seconds: u64) -> Self {
+        self.configuration.report_interval = Some(seconds);
+        self
+    }
+
+    /// Runs the test until it is stopped, keeping the packets of the last `retention_window`
+    /// seconds, or of the default window without one.
+    pub fn continuous(mut self, retention_window: Option<u64>) -> Self {
+        self.configuration.continuous = true;
+        self.configuration.retention_window = retention_window;
+        self
+    }
+
+    pub fn skew_estimator(mut self, skew_estimator: SkewEstimator) -> Self {
+        self.configuration.skew_estimator = Some(skew_estimator);
+        self
+    }
+
+    pub fn offset_estimation(mut self, offset_estimation: GamlrConfiguration) -> Self {
+        self.configuration.offset_estimation = offset_estimation;
+        self
+    }
+
+    pub fn confidence(mut self, confidence: ConfidenceConfiguration) -> Self {
+        self.configuration.confidence = Some(confidence);
+        self
+    }
+
+    /// Adds a codec whose voice quality is estimated.
+    pub fn voice_codec(mut self, codec: Codec) -> Self {
+        self.configuration.voice_codecs.push(codec);
+        self
+    }
+
+    /// Adds an SLA threshold evaluated on the results of every target.
+    pub fn sla(mut self, threshold: SlaThreshold) -> Self {
+        self.configuration.sla.push(threshold);
+        self
+    }
+
+    /// Records the round trip time of every packet in the results.
+    pub fn rtt_samples(mut self) -> Self {
+        self.configuration.rtt_samples = true;
+        self
+    }
+
+    pub fn baseline(mut self, baseline: BaselineConfiguration) -> Self {
+        self.configuration.baseline = Some(baseline);
+        self
+    }
+
+    pub fn packet_export(mut self, packet_export: PacketExportConfiguration) -> Self {
+        self.configuration.packet_export = Some(packet_export);
+        self
+    }
+
+    pub fn metrics_address(mut self, metrics_address: SocketAddr) -> Self {
+        self.configuration.metrics_address = Some(metrics_address);
+        self
+    }
+
+    /// Adds a metrics database the interval reports are pushed to.
+    pub fn push(mut self, push: PushConfiguration) -> Self {
+        self.configuration.push.push(push);
+        self
+    }
+
+    pub fn capture(mut self, capture: CaptureConfiguration) -> Self {
+        self.configuration.capture = Some(capture);
+        self
+    }
+
+    pub fn build(self) -> Result<TwampConfiguration, CommonError> {
+        build(match self.control_host {
+            Some(control_host) => TwampMode::FullSender(FullSenderConfiguration {
+                control_host,
+                session: self.configuration,
+            }),
+            None => TwampMode::LightSender(self.configuration),
+        })
+    }
+}
+
+/// Builder of the configuration of a `LIGHT_REFLECTOR`.
+#[derive(Debug, Clone)]
+pub struct ReflectorBuilder {
+    configuration: LightReflectorConfiguration,
+}
+
+impl ReflectorBuilder {
+    pub fn source_ip_address(mut self, source_ip_address: SocketAddr) -> Self {
+        self.configuration.source_ip_address = source_ip_address;
+        self
+    }
+
+    /// Sets the time after which a session that stopped sending is closed, in seconds.
+    pub fn ref_wait(mut self, seconds: u64) -> Self {
+        self.configuration.ref_wait = seconds;
+        self
+    }
+
+    pub fn metrics_address(mut self, metrics_address: SocketAddr) -> Self {
+        self.configuration.metrics_address = Some(metrics_address);
+        self
+    }
+
+    pub fn capture(mut self, capture: CaptureConfiguration) -> Self {
+        self.configuration.capture = Some(capture);
+        self
+    }
+
+    /// Adds an address the reflector listens on, along with `source_ip_address`.
+    pub fn listen_address(mut self, address: SocketAddr) -> Self {
+        self.configuration.listen_addresses.push(address);
+        self
+    }
+
+    /// Adds a network of senders to reflect. Any sender is reflected when there are none.
+    pub fn allowed_sender(mut self, subnet: Subnet) -> Self {
+        self.configuration.allowed_senders.push(subnet);
+        self
+    }
+
+    pub fn max_sessions(mut self, max_sessions: usize) -> Self {
+        self.configuration.max_sessions = Some(max_sessions);
+        self
+    }
+
+    pub fn build(self) -> Result<TwampConfiguration, CommonError> {
+        build(TwampMode::LightReflector(self.configuration))
+    }
+}
+
+/// Builder of the configuration of a `FULL_REFLECTOR`.
+#[derive(Debug, Clone)]
+pub struct ServerBuilder {
+    configuration: ControlConfiguration,
+}
+
+impl ServerBuilder {
+    pub fn source_ip_address(mut self, source_ip_address: SocketAddr) -> Self {
+        self.configuration.source_ip_address = source_ip_address;
+        self
+    }
+
+    /// Sets the time after which a session that stopped sending is closed, in seconds.
+    pub fn ref_wait(mut self, seconds: u64) -> Self {
+        self.configuration.ref_wait = seconds;
+        self
+    }
+
+    /// Adds an address the server listens on, along with `source_ip_address`.
+    pub fn listen_address(mut self, address: SocketAddr) -> Self {
+        self.configuration.listen_addresses.push(address);
+        self
+    }
+
+    /// Adds a network of control clients to accept. Any client is accepted when there are
+    /// none.
+    pub fn allowed_sender(mut self, subnet: Subnet) -> Self {
+        self.configuration.allowed_senders.push(subnet);
+        self
+    }
+
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.configuration.max_connections = Some(max_connections);
+        self
+    }
+
+    pub fn build(self) -> Result<TwampConfiguration, CommonError> {
+        build(TwampMode::FullReflector(self.configuration))
+    }
+}
+
+/// Builder of the configuration of a `LIGHT_PMTU` discovery.
+#[derive(Debug, Clone)]
+pub struct PmtuBuilder {
+    configuration: PmtuConfiguration,
+}
+
+impl PmtuBuilder {
+    /// Adds a host whose path MTU is discovered.
+    pub fn host(mut self, host: SocketAddr) -> Self {
+        self.configuration.hosts.push(host);
+        self
+    }
+
+    pub fn source_ip_address(mut self, source_ip_address: SocketAddr) -> Self {
+        self.configuration.source_ip_address = source_ip_address;
+        self
+    }
+
+    /// Sets the time after which an unanswered probe is considered lost, in milliseconds.
+    pub fn probe_timeout(mut self, milliseconds: u64) -> Self {
+        self.configuration.probe_timeout = milliseconds;
+        self
+    }
+
+    /// Sets the largest IP packet size probed.
+    pub fn max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.configuration.max_packet_size = max_packet_size;
+        self
+    }
+
+    /// Sets the time after which the discovery is stopped, in seconds.
+    pub fn collection_period(mut self, seconds: u64) -> Self {
+        self.configuration.duration = seconds;
+        self
+    }
+
+    pub fn build(self) -> Result<TwampConfiguration, CommonError> {
+        build(TwampMode::LightPmtu(self.configuration))
+    }
+}
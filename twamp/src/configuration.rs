@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
+
+use crate::{
+    twamp_control::{ControlConfiguration, FullSenderConfiguration},
+    twamp_light_reflector::Configuration as LightReflectorConfiguration,
+    twamp_light_sender::{sink::SinkConfiguration, Configuration as LightSenderConfiguration},
+    twamp_pmtu::Configuration as PmtuConfiguration,
+};
+
+/// Mode of a TWAMP test, along with the settings of that mode.
+/// Configuration files select it with their `mode` field.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "mode", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TwampMode {
+    /// Sends test packets to TWAMP Light reflectors
+    LightSender(LightSenderConfiguration),
+    /// Reflects the test packets of TWAMP Light senders
+    LightReflector(LightReflectorConfiguration),
+    /// Finds the path MTU towards TWAMP Light reflectors
+    LightPmtu(PmtuConfiguration),
+    /// Requests test sessions from a TWAMP server and sends their test packets
+    FullSender(FullSenderConfiguration),
+    /// TWAMP server accepting the test sessions of full senders
+    FullReflector(ControlConfiguration),
+}
+
+impl TwampMode {
+    /// Name of the mode in the configuration files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TwampMode::LightSender(_) => "LIGHT_SENDER",
+            TwampMode::LightReflector(_) => "LIGHT_REFLECTOR",
+            TwampMode::LightPmtu(_) => "LIGHT_PMTU",
+            TwampMode::FullSender(_) => "FULL_SENDER",
+            TwampMode::FullReflector(_) => "FULL_REFLECTOR",
+        }
+    }
+}
+
+impl Validate for TwampMode {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            TwampMode::LightSender(configuration) => configuration.validate(),
+            TwampMode::LightReflector(configuration) => configuration.validate(),
+            TwampMode::LightPmtu(configuration) => configuration.validate(),
+            TwampMode::FullSender(configuration) => configuration.validate(),
+            TwampMode::FullReflector(configuration) => configuration.validate(),
+        }
+    }
+}
+
+/// Configuration of a TWAMP test: its mode and the settings shared by every mode.
+///
+/// Configuration files hold the settings of the mode next to the shared ones. Settings that
+/// are left out take their default value, and settings of other modes are ignored.
+#[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TwampConfiguration {
+    #[serde(flatten)]
+    #[validate]
+    pub mode: TwampMode,
+    /// Sinks the outputs of the test are written to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sinks: Vec<SinkConfiguration>,
+}
+
+impl TwampConfiguration {
+    pub fn new(mode: TwampMode) -> Self {
+        Self {
+            mode,
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Adds a sink the outputs of the test are written to.
+    pub fn with_sink(mut self, sink: SinkConfiguration) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+}
+
+impl From<TwampMode> for TwampConfiguration {
+    fn from(mode: TwampMode) -> Self {
+        Self::new(mode)
+    }
+}
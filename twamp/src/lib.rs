@@ -1,119 +1,197 @@
-use std::net::SocketAddr;
+use std::sync::{mpsc::Sender, Arc, Mutex};
 
 use crate::twamp_light_reflector::reflector::Reflector;
-use crate::twamp_light_reflector::Configuration as ReflectorConfiguration;
-
-use crate::twamp_light_sender::Configuration as LightConfiguration;
-use network_commons::{error::CommonError, Strategy};
-use serde::{Deserialize, Serialize};
-use twamp_control::{
-    control::Control, control_client::ControlClient, ClientConfiguration, ControlConfiguration,
+use crate::twamp_pmtu::discovery::PathMtuDiscovery;
+pub use agent::Agent;
+pub use builder::{PmtuBuilder, ReflectorBuilder, SenderBuilder, ServerBuilder};
+pub use configuration::{TwampConfiguration, TwampMode};
+use network_commons::{error::CommonError, stop_handle::StopHandle, Strategy};
+pub use scheduler::{JobConfiguration, SchedulerConfiguration};
+pub use twamp_common::access::Subnet;
+pub use twamp_common::monitor::{ActiveSession, SessionMonitor};
+pub use twamp_common::reload::ReloadHandle;
+use twamp_control::{control::Control, control_client::ControlClient, ClientConfiguration};
+pub use twamp_control::{
+    ControlConfiguration as FullReflectorConfiguration, FullSenderConfiguration,
+};
+pub use twamp_light_reflector::Configuration as LightReflectorConfiguration;
+pub use twamp_light_sender::baseline::{Baseline, BaselineConfiguration};
+pub use twamp_light_sender::packet_export::{
+    load_sessions, read_packet_records, ExportFormat, PacketExportConfiguration, PacketRecord,
+};
+pub use twamp_light_sender::push::{PushConfiguration, PushFormat, PushTag, PushTransport};
+pub use twamp_light_sender::replay::load_capture_sessions;
+pub use twamp_light_sender::report::IntervalReport;
+pub use twamp_light_sender::result::{
+    CorrectedOwdStatistics, NetworkStatistics, SessionResult, TwampResult,
 };
-pub use twamp_light_sender::result::TwampResult;
+use twamp_light_sender::sink::ReportChannel;
+pub use twamp_light_sender::sink::{SinkConfiguration, TwampSink, TwampSinks};
+pub use twamp_light_sender::size_profile::{SizeClass, SizeProfile, SizeSchedule};
+pub use twamp_light_sender::sla::{SlaMetric, SlaThreshold, SlaVerdict, SlaViolation};
 use twamp_light_sender::twamp_light::SessionSender;
+pub use twamp_light_sender::twamp_light::{calculate_session_results, ResultAnalysis};
+pub use twamp_light_sender::Configuration as LightSenderConfiguration;
+pub use twamp_light_sender::TargetConfiguration;
+pub use twamp_pmtu::Configuration as PmtuConfiguration;
 use validator::Validate;
 
+mod agent;
+mod builder;
+mod configuration;
+mod scheduler;
 mod twamp_common;
 mod twamp_control;
 mod twamp_light_reflector;
 mod twamp_light_sender;
-#[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
-
-pub struct TwampConfiguration {
-    pub test_session_hosts: Option<Vec<SocketAddr>>,
-    pub control_host: Option<SocketAddr>,
-    pub mode: String,
-    pub source_ip_address: Option<String>,
-    pub collection_period: Option<u64>,
-    pub packet_interval: Option<u64>,
-    pub padding: Option<usize>,
-    pub last_message_timeout: Option<u64>,
-    pub ref_wait: Option<u64>,
-}
+mod twamp_pmtu;
+/// Strategy generated by `Twamp` for the configured mode.
+pub type TwampStrategy = Box<dyn Strategy<TwampResult, CommonError>>;
 
 pub struct Twamp {
     configuration: TwampConfiguration,
+    sinks: TwampSinks,
+    stop_handle: Option<StopHandle>,
+    monitor: SessionMonitor,
+    reload_handle: ReloadHandle,
 }
 
 impl Twamp {
     pub fn new(configuration: TwampConfiguration) -> Self {
-        Self { configuration }
+        Self {
+            configuration,
+            sinks: TwampSinks::new(),
+            stop_handle: None,
+            monitor: SessionMonitor::default(),
+            reload_handle: ReloadHandle::default(),
+        }
     }
 
-    pub fn generate(&self) -> Result<Box<dyn Strategy<TwampResult, CommonError>>, CommonError> {
-        let test_session_hosts = self
-            .configuration
-            .test_session_hosts
-            .iter()
-            .flat_map(|host| host.clone())
-            .collect::<Vec<SocketAddr>>();
-        let source_ip = self
-            .configuration
-            .clone()
-            .source_ip_address
-            .unwrap_or("0.0.0.0:0".to_string())
-            .parse()?;
-        match self.configuration.mode.as_str() {
-            "LIGHT_SENDER" => {
-                let configuration = LightConfiguration::new(
-                    &test_session_hosts,
-                    &source_ip,
-                    self.configuration.collection_period.unwrap_or_default(),
-                    self.configuration.packet_interval.unwrap_or_default(),
-                    self.configuration.padding.unwrap_or_default(),
-                    self.configuration.last_message_timeout.unwrap_or_default(),
-                );
-                configuration
-                    .validate()
-                    .map_err(CommonError::ValidationError)?;
-                let twamp_light = SessionSender::new(&configuration);
+    /// Sets the channel through which the `LIGHT_SENDER` delivers its interval reports
+    /// when `report_interval` is configured.
+    pub fn set_report_channel(&mut self, sender: Sender<IntervalReport>) {
+        self.add_sink(Arc::new(Mutex::new(ReportChannel::new(sender))));
+    }
+
+    /// Adds a sink to the generated strategies, along with the `sinks` of the configuration.
+    pub fn add_sink(&mut self, sink: TwampSink) {
+        self.sinks.add(sink);
+    }
+
+    /// Sets the `StopHandle` given to the generated strategies.
+    pub fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+
+    /// Sets the monitor listing the sessions of the generated `LIGHT_REFLECTOR` and
+    /// `FULL_REFLECTOR` strategies.
+    pub fn set_session_monitor(&mut self, monitor: SessionMonitor) {
+        self.monitor = monitor;
+    }
+
+    /// Sets the handle reloading the configuration of the generated `LIGHT_REFLECTOR` and
+    /// `FULL_REFLECTOR` strategies while they run.
+    pub fn set_reload_handle(&mut self, reload_handle: ReloadHandle) {
+        self.reload_handle = reload_handle;
+    }
+
+    /// Generates the strategy along with the `StopHandle` that stops it.
+    /// A new handle is created if none was set.
+    pub fn generate_with_stop_handle(
+        &mut self,
+    ) -> Result<(TwampStrategy, StopHandle), CommonError> {
+        let stop_handle = match &self.stop_handle {
+            Some(stop_handle) => stop_handle.clone(),
+            None => StopHandle::new()?,
+        };
+        self.stop_handle = Some(stop_handle.clone());
+        Ok((self.generate()?, stop_handle))
+    }
+
+    pub fn generate(&self) -> Result<TwampStrategy, CommonError> {
+        let mut sinks = self.sinks.clone();
+        for configuration in &self.configuration.sinks {
+            sinks.add(configuration.create()?);
+        }
+        let strategy = self.generate_strategy(&mut sinks)?;
+        let mut strategy: TwampStrategy = Box::new(SinkStrategy { strategy, sinks });
+        if let Some(stop_handle) = &self.stop_handle {
+            strategy.set_stop_handle(stop_handle.clone());
+        }
+        Ok(strategy)
+    }
+
+    /// Generates the strategy of the mode, adding the sinks of its configuration to `sinks`
+    /// so they are started and completed along with the others.
+    fn generate_strategy(&self, sinks: &mut TwampSinks) -> Result<TwampStrategy, CommonError> {
+        self.configuration
+            .validate()
+            .map_err(CommonError::ValidationError)?;
+        match &self.configuration.mode {
+            TwampMode::LightSender(configuration) => {
+                let mut twamp_light = SessionSender::new(configuration);
+                twamp_light
+                    .create_sinks()?
+                    .into_iter()
+                    .for_each(|sink| sinks.add(sink));
+                twamp_light.set_sinks(sinks.clone());
                 Ok(Box::new(twamp_light))
             }
-            "LIGHT_REFLECTOR" => {
-                let configuration = ReflectorConfiguration::new(
-                    &source_ip,
-                    self.configuration.ref_wait.unwrap_or(900),
-                );
-                configuration
-                    .validate()
-                    .map_err(CommonError::ValidationError)?;
-                Ok(Box::new(Reflector::new(configuration)))
+            TwampMode::LightPmtu(configuration) => {
+                Ok(Box::new(PathMtuDiscovery::new(configuration.clone())))
             }
-            "FULL_SENDER" => {
+            TwampMode::LightReflector(configuration) => {
+                let mut reflector = Reflector::new(configuration.clone());
+                reflector.set_session_monitor(self.monitor.clone());
+                reflector.set_reload_handle(self.reload_handle.clone());
+                Ok(Box::new(reflector))
+            }
+            TwampMode::FullSender(configuration) => {
                 let control_configuration = ClientConfiguration::new(
-                    &self.configuration.mode,
-                    &source_ip,
-                    self.configuration.control_host.as_ref().unwrap(),
-                );
-                let sesssion_configuration = LightConfiguration::new(
-                    &test_session_hosts,
-                    &source_ip,
-                    self.configuration.collection_period.unwrap_or_default(),
-                    self.configuration.packet_interval.unwrap_or_default(),
-                    self.configuration.padding.unwrap_or_default(),
-                    self.configuration.last_message_timeout.unwrap_or_default(),
+                    self.configuration.mode.name(),
+                    &configuration.session.source_ip_address,
+                    &configuration.control_host,
                 );
-                Ok(Box::new(ControlClient::new(
-                    &control_configuration,
-                    &sesssion_configuration,
-                )))
+                let mut control_client =
+                    ControlClient::new(&control_configuration, &configuration.session);
+                control_client
+                    .create_sinks()?
+                    .into_iter()
+                    .for_each(|sink| sinks.add(sink));
+                control_client.set_sinks(sinks.clone());
+                Ok(Box::new(control_client))
             }
-            "FULL_REFLECTOR" => {
-                let configuration = ControlConfiguration {
-                    source_ip_address: self
-                        .configuration
-                        .clone()
-                        .source_ip_address
-                        .unwrap_or("0.0.0.0:0".to_string())
-                        .parse()?,
-                    ref_wait: self.configuration.last_message_timeout.unwrap_or(900),
-                };
-                configuration
-                    .validate()
-                    .map_err(CommonError::ValidationError)?;
-                Ok(Box::new(Control::new(configuration)))
+            TwampMode::FullReflector(configuration) => {
+                let mut control = Control::new(configuration.clone());
+                control.set_session_monitor(self.monitor.clone());
+                control.set_reload_handle(self.reload_handle.clone());
+                Ok(Box::new(control))
             }
-            _ => panic!("No such mode"),
         }
     }
 }
+
+/// Delivers the start and the result of a strategy to the sinks of the test.
+struct SinkStrategy {
+    strategy: TwampStrategy,
+    sinks: TwampSinks,
+}
+
+impl Strategy<TwampResult, CommonError> for SinkStrategy {
+    fn execute(&mut self) -> Result<TwampResult, CommonError> {
+        self.sinks.start()?;
+        let result = self.strategy.execute();
+        match &result {
+            Ok(result) => self.sinks.complete(result),
+            Err(e) => self.sinks.complete(&TwampResult {
+                session_results: vec![],
+                error: Some(e.to_string()),
+            }),
+        }
+        result
+    }
+
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.strategy.set_stop_handle(stop_handle);
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::HashSet, time::Duration};
+
+use network_commons::{
+    error::CommonError,
+    schedule::{CronExpression, Schedule},
+    scheduler::{Job, Scheduler},
+};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    configuration::TwampConfiguration,
+    twamp_common::{monitor::SessionMonitor, reload::ReloadHandle},
+    twamp_light_sender::sink::SinkConfiguration,
+    Twamp, TwampMode, TwampResult,
+};
+
+const DEFAULT_MAX_CONCURRENT_JOBS: usize = 4;
+
+/// Jobs of a monitoring agent, each running a TWAMP test on its schedule.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[validate(schema(function = "validate_job_names"))]
+pub struct SchedulerConfiguration {
+    /// Largest number of scheduled tests running at the same time. Jobs without a schedule,
+    /// such as reflectors, don't count.
+    #[serde(default = "default_max_concurrent_jobs")]
+    #[validate(range(min = 1, message = "max_concurrent_jobs must be at least 1"))]
+    pub max_concurrent_jobs: usize,
+    #[serde(default)]
+    #[validate]
+    pub jobs: Vec<JobConfiguration>,
+}
+
+/// A TWAMP test run on a schedule, with `every` a number of seconds or `cron` an expression.
+///
+/// A job without a schedule runs once when it is added, then whenever it is asked to.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[validate(schema(function = "validate_job_schedule"))]
+pub struct JobConfiguration {
+    /// Name of the job, which the management API takes from the path instead
+    #[serde(default)]
+    #[validate(length(min = 1, message = "jobs must have a name"))]
+    pub name: String,
+    /// Period of the runs, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "every must be at least 1 second"))]
+    pub every: Option<u64>,
+    /// Minutes of the runs, in UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<CronExpression>,
+    /// Largest random delay of every run, in seconds
+    #[serde(default)]
+    pub jitter: u64,
+    /// Test of every run, whose `sinks` receive the results of the job. Scheduled runs
+    /// append to the files of the sinks unless they are set to replace them.
+    #[validate]
+    pub configuration: TwampConfiguration,
+}
+
+impl SchedulerConfiguration {
+    /// Validates the jobs and makes them a scheduler.
+    pub fn scheduler(&self) -> Result<Scheduler<TwampResult>, CommonError> {
+        self.validate().map_err(CommonError::ValidationError)?;
+        let mut scheduler = Scheduler::new(self.max_concurrent_jobs)?;
+        for job in &self.jobs {
+            scheduler.add_job(job.job())?;
+        }
+        Ok(scheduler)
+    }
+}
+
+impl JobConfiguration {
+    /// When the job runs, if it is scheduled.
+    pub fn schedule(&self) -> Option<Schedule> {
+        match (self.every, &self.cron) {
+            (Some(period), _) => Some(Schedule::Every(period)),
+            (None, Some(expression)) => Some(Schedule::Cron(expression.clone())),
+            (None, None) => None,
+        }
+    }
+
+    /// Job whose runs generate the strategy of the configuration.
+    pub fn job(&self) -> Job<TwampResult> {
+        self.managed_job(SessionMonitor::default(), ReloadHandle::default())
+    }
+
+    /// Job whose runs report their sessions to `monitor` and reload their configuration
+    /// through `reload_handle`.
+    pub(crate) fn managed_job(
+        &self,
+        monitor: SessionMonitor,
+        reload_handle: ReloadHandle,
+    ) -> Job<TwampResult> {
+        let mut configuration = self.configuration.clone();
+        if self.schedule().is_some() {
+            configuration
+                .sinks
+                .iter_mut()
+                .for_each(SinkConfiguration::append_by_default);
+        }
+        Job::new(self.name.clone(), self.schedule(), move || {
+            let mut twamp = Twamp::new(configuration.clone());
+            twamp.set_session_monitor(monitor.clone());
+            twamp.set_reload_handle(reload_handle.clone());
+            twamp.generate()
+        })
+        .with_jitter(Duration::from_secs(self.jitter))
+    }
+}
+
+fn default_max_concurrent_jobs() -> usize {
+    DEFAULT_MAX_CONCURRENT_JOBS
+}
+
+/// Results are reported by job name, so names must be unique.
+fn validate_job_names(configuration: &SchedulerConfiguration) -> Result<(), ValidationError> {
+    let mut names = HashSet::new();
+    if !configuration
+        .jobs
+        .iter()
+        .all(|job| names.insert(job.name.as_str()))
+    {
+        return Err(ValidationError::new("every job must have a different name"));
+    }
+    Ok(())
+}
+
+/// A job has at most one schedule, and a scheduled run must end on its own for the next one
+/// to start, which rules out the reflectors and the continuous senders.
+fn validate_job_schedule(job: &JobConfiguration) -> Result<(), ValidationError> {
+    if job.every.is_some() && job.cron.is_some() {
+        return Err(ValidationError::new(
+            "a job runs either every few seconds or on a cron expression, not both",
+        ));
+    }
+    if job.schedule().is_none() {
+        return Ok(());
+    }
+    match &job.configuration.mode {
+        TwampMode::LightSender(configuration) if !configuration.continuous => Ok(()),
+        TwampMode::FullSender(configuration) if !configuration.session.continuous => Ok(()),
+        TwampMode::LightPmtu(_) => Ok(()),
+        _ => Err(ValidationError::new(
+            "scheduled jobs must run a LIGHT_SENDER, FULL_SENDER or LIGHT_PMTU test that is not continuous",
+        )),
+    }
+}
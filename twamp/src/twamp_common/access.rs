@@ -0,0 +1,95 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Network of senders, such as `10.0.0.0/8`, `2001:db8::/32` or a single address.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Subnet {
+    address: IpAddr,
+    prefix_length: u8,
+}
+
+impl Subnet {
+    /// Whether the subnet holds `address`, IPv4-mapped IPv6 addresses being read as the IPv4
+    /// addresses they map, as dual-stack sockets report their IPv4 senders.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_length as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_length as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_length) = match s.split_once('/') {
+            Some((address, prefix_length)) => (address, Some(prefix_length)),
+            None => (s, None),
+        };
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("invalid subnet '{}': invalid address", s))?;
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_length = match prefix_length {
+            Some(prefix_length) => prefix_length
+                .parse()
+                .ok()
+                .filter(|prefix_length| *prefix_length <= max)
+                .ok_or_else(|| format!("invalid subnet '{}': invalid prefix length", s))?,
+            None => max,
+        };
+        // An IPv4-mapped network is kept as the IPv4 network it maps
+        if let IpAddr::V6(mapped) = address {
+            if let (Some(address), 96..) = (mapped.to_ipv4_mapped(), prefix_length) {
+                return Ok(Self {
+                    address: IpAddr::V4(address),
+                    prefix_length: prefix_length - 96,
+                });
+            }
+        }
+        Ok(Self {
+            address,
+            prefix_length,
+        })
+    }
+}
+
+impl TryFrom<String> for Subnet {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Subnet> for String {
+    fn from(subnet: Subnet) -> Self {
+        subnet.to_string()
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_length)
+    }
+}
+
+/// Whether a sender belongs to one of the `allowed` networks, any sender being allowed when
+/// there are none.
+pub fn is_allowed(allowed: &[Subnet], address: IpAddr) -> bool {
+    allowed.is_empty() || allowed.iter().any(|subnet| subnet.contains(address))
+}
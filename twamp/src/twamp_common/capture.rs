@@ -0,0 +1,432 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::BufWriter,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+use network_commons::{
+    error::CommonError,
+    pcap::{CaptureConfiguration, Datagram, Direction, PcapWriter},
+    time::DateTime,
+};
+
+/// Largest number of packets waiting for the transmit timestamp of a sent packet.
+const MAX_PENDING: usize = 4096;
+
+/// A packet waiting to be written.
+#[derive(Debug)]
+struct PendingDatagram {
+    /// Session index and sequence number of a sent packet waiting for its transmit timestamp
+    sent: Option<(usize, u32)>,
+    direction: Direction,
+    source: SocketAddr,
+    destination: SocketAddr,
+    tos: u8,
+    /// Receive timestamp, or time at which a sent packet was handed to the kernel
+    timestamp: DateTime,
+    payload: Vec<u8>,
+}
+
+/// Writes the test packets sent and received on a socket to a capture file.
+///
+/// Received packets are written with their kernel receive timestamp. Sent packets are kept
+/// until their kernel transmit timestamp is retrieved from the error queue, along with the
+/// packets received after them so the file stays in time order. Sent packets that never get
+/// their transmit timestamp are written with the time they were handed to the kernel.
+///
+/// When the socket is bound to an unspecified address, the local address of a packet is the
+/// one it was received on, or the one the kernel routes the packets to its peer from.
+#[derive(Debug)]
+pub struct PacketCapture {
+    writer: PcapWriter<BufWriter<File>>,
+    local_address: SocketAddr,
+    /// Local address of the packets exchanged with a peer, when the socket is bound to an
+    /// unspecified address
+    local_ips: HashMap<IpAddr, IpAddr>,
+    /// Packets waiting for the transmit timestamp of a sent packet, in processing order
+    pending: VecDeque<PendingDatagram>,
+}
+
+impl PacketCapture {
+    /// Creates the capture file of the packets of the socket bound to `local_address`.
+    pub fn create(
+        configuration: &CaptureConfiguration,
+        local_address: SocketAddr,
+    ) -> Result<Self, CommonError> {
+        Ok(Self {
+            writer: PcapWriter::create(configuration)?,
+            local_address,
+            local_ips: HashMap::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Keeps a packet sent by the session at `session_index` until its transmit timestamp
+    /// is known.
+    pub fn sent(
+        &mut self,
+        session_index: usize,
+        sender_seq: u32,
+        destination: SocketAddr,
+        tos: u8,
+        payload: Vec<u8>,
+    ) -> Result<(), CommonError> {
+        let source = self.local_address_for(destination.ip());
+        self.push_pending(PendingDatagram {
+            sent: Some((session_index, sender_seq)),
+            direction: Direction::Outbound,
+            source,
+            destination,
+            tos,
+            timestamp: DateTime::utc_now(),
+            payload,
+        })
+    }
+
+    /// Writes a pending sent packet with its transmit `timestamp`, followed by the packets
+    /// received after it.
+    /// Timestamps are delivered in sending order, so the packets sent before it never get
+    /// theirs.
+    pub fn transmitted(
+        &mut self,
+        session_index: usize,
+        sender_seq: u32,
+        timestamp: DateTime,
+    ) -> Result<(), CommonError> {
+        let Some(position) = self
+            .pending
+            .iter()
+            .position(|pending| pending.sent == Some((session_index, sender_seq)))
+        else {
+            return Ok(());
+        };
+        for _ in 0..position {
+            self.write_oldest_pending()?;
+        }
+        if let Some(mut pending) = self.pending.pop_front() {
+            pending.timestamp = timestamp;
+            self.write_pending(&pending)?;
+        }
+        while self
+            .pending
+            .front()
+            .is_some_and(|pending| pending.sent.is_none())
+        {
+            self.write_oldest_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Writes a packet sent to `destination` at `timestamp`.
+    pub fn write_sent(
+        &mut self,
+        destination: SocketAddr,
+        tos: u8,
+        timestamp: DateTime,
+        payload: &[u8],
+    ) -> Result<(), CommonError> {
+        let source = self.local_address_for(destination.ip());
+        self.writer.write(&Datagram {
+            timestamp,
+            direction: Direction::Outbound,
+            source,
+            destination,
+            tos,
+            payload,
+        })
+    }
+
+    /// Writes a packet received from `source` on the local address `destination` at
+    /// `timestamp`, once the packets sent before it are written. `destination` is only
+    /// needed when the socket is bound to an unspecified address.
+    pub fn received(
+        &mut self,
+        source: SocketAddr,
+        destination: Option<IpAddr>,
+        timestamp: DateTime,
+        payload: &[u8],
+    ) -> Result<(), CommonError> {
+        if let Some(destination) = destination {
+            if self.local_address.ip().is_unspecified() {
+                self.local_ips.insert(source.ip(), destination);
+            }
+        }
+        let destination = self.local_address_for(source.ip());
+        if !self.pending.is_empty() {
+            return self.push_pending(PendingDatagram {
+                sent: None,
+                direction: Direction::Inbound,
+                source,
+                destination,
+                tos: 0,
+                timestamp,
+                payload: payload.to_vec(),
+            });
+        }
+        self.writer.write(&Datagram {
+            timestamp,
+            direction: Direction::Inbound,
+            source,
+            destination,
+            tos: 0,
+            payload,
+        })
+    }
+
+    pub fn flush(&mut self) -> Result<(), CommonError> {
+        self.writer.flush()
+    }
+
+    /// Writes the packets still waiting for a transmit timestamp and flushes the file.
+    /// This is meant to be called once the test is over.
+    pub fn finish(&mut self) -> Result<(), CommonError> {
+        while !self.pending.is_empty() {
+            self.write_oldest_pending()?;
+        }
+        self.flush()
+    }
+
+    /// Local address of the packets exchanged with `peer`.
+    fn local_address_for(&mut self, peer: IpAddr) -> SocketAddr {
+        if !self.local_address.ip().is_unspecified() {
+            return self.local_address;
+        }
+        let unspecified = self.local_address.ip();
+        let ip = *self
+            .local_ips
+            .entry(peer)
+            .or_insert_with(|| routed_ip(unspecified, peer).unwrap_or(unspecified));
+        SocketAddr::new(ip, self.local_address.port())
+    }
+
+    fn push_pending(&mut self, pending: PendingDatagram) -> Result<(), CommonError> {
+        if self.pending.len() >= MAX_PENDING {
+            self.write_oldest_pending()?;
+        }
+        self.pending.push_back(pending);
+        Ok(())
+    }
+
+    fn write_oldest_pending(&mut self) -> Result<(), CommonError> {
+        if let Some(pending) = self.pending.pop_front() {
+            self.write_pending(&pending)?;
+        }
+        Ok(())
+    }
+
+    fn write_pending(&mut self, pending: &PendingDatagram) -> Result<(), CommonError> {
+        self.writer.write(&Datagram {
+            timestamp: pending.timestamp,
+            direction: pending.direction,
+            source: pending.source,
+            destination: pending.destination,
+            tos: pending.tos,
+            payload: &pending.payload,
+        })
+    }
+}
+
+/// Local address the kernel sends the packets to `peer` from. Connecting a UDP socket
+/// resolves the route without sending anything.
+fn routed_ip(unspecified: IpAddr, peer: IpAddr) -> Option<IpAddr> {
+    let socket = UdpSocket::bind(SocketAddr::new(unspecified, 0)).ok()?;
+    socket.connect(SocketAddr::new(peer, 9)).ok()?;
+    socket.local_addr().ok().map(|address| address.ip())
+}
+
+/// A packet handed to the thread of a `CaptureThread`.
+#[derive(Debug)]
+enum CaptureEvent {
+    Sent {
+        session_index: usize,
+        sender_seq: u32,
+        destination: SocketAddr,
+        tos: u8,
+        payload: Vec<u8>,
+    },
+    Transmitted {
+        session_index: usize,
+        sender_seq: u32,
+        timestamp: DateTime,
+    },
+    Reflected {
+        destination: SocketAddr,
+        tos: u8,
+        timestamp: DateTime,
+        payload: Vec<u8>,
+    },
+    Received {
+        source: SocketAddr,
+        destination: Option<IpAddr>,
+        timestamp: DateTime,
+        payload: Vec<u8>,
+    },
+    Finish,
+}
+
+/// Handle through which the callbacks of a test hand their packets to a `CaptureThread`,
+/// without waiting for the capture file.
+///
+/// Packets handed once the capture is finished are dropped.
+#[derive(Debug, Clone)]
+pub struct CaptureHandle {
+    events: Sender<CaptureEvent>,
+}
+
+impl CaptureHandle {
+    /// Hands a packet sent by the session at `session_index`, see `PacketCapture::sent`.
+    pub fn sent(
+        &self,
+        session_index: usize,
+        sender_seq: u32,
+        destination: SocketAddr,
+        tos: u8,
+        payload: Vec<u8>,
+    ) {
+        self.send(CaptureEvent::Sent {
+            session_index,
+            sender_seq,
+            destination,
+            tos,
+            payload,
+        });
+    }
+
+    /// Hands the transmit timestamp of a sent packet, see `PacketCapture::transmitted`.
+    pub fn transmitted(&self, session_index: usize, sender_seq: u32, timestamp: DateTime) {
+        self.send(CaptureEvent::Transmitted {
+            session_index,
+            sender_seq,
+            timestamp,
+        });
+    }
+
+    /// Hands a packet sent to `destination` at `timestamp`, see `PacketCapture::write_sent`.
+    pub fn write_sent(
+        &self,
+        destination: SocketAddr,
+        tos: u8,
+        timestamp: DateTime,
+        payload: Vec<u8>,
+    ) {
+        self.send(CaptureEvent::Reflected {
+            destination,
+            tos,
+            timestamp,
+            payload,
+        });
+    }
+
+    /// Hands a received packet, see `PacketCapture::received`.
+    pub fn received(
+        &self,
+        source: SocketAddr,
+        destination: Option<IpAddr>,
+        timestamp: DateTime,
+        payload: &[u8],
+    ) {
+        self.send(CaptureEvent::Received {
+            source,
+            destination,
+            timestamp,
+            payload: payload.to_vec(),
+        });
+    }
+
+    fn send(&self, event: CaptureEvent) {
+        if self.events.send(event).is_err() {
+            log::debug!("The capture is finished, dropping a packet");
+        }
+    }
+}
+
+/// Thread writing the packets handed by the callbacks of a test to a capture file, so the
+/// callbacks never wait for the file. The file is flushed whenever the thread runs out of
+/// packets to write.
+#[derive(Debug)]
+pub struct CaptureThread {
+    handle: CaptureHandle,
+    worker: JoinHandle<Result<(), CommonError>>,
+}
+
+impl CaptureThread {
+    /// Creates the capture file of the packets of the socket bound to `local_address` and
+    /// starts the thread writing to it.
+    pub fn spawn(
+        configuration: &CaptureConfiguration,
+        local_address: SocketAddr,
+    ) -> Result<Self, CommonError> {
+        let capture = PacketCapture::create(configuration, local_address)?;
+        let (events, receiver) = mpsc::channel();
+        let worker = std::thread::Builder::new()
+            .name("capture".to_string())
+            .spawn(move || write_events(capture, receiver))?;
+        Ok(Self {
+            handle: CaptureHandle { events },
+            worker,
+        })
+    }
+
+    pub fn handle(&self) -> CaptureHandle {
+        self.handle.clone()
+    }
+
+    /// Writes the packets handed so far, including those still waiting for a transmit
+    /// timestamp, and stops the thread.
+    /// This is meant to be called once the test is over.
+    pub fn finish(self) -> Result<(), CommonError> {
+        self.handle.send(CaptureEvent::Finish);
+        self.worker
+            .join()
+            .map_err(|_| CommonError::Generic("The capture thread panicked".to_string()))?
+    }
+}
+
+fn write_events(
+    mut capture: PacketCapture,
+    events: Receiver<CaptureEvent>,
+) -> Result<(), CommonError> {
+    while let Ok(event) = events.recv() {
+        let mut next = Some(event);
+        while let Some(event) = next.take() {
+            let written = match event {
+                CaptureEvent::Sent {
+                    session_index,
+                    sender_seq,
+                    destination,
+                    tos,
+                    payload,
+                } => capture.sent(session_index, sender_seq, destination, tos, payload),
+                CaptureEvent::Transmitted {
+                    session_index,
+                    sender_seq,
+                    timestamp,
+                } => capture.transmitted(session_index, sender_seq, timestamp),
+                CaptureEvent::Reflected {
+                    destination,
+                    tos,
+                    timestamp,
+                    payload,
+                } => capture.write_sent(destination, tos, timestamp, &payload),
+                CaptureEvent::Received {
+                    source,
+                    destination,
+                    timestamp,
+                    payload,
+                } => capture.received(source, destination, timestamp, &payload),
+                CaptureEvent::Finish => return capture.finish(),
+            };
+            if let Err(e) = written {
+                log::error!("Failed to capture a packet: {}", e);
+            }
+            next = events.try_recv().ok();
+        }
+        if let Err(e) = capture.flush() {
+            log::error!("Failed to flush the capture file: {}", e);
+        }
+    }
+    capture.finish()
+}
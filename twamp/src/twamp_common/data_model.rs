@@ -22,6 +22,12 @@ pub struct PacketResults {
     pub t3: Option<DateTime>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub t4: Option<DateTime>,
+    /// Size of the sent IP packet, when the test uses a size profile
+    #[serde(default)]
+    pub size: Option<usize>,
+    /// TTL of the sent packet when it reached the reflector, as reported by the reflector
+    #[serde(default)]
+    pub sender_ttl: Option<u8>,
 }
 /// `SessionPackets` holds the address and optionally the packets of a test session.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -44,13 +50,21 @@ impl Serialize for PacketResults {
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("PacketResults", 6)?;
+        let mut s = serializer.serialize_struct("PacketResults", 8)?;
         s.serialize_field("sender_seq", &self.sender_seq)?;
         s.serialize_field("reflector_seq", &self.reflector_seq)?;
         s.serialize_field("t1", &self.t1)?;
         s.serialize_field("t2", &self.t2)?;
         s.serialize_field("t3", &self.t3)?;
         s.serialize_field("t4", &self.t4)?;
+        match self.size {
+            Some(size) => s.serialize_field("size", &size)?,
+            None => s.skip_field("size")?,
+        }
+        match self.sender_ttl {
+            Some(sender_ttl) => s.serialize_field("sender_ttl", &sender_ttl)?,
+            None => s.skip_field("sender_ttl")?,
+        }
         s.end()
     }
 }
@@ -209,6 +223,7 @@ pub enum SenderSessionState {
 }
 
 /// The control connection.
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct CtrlConnection {
     /// The name of the control connection.
@@ -36,6 +36,8 @@ impl Message for SenderMessage {
             t2: None,
             t3: None,
             t4: None,
+            size: None,
+            sender_ttl: None,
         }
     }
 }
@@ -78,6 +80,8 @@ impl Message for ReflectedMessage {
             t2: DateTime::try_from(self.receive_timestamp).ok(),
             t3: DateTime::try_from(self.timestamp).ok(),
             t4: None,
+            size: None,
+            sender_ttl: Some(self.sender_ttl),
         }
     }
 }
@@ -0,0 +1,209 @@
+use std::{net::SocketAddr, time::Duration};
+
+use network_commons::{
+    error::CommonError,
+    metrics::{MetricType, OpenMetricsEncoder},
+    time::DateTime,
+};
+
+use super::session::Session;
+
+/// Interval at which the metrics exposed by the OpenMetrics endpoint are refreshed
+pub const METRICS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of nanoseconds in a second, the unit of the exposed delays
+const NANOS_PER_SECOND: f64 = 1e9;
+
+/// Counts of the packets of a session that are complete or lost.
+#[derive(Debug, Default, Clone, Copy)]
+struct PacketCounters {
+    /// Sequence number of the last counted packet
+    last_seq: Option<u32>,
+    sent: u64,
+    lost: u64,
+}
+
+/// Renders the metrics of the sessions of a running sender.
+///
+/// The delay histograms and jitters come from the streaming statistics of the sessions.
+/// The packet counters only count the packets sent before the last message timeout, so a
+/// packet is never counted as lost while its reflection may still arrive, and they keep
+/// growing when the retention window of a continuous test drops old packets.
+#[derive(Debug)]
+pub struct SenderMetrics {
+    last_message_timeout: Duration,
+    counters: Vec<PacketCounters>,
+}
+
+impl SenderMetrics {
+    pub fn new(sessions: usize, last_message_timeout: Duration) -> Self {
+        Self {
+            last_message_timeout,
+            counters: vec![PacketCounters::default(); sessions],
+        }
+    }
+
+    /// Counts the packets that aged past the last message timeout and encodes the metrics
+    /// of every session.
+    pub fn encode(&mut self, sessions: &[Session]) -> Result<String, CommonError> {
+        let deadline = DateTime::utc_now() - self.last_message_timeout;
+        let mut snapshots = Vec::with_capacity(sessions.len());
+        for (session, counters) in sessions.iter().zip(self.counters.iter_mut()) {
+            let results = session.results.read()?;
+            // Results are stored in sending order
+            let start = counters.last_seq.map_or(0, |last| {
+                results.partition_point(|packet| packet.sender_seq <= last)
+            });
+            for packet in results[start..]
+                .iter()
+                .take_while(|packet| (packet.t1 - deadline).as_nanos() <= 0)
+            {
+                counters.sent += 1;
+                if packet.t4.is_none() {
+                    counters.lost += 1;
+                }
+                counters.last_seq = Some(packet.sender_seq);
+            }
+            let mut labels = vec![("target", session.tx_socket_address.to_string())];
+            if let Some(label) = &session.label {
+                labels.push(("label", label.clone()));
+            }
+            snapshots.push((labels, session.statistics_snapshot()?, *counters));
+        }
+        let label_refs: Vec<Vec<(&str, &str)>> = snapshots
+            .iter()
+            .map(|(labels, _, _)| {
+                labels
+                    .iter()
+                    .map(|(name, value)| (*name, value.as_str()))
+                    .collect()
+            })
+            .collect();
+
+        let mut encoder = OpenMetricsEncoder::new();
+        encoder.family(
+            "twamp_rtt_seconds",
+            MetricType::Histogram,
+            "Round trip time of the reflected packets",
+        );
+        for (labels, (_, statistics, _)) in label_refs.iter().zip(&snapshots) {
+            encoder.histogram(
+                "twamp_rtt_seconds",
+                labels,
+                &statistics.rtt_histogram,
+                NANOS_PER_SECOND,
+            );
+        }
+        encoder.family(
+            "twamp_forward_owd_seconds",
+            MetricType::Histogram,
+            "One-way delay from the sender to the reflector, including the offset between their clocks",
+        );
+        for (labels, (_, statistics, _)) in label_refs.iter().zip(&snapshots) {
+            encoder.histogram(
+                "twamp_forward_owd_seconds",
+                labels,
+                &statistics.forward_owd_histogram,
+                NANOS_PER_SECOND,
+            );
+        }
+        encoder.family(
+            "twamp_backward_owd_seconds",
+            MetricType::Histogram,
+            "One-way delay from the reflector to the sender, including the offset between their clocks",
+        );
+        for (labels, (_, statistics, _)) in label_refs.iter().zip(&snapshots) {
+            encoder.histogram(
+                "twamp_backward_owd_seconds",
+                labels,
+                &statistics.backward_owd_histogram,
+                NANOS_PER_SECOND,
+            );
+        }
+        encoder.family(
+            "twamp_forward_jitter_seconds",
+            MetricType::Gauge,
+            "Smoothed variation of the forward one-way delay",
+        );
+        for (labels, (_, statistics, _)) in label_refs.iter().zip(&snapshots) {
+            if let Some(jitter) = statistics.forward_jitter.ewma() {
+                encoder.gauge(
+                    "twamp_forward_jitter_seconds",
+                    labels,
+                    jitter / NANOS_PER_SECOND,
+                );
+            }
+        }
+        encoder.family(
+            "twamp_backward_jitter_seconds",
+            MetricType::Gauge,
+            "Smoothed variation of the backward one-way delay",
+        );
+        for (labels, (_, statistics, _)) in label_refs.iter().zip(&snapshots) {
+            if let Some(jitter) = statistics.backward_jitter.ewma() {
+                encoder.gauge(
+                    "twamp_backward_jitter_seconds",
+                    labels,
+                    jitter / NANOS_PER_SECOND,
+                );
+            }
+        }
+        encoder.family(
+            "twamp_packets_sent",
+            MetricType::Counter,
+            "Packets sent before the last message timeout",
+        );
+        for (labels, (_, _, counters)) in label_refs.iter().zip(&snapshots) {
+            encoder.counter("twamp_packets_sent", labels, counters.sent);
+        }
+        encoder.family(
+            "twamp_packets_received",
+            MetricType::Counter,
+            "Packets sent before the last message timeout that were reflected back",
+        );
+        for (labels, (_, _, counters)) in label_refs.iter().zip(&snapshots) {
+            encoder.counter(
+                "twamp_packets_received",
+                labels,
+                counters.sent - counters.lost,
+            );
+        }
+        encoder.family(
+            "twamp_packets_lost",
+            MetricType::Counter,
+            "Packets sent before the last message timeout that were not reflected back",
+        );
+        for (labels, (_, _, counters)) in label_refs.iter().zip(&snapshots) {
+            encoder.counter("twamp_packets_lost", labels, counters.lost);
+        }
+        Ok(encoder.finish())
+    }
+}
+
+/// Encodes the metrics of a reflector listening on `address`.
+pub fn encode_reflector_metrics(
+    address: SocketAddr,
+    sessions: &[Session],
+    reflected_packets: u64,
+) -> String {
+    let address = address.to_string();
+    let labels = [("address", address.as_str())];
+    let mut encoder = OpenMetricsEncoder::new();
+    encoder.family(
+        "twamp_reflector_sessions",
+        MetricType::Gauge,
+        "Sessions of the senders that are currently reflected",
+    );
+    encoder.gauge("twamp_reflector_sessions", &labels, sessions.len() as f64);
+    encoder.family(
+        "twamp_reflector_packets_reflected",
+        MetricType::Counter,
+        "Packets reflected back to their sender",
+    );
+    encoder.counter(
+        "twamp_reflector_packets_reflected",
+        &labels,
+        reflected_packets,
+    );
+    encoder.finish()
+}
@@ -1,4 +1,25 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+pub mod access;
+pub mod capture;
 pub mod data_model;
 pub mod message;
+pub mod metrics;
+pub mod monitor;
+pub mod reload;
 pub mod session;
+pub mod statistics;
 pub const MIN_UNAUTH_PADDING: usize = 27;
+
+/// Time in seconds after which a reflector closes a session that stopped sending, the
+/// REFWAIT of RFC 5357
+pub const DEFAULT_REF_WAIT: u64 = 900;
+
+/// Address the test sockets are bound to when none is configured.
+pub fn default_source_ip_address() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+}
+
+pub fn default_ref_wait() -> u64 {
+    DEFAULT_REF_WAIT
+}
@@ -0,0 +1,89 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use network_commons::time::DateTime;
+use serde::Serialize;
+
+use super::session::Session;
+
+type SessionLister = Box<dyn Fn() -> Vec<ActiveSession> + Send>;
+
+/// Test session currently reflected by a `LIGHT_REFLECTOR` or a `FULL_REFLECTOR`.
+#[derive(Serialize, Debug, Clone)]
+pub struct ActiveSession {
+    /// Address the sender sends its test packets from
+    pub sender: SocketAddr,
+    /// Address the test packets are reflected from
+    pub reflector: SocketAddr,
+    pub reflected_packets: u64,
+    /// When the last test packet of the sender was received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_packet: Option<DateTime>,
+}
+
+impl ActiveSession {
+    fn new(session: &Session) -> Self {
+        Self {
+            sender: session.tx_socket_address,
+            reflector: session.rx_socket_address,
+            reflected_packets: session.seq_number.load(Ordering::SeqCst) as u64,
+            last_packet: session.last_packet(),
+        }
+    }
+}
+
+/// Lists the sessions of the reflector the monitor was given to, while it runs.
+///
+/// Clones share the reflector, so a monitor kept aside sees the sessions of the strategies
+/// generated with its clones.
+#[derive(Clone, Default)]
+pub struct SessionMonitor {
+    lister: Arc<Mutex<Option<SessionLister>>>,
+}
+
+impl SessionMonitor {
+    /// Sessions of the running reflector, empty when no reflector runs.
+    pub fn sessions(&self) -> Vec<ActiveSession> {
+        match self.lister.lock() {
+            Ok(lister) => lister.as_ref().map(|list| list()).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Starts listing the sessions returned by `list`.
+    pub(crate) fn watch<F>(&self, list: F)
+    where
+        F: Fn() -> Vec<ActiveSession> + Send + 'static,
+    {
+        if let Ok(mut lister) = self.lister.lock() {
+            *lister = Some(Box::new(list));
+        }
+    }
+
+    /// Stops listing sessions once the reflector returns.
+    pub(crate) fn clear(&self) {
+        if let Ok(mut lister) = self.lister.lock() {
+            *lister = None;
+        }
+    }
+}
+
+/// Describes the sessions of a reflector.
+pub(crate) fn list_sessions(sessions: &[Session]) -> Vec<ActiveSession> {
+    sessions.iter().map(ActiveSession::new).collect()
+}
+
+impl fmt::Debug for SessionMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionMonitor").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for SessionMonitor {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.lister, &other.lister)
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use network_commons::error::CommonError;
+
+use crate::configuration::TwampMode;
+
+type Reloader = Box<dyn Fn(&TwampMode) -> Result<(), CommonError> + Send>;
+
+/// Reloads the configuration of the reflector the handle was given to, while it runs.
+///
+/// As with the `SessionMonitor`, clones share the reflector.
+#[derive(Clone, Default)]
+pub struct ReloadHandle {
+    reloader: Arc<Mutex<Option<Reloader>>>,
+}
+
+impl ReloadHandle {
+    /// Applies the configuration to the running `LIGHT_REFLECTOR` or `FULL_REFLECTOR`,
+    /// keeping its sessions. Returns whether a reflector was running.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the configuration is invalid, is not of the mode of
+    /// the reflector, or changes a setting that requires a restart. The running configuration
+    /// is left unchanged then.
+    pub fn reload(&self, mode: &TwampMode) -> Result<bool, CommonError> {
+        match self.reloader.lock()?.as_ref() {
+            Some(reload) => reload(mode).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Starts applying the reloaded configurations with `reload`.
+    pub(crate) fn watch<F>(&self, reload: F)
+    where
+        F: Fn(&TwampMode) -> Result<(), CommonError> + Send + 'static,
+    {
+        if let Ok(mut reloader) = self.reloader.lock() {
+            *reloader = Some(Box::new(reload));
+        }
+    }
+
+    /// Stops reloading once the reflector returns.
+    pub(crate) fn clear(&self) {
+        if let Ok(mut reloader) = self.reloader.lock() {
+            *reloader = None;
+        }
+    }
+}
+
+impl fmt::Debug for ReloadHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadHandle").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for ReloadHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.reloader, &other.reloader)
+    }
+}
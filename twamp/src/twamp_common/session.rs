@@ -1,9 +1,13 @@
 use network_commons::{
-    error::CommonError, socket::Socket, stats::offset_estimator::estimate, time::DateTime,
+    error::CommonError,
+    socket::Socket,
+    stats::offset_estimator::{GamlrConfiguration, GamlrEstimator, OffsetEstimate},
+    time::DateTime,
     udp_socket::TimestampedUdpSocket,
 };
 
 use std::{
+    collections::BTreeMap,
     net::SocketAddr,
     os::fd::IntoRawFd,
     sync::{
@@ -12,19 +16,40 @@ use std::{
     },
 };
 
-use super::data_model::{Message, PacketResults, SessionPackets, TimestampsResult};
+use crate::twamp_light_sender::sla::SlaThreshold;
+
+use super::{
+    data_model::{Message, PacketResults, SessionPackets, TimestampsResult},
+    statistics::{ClockOffset, LossCounter, OffsetEstimator, SessionStatistics},
+};
 
 /// A `Session` represents a communication with a remote sender.
 /// It maintains a sequence number and a collection of `PacketResults`.
 /// A session also provides several methods for adding new packets to the session,
 /// getting the latest result, and analyzing packet loss.
+/// The streaming `statistics` are updated in sending order, once a packet has been reflected
+/// and its transmit timestamp has been corrected, or once it is given up on.
+/// Only the computation is streaming: the `results` keep every packet until the end of a
+/// bounded test, as the clock offset, skew and confidence intervals need them. They are only
+/// pruned to the retention window in continuous mode.
 #[derive(Debug)]
 pub struct Session {
     pub rx_socket_address: SocketAddr,
     pub tx_socket_address: SocketAddr,
     pub seq_number: AtomicU32,
     pub results: Arc<RwLock<Vec<PacketResults>>>,
+    pub statistics: Arc<RwLock<SessionStatistics>>,
     pub last_updated: usize,
+    /// Index of the first packet that was not added to the `statistics` yet
+    pub folded: usize,
+    /// Index of the first packet that was not part of a report window yet
+    pub report_cursor: usize,
+    /// Name of the target of the session
+    pub label: Option<String>,
+    /// Free form tags of the target of the session
+    pub tags: BTreeMap<String, String>,
+    /// SLA thresholds evaluated on the results of the session
+    pub sla: Vec<SlaThreshold>,
 }
 
 impl Session {
@@ -35,7 +60,13 @@ impl Session {
             tx_socket_address: tx,
             seq_number: AtomicU32::new(0),
             results: Arc::new(RwLock::new(Vec::new())),
+            statistics: Arc::new(RwLock::new(SessionStatistics::default())),
             last_updated: 0,
+            folded: 0,
+            report_cursor: 0,
+            label: None,
+            tags: BTreeMap::new(),
+            sla: Vec::new(),
         }
     }
 
@@ -44,14 +75,16 @@ impl Session {
     pub fn add_to_received(&self, message: impl Message, t4: DateTime) -> Result<(), CommonError> {
         let mut write_lock = self.results.write()?;
         let packet_results = message.packet_results();
-        if let Some(results) = write_lock
-            .iter_mut()
-            .find(|result| result.sender_seq == packet_results.sender_seq)
+        if let Some(index) = write_lock
+            .iter()
+            .position(|result| result.sender_seq == packet_results.sender_seq)
         {
+            let results = &mut write_lock[index];
             results.reflector_seq = packet_results.reflector_seq;
             results.t2 = packet_results.t2;
             results.t3 = packet_results.t3;
             results.t4 = Some(t4);
+            results.sender_ttl = packet_results.sender_ttl;
             log::debug!("Received packet results {:#?}", results);
         };
         Ok(())
@@ -59,8 +92,17 @@ impl Session {
 
     /// Adds a sent packet to the session's results and increments the sequence number.
     pub fn add_to_sent(&self, message: impl Message) -> Result<(), CommonError> {
-        let packet_result = message.packet_results();
+        self.push_sent(message.packet_results())
+    }
 
+    /// Adds a sent packet of `size` bytes to the session's results and increments the sequence number.
+    pub fn add_sized_to_sent(&self, message: impl Message, size: usize) -> Result<(), CommonError> {
+        let mut packet_result = message.packet_results();
+        packet_result.size = Some(size);
+        self.push_sent(packet_result)
+    }
+
+    fn push_sent(&self, packet_result: PacketResults) -> Result<(), CommonError> {
         self.results
             .write()
             .map(|mut results| results.push(packet_result))?;
@@ -82,115 +124,180 @@ impl Session {
                     t2: last_result.t2,
                     t3: last_result.t3,
                     t4: last_result.t4,
+                    size: last_result.size,
+                    sender_ttl: last_result.sender_ttl,
                 }]),
             },
             error: None,
         })
     }
 
-    /// Updates the transmit timestamps for the packet results based on the provided iterator.
+    /// Returns when the reflector received the last packet of this session.
+    pub fn last_packet(&self) -> Option<DateTime> {
+        let results = self.results.read().ok()?;
+        results.last().and_then(|packet| packet.t2)
+    }
+
+    /// Updates the transmit timestamps of the packets with the provided sequence numbers.
+    /// Timestamps are expected in sending order. Packets whose timestamp was never
+    /// delivered keep the timestamp taken when they were sent.
     pub fn update_tx_timestamps(
         &mut self,
-        mut timestamps: impl Iterator<Item = DateTime>,
+        timestamps: impl Iterator<Item = (u32, DateTime)>,
     ) -> Result<(), CommonError> {
         let mut results = self.results.write()?;
-        for result in results.iter_mut().skip(self.last_updated) {
-            log::debug!("Updating timestamps for packet {}", result.sender_seq);
-            if let Some(timestamp) = timestamps.next() {
-                let delta = timestamp - result.t1;
-                log::debug!("Delta: {:?}", delta);
-
-                result.t1 = timestamp;
-                self.last_updated += 1;
-            }
+        for (sender_seq, timestamp) in timestamps {
+            let Some(index) = results
+                .iter()
+                .skip(self.last_updated)
+                .position(|result| result.sender_seq == sender_seq)
+                .map(|position| self.last_updated + position)
+            else {
+                continue;
+            };
+            log::debug!("Updating timestamps for packet {}", sender_seq);
+            let delta = timestamp - results[index].t1;
+            log::debug!("Delta: {:?}", delta);
+            results[index].t1 = timestamp;
+            self.last_updated = index + 1;
         }
 
         Ok(())
     }
 
-    /// Analyzes the packet loss in this session.
-    /// Returns a tuple containing the counts of forward, backward, and total lost packets.
-    pub fn analyze_packet_loss(&'_ self) -> Result<(u32, u32, u32), CommonError> {
-        let read_lock = self.results.read().map_err(|_| CommonError::Lock)?;
-        let mut forward_loss: i32 = 0;
-
-        let mut total_loss = 0;
-        let mut results: Vec<PacketResults> = read_lock.iter().cloned().collect();
-
-        results.sort_unstable_by_key(|p| p.sender_seq);
-
-        let mut last_successful_sender_seq: Option<u32> = None;
-        let mut last_successful_reflector_seq: Option<u32> = None;
-
-        // Check if the first packet is lost and increment the total_loss counter accordingly
-        if results
-            .first()
-            .map(|p| p.reflector_seq.is_none())
-            .unwrap_or(false)
-        {
-            total_loss += 1;
-        }
-
-        for current in results.iter().skip(1) {
-            if current.reflector_seq.is_none() {
-                total_loss += 1;
-            } else {
-                if let Some(last_sender_seq) = last_successful_sender_seq {
-                    if let Some(last_reflector_seq) = last_successful_reflector_seq {
-                        let current_reflector_seq = current.reflector_seq.unwrap_or(0);
-                        let delta = (current.sender_seq as i32 - last_sender_seq as i32)
-                            - (current_reflector_seq as i32 - last_reflector_seq as i32);
-
-                        if delta >= 0 {
-                            forward_loss += delta;
-                        }
-                    }
-                }
-
-                last_successful_sender_seq = Some(current.sender_seq);
-                last_successful_reflector_seq = current.reflector_seq;
+    /// Adds the packets to the session statistics in sending order, up to the first packet
+    /// that is still pending. A packet is added once it was reflected and its transmit
+    /// timestamp was corrected, or once it was sent before `deadline`, after which it is
+    /// lost if it was not reflected. Without a deadline every packet is added.
+    pub fn fold_statistics(&mut self, deadline: Option<DateTime>) -> Result<(), CommonError> {
+        let results = self.results.read()?;
+        let mut statistics = self.statistics.write()?;
+        for (index, result) in results.iter().enumerate().skip(self.folded) {
+            let complete = index < self.last_updated && result.t4.is_some();
+            let expired = deadline.is_none_or(|deadline| (result.t1 - deadline).as_nanos() <= 0);
+            if !complete && !expired {
+                break;
             }
+            statistics.update(result);
+            self.folded = index + 1;
         }
+        Ok(())
+    }
 
-        let backward_loss = total_loss - forward_loss;
+    /// Adds every remaining packet to the session statistics, including the reflected
+    /// packets whose transmit timestamps were never corrected. This is meant to be called
+    /// once the test is over, since no timestamp correction can happen afterwards.
+    pub fn finalize_statistics(&mut self) -> Result<(), CommonError> {
+        self.fold_statistics(None)?;
+        self.last_updated = self.folded;
+        Ok(())
+    }
 
-        Ok((forward_loss as u32, backward_loss as u32, total_loss as u32))
+    /// Returns a copy of the packets sent since the previous report window up to `deadline`
+    /// and moves the report window forward.
+    /// When `deadline` is `None` all the remaining packets are returned.
+    pub fn next_report_window(
+        &mut self,
+        deadline: Option<DateTime>,
+    ) -> Result<Vec<PacketResults>, CommonError> {
+        let results = self.results.read()?;
+        let window: Vec<PacketResults> = results
+            .iter()
+            .skip(self.report_cursor)
+            .take_while(|result| {
+                deadline
+                    .map(|deadline| (result.t1 - deadline).as_nanos() <= 0)
+                    .unwrap_or(true)
+            })
+            .cloned()
+            .collect();
+        self.report_cursor += window.len();
+        Ok(window)
     }
 
-    /// Calculates the GAMLR offset for this session.
-    /// Uses the provided OrderStatisticsTrees for forward and backward One-Way Delay.
-    pub fn calculate_gamlr_offset(&self, forward_owd: &[f64], backward_owd: &[f64]) -> Option<f64> {
-        // let results = self.results.read().ok()?;
-        if forward_owd.len() < 5 || backward_owd.len() < 5 {
-            return None;
-        }
+    /// Removes the packets sent before `before` from the session's results, once they were
+    /// added to the session statistics. Returns the number of removed packets.
+    pub fn prune_results(&mut self, before: DateTime) -> Result<usize, CommonError> {
+        self.fold_statistics(Some(before))?;
+        let mut results = self.results.write()?;
+        // Results are stored in sending order, so the stale ones are at the front
+        let stale = results
+            .iter()
+            .take_while(|result| (result.t1 - before).as_nanos() < 0)
+            .count();
+        assert!(stale <= self.folded, "pruned packets must be folded first");
+        results.drain(..stale);
+        self.folded -= stale;
+        self.last_updated = self.last_updated.saturating_sub(stale);
+        self.report_cursor = self.report_cursor.saturating_sub(stale);
+        Ok(stale)
+    }
 
-        // Ensure that we have complete chunks for the estimate
-        let f_chunks: Vec<_> = forward_owd
-            .chunks(5)
-            .filter(|chunk| chunk.len() == 5)
-            .collect();
-        let f_len = f_chunks.len();
-        let b_chunks: Vec<_> = backward_owd
-            .chunks(5)
-            .filter(|chunk| chunk.len() == 5)
-            .collect();
-        let b_len = b_chunks.len();
+    /// Returns a copy of the current session statistics.
+    pub fn statistics_snapshot(&self) -> Result<SessionStatistics, CommonError> {
+        Ok(self.statistics.read()?.clone())
+    }
 
-        let mut f_offset = 0.0;
-        let mut b_offset = 0.0;
+    /// Analyzes the packet loss in this session.
+    /// Returns a tuple containing the counts of forward, backward, and total lost packets.
+    pub fn analyze_packet_loss(&'_ self) -> Result<(u32, u32, u32), CommonError> {
+        let read_lock = self.results.read().map_err(|_| CommonError::Lock)?;
+        Ok(analyze_packet_loss(&read_lock))
+    }
 
-        for slice in f_chunks {
-            f_offset += estimate(slice.to_owned());
-        }
-        for slice in b_chunks {
-            b_offset += estimate(slice.to_owned());
-        }
+    /// Calculates the GAMLR offset for this session.
+    /// Uses the provided forward and backward One-Way Delays, in sending order.
+    pub fn calculate_gamlr_offset(&self, forward_owd: &[f64], backward_owd: &[f64]) -> Option<f64> {
+        self.estimate_clock_offset(forward_owd, backward_owd, &GamlrConfiguration::default())
+            .filter(|clock_offset| clock_offset.estimator == OffsetEstimator::Gamlr)
+            .map(|clock_offset| clock_offset.offset())
+    }
 
-        f_offset /= f_len as f64;
-        b_offset /= b_len as f64;
+    /// Estimates the offset of the reflector clock from the forward and backward One-Way
+    /// Delays, in sending order since the GAMLR estimator works on chunks of consecutive delays.
+    /// The GAMLR estimator needs a complete chunk of delays in each direction, otherwise
+    /// the offset is derived from the minimum delays, as it is with invalid settings.
+    pub fn estimate_clock_offset(
+        &self,
+        forward_owd: &[f64],
+        backward_owd: &[f64],
+        configuration: &GamlrConfiguration,
+    ) -> Option<ClockOffset> {
+        let minimum = |owd: &[f64]| owd.iter().copied().reduce(f64::min);
+        let (min_forward, min_backward) = (minimum(forward_owd)?, minimum(backward_owd)?);
+        let estimates = GamlrEstimator::new(configuration.clone())
+            .map_err(|e| log::warn!("Invalid offset estimation settings: {}", e))
+            .ok()
+            .and_then(|mut estimator| {
+                estimator
+                    .estimate(forward_owd)
+                    .zip(estimator.estimate(backward_owd))
+            });
+        let Some((forward, backward)) = estimates else {
+            return Some(ClockOffset {
+                estimator: OffsetEstimator::MinimumDelay,
+                estimate: OffsetEstimate::new(
+                    (min_forward - min_backward) / 2.0,
+                    None,
+                    configuration.confidence_level,
+                    0,
+                ),
+            });
+        };
 
-        Some((f_offset - b_offset) / 2.0)
+        let standard_error = forward
+            .standard_error
+            .zip(backward.standard_error)
+            .map(|(f_error, b_error)| (f_error.powi(2) + b_error.powi(2)).sqrt() / 2.0);
+        Some(ClockOffset {
+            estimator: OffsetEstimator::Gamlr,
+            estimate: OffsetEstimate::new(
+                (forward.offset - backward.offset) / 2.0,
+                standard_error,
+                configuration.confidence_level,
+                forward.chunks.min(backward.chunks),
+            ),
+        })
     }
 
     pub fn create_udp_socket(&mut self) -> Result<TimestampedUdpSocket, CommonError> {
@@ -198,8 +305,22 @@ impl Session {
         let mut my_socket = TimestampedUdpSocket::new(socket.into_raw_fd());
         my_socket.set_fcntl_options()?;
         my_socket.set_socket_options(libc::SOL_IP, libc::IP_RECVERR, Some(1))?;
-        my_socket.set_timestamping_options()?;
+        my_socket.set_rx_timestamping_options()?;
 
         Ok(my_socket)
     }
 }
+
+/// Analyzes the packet loss of a set of packets.
+/// Returns a tuple containing the counts of forward, backward, and total lost packets.
+pub fn analyze_packet_loss(packets: &[PacketResults]) -> (u32, u32, u32) {
+    let mut results: Vec<&PacketResults> = packets.iter().collect();
+    results.sort_unstable_by_key(|p| p.sender_seq);
+    let mut loss = LossCounter::default();
+    results.into_iter().for_each(|packet| loss.update(packet));
+    (
+        loss.forward_loss as u32,
+        loss.backward_loss() as u32,
+        loss.total_loss as u32,
+    )
+}
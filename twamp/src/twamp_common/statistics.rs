@@ -0,0 +1,271 @@
+use network_commons::stats::{
+    offset_estimator::OffsetEstimate,
+    skew_estimator::{SkewEstimate, SkewEstimator},
+    streaming::{Histogram, StreamingSummary, DEFAULT_EWMA_ALPHA},
+    voice_quality::BurstCounter,
+};
+use serde::{Deserialize, Serialize};
+
+use super::data_model::PacketResults;
+
+/// `SessionStatistics` holds the streaming statistics of a test session.
+/// It is updated every time a complete packet is added, so it can be read at any
+/// point of the test without going through the stored `PacketResults`.
+/// All values are in nanoseconds.
+#[derive(Debug, Clone)]
+pub struct SessionStatistics {
+    pub rtt: StreamingSummary,
+    pub forward_owd: StreamingSummary,
+    pub backward_owd: StreamingSummary,
+    pub process_time: StreamingSummary,
+    pub forward_jitter: StreamingSummary,
+    pub backward_jitter: StreamingSummary,
+    /// Distributions of the delays, exposed as metrics
+    pub rtt_histogram: Histogram,
+    pub forward_owd_histogram: Histogram,
+    pub backward_owd_histogram: Histogram,
+    /// Number of reflected packets added to the statistics
+    pub received_packets: usize,
+    /// Losses of the packets added to the statistics
+    pub loss: LossCounter,
+    prev_forward_owd: Option<f64>,
+    prev_backward_owd: Option<f64>,
+}
+
+/// Quantiles tracked for the round trip times, with the tail quantiles used by SLA thresholds
+const RTT_QUANTILES: [f64; 5] = [0.25, 0.5, 0.75, 0.95, 0.99];
+
+impl Default for SessionStatistics {
+    fn default() -> Self {
+        Self {
+            rtt: StreamingSummary::new(&RTT_QUANTILES, DEFAULT_EWMA_ALPHA),
+            forward_owd: StreamingSummary::default(),
+            backward_owd: StreamingSummary::default(),
+            process_time: StreamingSummary::default(),
+            forward_jitter: StreamingSummary::default(),
+            backward_jitter: StreamingSummary::default(),
+            rtt_histogram: Histogram::default(),
+            forward_owd_histogram: Histogram::default(),
+            backward_owd_histogram: Histogram::default(),
+            received_packets: 0,
+            loss: LossCounter::default(),
+            prev_forward_owd: None,
+            prev_backward_owd: None,
+        }
+    }
+}
+
+impl SessionStatistics {
+    /// Adds a packet to the statistics. Packets are expected in sending order, as the loss
+    /// counters and the jitters compare every packet with the previous one.
+    /// Packets that were not reflected only count as lost.
+    pub fn update(&mut self, packet: &PacketResults) {
+        self.loss.update(packet);
+        if packet.t2.is_none() || packet.t3.is_none() {
+            return;
+        }
+        self.received_packets += 1;
+
+        if let Some(rtt) = packet.calculate_rtt() {
+            self.rtt.push(rtt.as_nanos() as f64);
+            self.rtt_histogram.push(rtt.as_nanos() as f64);
+        }
+
+        if let Some(owd) = packet.calculate_owd_forward() {
+            let owd = owd.as_nanos() as f64;
+            self.forward_owd.push(owd);
+            self.forward_owd_histogram.push(owd);
+            if let Some(prev_fwd) = self.prev_forward_owd {
+                self.forward_jitter.push((owd - prev_fwd).abs());
+            }
+            self.prev_forward_owd = Some(owd);
+        }
+
+        if let Some(owd) = packet.calculate_owd_backward() {
+            let owd = owd.as_nanos() as f64;
+            self.backward_owd.push(owd);
+            self.backward_owd_histogram.push(owd);
+            if let Some(prev_bwd) = self.prev_backward_owd {
+                self.backward_jitter.push((owd - prev_bwd).abs());
+            }
+            self.prev_backward_owd = Some(owd);
+        }
+
+        if let Some(rpd) = packet.calculate_rpd() {
+            self.process_time.push(rpd.as_nanos() as f64);
+        }
+    }
+}
+
+/// Counts of the sent and lost packets of a session.
+///
+/// Forward losses are told from backward ones through the reflector sequence numbers: the
+/// reflector numbers the packets it received, so a gap between the sender sequence numbers of
+/// two reflected packets that is wider than the gap between their reflector sequence numbers
+/// is made of packets lost on the way to the reflector.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LossCounter {
+    pub sent: u64,
+    pub forward_loss: u64,
+    pub total_loss: u64,
+    /// Loss pattern of the packets, for the burst ratio of the voice quality
+    pub bursts: BurstCounter,
+    /// Sender and reflector sequence numbers of the last reflected packet
+    last_reflected: Option<(u32, u32)>,
+}
+
+impl LossCounter {
+    /// Counts a packet, which is lost if it was not reflected.
+    pub fn update(&mut self, packet: &PacketResults) {
+        self.sent += 1;
+        self.bursts.push(packet.reflector_seq.is_none());
+        let Some(reflector_seq) = packet.reflector_seq else {
+            self.total_loss += 1;
+            return;
+        };
+        if let Some((last_sender_seq, last_reflector_seq)) = self.last_reflected {
+            let gap = i64::from(packet.sender_seq.wrapping_sub(last_sender_seq))
+                - i64::from(reflector_seq.wrapping_sub(last_reflector_seq));
+            if gap > 0 {
+                self.forward_loss += gap as u64;
+            }
+        }
+        self.last_reflected = Some((packet.sender_seq, reflector_seq));
+    }
+
+    pub fn backward_loss(&self) -> u64 {
+        self.total_loss.saturating_sub(self.forward_loss)
+    }
+
+    /// Ratio of lost packets, 0 when no packet was sent.
+    pub fn loss_ratio(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            self.total_loss as f64 / self.sent as f64
+        }
+    }
+}
+
+/// Method used to estimate the offset between the sender and reflector clocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OffsetEstimator {
+    /// Gamma model based estimator of `stats::offset_estimator`
+    #[default]
+    Gamlr,
+    /// Half the difference between the minimum forward and backward delays,
+    /// used when there are too few delays for the GAMLR estimator
+    MinimumDelay,
+}
+
+/// Estimated offset of the reflector clock relative to the sender clock, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockOffset {
+    pub estimator: OffsetEstimator,
+    pub estimate: OffsetEstimate,
+}
+
+impl ClockOffset {
+    pub fn offset(&self) -> f64 {
+        self.estimate.offset
+    }
+}
+
+/// Estimated skew of the reflector clock relative to the sender clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockSkew {
+    pub estimator: SkewEstimator,
+    /// Drift of the reflector clock in nanoseconds per nanosecond
+    pub skew: f64,
+}
+
+impl ClockSkew {
+    /// Estimates the skew from the forward and backward delays of a session.
+    /// The drift of the reflector clock makes the forward delays grow as much as the
+    /// backward delays shrink, so the skew is half the difference of their slopes.
+    pub fn estimate(estimator: SkewEstimator, owd_series: &OwdSeries) -> Option<Self> {
+        let forward = estimator.estimate(&owd_series.forward_owd)?;
+        let backward = estimator.estimate(&owd_series.backward_owd)?;
+        Some(Self {
+            estimator,
+            skew: (forward.skew - backward.skew) / 2.0,
+        })
+    }
+
+    fn as_estimate(&self, sign: f64) -> SkewEstimate {
+        SkewEstimate {
+            skew: sign * self.skew,
+            intercept: 0.0,
+        }
+    }
+}
+
+/// One-way delays of the reflected packets of a session, paired with the time elapsed
+/// between the first packet and the packet being sent.
+/// All values are in nanoseconds.
+#[derive(Debug, Clone, Default)]
+pub struct OwdSeries {
+    pub forward_owd: Vec<(f64, f64)>,
+    pub backward_owd: Vec<(f64, f64)>,
+}
+
+impl OwdSeries {
+    pub fn new(packets: &[PacketResults]) -> Self {
+        let mut series = Self::default();
+        let Some(start) = packets.first().map(|packet| packet.t1) else {
+            return series;
+        };
+        for packet in packets
+            .iter()
+            .filter(|packet| packet.t2.is_some() && packet.t3.is_some())
+        {
+            let time = (packet.t1 - start).as_nanos() as f64;
+            if let Some(owd) = packet.calculate_owd_forward() {
+                series.forward_owd.push((time, owd.as_nanos() as f64));
+            }
+            if let Some(owd) = packet.calculate_owd_backward() {
+                series.backward_owd.push((time, owd.as_nanos() as f64));
+            }
+        }
+        series
+    }
+
+    /// Returns the forward and backward delays, with the drift of the reflector clock
+    /// since the first packet removed when a skew is provided.
+    pub fn detrended(&self, clock_skew: Option<&ClockSkew>) -> (Vec<f64>, Vec<f64>) {
+        match clock_skew {
+            Some(clock_skew) => (
+                clock_skew.as_estimate(1.0).detrend(&self.forward_owd),
+                clock_skew.as_estimate(-1.0).detrend(&self.backward_owd),
+            ),
+            None => (
+                self.forward_owd.iter().map(|(_, owd)| *owd).collect(),
+                self.backward_owd.iter().map(|(_, owd)| *owd).collect(),
+            ),
+        }
+    }
+}
+
+/// Forward and backward one-way delays of a session, corrected with a `ClockOffset`.
+/// All values are in nanoseconds.
+#[derive(Debug, Clone, Default)]
+pub struct CorrectedOwd {
+    pub forward_owd: StreamingSummary,
+    pub backward_owd: StreamingSummary,
+}
+
+impl CorrectedOwd {
+    /// Removes the clock offset from the forward and backward delays.
+    /// The offset adds to the forward delays and subtracts from the backward ones.
+    pub fn new(forward_owd: &[f64], backward_owd: &[f64], clock_offset: &ClockOffset) -> Self {
+        let mut corrected = Self::default();
+        forward_owd
+            .iter()
+            .for_each(|owd| corrected.forward_owd.push(owd - clock_offset.offset()));
+        backward_owd
+            .iter()
+            .for_each(|owd| corrected.backward_owd.push(owd + clock_offset.offset()));
+        corrected
+    }
+}
@@ -1,26 +1,44 @@
-use libc::close;
 #[cfg(target_os = "linux")]
 use network_commons::epoll_loop::LinuxEventLoop as EventLoop;
 use std::{
+    collections::HashMap,
+    net::SocketAddr,
     os::fd::{AsRawFd, IntoRawFd},
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
 
 use network_commons::{
-    epoll_loop::EventLoopMessages, error::CommonError, event_loop::EventLoopTrait, socket::Socket,
-    tcp_socket::TimestampedTcpSocket, udp_socket::TimestampedUdpSocket, Strategy,
+    epoll_loop::{register_from_thread, DuplexChannel, EventLoopMessages},
+    error::CommonError,
+    event_loop::{CallBack, EventLoopTrait, Itimerspec},
+    socket::Socket,
+    stop_handle::StopHandle,
+    tcp_socket::TimestampedTcpSocket,
+    udp_socket::TimestampedUdpSocket,
+    Strategy,
 };
 
 use crate::{
-    twamp_common::data_model::{Mode, Modes},
+    twamp_common::{
+        access::is_allowed,
+        data_model::{Mode, Modes},
+        monitor::{list_sessions, SessionMonitor},
+        reload::ReloadHandle,
+    },
     twamp_light_sender::result::TwampResult,
+    TwampMode,
 };
 
 use super::{control_session::ControlSession, ControlConfiguration};
+use validator::Validate;
 
 pub struct Control {
     configuration: ControlConfiguration,
     control_sessions: Arc<RwLock<Vec<ControlSession>>>,
+    stop_handle: Option<StopHandle>,
+    monitor: SessionMonitor,
+    reload_handle: ReloadHandle,
 }
 
 impl Control {
@@ -28,16 +46,37 @@ impl Control {
         Self {
             configuration,
             control_sessions: Arc::new(RwLock::new(Vec::new())),
+            stop_handle: None,
+            monitor: SessionMonitor::default(),
+            reload_handle: ReloadHandle::default(),
         }
     }
+
+    /// Sets the monitor listing the test sessions while the server runs.
+    pub fn set_session_monitor(&mut self, monitor: SessionMonitor) {
+        self.monitor = monitor;
+    }
+
+    /// Sets the handle reloading the configuration while the server runs.
+    pub fn set_reload_handle(&mut self, reload_handle: ReloadHandle) {
+        self.reload_handle = reload_handle;
+    }
 }
 
 impl Strategy<TwampResult, CommonError> for Control {
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+
     fn execute(&mut self) -> std::result::Result<TwampResult, CommonError> {
         // std::thread::scope(|scp| {
         let (tx, rx) = std::sync::mpsc::channel();
+        let worker_stop_handle = self.stop_handle.clone();
         let _thread_handle = std::thread::spawn(move || -> std::result::Result<(), CommonError> {
             let mut event_loop: EventLoop<TimestampedUdpSocket> = EventLoop::new(1024).unwrap();
+            if let Some(stop_handle) = &worker_stop_handle {
+                event_loop.register_stop_handle(stop_handle)?;
+            }
             let event_sender = event_loop.get_communication_channel();
             tx.send(event_sender).unwrap();
             event_loop.run()?;
@@ -46,70 +85,241 @@ impl Strategy<TwampResult, CommonError> for Control {
         // Get event sender from worker thread event loop
         let duplex_channel = rx.recv().unwrap();
 
-        // Create the TcpSocket
-        let addr = self.configuration.source_ip_address;
-        let listener = mio::net::TcpListener::bind(addr)?;
-
-        let mut socket = TimestampedTcpSocket::new(listener.into_raw_fd());
-        log::info!("Created tcp socket");
-
-        #[cfg(target_os = "linux")]
-        socket.set_fcntl_options()?;
-        log::info!("Set socket options");
-        socket.set_timestamping_options()?;
-
-        socket.listen(0)?;
         // Create the event loop
         let mut event_loop = EventLoop::new(1024)?;
+        if let Some(stop_handle) = &self.stop_handle {
+            event_loop.register_stop_handle(stop_handle)?;
+        }
 
         let event_sender = event_loop.get_communication_channel();
-        // Register the socket
-        let control_sessions = self.control_sessions.clone();
+        // Settings that a reload changes while the server runs
+        let settings = Arc::new(RwLock::new(self.configuration.clone()));
         // Accept incoming connections
-        let _register_result = event_loop.register_event_source(
-            socket,
-            Box::new(move |listener: &mut TimestampedTcpSocket, token| {
-                let event_sender = event_sender.clone();
-                let (mut timestamped_socket, socket_address) = listener.accept()?;
-                let timestamped_socket_raw_fd = timestamped_socket.as_raw_fd();
-                let wes = duplex_channel.clone();
-
-                let mut modes = Modes::new(0);
-                modes.set(Mode::Unauthenticated);
-
-                let mut control_session =
-                    ControlSession::new(timestamped_socket_raw_fd, modes, 1, 1, wes);
-                log::info!("Accepted connection from {}", socket_address);
-                log::info!("Internal token: {:?}", token);
-
-                control_session.transition(&mut timestamped_socket)?;
-                control_sessions.write().unwrap().push(control_session);
-                let arc_sessions = Arc::clone(&control_sessions);
-                let _ = event_sender.try_lock()?.send(EventLoopMessages::Register((
-                    timestamped_socket,
-                    Box::new(move |socket, _token| {
-                        let mut cs_lock = arc_sessions.try_write().unwrap();
-                        let control_session_entry = cs_lock
-                            .iter_mut()
-                            .find(|session| session.id == socket.as_raw_fd());
-                        if let Some(cs) = control_session_entry {
-                            if let Err(e) = cs.transition(socket) {
-                                log::info!("Closing control socket, {}", e);
-                                unsafe { close(socket.as_raw_fd()) };
-                            }
-                        }
-                        Ok(0)
-                    }),
+        let mut listeners = HashMap::new();
+        let addresses = std::iter::once(&self.configuration.source_ip_address)
+            .chain(&self.configuration.listen_addresses);
+        for address in addresses {
+            let token = event_loop.register_event_source(
+                create_listener(*address)?,
+                create_accept_callback(
+                    self.control_sessions.clone(),
+                    settings.clone(),
+                    duplex_channel.clone(),
+                    event_sender.clone(),
+                ),
+            )?;
+            listeners.insert(*address, token);
+        }
+        // The test sessions of every connection are expired by a timer of the main listener
+        if let Some(token) = listeners.remove(&self.configuration.source_ip_address) {
+            let timer_spec = Itimerspec {
+                it_interval: Duration::from_secs(1),
+                it_value: Duration::from_secs(1),
+            };
+            event_loop.register_timer(
+                &timer_spec,
+                &token,
+                create_expiry_callback(self.control_sessions.clone(), settings.clone()),
+            )?;
+        }
+        let listeners = Mutex::new(listeners);
+        let control_sessions = self.control_sessions.clone();
+        self.reload_handle.watch(move |mode: &TwampMode| {
+            let TwampMode::FullReflector(configuration) = mode else {
+                return Err(CommonError::Generic(format!(
+                    "A FULL_REFLECTOR can't reload a {} configuration",
+                    mode.name()
                 )));
+            };
+            configuration
+                .validate()
+                .map_err(CommonError::ValidationError)?;
+            if configuration.source_ip_address != settings.read()?.source_ip_address {
+                return Err(CommonError::Generic(
+                    "source_ip_address can't change while the server runs".to_string(),
+                ));
+            }
+            let mut listeners = listeners.lock()?;
+            // Binds the added addresses first, so that a failure leaves the server unchanged
+            let added = configuration
+                .listen_addresses
+                .iter()
+                .filter(|address| !listeners.contains_key(address))
+                .map(|address| Ok((*address, create_listener(*address)?)))
+                .collect::<Result<Vec<_>, CommonError>>()?;
+            for (address, listener) in added {
+                let callback = create_accept_callback(
+                    control_sessions.clone(),
+                    settings.clone(),
+                    duplex_channel.clone(),
+                    event_sender.clone(),
+                );
+                let token = register_from_thread(&event_sender, listener, callback)?;
+                listeners.insert(address, token);
+            }
+            let removed: Vec<SocketAddr> = listeners
+                .keys()
+                .filter(|address| !configuration.listen_addresses.contains(address))
+                .copied()
+                .collect();
+            for address in removed {
+                if let Some(token) = listeners.remove(&address) {
+                    event_sender
+                        .lock()?
+                        .send(EventLoopMessages::Unregister(token))?;
+                    log::info!("Stopped listening on {}", address);
+                }
+            }
+            *settings.write()? = configuration.clone();
+            log::info!("Reloaded the configuration of the server");
+            Ok(())
+        });
 
-                Ok(0)
-            }),
-        )?;
-
-        event_loop.run()?;
+        let monitored_sessions = self.control_sessions.clone();
+        self.monitor.watch(move || {
+            let Ok(control_sessions) = monitored_sessions.read() else {
+                return Vec::new();
+            };
+            control_sessions
+                .iter()
+                .filter_map(|control_session| control_session.twamp_sessions().read().ok())
+                .flat_map(|sessions| list_sessions(&sessions))
+                .collect()
+        });
+        let result = event_loop.run();
+        self.monitor.clear();
+        self.reload_handle.clear();
+        result?;
         Ok(TwampResult {
             session_results: Vec::new(),
             error: None,
         })
     }
 }
+
+/// Creates a listening socket for the control connections.
+fn create_listener(address: SocketAddr) -> Result<TimestampedTcpSocket, CommonError> {
+    let listener = mio::net::TcpListener::bind(address)?;
+    let mut socket = TimestampedTcpSocket::new(listener.into_raw_fd());
+    #[cfg(target_os = "linux")]
+    socket.set_fcntl_options()?;
+    socket.set_timestamping_options()?;
+    socket.listen(0)?;
+    log::info!("Listening for control connections on {}", address);
+    Ok(socket)
+}
+
+/// Accepts the control connections of the allowed clients, up to `max_connections`, and
+/// registers them with the event loop.
+fn create_accept_callback(
+    control_sessions: Arc<RwLock<Vec<ControlSession>>>,
+    settings: Arc<RwLock<ControlConfiguration>>,
+    duplex_channel: Arc<Mutex<DuplexChannel<TimestampedUdpSocket>>>,
+    event_sender: Arc<Mutex<DuplexChannel<TimestampedTcpSocket>>>,
+) -> CallBack<TimestampedTcpSocket> {
+    Box::new(move |listener: &mut TimestampedTcpSocket, token| {
+        // Failing to accept a connection must not close the listener
+        let (mut timestamped_socket, socket_address) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(e) => {
+                log::debug!("Failed to accept a control connection: {}", e);
+                return Ok(0);
+            }
+        };
+        {
+            let settings = settings.read()?;
+            if !is_allowed(&settings.allowed_senders, socket_address.ip()) {
+                log::info!(
+                    "Refused connection from {}, which is not allowed",
+                    socket_address
+                );
+                return Ok(0);
+            }
+            let connections = control_sessions.read()?.len();
+            if settings
+                .max_connections
+                .is_some_and(|max| connections >= max)
+            {
+                log::info!(
+                    "Refused connection from {}, the server has {} connections",
+                    socket_address,
+                    connections
+                );
+                return Ok(0);
+            }
+        }
+        let timestamped_socket_raw_fd = timestamped_socket.as_raw_fd();
+        let wes = duplex_channel.clone();
+
+        let mut modes = Modes::new(0);
+        modes.set(Mode::Unauthenticated);
+
+        let mut control_session = ControlSession::new(timestamped_socket_raw_fd, modes, 1, 1, wes);
+        log::info!("Accepted connection from {}", socket_address);
+        log::info!("Internal token: {:?}", token);
+
+        if let Err(e) = control_session.transition(&mut timestamped_socket) {
+            log::info!("Closing control socket, {}", e);
+            return Ok(0);
+        }
+        control_sessions.write()?.push(control_session);
+        let arc_sessions = Arc::clone(&control_sessions);
+        let connection_sender = event_sender.clone();
+        let _ = event_sender.lock()?.send(EventLoopMessages::Register((
+            timestamped_socket,
+            Box::new(move |socket, token| {
+                let mut cs_lock = arc_sessions.write()?;
+                let id = socket.as_raw_fd();
+                let Some(position) = cs_lock.iter().position(|session| session.id == id) else {
+                    return Ok(0);
+                };
+                let result = match is_closed(socket) {
+                    true => Err(CommonError::Generic("closed by the client".to_string())),
+                    false => cs_lock[position].transition(socket),
+                };
+                // Dropping the socket once unregistered closes it
+                if let Err(e) = result {
+                    log::info!("Closing control socket, {}", e);
+                    cs_lock.remove(position);
+                    connection_sender
+                        .lock()?
+                        .send(EventLoopMessages::Unregister(token))?;
+                }
+                Ok(0)
+            }),
+        )));
+
+        Ok(0)
+    })
+}
+
+/// Closes the test sessions that received no packet for the `ref_wait` of the settings,
+/// which a reload changes while the server runs.
+fn create_expiry_callback(
+    control_sessions: Arc<RwLock<Vec<ControlSession>>>,
+    settings: Arc<RwLock<ControlConfiguration>>,
+) -> CallBack<TimestampedTcpSocket> {
+    Box::new(move |_listener: &mut TimestampedTcpSocket, _| {
+        let ref_wait = Duration::from_secs(settings.read()?.ref_wait);
+        for control_session in control_sessions.write()?.iter_mut() {
+            if let Err(e) = control_session.expire_sessions(ref_wait) {
+                log::error!("Failed to expire the test sessions: {}", e);
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Whether the client closed the connection, leaving nothing to read.
+fn is_closed(socket: &TimestampedTcpSocket) -> bool {
+    let mut byte = 0u8;
+    let result = unsafe {
+        libc::recv(
+            socket.as_raw_fd(),
+            &mut byte as *mut u8 as *mut libc::c_void,
+            1,
+            libc::MSG_PEEK | libc::MSG_DONTWAIT,
+        )
+    };
+    result == 0
+}
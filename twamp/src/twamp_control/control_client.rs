@@ -1,5 +1,5 @@
 use std::{
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
     time::Duration,
 };
 
@@ -8,6 +8,7 @@ use network_commons::{
     error::CommonError,
     event_loop::{EventLoopTrait, Itimerspec},
     socket::Socket,
+    stop_handle::StopHandle,
     tcp_socket::TimestampedTcpSocket,
     udp_socket::TimestampedUdpSocket,
     Strategy,
@@ -20,7 +21,10 @@ use crate::{
     },
     twamp_control::control_client_session::ClientControlSession,
     twamp_light_sender::{
-        twamp_light::calculate_session_results, Configuration as TestSessionsConfiguration,
+        packet_export::{PacketExporter, PacketFeed},
+        sink::{TwampSink, TwampSinks},
+        twamp_light::{calculate_session_results, ResultAnalysis},
+        Configuration as TestSessionsConfiguration,
     },
     TwampResult,
 };
@@ -33,6 +37,8 @@ pub struct ControlClient {
     /// The control connections of the control client.
     control_configuration: ClientConfiguration,
     test_sessions_configuration: TestSessionsConfiguration,
+    stop_handle: Option<StopHandle>,
+    sinks: TwampSinks,
 }
 
 impl ControlClient {
@@ -46,14 +52,39 @@ impl ControlClient {
         Self {
             control_configuration: configuration.to_owned(),
             test_sessions_configuration: test_sessions_configuration.to_owned(),
+            stop_handle: None,
+            sinks: TwampSinks::new(),
         }
     }
+
+    /// Sets the sinks the packets of the test are delivered to while it runs, which include
+    /// those of `create_sinks`.
+    pub fn set_sinks(&mut self, sinks: TwampSinks) {
+        self.sinks = sinks;
+    }
+
+    /// Creates the sink of the packet export of the configuration.
+    pub fn create_sinks(&self) -> Result<Vec<TwampSink>, CommonError> {
+        let mut sinks: Vec<TwampSink> = Vec::new();
+        if let Some(configuration) = &self.test_sessions_configuration.packet_export {
+            sinks.push(Arc::new(Mutex::new(PacketExporter::create(configuration)?)));
+        }
+        Ok(sinks)
+    }
 }
 
 impl Strategy<TwampResult, CommonError> for ControlClient {
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+
     fn execute(&mut self) -> Result<TwampResult, CommonError> {
         log::info!("Executing control client");
+        let analysis = ResultAnalysis::new(&self.test_sessions_configuration);
+        let baseline = analysis.load_baseline()?;
+        let sinks = self.sinks.clone();
         let (tx, rx) = std::sync::mpsc::channel();
+        let worker_stop_handle = self.stop_handle.clone();
         let overtime = Duration::from_secs(self.test_sessions_configuration.last_message_timeout);
         let duration = Duration::from_secs(self.test_sessions_configuration.duration);
         let sessions_handle =
@@ -63,6 +94,9 @@ impl Strategy<TwampResult, CommonError> for ControlClient {
                     it_interval: Duration::ZERO,
                     it_value: overtime,
                 });
+                if let Some(stop_handle) = &worker_stop_handle {
+                    event_loop.register_stop_handle(stop_handle)?;
+                }
 
                 let event_sender = event_loop.get_communication_channel();
                 tx.send(event_sender)?;
@@ -93,7 +127,11 @@ impl Strategy<TwampResult, CommonError> for ControlClient {
         let sessions = sessions_configuration
             .hosts
             .iter()
-            .map(|host| Session::new(sessions_configuration.source_ip_address, *host))
+            .map(|host| {
+                let mut session = Session::new(sessions_configuration.source_ip_address, *host);
+                session.sla = sessions_configuration.sla.clone();
+                session
+            })
             .collect::<Vec<Session>>();
         let rc_sessions = Arc::new(RwLock::new(sessions));
 
@@ -105,6 +143,14 @@ impl Strategy<TwampResult, CommonError> for ControlClient {
             sessions_configuration,
             wes,
         );
+        // The complete packets are delivered to the sinks by the test sessions while they run
+        let packet_feed = if sinks.wants_packets() {
+            let packet_feed = Arc::new(Mutex::new(PacketFeed::new(rc_sessions.try_read()?.len())));
+            client_control_session.set_packet_delivery(packet_feed.clone(), sinks.clone());
+            Some(packet_feed)
+        } else {
+            None
+        };
         log::info!("Created tcp socket");
 
         #[cfg(target_os = "linux")]
@@ -124,13 +170,23 @@ impl Strategy<TwampResult, CommonError> for ControlClient {
             it_interval: Duration::ZERO,
             it_value: overtime,
         });
+        if let Some(stop_handle) = &self.stop_handle {
+            control_event_loop.register_stop_handle(stop_handle)?;
+        }
         control_event_loop.add_duration(&Itimerspec {
             it_interval: Duration::ZERO,
             it_value: duration + overtime,
         })?;
         control_event_loop.run()?;
         let _ = sessions_handle.join();
-        let session_results = calculate_session_results(rc_sessions)?;
+        if let Some(packet_feed) = packet_feed {
+            sinks.packets(
+                &packet_feed
+                    .lock()?
+                    .next_packets(&rc_sessions.try_read()?, None)?,
+            );
+        }
+        let session_results = calculate_session_results(rc_sessions, &analysis, baseline.as_ref())?;
         Ok(TwampResult {
             session_results,
             error: None,
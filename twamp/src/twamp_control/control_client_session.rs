@@ -8,9 +8,15 @@ use crate::twamp_common::message::ServerGreeting;
 use crate::twamp_common::message::ServerStart;
 use crate::twamp_common::message::{AcceptSessionMessage, ClientSetupResponse};
 use crate::twamp_common::session::Session;
+use crate::twamp_light_sender::packet_export::PacketFeed;
+use crate::twamp_light_sender::sink::TwampSinks;
+use crate::twamp_light_sender::twamp_light::create_packet_callback;
 use crate::twamp_light_sender::twamp_light::create_rx_callback;
-use crate::twamp_light_sender::twamp_light::create_tx_callback;
+use crate::twamp_light_sender::twamp_light::create_target_tx_callback;
+use crate::twamp_light_sender::twamp_light::create_tx_correct_callback;
 use crate::twamp_light_sender::twamp_light::SessionSender;
+use crate::twamp_light_sender::twamp_light::TxTracker;
+use crate::twamp_light_sender::twamp_light::PACKET_DELIVERY_INTERVAL;
 use crate::twamp_light_sender::Configuration;
 use bebytes::BeBytes;
 use network_commons::epoll_loop::DuplexChannel;
@@ -32,6 +38,7 @@ pub struct ClientControlSession {
     auth_timeout: std::time::Duration,
     pub id: i32,
     negotiation_timeout: std::time::Duration,
+    packet_delivery: Option<(Arc<Mutex<PacketFeed>>, TwampSinks)>,
     rc_sessions: Arc<RwLock<Vec<Session>>>,
     retry_count: u32,
     rx_buffer: [u8; 1 << 16],
@@ -61,12 +68,19 @@ impl ClientControlSession {
             retry_count,
             auth_timeout: std::time::Duration::from_secs(30),
             negotiation_timeout: std::time::Duration::from_secs(30),
+            packet_delivery: None,
             start_timeout: std::time::Duration::from_secs(10),
             rx_buffer: [0; 1 << 16],
             worker_event_sender,
         }
     }
 
+    /// Delivers the complete packets of the test sessions from `packet_feed` to `sinks`
+    /// while they run.
+    pub fn set_packet_delivery(&mut self, packet_feed: Arc<Mutex<PacketFeed>>, sinks: TwampSinks) {
+        self.packet_delivery = Some((packet_feed, sinks));
+    }
+
     // Method to transition to the next state of the state machine
     pub fn transition(&mut self, socket: &mut TimestampedTcpSocket) -> Result<(), CommonError> {
         match self.state {
@@ -158,7 +172,7 @@ impl ClientControlSession {
                 let timeout = self.test_session.last_message_timeout;
                 let sender_port = self.test_session.source_ip_address.port();
                 let sender_ip = self.test_session.source_ip_address.ip();
-                let receiver_address = self.test_session.targets.first().unwrap();
+                let receiver_address = self.test_session.targets.first().unwrap().host;
                 let request_tw_session_builder = RequestTwSessionBuilder::new()
                     .request_type(TwampControlCommand::RequestTwSession)
                     .ipvn(ipvn)
@@ -250,11 +264,15 @@ impl ClientControlSession {
             SenderSessionState::TestInProgress => {
                 // We can now start the test sessions
 
+                // The session was requested for the first target
+                let target = self.test_session.targets.first().cloned().ok_or_else(|| {
+                    CommonError::Generic("No target to send the test packets to".to_string())
+                })?;
                 let session_socket = self.test_session.create_udp_socket()?;
 
                 let rx_message = EventLoopMessages::Register((
                     session_socket,
-                    Box::new(create_rx_callback(self.rc_sessions.clone()))
+                    Box::new(create_rx_callback(self.rc_sessions.clone(), None))
                         as Box<
                             dyn FnMut(
                                     &mut TimestampedUdpSocket,
@@ -278,12 +296,19 @@ impl ClientControlSession {
                     log::info!("Slept");
                     let sender_lock = self.worker_event_sender.try_lock()?;
                     if let Ok(token) = sender_lock.get_token() {
+                        // The transmit timestamps are matched to the packets through the
+                        // tracker, as for a light sender
+                        let tracker = Arc::new(Mutex::new(TxTracker::default()));
                         let tx_message = EventLoopMessages::RegisterTimed((
                             timer_spec,
                             token,
-                            Box::new(create_tx_callback(
+                            Box::new(create_target_tx_callback(
                                 self.rc_sessions.clone(),
-                                self.test_session.padding,
+                                tracker.clone(),
+                                0,
+                                target.clone(),
+                                None,
+                                None,
                             ))
                                 as Box<
                                     dyn FnMut(
@@ -295,6 +320,54 @@ impl ClientControlSession {
                                 >,
                         ));
                         sender_lock.send(tx_message)?;
+                        let tx_correction_timer_spec = Itimerspec {
+                            it_interval: Duration::from_millis(150),
+                            it_value: Duration::from_nanos(1),
+                        };
+                        let tx_correct_message = EventLoopMessages::RegisterTimed((
+                            tx_correction_timer_spec,
+                            token,
+                            Box::new(create_tx_correct_callback(
+                                self.rc_sessions.clone(),
+                                tracker,
+                                None,
+                                self.test_session.last_message_timeout,
+                            ))
+                                as Box<
+                                    dyn FnMut(
+                                            &mut TimestampedUdpSocket,
+                                            Token,
+                                        )
+                                            -> Result<isize, CommonError>
+                                        + Send,
+                                >,
+                        ));
+                        sender_lock.send(tx_correct_message)?;
+                        if let Some((packet_feed, sinks)) = &self.packet_delivery {
+                            let packet_timer_spec = Itimerspec {
+                                it_interval: PACKET_DELIVERY_INTERVAL,
+                                it_value: PACKET_DELIVERY_INTERVAL,
+                            };
+                            let packet_message = EventLoopMessages::RegisterTimed((
+                                packet_timer_spec,
+                                token,
+                                Box::new(create_packet_callback(
+                                    self.rc_sessions.clone(),
+                                    packet_feed.clone(),
+                                    sinks.clone(),
+                                    self.test_session.last_message_timeout,
+                                ))
+                                    as Box<
+                                        dyn FnMut(
+                                                &mut TimestampedUdpSocket,
+                                                Token,
+                                            )
+                                                -> Result<isize, CommonError>
+                                            + Send,
+                                    >,
+                            ));
+                            sender_lock.send(packet_message)?;
+                        }
                         log::info!("Registered callbacks");
                         break;
                     }
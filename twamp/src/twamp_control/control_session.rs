@@ -6,6 +6,7 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
+use std::time::Duration;
 
 use crate::twamp_common::data_model::AcceptFields;
 use crate::twamp_common::data_model::ErrorEstimate;
@@ -28,9 +29,11 @@ use crate::twamp_common::session::Session;
 
 use bebytes::BeBytes;
 
+use network_commons::epoll_loop::register_from_thread;
 use network_commons::epoll_loop::DuplexChannel;
 use network_commons::epoll_loop::EventLoopMessages;
 use network_commons::error::CommonError;
+use network_commons::event_loop::Token;
 use network_commons::time::DateTime;
 use network_commons::time::NtpTimestamp;
 use network_commons::udp_socket::TimestampedUdpSocket;
@@ -51,9 +54,16 @@ pub struct ControlSession {
     rx_buffer: [u8; 1 << 16],
     worker_event_sender: Arc<Mutex<DuplexChannel<TimestampedUdpSocket>>>,
     start_time: DateTime,
+    /// Addresses of the test sessions, with the token of their socket in the worker event loop
+    test_sockets: Vec<(SocketAddr, Token)>,
 }
 
 impl ControlSession {
+    /// Test sessions requested over the control connection.
+    pub fn twamp_sessions(&self) -> &Arc<RwLock<Vec<Session>>> {
+        &self.twamp_sessions
+    }
+
     // Method to create a new TWAMP control session with the initial state and TCP connection
     pub fn new(
         token: i32,
@@ -78,9 +88,45 @@ impl ControlSession {
             rx_buffer: [0; 1 << 16],
             worker_event_sender,
             start_time,
+            test_sockets: Vec::new(),
         }
     }
 
+    /// Closes the test sessions whose last packet was received more than `ref_wait` ago:
+    /// their sessions are dropped and their socket is unregistered from the worker event loop.
+    /// Test sessions that did not receive a packet yet are kept.
+    pub fn expire_sessions(&mut self, ref_wait: Duration) -> Result<(), CommonError> {
+        let now = DateTime::utc_now();
+        let mut sessions = self.twamp_sessions.write()?;
+        let is_stale = |address: &SocketAddr| {
+            let mut last_packets = sessions
+                .iter()
+                .filter(|session| session.rx_socket_address == *address)
+                .filter_map(Session::last_packet)
+                .peekable();
+            last_packets.peek().is_some()
+                && last_packets
+                    .all(|last_packet| (now - last_packet).as_nanos() > ref_wait.as_nanos() as i64)
+        };
+        let (expired, active): (Vec<_>, Vec<_>) = self
+            .test_sockets
+            .drain(..)
+            .partition(|(address, _)| is_stale(address));
+        self.test_sockets = active;
+        for (address, token) in expired {
+            sessions.retain(|session| session.rx_socket_address != address);
+            self.worker_event_sender
+                .lock()?
+                .send(EventLoopMessages::Unregister(token))?;
+            log::info!(
+                "Closed the test session on {}, which received no packet for {:?}",
+                address,
+                ref_wait
+            );
+        }
+        Ok(())
+    }
+
     // Method to transition to the next state of the state machine
     pub fn transition(&mut self, socket: &mut TimestampedTcpSocket) -> Result<(), CommonError> {
         match self.state {
@@ -227,15 +273,15 @@ impl ControlSession {
                                         let udp_socket = session.create_udp_socket()?;
                                         drop(sessions_lock);
 
-                                        let _ = self.worker_event_sender.try_lock()?.send(
-                                            EventLoopMessages::Register((
-                                                udp_socket,
-                                                Box::new(rx_callback(
-                                                    source_address,
-                                                    self.twamp_sessions.clone(),
-                                                )?),
-                                            )),
-                                        );
+                                        let token = register_from_thread(
+                                            &self.worker_event_sender,
+                                            udp_socket,
+                                            Box::new(rx_callback(
+                                                source_address,
+                                                self.twamp_sessions.clone(),
+                                            )?),
+                                        )?;
+                                        self.test_sockets.push((source_address, token));
                                         let accept_message = AcceptSessionMessage::new(
                                             AcceptFields::Ok,
                                             0,
@@ -3,14 +3,57 @@ use std::net::SocketAddr;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+use crate::{
+    twamp_common::{access::Subnet, default_ref_wait, default_source_ip_address, DEFAULT_REF_WAIT},
+    twamp_light_sender::Configuration as SenderConfiguration,
+};
+
 pub mod control;
 pub mod control_client;
 pub mod control_client_session;
 pub mod control_session;
+
+/// Settings of a `FULL_REFLECTOR`, the control server.
 #[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct ControlConfiguration {
+    #[serde(default = "default_source_ip_address")]
     pub source_ip_address: SocketAddr,
+    /// Time in seconds after which a session that stopped sending is closed
+    #[serde(default = "default_ref_wait")]
+    #[validate(range(min = 1, max = 86400))]
     pub ref_wait: u64,
+    /// Other addresses the server listens on, which can change while it runs
+    #[serde(default)]
+    pub listen_addresses: Vec<SocketAddr>,
+    /// Networks the control clients must belong to, any client being accepted when empty
+    #[serde(default)]
+    pub allowed_senders: Vec<Subnet>,
+    /// Largest number of control connections open at the same time, the connections of
+    /// other clients being refused
+    #[validate(range(min = 1))]
+    pub max_connections: Option<usize>,
+}
+
+impl Default for ControlConfiguration {
+    fn default() -> Self {
+        Self {
+            source_ip_address: default_source_ip_address(),
+            ref_wait: DEFAULT_REF_WAIT,
+            listen_addresses: Vec::new(),
+            allowed_senders: Vec::new(),
+            max_connections: None,
+        }
+    }
+}
+
+/// Settings of a `FULL_SENDER`: the control server the test sessions are requested from,
+/// along with the settings of the sessions.
+#[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct FullSenderConfiguration {
+    pub control_host: SocketAddr,
+    #[serde(flatten)]
+    #[validate]
+    pub session: SenderConfiguration,
 }
 
 #[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
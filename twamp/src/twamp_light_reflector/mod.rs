@@ -1,34 +1,56 @@
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::net::SocketAddr;
 
+use network_commons::pcap::CaptureConfiguration;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+use crate::twamp_common::{
+    access::Subnet, default_ref_wait, default_source_ip_address, DEFAULT_REF_WAIT,
+};
+
 pub mod reflector;
 
+/// Settings of a `LIGHT_REFLECTOR`.
 #[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Configuration {
-    #[validate(contains = "LIGHT")]
-    pub mode: String,
+    #[serde(default = "default_source_ip_address")]
     pub source_ip_address: SocketAddr,
+    /// Time in seconds after which a session that stopped sending is closed
+    #[serde(default = "default_ref_wait")]
+    #[validate(range(min = 1, max = 86400))]
     pub ref_wait: u64,
+    /// Address on which the metrics of the reflector are served in OpenMetrics format
+    pub metrics_address: Option<SocketAddr>,
+    /// File to which every received and reflected packet is written in pcap or pcapng format
+    pub capture: Option<CaptureConfiguration>,
+    /// Other addresses the reflector listens on, which can change while it runs
+    #[serde(default)]
+    pub listen_addresses: Vec<SocketAddr>,
+    /// Networks the senders must belong to, any sender being reflected when empty
+    #[serde(default)]
+    pub allowed_senders: Vec<Subnet>,
+    /// Largest number of sessions reflected at the same time, the packets of other senders
+    /// being dropped
+    #[validate(range(min = 1))]
+    pub max_sessions: Option<usize>,
 }
 
 impl Configuration {
     pub fn new(source_ip_address: &SocketAddr, ref_wait: u64) -> Self {
         Self {
-            mode: "LIGHT".to_string(),
             source_ip_address: *source_ip_address,
             ref_wait,
+            metrics_address: None,
+            capture: None,
+            listen_addresses: Vec::new(),
+            allowed_senders: Vec::new(),
+            max_sessions: None,
         }
     }
 }
 
 impl Default for Configuration {
     fn default() -> Self {
-        Self {
-            mode: Default::default(),
-            source_ip_address: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
-            ref_wait: Default::default(),
-        }
+        Self::new(&default_source_ip_address(), DEFAULT_REF_WAIT)
     }
 }
@@ -1,45 +1,83 @@
+use crate::twamp_common::access::is_allowed;
+use crate::twamp_common::capture::{CaptureHandle, CaptureThread};
 use crate::twamp_common::data_model::ErrorEstimate;
 use crate::twamp_common::message::ReflectedMessage;
+use crate::twamp_common::metrics::{encode_reflector_metrics, METRICS_REFRESH_INTERVAL};
+use crate::twamp_common::monitor::{list_sessions, SessionMonitor};
+use crate::twamp_common::reload::ReloadHandle;
 use crate::twamp_common::session::Session;
 use crate::twamp_common::MIN_UNAUTH_PADDING;
+use crate::TwampMode;
 #[cfg(target_os = "linux")]
 use network_commons::epoll_loop::LinuxEventLoop as EventLoop;
 
 use bebytes::BeBytes;
 
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::RwLock;
-use std::{os::fd::IntoRawFd, sync::atomic::Ordering, time::Duration};
+use std::{
+    os::fd::IntoRawFd,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
-use network_commons::{error::CommonError, socket::Socket, Strategy, TestResult};
 use network_commons::{
-    event_loop::{EventLoopTrait, Itimerspec},
+    epoll_loop::{register_from_thread, DuplexChannel, EventLoopMessages},
+    event_loop::{EventLoopTrait, Itimerspec, Token},
+    metrics::MetricsServer,
     time::{DateTime, NtpTimestamp},
     udp_socket::TimestampedUdpSocket,
 };
+use network_commons::{
+    error::CommonError, socket::Socket, stop_handle::StopHandle, Strategy, TestResult,
+};
 
 use crate::{twamp_common::message::SenderMessage, twamp_light_sender::result::TwampResult};
 
 use super::Configuration;
+use validator::Validate;
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Reflector {
     pub configuration: Configuration,
+    stop_handle: Option<StopHandle>,
+    monitor: SessionMonitor,
+    reload_handle: ReloadHandle,
 }
 
 impl Reflector {
     pub fn new(configuration: Configuration) -> Self {
-        Self { configuration }
+        Self {
+            configuration,
+            stop_handle: None,
+            monitor: SessionMonitor::default(),
+            reload_handle: ReloadHandle::default(),
+        }
+    }
+
+    /// Sets the handle reloading the configuration while the reflector runs.
+    pub fn set_reload_handle(&mut self, reload_handle: ReloadHandle) {
+        self.reload_handle = reload_handle;
+    }
+
+    /// Sets the monitor listing the sessions while the reflector runs.
+    pub fn set_session_monitor(&mut self, monitor: SessionMonitor) {
+        self.monitor = monitor;
     }
 
-    pub fn create_socket(&mut self) -> Result<TimestampedUdpSocket, CommonError> {
-        let socket = mio::net::UdpSocket::bind(self.configuration.source_ip_address)?;
+    pub fn create_socket(address: SocketAddr) -> Result<TimestampedUdpSocket, CommonError> {
+        let socket = mio::net::UdpSocket::bind(address)?;
         let mut my_socket = TimestampedUdpSocket::new(socket.into_raw_fd());
         my_socket.set_fcntl_options()?;
-        my_socket.set_timestamping_options()?;
+        my_socket.set_rx_timestamping_options()?;
         my_socket.set_socket_options(libc::SOL_IP, libc::IP_RECVERR, Some(1))?;
         my_socket.set_socket_options(libc::IPPROTO_IP, libc::IP_RECVTOS, Some(1))?;
+        // The capture needs the local address of the packets received on an unspecified
+        // address
+        my_socket.set_packet_info_options()?;
 
         Ok(my_socket)
     }
@@ -49,35 +87,126 @@ impl Reflector {
         event_loop: &mut EventLoop<TimestampedUdpSocket>,
         source_ip_address: SocketAddr,
         sessions: Arc<RwLock<Vec<Session>>>,
-        ref_wait: u64,
-    ) -> Result<(), CommonError> {
-        let socket = self.create_socket()?;
+        settings: Arc<RwLock<Configuration>>,
+        reflected_packets: Arc<AtomicU64>,
+        capture: Option<CaptureHandle>,
+    ) -> Result<Token, CommonError> {
+        let socket = Self::create_socket(source_ip_address)?;
         let rx_token = event_loop.register_event_source(
             socket,
-            Box::new(rx_callback(source_ip_address, sessions.clone())),
+            Box::new(rx_callback(
+                source_ip_address,
+                sessions.clone(),
+                settings.clone(),
+                reflected_packets,
+                capture,
+            )),
         )?;
         let timer_spec = Itimerspec {
             it_interval: Duration::from_secs(1),
             it_value: Duration::from_secs(1),
         };
         let _tx_token =
-            cleanup_stale_sessions(event_loop, timer_spec, rx_token, sessions, ref_wait)?;
-        Ok(())
+            cleanup_stale_sessions(event_loop, timer_spec, rx_token, sessions, settings)?;
+        Ok(rx_token)
     }
 }
 
 impl Strategy<TwampResult, CommonError> for Reflector {
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+
     fn execute(&mut self) -> std::result::Result<TwampResult, CommonError> {
         // Create the socket
         let source_ip_address = self.configuration.source_ip_address;
         let sessions: Arc<RwLock<Vec<Session>>> = Arc::new(RwLock::new(Vec::new()));
         // Creates the event loop with a default socket
         let mut event_loop = EventLoop::new(1024)?;
-        let ref_wait = self.configuration.ref_wait;
-        self.create_session(&mut event_loop, source_ip_address, sessions, ref_wait)?;
+        // Settings that a reload changes while the reflector runs
+        let settings = Arc::new(RwLock::new(self.configuration.clone()));
+        let reflected_packets = Arc::new(AtomicU64::new(0));
+        let capture = match &self.configuration.capture {
+            Some(configuration) => Some(CaptureThread::spawn(configuration, source_ip_address)?),
+            None => None,
+        };
+        let capture_handle = capture.as_ref().map(CaptureThread::handle);
+        let rx_token = self.create_session(
+            &mut event_loop,
+            source_ip_address,
+            sessions.clone(),
+            settings.clone(),
+            reflected_packets.clone(),
+            capture_handle.clone(),
+        )?;
+        let mut listeners = HashMap::new();
+        for address in &self.configuration.listen_addresses {
+            let token = event_loop.register_event_source(
+                Self::create_socket(*address)?,
+                Box::new(rx_callback(
+                    *address,
+                    sessions.clone(),
+                    settings.clone(),
+                    reflected_packets.clone(),
+                    capture_handle.clone(),
+                )),
+            )?;
+            listeners.insert(*address, token);
+        }
+        if let Some(stop_handle) = &self.stop_handle {
+            event_loop.register_stop_handle(stop_handle)?;
+        }
+        self.reload_handle.watch(create_reload_callback(
+            event_loop.get_communication_channel(),
+            Mutex::new(listeners),
+            sessions.clone(),
+            settings,
+            reflected_packets.clone(),
+            capture_handle.clone(),
+        ));
+        let monitored_sessions = sessions.clone();
+        self.monitor.watch(move || match monitored_sessions.read() {
+            Ok(sessions) => list_sessions(&sessions),
+            Err(_) => Vec::new(),
+        });
+        // Serve the metrics, refreshed by a timer of the reflector loop
+        let metrics_server = match self.configuration.metrics_address {
+            Some(metrics_address) => {
+                let snapshot = Arc::new(RwLock::new(encode_reflector_metrics(
+                    source_ip_address,
+                    &[],
+                    0,
+                )));
+                let metrics_timer_spec = Itimerspec {
+                    it_interval: METRICS_REFRESH_INTERVAL,
+                    it_value: METRICS_REFRESH_INTERVAL,
+                };
+                event_loop.register_timer(
+                    &metrics_timer_spec,
+                    &rx_token,
+                    Box::new(create_metrics_callback(
+                        source_ip_address,
+                        sessions,
+                        reflected_packets,
+                        snapshot.clone(),
+                    )),
+                )?;
+                Some(MetricsServer::spawn(metrics_address, snapshot)?)
+            }
+            None => None,
+        };
 
         // Run the event loop
-        event_loop.run()?;
+        let result = event_loop.run();
+        self.monitor.clear();
+        self.reload_handle.clear();
+        result?;
+        if let Some(metrics_server) = metrics_server {
+            metrics_server.stop()?;
+        }
+        if let Some(capture) = capture {
+            capture.finish()?;
+        }
 
         Ok(TwampResult {
             session_results: Vec::new(),
@@ -91,12 +220,13 @@ pub fn cleanup_stale_sessions(
     timer_spec: Itimerspec,
     rx_token: network_commons::event_loop::Token,
     sessions_clone: Arc<RwLock<Vec<Session>>>,
-    ref_wait: u64,
+    settings: Arc<RwLock<Configuration>>,
 ) -> Result<network_commons::event_loop::Token, CommonError> {
     event_loop.register_timer(
         &timer_spec,
         &rx_token,
         Box::new(move |_inner_socket, _| {
+            let ref_wait = settings.read()?.ref_wait;
             let mut sessions_lock = sessions_clone.write()?;
             sessions_lock.retain(|session| {
                 if let Some(session) = session.get_latest_result() {
@@ -120,27 +250,152 @@ pub fn cleanup_stale_sessions(
     )
 }
 
+/// Applies a reloaded configuration: the listening sockets of the added `listen_addresses` are
+/// registered with the running event loop and those of the removed ones unregistered, while
+/// the sessions go on. The other listening socket, the metrics server and the capture are set
+/// up once, so their settings can't change.
+fn create_reload_callback(
+    channel: Arc<Mutex<DuplexChannel<TimestampedUdpSocket>>>,
+    listeners: Mutex<HashMap<SocketAddr, Token>>,
+    sessions: Arc<RwLock<Vec<Session>>>,
+    settings: Arc<RwLock<Configuration>>,
+    reflected_packets: Arc<AtomicU64>,
+    capture: Option<CaptureHandle>,
+) -> impl Fn(&TwampMode) -> Result<(), CommonError> {
+    move |mode: &TwampMode| {
+        let TwampMode::LightReflector(configuration) = mode else {
+            return Err(CommonError::Generic(format!(
+                "A LIGHT_REFLECTOR can't reload a {} configuration",
+                mode.name()
+            )));
+        };
+        configuration
+            .validate()
+            .map_err(CommonError::ValidationError)?;
+        {
+            let current = settings.read()?;
+            if configuration.source_ip_address != current.source_ip_address
+                || configuration.metrics_address != current.metrics_address
+                || configuration.capture != current.capture
+            {
+                return Err(CommonError::Generic(
+                    "source_ip_address, metrics_address and capture can't change while the reflector runs"
+                        .to_string(),
+                ));
+            }
+        }
+        let mut listeners = listeners.lock()?;
+        // Binds the added addresses first, so that a failure leaves the reflector unchanged
+        let added = configuration
+            .listen_addresses
+            .iter()
+            .filter(|address| !listeners.contains_key(address))
+            .map(|address| Ok((*address, Reflector::create_socket(*address)?)))
+            .collect::<Result<Vec<_>, CommonError>>()?;
+        for (address, socket) in added {
+            let callback = rx_callback(
+                address,
+                sessions.clone(),
+                settings.clone(),
+                reflected_packets.clone(),
+                capture.clone(),
+            );
+            let token = register_from_thread(&channel, socket, Box::new(callback))?;
+            log::info!("Listening on {}", address);
+            listeners.insert(address, token);
+        }
+        let removed: Vec<SocketAddr> = listeners
+            .keys()
+            .filter(|address| !configuration.listen_addresses.contains(address))
+            .copied()
+            .collect();
+        for address in removed {
+            if let Some(token) = listeners.remove(&address) {
+                channel.lock()?.send(EventLoopMessages::Unregister(token))?;
+                log::info!("Stopped listening on {}", address);
+            }
+        }
+        *settings.write()? = configuration.clone();
+        log::info!("Reloaded the configuration of the reflector");
+        Ok(())
+    }
+}
+
+/// Renders the metrics of the reflector into the `snapshot` served by the metrics server.
+/// Failures are logged so they don't stop the reflector.
+pub fn create_metrics_callback(
+    rx_socket_address: SocketAddr,
+    sessions: Arc<RwLock<Vec<Session>>>,
+    reflected_packets: Arc<AtomicU64>,
+    snapshot: Arc<RwLock<String>>,
+) -> impl Fn(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    move |_inner_socket: &mut TimestampedUdpSocket, _| {
+        let updated = sessions
+            .read()
+            .map_err(CommonError::from)
+            .and_then(|sessions| {
+                let metrics = encode_reflector_metrics(
+                    rx_socket_address,
+                    &sessions,
+                    reflected_packets.load(Ordering::Relaxed),
+                );
+                *snapshot.write()? = metrics;
+                Ok(())
+            });
+        if let Err(e) = updated {
+            log::error!("Failed to update the metrics: {}", e);
+        }
+        Ok(0)
+    }
+}
+
+/// Reflects the received packets. When a `capture` is set, the received packets are written
+/// to it with their kernel receive timestamp, and the reflected packets with their transmit
+/// timestamp `t3`, since the reflector doesn't retrieve the kernel transmit timestamps.
 pub fn rx_callback(
     rx_socket_address: SocketAddr,
     sessions: Arc<RwLock<Vec<Session>>>,
+    settings: Arc<RwLock<Configuration>>,
+    reflected_packets: Arc<AtomicU64>,
+    capture: Option<CaptureHandle>,
 ) -> impl Fn(&mut TimestampedUdpSocket, network_commons::event_loop::Token) -> Result<isize, CommonError>
 {
     move |inner_socket: &mut TimestampedUdpSocket, _| {
         let buffer = &mut [0; 1 << 16];
-        let (result, socket_address, timestamp) = inner_socket.receive_from(buffer)?;
+        let (result, socket_address, destination, timestamp) =
+            inner_socket.receive_from_to(buffer)?;
         log::debug!("Received {} bytes from {}", result, socket_address);
+        let max_sessions = {
+            let settings = settings.read()?;
+            if !is_allowed(&settings.allowed_senders, socket_address.ip()) {
+                log::debug!(
+                    "Dropping a packet of {}, which is not allowed",
+                    socket_address
+                );
+                return Ok(result);
+            }
+            settings.max_sessions
+        };
         let (twamp_test_message, _bytes_written): (SenderMessage, usize) =
             SenderMessage::try_from_be_bytes(&buffer[..result.max(0) as usize])?;
         let mut sessions_lock = sessions.write()?;
+        let reflected_at = DateTime::utc_now();
         let session_option = sessions_lock.iter().find(|session| {
             (session.rx_socket_address == rx_socket_address)
                 && (session.tx_socket_address == socket_address)
         });
+        if session_option.is_none() && max_sessions.is_some_and(|max| sessions_lock.len() >= max) {
+            log::debug!(
+                "Dropping a packet of {}, the reflector has no session left",
+                socket_address
+            );
+            return Ok(result);
+        }
 
-        if let Some(session) = session_option {
+        let reflected_message = if let Some(session) = session_option {
             let reflected_message = ReflectedMessage {
                 reflector_sequence_number: session.seq_number.load(Ordering::SeqCst),
-                timestamp: NtpTimestamp::from(DateTime::utc_now()),
+                timestamp: NtpTimestamp::from(reflected_at),
                 error_estimate: ErrorEstimate::new(1, 0, 1, 1),
                 mbz1: 0,
                 receive_timestamp: NtpTimestamp::from(timestamp),
@@ -154,14 +409,16 @@ pub fn rx_callback(
             log::debug!("Reflected message: \n {:?}", reflected_message);
 
             inner_socket.send_to(&socket_address, reflected_message.clone())?;
-            session.add_to_sent(reflected_message)?;
+            session.add_to_sent(reflected_message.clone())?;
+            reflected_packets.fetch_add(1, Ordering::Relaxed);
+            reflected_message
         } else {
             // Create session
             let session = Session::new(rx_socket_address, socket_address);
             // Create Reflected message
             let reflected_message = ReflectedMessage {
                 reflector_sequence_number: session.seq_number.load(Ordering::SeqCst),
-                timestamp: NtpTimestamp::from(DateTime::utc_now()),
+                timestamp: NtpTimestamp::from(reflected_at),
                 error_estimate: ErrorEstimate::new(0, 0, 0, 1),
                 mbz1: 0,
                 receive_timestamp: NtpTimestamp::from(timestamp),
@@ -176,14 +433,32 @@ pub fn rx_callback(
             // Send message
             inner_socket.send_to(&socket_address, reflected_message.clone())?;
             // Add message results to session
-            session.add_to_sent(reflected_message)?;
+            session.add_to_sent(reflected_message.clone())?;
+            reflected_packets.fetch_add(1, Ordering::Relaxed);
             // Store session
             sessions_lock.push(session);
+            reflected_message
+        };
+        if let Some(capture) = &capture {
+            capture.received(
+                socket_address,
+                destination,
+                timestamp,
+                &buffer[..result.max(0) as usize],
+            );
+            capture.write_sent(
+                socket_address,
+                0,
+                reflected_at,
+                reflected_message.to_be_bytes(),
+            );
         }
         Ok(result)
     }
 }
 
+/// Result of a light reflector, which has no statistics of its own to report.
+#[allow(dead_code)]
 pub struct SessionResult {}
 
 impl TestResult for SessionResult {}
@@ -0,0 +1,193 @@
+use std::{fs, net::SocketAddr, path::PathBuf};
+
+use network_commons::{
+    error::CommonError,
+    stats::hypothesis::{mann_whitney_u, two_proportion_test, TestOutcome},
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::result::{SessionResult, TwampResult};
+
+/// Significance level of a default `BaselineConfiguration`
+pub const DEFAULT_SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// Settings of the comparison of a test with a previous run.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BaselineConfiguration {
+    /// Path of the JSON `TwampResult` of the previous run
+    pub path: PathBuf,
+    /// Largest p-value of a difference that is reported as a regression
+    #[serde(default = "default_significance_level")]
+    #[validate(range(min = 0.0001, max = 0.2))]
+    pub significance_level: f64,
+    /// Whether a regression fails the test, as an SLA violation does
+    #[serde(default)]
+    pub fail_on_regression: bool,
+}
+
+fn default_significance_level() -> f64 {
+    DEFAULT_SIGNIFICANCE_LEVEL
+}
+
+/// Results of a previous run that the sessions of a test are compared with.
+///
+/// A session is compared with the baseline session of the same label, or of the same address
+/// when either has no label. The round trip times are compared with a Mann-Whitney U test,
+/// which requires the baseline to carry its `rtt_samples`, and the losses with a
+/// two-proportion test.
+#[derive(Debug, Clone)]
+pub struct Baseline {
+    session_results: Vec<SessionResult>,
+    significance_level: f64,
+    fail_on_regression: bool,
+}
+
+impl Baseline {
+    pub fn new(result: TwampResult, significance_level: f64) -> Self {
+        Self {
+            session_results: result.session_results,
+            significance_level,
+            fail_on_regression: false,
+        }
+    }
+
+    /// Makes the regressions fail the test.
+    pub fn fail_on_regression(mut self) -> Self {
+        self.fail_on_regression = true;
+        self
+    }
+
+    /// Reads the baseline result from the configured file.
+    pub fn load(configuration: &BaselineConfiguration) -> Result<Self, CommonError> {
+        let contents = fs::read_to_string(&configuration.path)?;
+        let result: TwampResult = serde_json::from_str(&contents).map_err(|e| {
+            CommonError::Generic(format!(
+                "Invalid baseline {}: {}",
+                configuration.path.display(),
+                e
+            ))
+        })?;
+        let baseline = Self::new(result, configuration.significance_level);
+        Ok(match configuration.fail_on_regression {
+            true => baseline.fail_on_regression(),
+            false => baseline,
+        })
+    }
+
+    /// Compares a session with its baseline session.
+    /// Returns `None` when the baseline has no matching session.
+    pub fn compare(&self, current: &SessionResult) -> Option<BaselineComparison> {
+        let baseline = self.session_results.iter().find(|baseline| {
+            match (&baseline.label, &current.label) {
+                (Some(baseline_label), Some(current_label)) => baseline_label == current_label,
+                _ => baseline.address == current.address,
+            }
+        })?;
+        let rtt = RttComparison::new(baseline, current, self.significance_level);
+        let loss = LossComparison::new(baseline, current, self.significance_level);
+        let regressed = rtt.as_ref().is_some_and(|rtt| rtt.regressed)
+            || loss.as_ref().is_some_and(|loss| loss.regressed);
+        Some(BaselineComparison {
+            baseline_address: baseline.address,
+            significance_level: self.significance_level,
+            fail_on_regression: self.fail_on_regression,
+            regressed,
+            rtt,
+            loss,
+        })
+    }
+}
+
+/// Comparison of a session with its baseline session.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BaselineComparison {
+    pub baseline_address: SocketAddr,
+    pub significance_level: f64,
+    /// Whether a regression fails the test
+    #[serde(default)]
+    pub fail_on_regression: bool,
+    /// Whether the round trip times or the losses are significantly higher than the baseline
+    pub regressed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt: Option<RttComparison>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loss: Option<LossComparison>,
+}
+
+/// Mann-Whitney U test of whether the round trip times got longer than the baseline.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RttComparison {
+    pub baseline_median: f64,
+    pub current_median: f64,
+    /// Ratio of the pairs of round trip times in which the current one is the longer
+    pub probability_longer: f64,
+    #[serde(flatten)]
+    pub test: TestOutcome,
+    pub regressed: bool,
+}
+
+impl RttComparison {
+    fn new(
+        baseline: &SessionResult,
+        current: &SessionResult,
+        significance_level: f64,
+    ) -> Option<Self> {
+        let (baseline_samples, current_samples) = (&baseline.rtt_samples, &current.rtt_samples);
+        let test = mann_whitney_u(baseline_samples, current_samples)?;
+        Some(Self {
+            baseline_median: median(baseline_samples),
+            current_median: median(current_samples),
+            probability_longer: test.statistic
+                / (baseline_samples.len() * current_samples.len()) as f64,
+            regressed: test.is_significant(significance_level),
+            test,
+        })
+    }
+}
+
+/// Two-proportion test of whether the ratio of lost packets grew above the baseline.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LossComparison {
+    pub baseline_loss_ratio: f64,
+    pub current_loss_ratio: f64,
+    #[serde(flatten)]
+    pub test: TestOutcome,
+    pub regressed: bool,
+}
+
+impl LossComparison {
+    fn new(
+        baseline: &SessionResult,
+        current: &SessionResult,
+        significance_level: f64,
+    ) -> Option<Self> {
+        let (baseline_lost, baseline_sent) = losses(baseline)?;
+        let (current_lost, current_sent) = losses(current)?;
+        let test = two_proportion_test(baseline_lost, baseline_sent, current_lost, current_sent)?;
+        Some(Self {
+            baseline_loss_ratio: baseline_lost as f64 / baseline_sent as f64,
+            current_loss_ratio: current_lost as f64 / current_sent as f64,
+            regressed: test.is_significant(significance_level),
+            test,
+        })
+    }
+}
+
+/// Returns the number of lost and sent packets of a session.
+fn losses(result: &SessionResult) -> Option<(usize, usize)> {
+    let statistics = result.network_statistics.as_ref()?;
+    let lost = statistics.total_loss as usize;
+    Some((lost, statistics.total_packets + lost))
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_owned();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
@@ -1,25 +1,179 @@
-use std::net::SocketAddr;
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::SocketAddr,
+};
 
+use network_commons::{
+    pcap::CaptureConfiguration,
+    stats::{
+        confidence::ConfidenceConfiguration, offset_estimator::GamlrConfiguration,
+        skew_estimator::SkewEstimator, voice_quality::Codec,
+    },
+};
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
+use crate::twamp_common::default_source_ip_address;
+
+use self::{
+    baseline::BaselineConfiguration,
+    packet_export::PacketExportConfiguration,
+    push::PushConfiguration,
+    size_profile::{validate_size_profile, SizeProfile},
+    sla::SlaThreshold,
+};
+
+pub mod baseline;
+pub mod packet_export;
+pub mod push;
+pub mod replay;
+pub mod report;
 pub mod result;
+pub mod sink;
+pub mod size_profile;
+pub mod sla;
 pub mod twamp_light;
 
+/// Settings of a single target of a sender test.
+/// Every setting that is not provided falls back to the setting of the test.
+#[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TargetConfiguration {
+    pub host: SocketAddr,
+    #[validate(range(min = 0, max = 1024))]
+    pub padding: Option<usize>,
+    /// Sizes of the packets sent to this target, replaces the `padding`
+    #[validate(custom = "validate_size_profile")]
+    pub size_profile: Option<SizeProfile>,
+    /// Interval in milliseconds at which packets are sent to this target
+    #[validate(range(min = 1, max = 1000))]
+    pub packet_interval: Option<u64>,
+    /// DSCP value of the packets sent to this target
+    #[validate(range(max = 63))]
+    pub dscp: Option<u8>,
+    /// Delay in milliseconds between the start of the test and the first packet
+    #[validate(range(max = 3600000))]
+    pub start_offset: Option<u64>,
+    /// Time in seconds during which packets are sent to this target once it started.
+    /// Packets are sent until the end of the test if not set.
+    #[validate(range(min = 1, max = 86400))]
+    pub duration: Option<u64>,
+    /// Name of the target, added to its results
+    pub label: Option<String>,
+    /// Free form tags, added to the results of the target
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// SLA thresholds of this target, replace the thresholds of the test
+    #[validate]
+    pub sla: Option<Vec<SlaThreshold>>,
+}
+
+impl TargetConfiguration {
+    /// Creates a target that uses the settings of the test.
+    pub fn new(host: SocketAddr) -> Self {
+        Self {
+            host,
+            padding: None,
+            size_profile: None,
+            packet_interval: None,
+            dscp: None,
+            start_offset: None,
+            duration: None,
+            label: None,
+            tags: BTreeMap::new(),
+            sla: None,
+        }
+    }
+}
+
+/// Settings of a `LIGHT_SENDER` test, and of the test sessions of a `FULL_SENDER`.
 #[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[validate(schema(function = "validate_continuous_mode"))]
+#[validate(schema(function = "validate_targets"))]
+#[validate(schema(function = "validate_push"))]
 pub struct Configuration {
+    #[serde(rename = "test_session_hosts", default)]
     pub hosts: Vec<SocketAddr>,
+    /// Targets with their own settings, tested along with the `hosts`
+    #[serde(default)]
+    #[validate]
+    pub targets: Vec<TargetConfiguration>,
+    #[serde(default = "default_source_ip_address")]
     pub source_ip_address: SocketAddr,
-    #[validate(range(min = 1, max = 3600))]
+    /// Duration of the test in seconds, ignored in continuous mode
+    #[serde(rename = "collection_period", default)]
     pub duration: u64,
+    /// Interval in milliseconds at which packets are sent
+    #[serde(default = "default_packet_interval")]
     #[validate(range(min = 1, max = 1000))]
     pub packet_interval: u64,
+    #[serde(default)]
     #[validate(range(min = 0, max = 1024))]
     pub padding: usize,
+    /// Sizes of the packets, replaces the `padding`
+    #[validate(custom = "validate_size_profile")]
+    pub size_profile: Option<SizeProfile>,
+    /// Time in seconds during which reflected packets are still awaited once the last
+    /// packet is sent
+    #[serde(default)]
     #[validate(range(min = 0, max = 1000))]
     pub last_message_timeout: u64,
+    /// Interval in seconds at which reports of the running test are produced
+    #[validate(range(min = 1, max = 3600))]
+    pub report_interval: Option<u64>,
+    /// Runs the test until it is stopped instead of for `duration` seconds
+    #[serde(default)]
+    pub continuous: bool,
+    /// Time in seconds for which packet results are kept in continuous mode.
+    /// Rolling statistics are calculated over this window.
+    #[validate(range(min = 1, max = 86400))]
+    pub retention_window: Option<u64>,
+    /// Method used to remove the drift between the sender and reflector clocks
+    /// from the one-way delays
+    pub skew_estimator: Option<SkewEstimator>,
+    /// Settings of the GAMLR clock offset estimator
+    #[serde(default)]
+    #[validate]
+    pub offset_estimation: GamlrConfiguration,
+    /// Settings of the confidence intervals added to the results
+    #[validate]
+    pub confidence: Option<ConfidenceConfiguration>,
+    /// Codecs whose voice quality is estimated from the results
+    #[serde(default)]
+    pub voice_codecs: Vec<Codec>,
+    /// SLA thresholds evaluated on the results of every target
+    #[serde(default)]
+    #[validate]
+    pub sla: Vec<SlaThreshold>,
+    /// Records the round trip time of every packet in the results, so they can serve as
+    /// a baseline
+    #[serde(default)]
+    pub rtt_samples: bool,
+    /// Previous run the results are compared with, the samples are then always recorded
+    #[validate]
+    pub baseline: Option<BaselineConfiguration>,
+    /// File to which every packet is written while the test runs
+    #[validate]
+    pub packet_export: Option<PacketExportConfiguration>,
+    /// Address on which the metrics of the running test are served in OpenMetrics format
+    pub metrics_address: Option<SocketAddr>,
+    /// Metrics databases the interval reports are pushed to
+    #[serde(default)]
+    #[validate]
+    pub push: Vec<PushConfiguration>,
+    /// File to which every sent and received packet is written in pcap or pcapng format
+    pub capture: Option<CaptureConfiguration>,
 }
 
+/// Default interval in milliseconds at which packets are sent
+pub(crate) const DEFAULT_PACKET_INTERVAL: u64 = 100;
+
+fn default_packet_interval() -> u64 {
+    DEFAULT_PACKET_INTERVAL
+}
+
+/// Default retention window in seconds for the continuous mode
+pub(crate) const DEFAULT_RETENTION_WINDOW: u64 = 300;
+
 const NETWORK_PRECISION: i32 = 0;
 
 impl Configuration {
@@ -33,11 +187,110 @@ impl Configuration {
     ) -> Self {
         Self {
             hosts: hosts.to_owned(),
+            targets: Vec::new(),
             source_ip_address: source_ip_address.to_owned(),
             duration,
             packet_interval,
             padding,
+            size_profile: None,
             last_message_timeout,
+            report_interval: None,
+            continuous: false,
+            retention_window: None,
+            skew_estimator: None,
+            offset_estimation: GamlrConfiguration::default(),
+            confidence: None,
+            voice_codecs: Vec::new(),
+            sla: Vec::new(),
+            rtt_samples: false,
+            baseline: None,
+            packet_export: None,
+            metrics_address: None,
+            push: Vec::new(),
+            capture: None,
         }
     }
+
+    /// Returns every target of the test, the `hosts` being targets with the test settings.
+    pub fn all_targets(&self) -> Vec<TargetConfiguration> {
+        self.hosts
+            .iter()
+            .map(|host| TargetConfiguration::new(*host))
+            .chain(self.targets.iter().cloned())
+            .collect()
+    }
+}
+
+impl Default for Configuration {
+    /// A test without targets, with the defaults of the configuration files.
+    fn default() -> Self {
+        Self::new(
+            &[],
+            &default_source_ip_address(),
+            0,
+            DEFAULT_PACKET_INTERVAL,
+            0,
+            0,
+        )
+    }
+}
+
+/// A test needs at least one target, and a host can only be targeted once since
+/// the reflected packets are matched to their session by address.
+/// Every target is reached from the socket of the source address, so it must be of the
+/// same address family.
+fn validate_targets(configuration: &Configuration) -> Result<(), ValidationError> {
+    let targets = configuration.all_targets();
+    if targets.is_empty() {
+        return Err(ValidationError::new("at least one target is required"));
+    }
+    let source_is_ipv4 = configuration.source_ip_address.is_ipv4();
+    if targets
+        .iter()
+        .any(|target| target.host.is_ipv4() != source_is_ipv4)
+    {
+        return Err(ValidationError::new(
+            "every target must have the address family of the source_ip_address",
+        ));
+    }
+    let mut hosts = HashSet::new();
+    if !targets.iter().all(|target| hosts.insert(target.host)) {
+        return Err(ValidationError::new(
+            "every target must have a different host",
+        ));
+    }
+    Ok(())
+}
+
+/// The pushed metrics come from the interval reports.
+fn validate_push(configuration: &Configuration) -> Result<(), ValidationError> {
+    if !configuration.push.is_empty() && configuration.report_interval.is_none() {
+        return Err(ValidationError::new("push requires a report_interval"));
+    }
+    Ok(())
+}
+
+/// A bounded test must last between 1 and 3600 seconds. A continuous test has no duration,
+/// but needs a report interval to emit its results and a retention window that covers it.
+fn validate_continuous_mode(configuration: &Configuration) -> Result<(), ValidationError> {
+    if !configuration.continuous {
+        if !(1..=3600).contains(&configuration.duration) {
+            return Err(ValidationError::new(
+                "collection_period must be between 1 and 3600",
+            ));
+        }
+        return Ok(());
+    }
+    let report_interval = configuration
+        .report_interval
+        .ok_or_else(|| ValidationError::new("continuous mode requires a report_interval"))?;
+    let retention_window = configuration
+        .retention_window
+        .unwrap_or(DEFAULT_RETENTION_WINDOW);
+    if retention_window < report_interval + configuration.last_message_timeout {
+        return Err(ValidationError::new(
+            "retention_window must cover report_interval and last_message_timeout",
+        ));
+    }
+    Ok(())
 }
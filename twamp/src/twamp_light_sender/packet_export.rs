@@ -0,0 +1,328 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::Ordering,
+};
+
+use network_commons::{error::CommonError, sink::ResultSink, time::DateTime};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::twamp_common::{data_model::PacketResults, session::Session};
+
+use super::{report::IntervalReport, result::TwampResult};
+
+/// Format of the packet export files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ExportFormat {
+    /// Comma separated values with a header line, empty cells for missing values
+    Csv,
+    /// One JSON object per line, missing values are `null`
+    JsonLines,
+}
+
+/// Settings of the export of every packet of a test.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PacketExportConfiguration {
+    /// File the packets are written to, replaced if it exists
+    pub path: PathBuf,
+    pub format: ExportFormat,
+}
+
+const CSV_HEADER: &str =
+    "address,sender_seq,reflector_seq,t1,t2,t3,t4,size,sender_ttl,rtt,forward_owd,backward_owd";
+
+/// A packet of a session, as written to the export files.
+/// Timestamps are in nanoseconds since the Unix epoch and delays in nanoseconds.
+/// The one-way delays include the offset between the sender and reflector clocks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PacketRecord {
+    /// Address of the reflector
+    pub address: SocketAddr,
+    pub sender_seq: u32,
+    pub reflector_seq: Option<u32>,
+    pub t1: u64,
+    pub t2: Option<u64>,
+    pub t3: Option<u64>,
+    pub t4: Option<u64>,
+    pub size: Option<usize>,
+    pub sender_ttl: Option<u8>,
+    pub rtt: Option<i64>,
+    pub forward_owd: Option<i64>,
+    pub backward_owd: Option<i64>,
+}
+
+impl PacketRecord {
+    pub fn new(address: SocketAddr, packet: &PacketResults) -> Self {
+        Self {
+            address,
+            sender_seq: packet.sender_seq,
+            reflector_seq: packet.reflector_seq,
+            t1: packet.t1.to_nanos(),
+            t2: packet.t2.map(|t2| t2.to_nanos()),
+            t3: packet.t3.map(|t3| t3.to_nanos()),
+            t4: packet.t4.map(|t4| t4.to_nanos()),
+            size: packet.size,
+            sender_ttl: packet.sender_ttl,
+            // The time the packet spent on the reflector is left out of the exported round trip
+            rtt: packet.t4.map(|t4| {
+                let reflector_time = packet
+                    .t2
+                    .zip(packet.t3)
+                    .map(|(t2, t3)| (t3 - t2).as_nanos());
+                (t4 - packet.t1).as_nanos() - reflector_time.unwrap_or_default()
+            }),
+            forward_owd: packet.calculate_owd_forward().map(|owd| owd.as_nanos()),
+            backward_owd: packet.calculate_owd_backward().map(|owd| owd.as_nanos()),
+        }
+    }
+
+    /// Returns the packet of the record, the derived delays are left out.
+    pub fn packet_results(&self) -> PacketResults {
+        PacketResults {
+            sender_seq: self.sender_seq,
+            reflector_seq: self.reflector_seq,
+            t1: DateTime::from_nanos(self.t1),
+            t2: self.t2.map(DateTime::from_nanos),
+            t3: self.t3.map(DateTime::from_nanos),
+            t4: self.t4.map(DateTime::from_nanos),
+            size: self.size,
+            sender_ttl: self.sender_ttl,
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        fn cell<T: ToString>(value: Option<T>) -> String {
+            value.map(|value| value.to_string()).unwrap_or_default()
+        }
+        [
+            self.address.to_string(),
+            self.sender_seq.to_string(),
+            cell(self.reflector_seq),
+            self.t1.to_string(),
+            cell(self.t2),
+            cell(self.t3),
+            cell(self.t4),
+            cell(self.size),
+            cell(self.sender_ttl),
+            cell(self.rtt),
+            cell(self.forward_owd),
+            cell(self.backward_owd),
+        ]
+        .join(",")
+    }
+
+    fn from_csv(line: &str) -> Result<Self, CommonError> {
+        let cells: Vec<&str> = line.split(',').collect();
+        if cells.len() != CSV_HEADER.split(',').count() {
+            return Err(CommonError::Generic(format!(
+                "Invalid packet record {}",
+                line
+            )));
+        }
+        fn parse<T: FromStr>(cell: &str) -> Result<T, CommonError> {
+            cell.parse()
+                .map_err(|_| CommonError::Generic(format!("Invalid packet record value {}", cell)))
+        }
+        fn parse_option<T: FromStr>(cell: &str) -> Result<Option<T>, CommonError> {
+            (!cell.is_empty()).then(|| parse(cell)).transpose()
+        }
+        Ok(Self {
+            address: parse(cells[0])?,
+            sender_seq: parse(cells[1])?,
+            reflector_seq: parse_option(cells[2])?,
+            t1: parse(cells[3])?,
+            t2: parse_option(cells[4])?,
+            t3: parse_option(cells[5])?,
+            t4: parse_option(cells[6])?,
+            size: parse_option(cells[7])?,
+            sender_ttl: parse_option(cells[8])?,
+            rtt: parse_option(cells[9])?,
+            forward_owd: parse_option(cells[10])?,
+            backward_owd: parse_option(cells[11])?,
+        })
+    }
+}
+
+/// Collects the packets of the sessions of a test while it runs, so they can be delivered to
+/// the sinks that want them.
+///
+/// Every call to `next_packets` returns the packets sent up to a deadline that were not
+/// returned yet. The deadline is meant to trail the current time by the last message timeout,
+/// so the packets are complete or lost by the time they are delivered.
+#[derive(Debug)]
+pub struct PacketFeed {
+    /// Sequence number of the last packet returned for every session
+    delivered: Vec<Option<u32>>,
+}
+
+impl PacketFeed {
+    /// Creates the feed of a test of `sessions` sessions.
+    pub fn new(sessions: usize) -> Self {
+        Self {
+            delivered: vec![None; sessions],
+        }
+    }
+
+    /// Returns the packets sent up to `deadline` that were not returned yet.
+    /// When `deadline` is `None` all the remaining packets are returned.
+    pub fn next_packets(
+        &mut self,
+        sessions: &[Session],
+        deadline: Option<DateTime>,
+    ) -> Result<Vec<PacketRecord>, CommonError> {
+        let mut records = Vec::new();
+        for (session, delivered) in sessions.iter().zip(self.delivered.iter_mut()) {
+            let results = session.results.read()?;
+            // Results are stored in sending order
+            let start = delivered.map_or(0, |last| {
+                results.partition_point(|packet| packet.sender_seq <= last)
+            });
+            for packet in results[start..].iter().take_while(|packet| {
+                deadline
+                    .map(|deadline| (packet.t1 - deadline).as_nanos() <= 0)
+                    .unwrap_or(true)
+            }) {
+                records.push(PacketRecord::new(session.tx_socket_address, packet));
+                *delivered = Some(packet.sender_seq);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Writes the packets of a test to a file while the test runs.
+#[derive(Debug)]
+pub struct PacketExporter {
+    writer: BufWriter<File>,
+    format: ExportFormat,
+}
+
+impl PacketExporter {
+    /// Creates the export file of a test.
+    pub fn create(configuration: &PacketExportConfiguration) -> Result<Self, CommonError> {
+        let mut writer = BufWriter::new(File::create(&configuration.path)?);
+        if configuration.format == ExportFormat::Csv {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(Self {
+            writer,
+            format: configuration.format,
+        })
+    }
+}
+
+impl ResultSink<TwampResult, IntervalReport, PacketRecord> for PacketExporter {
+    fn wants_intervals(&self) -> bool {
+        false
+    }
+
+    fn wants_packets(&self) -> bool {
+        true
+    }
+
+    fn on_packet(&mut self, packet: &PacketRecord) -> Result<(), CommonError> {
+        match self.format {
+            ExportFormat::Csv => writeln!(self.writer, "{}", packet.to_csv())?,
+            ExportFormat::JsonLines => {
+                let line = serde_json::to_string(packet)
+                    .map_err(|e| CommonError::Generic(e.to_string()))?;
+                writeln!(self.writer, "{}", line)?
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), CommonError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the packet records of an export file.
+pub fn read_packet_records(
+    path: &Path,
+    format: ExportFormat,
+) -> Result<Vec<PacketRecord>, CommonError> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    if format == ExportFormat::Csv {
+        match lines.next().transpose()? {
+            Some(header) if header.trim() == CSV_HEADER => {}
+            _ => {
+                return Err(CommonError::Generic(format!(
+                    "Missing packet export header in {}",
+                    path.display()
+                )))
+            }
+        }
+    }
+    lines
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| {
+            let line = line?;
+            match format {
+                ExportFormat::Csv => PacketRecord::from_csv(line.trim()),
+                ExportFormat::JsonLines => serde_json::from_str(&line).map_err(|e| {
+                    CommonError::Generic(format!("Invalid packet record {}: {}", line, e))
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Reloads the sessions of an export file, with their statistics, so their results can be
+/// calculated again.
+///
+/// Sessions are made in the order their reflector first appears in the file. The local
+/// address of the test is not exported, so the sessions get the unspecified address.
+pub fn load_sessions(path: &Path, format: ExportFormat) -> Result<Vec<Session>, CommonError> {
+    build_sessions(
+        read_packet_records(path, format)?
+            .into_iter()
+            .map(|record| {
+                let local_ip = match record.address.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+                };
+                (
+                    SocketAddr::new(local_ip, 0),
+                    record.address,
+                    record.packet_results(),
+                )
+            }),
+    )
+}
+
+/// Makes a session of the packets of every pair of sender and reflector addresses, in the
+/// order the pairs first appear, and calculates their statistics.
+pub(crate) fn build_sessions(
+    packets: impl IntoIterator<Item = (SocketAddr, SocketAddr, PacketResults)>,
+) -> Result<Vec<Session>, CommonError> {
+    let mut sessions: Vec<Session> = Vec::new();
+    for (sender, reflector, packet) in packets {
+        let index = match sessions.iter().position(|session| {
+            session.rx_socket_address == sender && session.tx_socket_address == reflector
+        }) {
+            Some(index) => index,
+            None => {
+                sessions.push(Session::new(sender, reflector));
+                sessions.len() - 1
+            }
+        };
+        sessions[index].results.write()?.push(packet);
+    }
+    for session in sessions.iter_mut() {
+        let next_seq = {
+            let mut results = session.results.write()?;
+            results.sort_by_key(|packet| packet.sender_seq);
+            results.last().map_or(0, |packet| packet.sender_seq + 1)
+        };
+        session.seq_number.store(next_seq, Ordering::Relaxed);
+        session.finalize_statistics()?;
+    }
+    Ok(sessions)
+}
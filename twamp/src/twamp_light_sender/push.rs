@@ -0,0 +1,384 @@
+use std::{
+    fmt::Write as FmtWrite,
+    io::Write,
+    net::{SocketAddr, TcpStream, UdpSocket},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use network_commons::{error::CommonError, sink::ResultSink};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::{
+    packet_export::PacketRecord,
+    report::IntervalReport,
+    result::{NetworkStatistics, SessionResult, TwampResult},
+    twamp_light::Target,
+};
+
+/// Largest UDP datagram sent by a `ResultPusher`, so it fits in the MTU of most paths
+const MAX_DATAGRAM_SIZE: usize = 1432;
+/// Longest time a `ResultPusher` waits to connect to the database or to write to it
+const PUSH_TIMEOUT: Duration = Duration::from_secs(2);
+/// Number of reports a `ResultPusher` holds while the database is slow, the next ones are
+/// dropped
+const PUSH_QUEUE_SIZE: usize = 16;
+
+/// Protocol of the pushed metrics.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PushFormat {
+    /// InfluxDB line protocol, one point per target and interval
+    InfluxLine,
+    /// StatsD gauges and counters, with the tags in the DogStatsD format
+    Statsd,
+}
+
+/// Transport of the pushed metrics.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PushTransport {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+/// Tag that can be added to the pushed metrics.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PushTag {
+    /// IP address the packets are sent from
+    Source,
+    /// Address of the reflector
+    Target,
+    /// DSCP value of the packets of the target
+    Dscp,
+    /// Label of the target
+    Label,
+}
+
+/// Settings of the push of the interval reports to a metrics database.
+#[derive(Validate, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PushConfiguration {
+    pub address: SocketAddr,
+    pub format: PushFormat,
+    #[serde(default)]
+    pub transport: PushTransport,
+    /// Name of the InfluxDB measurement, or prefix of the StatsD metrics
+    #[serde(default = "default_prefix")]
+    #[validate(length(min = 1, max = 64))]
+    pub prefix: String,
+    /// Tags added to every metric, when the target has them
+    #[serde(default = "default_tags")]
+    pub tags: Vec<PushTag>,
+}
+
+fn default_prefix() -> String {
+    "twamp".to_string()
+}
+
+fn default_tags() -> Vec<PushTag> {
+    vec![
+        PushTag::Source,
+        PushTag::Target,
+        PushTag::Dscp,
+        PushTag::Label,
+    ]
+}
+
+#[derive(Debug)]
+enum Connection {
+    Udp(UdpSocket),
+    /// Connected on the first push, and again after a failure
+    Tcp(Option<TcpStream>),
+}
+
+impl Connection {
+    fn send(&mut self, address: SocketAddr, lines: &[String]) -> Result<(), CommonError> {
+        match self {
+            Connection::Udp(socket) => {
+                // Lines are grouped in datagrams, never split between them
+                let mut datagram = String::new();
+                for line in lines {
+                    if !datagram.is_empty() && datagram.len() + line.len() + 1 > MAX_DATAGRAM_SIZE {
+                        socket.send(datagram.as_bytes())?;
+                        datagram.clear();
+                    }
+                    datagram.push_str(line);
+                    datagram.push('\n');
+                }
+                socket.send(datagram.as_bytes())?;
+            }
+            Connection::Tcp(stream) => {
+                let payload: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+                let mut connected = match stream.take() {
+                    Some(connected) => connected,
+                    None => {
+                        let connected = TcpStream::connect_timeout(&address, PUSH_TIMEOUT)?;
+                        connected.set_write_timeout(Some(PUSH_TIMEOUT))?;
+                        connected
+                    }
+                };
+                connected.write_all(payload.as_bytes())?;
+                *stream = Some(connected);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pushes the network statistics of every interval report to a metrics database.
+///
+/// Delays and jitters are in nanoseconds, as in the results, and the packet counts are
+/// those of the interval. As for every sink, failures to deliver are logged, so an
+/// unavailable database never stops the test. The reports are sent by a thread of the
+/// pusher, so a slow database doesn't delay the test either: the reports that find the
+/// queue of the thread full are dropped.
+#[derive(Debug)]
+pub struct ResultPusher {
+    configuration: PushConfiguration,
+    source: SocketAddr,
+    /// DSCP value of the targets, by address
+    dscp: Vec<(SocketAddr, Option<u8>)>,
+    queue: Option<SyncSender<Vec<String>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ResultPusher {
+    pub fn new(
+        configuration: &PushConfiguration,
+        source: SocketAddr,
+        targets: &[Target],
+    ) -> Result<Self, CommonError> {
+        let connection = match configuration.transport {
+            PushTransport::Udp => {
+                let local_address: SocketAddr = match configuration.address {
+                    SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+                    SocketAddr::V6(_) => "[::]:0".parse()?,
+                };
+                let socket = UdpSocket::bind(local_address)?;
+                socket.connect(configuration.address)?;
+                Connection::Udp(socket)
+            }
+            PushTransport::Tcp => Connection::Tcp(None),
+        };
+        let (queue, batches) = mpsc::sync_channel::<Vec<String>>(PUSH_QUEUE_SIZE);
+        let address = configuration.address;
+        let worker = std::thread::Builder::new()
+            .name("push".to_string())
+            .spawn(move || {
+                let mut connection = connection;
+                for lines in batches {
+                    if let Err(e) = connection.send(address, &lines) {
+                        log::error!("Failed to push results to {}: {}", address, e);
+                    }
+                }
+            })?;
+        Ok(Self {
+            configuration: configuration.clone(),
+            source,
+            dscp: targets
+                .iter()
+                .map(|target| (target.host, target.dscp))
+                .collect(),
+            queue: Some(queue),
+            worker: Some(worker),
+        })
+    }
+
+    /// Pushes the interval results of a report.
+    pub fn push(&mut self, report: &IntervalReport) -> Result<(), CommonError> {
+        let lines: Vec<String> = report
+            .session_results
+            .iter()
+            .filter_map(|result| {
+                let statistics = result.network_statistics.as_ref()?;
+                let tags = self.tags(result);
+                let lines = match self.configuration.format {
+                    PushFormat::InfluxLine => vec![influx_line(
+                        &self.configuration.prefix,
+                        &tags,
+                        statistics,
+                        report.interval_end.to_nanos(),
+                    )],
+                    PushFormat::Statsd => {
+                        statsd_lines(&self.configuration.prefix, &tags, statistics)
+                    }
+                };
+                Some(lines)
+            })
+            .flatten()
+            .collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let Some(queue) = &self.queue else {
+            return Ok(());
+        };
+        match queue.try_send(lines) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                log::warn!(
+                    "Results to push to {} are piling up, dropping those of the interval",
+                    self.configuration.address
+                );
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(CommonError::Generic(format!(
+                "The push of the results to {} stopped",
+                self.configuration.address
+            ))),
+        }
+    }
+
+    /// Waits for the queued reports to be pushed, and stops the thread that pushes them.
+    fn finish(&mut self) -> Result<(), CommonError> {
+        self.queue.take();
+        match self.worker.take() {
+            Some(worker) => worker
+                .join()
+                .map_err(|_| CommonError::Generic("The push thread panicked".to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn tags(&self, result: &SessionResult) -> Vec<(&'static str, String)> {
+        self.configuration
+            .tags
+            .iter()
+            .filter_map(|tag| match tag {
+                PushTag::Source => Some(("source", self.source.ip().to_string())),
+                PushTag::Target => Some(("target", result.address.to_string())),
+                PushTag::Dscp => self
+                    .dscp
+                    .iter()
+                    .find(|(host, _)| *host == result.address)
+                    .and_then(|(_, dscp)| *dscp)
+                    .map(|dscp| ("dscp", dscp.to_string())),
+                PushTag::Label => result.label.clone().map(|label| ("label", label)),
+            })
+            .collect()
+    }
+}
+
+impl ResultSink<TwampResult, IntervalReport, PacketRecord> for ResultPusher {
+    fn on_interval(&mut self, interval: &IntervalReport) -> Result<(), CommonError> {
+        self.push(interval)
+    }
+
+    fn on_complete(&mut self, _result: &TwampResult) -> Result<(), CommonError> {
+        self.finish()
+    }
+}
+
+impl Drop for ResultPusher {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("{}", e);
+        }
+    }
+}
+
+/// Values of the statistics that are pushed, the delays being left out when the interval has
+/// no reflected packet. Values that are not finite are left out as well, as neither format
+/// can carry them.
+fn fields(statistics: &NetworkStatistics) -> Vec<(&'static str, Option<f64>)> {
+    let fields = vec![
+        ("avg_rtt", statistics.avg_rtt),
+        ("min_rtt", statistics.min_rtt),
+        ("max_rtt", statistics.max_rtt),
+        ("median_rtt", statistics.median_rtt),
+        ("p95_rtt", statistics.p95_rtt),
+        ("p99_rtt", statistics.p99_rtt),
+        ("avg_forward_owd", statistics.avg_forward_owd),
+        ("avg_backward_owd", statistics.avg_backward_owd),
+        ("avg_forward_jitter", statistics.avg_forward_jitter),
+        ("avg_backward_jitter", statistics.avg_backward_jitter),
+    ];
+    fields
+        .into_iter()
+        .map(|(name, value)| (name, value.filter(|value| value.is_finite())))
+        .collect()
+}
+
+/// Packet counts of the interval.
+fn counts(statistics: &NetworkStatistics) -> [(&'static str, u64); 4] {
+    [
+        ("received_packets", statistics.total_packets as u64),
+        ("forward_loss", u64::from(statistics.forward_loss)),
+        ("backward_loss", u64::from(statistics.backward_loss)),
+        ("total_loss", u64::from(statistics.total_loss)),
+    ]
+}
+
+/// Formats the statistics of a target as a point of the InfluxDB line protocol.
+///
+/// # References
+///
+/// * [InfluxDB line protocol](https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/)
+fn influx_line(
+    measurement: &str,
+    tags: &[(&str, String)],
+    statistics: &NetworkStatistics,
+    timestamp: u64,
+) -> String {
+    let escape = |value: &str, special: &[char]| {
+        value.chars().fold(String::new(), |mut escaped, c| {
+            if special.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
+    };
+    let mut line = escape(measurement, &[',', ' ']);
+    for (name, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+        let _ = write!(line, ",{}={}", name, escape(value, &[',', '=', ' ']));
+    }
+    let fields: Vec<String> = fields(statistics)
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| format!("{}={}", name, value)))
+        .chain(
+            counts(statistics)
+                .into_iter()
+                .map(|(name, value)| format!("{}={}i", name, value)),
+        )
+        .collect();
+    let _ = write!(line, " {} {}", fields.join(","), timestamp);
+    line
+}
+
+/// Formats the statistics of a target as StatsD gauges, and its packet counts as counters.
+///
+/// # References
+///
+/// * [StatsD metric types](https://github.com/statsd/statsd/blob/master/docs/metric_types.md)
+fn statsd_lines(
+    prefix: &str,
+    tags: &[(&str, String)],
+    statistics: &NetworkStatistics,
+) -> Vec<String> {
+    // The separators of the format can't be part of a tag
+    let tags: Vec<String> = tags
+        .iter()
+        .map(|(name, value)| format!("{}:{}", name, value.replace([',', '|', '#', '\n'], "_")))
+        .collect();
+    let tags = if tags.is_empty() {
+        String::new()
+    } else {
+        format!("|#{}", tags.join(","))
+    };
+    fields(statistics)
+        .into_iter()
+        .filter_map(|(name, value)| {
+            value.map(|value| format!("{}.{}:{}|g{}", prefix, name, value, tags))
+        })
+        .chain(
+            counts(statistics)
+                .into_iter()
+                .map(|(name, value)| format!("{}.{}:{}|c{}", prefix, name, value, tags)),
+        )
+        .collect()
+}
@@ -0,0 +1,134 @@
+use std::{collections::HashMap, net::SocketAddr, path::Path};
+
+use bebytes::BeBytes;
+use network_commons::{
+    error::CommonError,
+    pcap::{read_udp_datagrams, CapturedDatagram},
+    time::{DateTime, NtpTimestamp},
+};
+
+use crate::twamp_common::{
+    data_model::PacketResults,
+    message::{ReflectedMessage, SenderMessage},
+    session::Session,
+    MIN_UNAUTH_PADDING,
+};
+
+use super::packet_export::build_sessions;
+
+/// Size of the smallest unauthenticated TWAMP-Test packet, as sent by a sender.
+const MIN_TEST_PACKET_SIZE: usize = 14 + MIN_UNAUTH_PADDING;
+
+/// Reloads the TWAMP-Test sessions of a pcap or pcapng capture, with their statistics, so their
+/// results can be calculated.
+///
+/// The capture can be taken anywhere on the path. Test packets are told apart from the rest
+/// of the UDP traffic by pairing the flows of opposite directions: a flow is a session when
+/// the packets of the opposite flow reflect its sequence numbers and timestamps. Sessions are
+/// made in the order they first appear in the capture.
+///
+/// The `t1`, `t2` and `t3` timestamps are read from the packets, and `t4` is the time at
+/// which the reflected packet was captured. Round trip times and backward delays are then
+/// only those of the sender when the capture was taken on its host.
+pub fn load_capture_sessions(path: &Path) -> Result<Vec<Session>, CommonError> {
+    let datagrams = read_udp_datagrams(path)?;
+    let mut flows: Vec<((SocketAddr, SocketAddr), Vec<&CapturedDatagram>)> = Vec::new();
+    let mut flow_indexes: HashMap<(SocketAddr, SocketAddr), usize> = HashMap::new();
+    for datagram in &datagrams {
+        let key = (datagram.source, datagram.destination);
+        let index = *flow_indexes.entry(key).or_insert_with(|| {
+            flows.push((key, Vec::new()));
+            flows.len() - 1
+        });
+        flows[index].1.push(datagram);
+    }
+
+    let mut packets = Vec::new();
+    for ((source, destination), sent) in &flows {
+        let Some(reflected) = flow_indexes
+            .get(&(*destination, *source))
+            .map(|index| &flows[*index].1)
+        else {
+            continue;
+        };
+        let session_packets = match_session(sent, reflected);
+        // The opposite flow is the sender when it got more of its packets reflected
+        let reverse_matches = matched_packets(&match_session(reflected, sent));
+        if matched_packets(&session_packets) == 0
+            || matched_packets(&session_packets) < reverse_matches
+        {
+            continue;
+        }
+        log::info!(
+            "Found TWAMP-Test session from {} to {} with {} packets",
+            source,
+            destination,
+            session_packets.len()
+        );
+        packets.extend(
+            session_packets
+                .into_iter()
+                .map(|packet| (*source, *destination, packet)),
+        );
+    }
+    build_sessions(packets)
+}
+
+fn matched_packets(packets: &[PacketResults]) -> usize {
+    packets.iter().filter(|packet| packet.t4.is_some()).count()
+}
+
+/// Reads the `sent` packets as sender packets and completes them with the `reflected`
+/// packets that reflect them.
+fn match_session(
+    sent: &[&CapturedDatagram],
+    reflected: &[&CapturedDatagram],
+) -> Vec<PacketResults> {
+    let mut packets: Vec<PacketResults> = Vec::new();
+    // Index and timestamp of the last packet sent with every sequence number
+    let mut indexes: HashMap<u32, (usize, NtpTimestamp)> = HashMap::new();
+    for datagram in sent {
+        if datagram.payload.len() < MIN_TEST_PACKET_SIZE {
+            continue;
+        }
+        let Ok((message, _)) = SenderMessage::try_from_be_bytes(&datagram.payload) else {
+            continue;
+        };
+        let Ok(t1) = DateTime::try_from(message.timestamp) else {
+            continue;
+        };
+        indexes.insert(message.sequence_number, (packets.len(), message.timestamp));
+        packets.push(PacketResults {
+            sender_seq: message.sequence_number,
+            reflector_seq: None,
+            t1,
+            t2: None,
+            t3: None,
+            t4: None,
+            size: None,
+            sender_ttl: None,
+        });
+    }
+    for datagram in reflected {
+        let Ok((message, _)) = ReflectedMessage::try_from_be_bytes(&datagram.payload) else {
+            continue;
+        };
+        let Some(packet) = indexes
+            .get(&message.sender_sequence_number)
+            .filter(|(_, timestamp)| *timestamp == message.sender_timestamp)
+            .map(|(index, _)| &mut packets[*index])
+        else {
+            continue;
+        };
+        // Duplicated reflections are ignored
+        if packet.t4.is_some() {
+            continue;
+        }
+        packet.reflector_seq = Some(message.reflector_sequence_number);
+        packet.t2 = DateTime::try_from(message.receive_timestamp).ok();
+        packet.t3 = DateTime::try_from(message.timestamp).ok();
+        packet.t4 = Some(datagram.timestamp);
+        packet.sender_ttl = Some(message.sender_ttl);
+    }
+    packets
+}
@@ -0,0 +1,102 @@
+use core::time::Duration;
+use std::sync::{Arc, RwLock};
+
+use network_commons::{error::CommonError, time::DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::twamp_common::session::Session;
+
+use super::{
+    result::SessionResult,
+    sink::TwampSinks,
+    twamp_light::{calculate_interval_results, calculate_rolling_results},
+};
+
+/// `IntervalReport` holds the results of every session for the packets sent
+/// between `interval_start` and `interval_end`.
+/// In continuous mode it also holds the results of the packets sent during the
+/// retention window that ends at `interval_end`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IntervalReport {
+    pub interval_start: DateTime,
+    pub interval_end: DateTime,
+    pub session_results: Vec<SessionResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolling_session_results: Option<Vec<SessionResult>>,
+    /// Whether every session met its SLA thresholds over the interval, and over the retention
+    /// window in continuous mode. Missing when no session has thresholds.
+    /// The verdict of each session is in the `sla` of its results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sla_passed: Option<bool>,
+}
+
+/// Produces the periodic `IntervalReport`s of a running test and delivers them to its sinks.
+///
+/// A report produced at time `t` covers the packets sent up to `t - last_message_timeout`,
+/// so every packet of the window had the chance to be reflected before it is accounted for.
+pub struct IntervalReporter {
+    sinks: TwampSinks,
+    last_message_timeout: Duration,
+    interval_start: DateTime,
+    retention_window: Option<Duration>,
+}
+
+impl IntervalReporter {
+    pub fn new(sinks: TwampSinks, last_message_timeout: Duration) -> Self {
+        Self {
+            sinks,
+            last_message_timeout,
+            interval_start: DateTime::utc_now(),
+            retention_window: None,
+        }
+    }
+
+    /// Adds the results of the packets sent during the `retention_window` to every report.
+    /// The packets are kept for that window by the test, not by the reporter.
+    pub fn set_retention_window(&mut self, retention_window: Duration) {
+        self.retention_window = Some(retention_window);
+    }
+
+    /// Reports the window that ended `last_message_timeout` ago.
+    pub fn report(&mut self, sessions: &Arc<RwLock<Vec<Session>>>) -> Result<(), CommonError> {
+        let interval_end = DateTime::utc_now() - self.last_message_timeout;
+        self.send(sessions, interval_end, Some(interval_end))
+    }
+
+    /// Reports every packet that was not part of a window yet.
+    /// This is meant to be called once the test is over.
+    pub fn flush(&mut self, sessions: &Arc<RwLock<Vec<Session>>>) -> Result<(), CommonError> {
+        self.send(sessions, DateTime::utc_now(), None)
+    }
+
+    fn send(
+        &mut self,
+        sessions: &Arc<RwLock<Vec<Session>>>,
+        interval_end: DateTime,
+        deadline: Option<DateTime>,
+    ) -> Result<(), CommonError> {
+        let session_results = calculate_interval_results(sessions, deadline)?;
+        let rolling_session_results = self
+            .retention_window
+            .map(|retention_window| {
+                calculate_rolling_results(sessions, interval_end - retention_window, interval_end)
+            })
+            .transpose()?;
+        let sla_passed = session_results
+            .iter()
+            .chain(rolling_session_results.iter().flatten())
+            .filter_map(|session_result| session_result.sla.as_ref())
+            .map(|verdict| verdict.passed)
+            .reduce(|passed, verdict_passed| passed && verdict_passed);
+        let report = IntervalReport {
+            interval_start: self.interval_start,
+            interval_end,
+            session_results,
+            rolling_session_results,
+            sla_passed,
+        };
+        self.interval_start = interval_end;
+        self.sinks.interval(&report);
+        Ok(())
+    }
+}
@@ -1,9 +1,27 @@
-use std::net::SocketAddr;
+use std::{collections::BTreeMap, net::SocketAddr};
 
-use network_commons::TestResult;
+use network_commons::{
+    error::CommonError,
+    stats::{
+        confidence::{
+            ConfidenceConfiguration, ConfidenceInterval, IntervalEstimator, IntervalMethod,
+        },
+        skew_estimator::SkewEstimator,
+        voice_quality::{mos, r_factor, Codec, PathImpairments},
+    },
+    TestResult,
+};
 use serde::{Deserialize, Serialize};
 
-use super::NETWORK_PRECISION;
+use crate::twamp_common::{
+    data_model::PacketResults,
+    session::Session,
+    statistics::{
+        ClockOffset, ClockSkew, CorrectedOwd, OffsetEstimator, OwdSeries, SessionStatistics,
+    },
+};
+
+use super::{baseline::BaselineComparison, sla::SlaVerdict, NETWORK_PRECISION};
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct NetworkStatistics {
@@ -42,6 +60,18 @@ pub struct NetworkStatistics {
         serialize_with = "round_option_f64_with_precision"
     )]
     pub high_percentile_rtt: Option<f64>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub p95_rtt: Option<f64>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub p99_rtt: Option<f64>,
     #[serde(
         skip_serializing_if = "Option::is_none",
         serialize_with = "round_option_f64_with_precision"
@@ -176,6 +206,375 @@ pub struct NetworkStatistics {
         serialize_with = "round_option_f64_with_precision"
     )]
     pub gamlr_offset: Option<f64>,
+    /// One-way delays with the estimated clock offset removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corrected_owd: Option<CorrectedOwdStatistics>,
+    /// Confidence intervals of the main statistics, when they are configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence_intervals: Option<ConfidenceIntervals>,
+    /// Estimated voice quality of every configured codec
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub voice_quality: Vec<VoiceQuality>,
+}
+
+impl NetworkStatistics {
+    /// Returns the impairments a voice call would suffer on the path, from the mean round trip
+    /// time without the reflector processing time, and the mean jitter of the worst direction.
+    /// The round trip time is used since the one-way delays include the offset of the clocks.
+    pub fn path_impairments(&self, loss_ratio: f64, burst_ratio: f64) -> Option<PathImpairments> {
+        let rtt = self.avg_rtt? - self.avg_process_time.unwrap_or_default();
+        let jitter = self
+            .avg_forward_jitter
+            .unwrap_or_default()
+            .max(self.avg_backward_jitter.unwrap_or_default());
+        Some(PathImpairments {
+            one_way_delay: rtt / 2.0 / NANOS_PER_MILLI,
+            jitter: jitter / NANOS_PER_MILLI,
+            loss_ratio,
+            burst_ratio,
+        })
+    }
+}
+
+impl NetworkStatistics {
+    /// Replaces the estimated medians and percentiles with the exact quantiles of `packets`,
+    /// which must be the packets the statistics were built from.
+    pub fn with_exact_quantiles(self, packets: &[PacketResults]) -> Self {
+        let reflected: Vec<&PacketResults> = packets
+            .iter()
+            .filter(|packet| packet.t2.is_some() && packet.t3.is_some())
+            .collect();
+        let sorted = |delay: fn(&PacketResults) -> Option<f64>| {
+            let mut delays: Vec<f64> = reflected
+                .iter()
+                .filter_map(|packet| delay(packet))
+                .collect();
+            delays.sort_by(|a, b| a.total_cmp(b));
+            delays
+        };
+        let rtt = sorted(|packet| packet.calculate_rtt().map(|rtt| rtt.as_nanos() as f64));
+        let f_owd = sorted(|packet| {
+            packet
+                .calculate_owd_forward()
+                .map(|owd| owd.as_nanos() as f64)
+        });
+        let b_owd = sorted(|packet| {
+            packet
+                .calculate_owd_backward()
+                .map(|owd| owd.as_nanos() as f64)
+        });
+        let rpd = sorted(|packet| packet.calculate_rpd().map(|rpd| rpd.as_nanos() as f64));
+        Self {
+            median_rtt: quantile(&rtt, 0.5),
+            low_percentile_rtt: quantile(&rtt, 0.25),
+            high_percentile_rtt: quantile(&rtt, 0.75),
+            p95_rtt: quantile(&rtt, 0.95),
+            p99_rtt: quantile(&rtt, 0.99),
+            median_forward_owd: quantile(&f_owd, 0.5),
+            low_percentile_forward_owd: quantile(&f_owd, 0.25),
+            high_percentile_forward_owd: quantile(&f_owd, 0.75),
+            median_backward_owd: quantile(&b_owd, 0.5),
+            low_percentile_backward_owd: quantile(&b_owd, 0.25),
+            high_percentile_backward_owd: quantile(&b_owd, 0.75),
+            median_process_time: quantile(&rpd, 0.5),
+            low_percentile_process_time: quantile(&rpd, 0.25),
+            high_percentile_process_time: quantile(&rpd, 0.75),
+            ..self
+        }
+    }
+}
+
+/// Quantile `p` of the `sorted` values, interpolated between the two closest ranks.
+fn quantile(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = p * last as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+pub(crate) const NANOS_PER_MILLI: f64 = 1e6;
+
+/// ITU-T G.107 E-model rating of a codec over the path of a session.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VoiceQuality {
+    pub codec: Codec,
+    #[serde(serialize_with = "round_f64_to_hundredths")]
+    pub r_factor: f64,
+    #[serde(serialize_with = "round_f64_to_hundredths")]
+    pub mos: f64,
+}
+
+impl VoiceQuality {
+    pub fn new(codec: Codec, impairments: &PathImpairments) -> Self {
+        let r_factor = r_factor(codec, impairments);
+        Self {
+            codec,
+            r_factor,
+            mos: mos(r_factor),
+        }
+    }
+}
+
+impl From<&SessionStatistics> for NetworkStatistics {
+    /// Builds the loss, delay and jitter statistics from the streaming statistics of a session.
+    /// Medians and percentiles are P² estimates, see `with_exact_quantiles`.
+    /// The offset estimate is left to be filled by the caller.
+    fn from(statistics: &SessionStatistics) -> Self {
+        let (rtt, f_owd, b_owd, rpd) = (
+            &statistics.rtt,
+            &statistics.forward_owd,
+            &statistics.backward_owd,
+            &statistics.process_time,
+        );
+        Self {
+            avg_rtt: rtt.mean(),
+            min_rtt: rtt.min(),
+            max_rtt: rtt.max(),
+            std_dev_rtt: rtt.std_dev(),
+            median_rtt: rtt.median(),
+            low_percentile_rtt: rtt.quantile(0.25),
+            high_percentile_rtt: rtt.quantile(0.75),
+            p95_rtt: rtt.quantile(0.95),
+            p99_rtt: rtt.quantile(0.99),
+            avg_forward_owd: f_owd.mean(),
+            min_forward_owd: f_owd.min(),
+            max_forward_owd: f_owd.max(),
+            std_dev_forward_owd: f_owd.std_dev(),
+            median_forward_owd: f_owd.median(),
+            low_percentile_forward_owd: f_owd.quantile(0.25),
+            high_percentile_forward_owd: f_owd.quantile(0.75),
+            avg_backward_owd: b_owd.mean(),
+            min_backward_owd: b_owd.min(),
+            max_backward_owd: b_owd.max(),
+            std_dev_backward_owd: b_owd.std_dev(),
+            median_backward_owd: b_owd.median(),
+            low_percentile_backward_owd: b_owd.quantile(0.25),
+            high_percentile_backward_owd: b_owd.quantile(0.75),
+            avg_process_time: rpd.mean(),
+            min_process_time: rpd.min(),
+            max_process_time: rpd.max(),
+            std_dev_process_time: rpd.std_dev(),
+            median_process_time: rpd.median(),
+            low_percentile_process_time: rpd.quantile(0.25),
+            high_percentile_process_time: rpd.quantile(0.75),
+            avg_forward_jitter: statistics.forward_jitter.mean(),
+            avg_backward_jitter: statistics.backward_jitter.mean(),
+            std_dev_forward_jitter: statistics.forward_jitter.std_dev(),
+            std_dev_backward_jitter: statistics.backward_jitter.std_dev(),
+            forward_loss: statistics.loss.forward_loss as u32,
+            backward_loss: statistics.loss.backward_loss() as u32,
+            total_loss: statistics.loss.total_loss as u32,
+            total_packets: statistics.received_packets,
+            ..Default::default()
+        }
+    }
+}
+
+/// One-way delay statistics corrected with the offset between the sender and reflector clocks.
+///
+/// The offset is the reflector clock minus the sender clock, so it is removed from the forward
+/// delays and added to the backward ones. When a skew was estimated, the drift of the reflector
+/// clock is removed as well, and the offset is the one at the start of the session.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct CorrectedOwdStatistics {
+    /// Estimator the offset comes from
+    pub estimator: OffsetEstimator,
+    #[serde(serialize_with = "round_f64_with_precision")]
+    pub offset: f64,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub offset_standard_error: Option<f64>,
+    /// Confidence interval of the offset, at the configured confidence level
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub offset_lower_bound: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub offset_upper_bound: Option<f64>,
+    /// Estimator the skew comes from, when the clock drift was removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skew_estimator: Option<SkewEstimator>,
+    /// Drift of the reflector clock in nanoseconds per second
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub skew: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub avg_forward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub min_forward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub max_forward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub std_dev_forward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub median_forward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub low_percentile_forward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub high_percentile_forward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub avg_backward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub min_backward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub max_backward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub std_dev_backward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub median_backward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub low_percentile_backward_owd: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_f64_with_precision"
+    )]
+    pub high_percentile_backward_owd: Option<f64>,
+}
+
+impl CorrectedOwdStatistics {
+    pub fn new(
+        clock_offset: &ClockOffset,
+        clock_skew: Option<&ClockSkew>,
+        corrected: &CorrectedOwd,
+    ) -> Self {
+        let (f_owd, b_owd) = (&corrected.forward_owd, &corrected.backward_owd);
+        Self {
+            estimator: clock_offset.estimator,
+            offset: clock_offset.offset(),
+            offset_standard_error: clock_offset.estimate.standard_error,
+            offset_lower_bound: clock_offset.estimate.lower_bound,
+            offset_upper_bound: clock_offset.estimate.upper_bound,
+            skew_estimator: clock_skew.map(|clock_skew| clock_skew.estimator),
+            skew: clock_skew.map(|clock_skew| clock_skew.skew * 1e9),
+            avg_forward_owd: f_owd.mean(),
+            min_forward_owd: f_owd.min(),
+            max_forward_owd: f_owd.max(),
+            std_dev_forward_owd: f_owd.std_dev(),
+            median_forward_owd: f_owd.median(),
+            low_percentile_forward_owd: f_owd.quantile(0.25),
+            high_percentile_forward_owd: f_owd.quantile(0.75),
+            avg_backward_owd: b_owd.mean(),
+            min_backward_owd: b_owd.min(),
+            max_backward_owd: b_owd.max(),
+            std_dev_backward_owd: b_owd.std_dev(),
+            median_backward_owd: b_owd.median(),
+            low_percentile_backward_owd: b_owd.quantile(0.25),
+            high_percentile_backward_owd: b_owd.quantile(0.75),
+        }
+    }
+}
+
+/// Confidence intervals of the statistics of a session.
+/// Delay intervals are in nanoseconds, and the loss ratio interval is a fraction of the sent packets.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfidenceIntervals {
+    pub level: f64,
+    pub method: IntervalMethod,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_interval_with_precision"
+    )]
+    pub mean_rtt: Option<ConfidenceInterval>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_interval_with_precision"
+    )]
+    pub median_rtt: Option<ConfidenceInterval>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_interval_with_precision"
+    )]
+    pub mean_forward_owd: Option<ConfidenceInterval>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_interval_with_precision"
+    )]
+    pub median_forward_owd: Option<ConfidenceInterval>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_interval_with_precision"
+    )]
+    pub mean_backward_owd: Option<ConfidenceInterval>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "round_option_interval_with_precision"
+    )]
+    pub median_backward_owd: Option<ConfidenceInterval>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loss_ratio: Option<ConfidenceInterval>,
+}
+
+impl ConfidenceIntervals {
+    /// Calculates the intervals from the round trip times, the raw one-way delays and the
+    /// number of lost packets out of the sent ones.
+    pub fn new(
+        configuration: &ConfidenceConfiguration,
+        rtt: &[f64],
+        owd_series: &OwdSeries,
+        lost_packets: usize,
+        sent_packets: usize,
+    ) -> Self {
+        let mut estimator = IntervalEstimator::new(configuration.clone());
+        let (f_owd, b_owd) = owd_series.detrended(None);
+        Self {
+            level: configuration.level,
+            method: configuration.method,
+            mean_rtt: estimator.mean(rtt),
+            median_rtt: estimator.median(rtt),
+            mean_forward_owd: estimator.mean(&f_owd),
+            median_forward_owd: estimator.median(&f_owd),
+            mean_backward_owd: estimator.mean(&b_owd),
+            median_backward_owd: estimator.median(&b_owd),
+            loss_ratio: estimator.proportion(lost_packets, sent_packets),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -185,6 +584,108 @@ pub struct SessionResult {
     pub status: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub network_statistics: Option<NetworkStatistics>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Statistics of every packet size, when the test uses a size profile
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub size_results: Vec<SizeResult>,
+    /// Largest IP packet size that went to the reflector and back, in path MTU tests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_mtu: Option<usize>,
+    /// Evaluation of the SLA thresholds of the target, when it has any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sla: Option<SlaVerdict>,
+    /// Round trip time of every reflected packet in nanoseconds, when they are recorded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rtt_samples: Vec<f64>,
+    /// Comparison with the matching session of the baseline, when the test has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<BaselineComparison>,
+}
+
+/// Statistics of the packets of a single size.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SizeResult {
+    pub size: usize,
+    pub network_statistics: NetworkStatistics,
+}
+
+impl SessionResult {
+    /// Creates the result of a session, evaluating the SLA thresholds of the session if it has
+    /// any. The status is `SlaViolated` when a threshold is not met, `Success` otherwise.
+    pub fn new(session: &Session, network_statistics: NetworkStatistics) -> Self {
+        let sla = (!session.sla.is_empty())
+            .then(|| SlaVerdict::evaluate(&session.sla, &network_statistics));
+        let status = match &sla {
+            Some(verdict) if !verdict.passed => "SlaViolated",
+            _ => "Success",
+        };
+        Self {
+            address: session.tx_socket_address,
+            status: Some(status.to_string()),
+            network_statistics: Some(network_statistics),
+            label: session.label.clone(),
+            tags: session.tags.clone(),
+            size_results: Vec::new(),
+            path_mtu: None,
+            sla,
+            rtt_samples: Vec::new(),
+            baseline: None,
+        }
+    }
+
+    /// Name of the target of the session: its label, or its address without one.
+    pub fn target(&self) -> String {
+        self.label
+            .clone()
+            .unwrap_or_else(|| self.address.to_string())
+    }
+
+    /// Describes the violated SLA thresholds of the session, if any.
+    pub fn sla_violations(&self) -> Option<String> {
+        let verdict = self.sla.as_ref().filter(|verdict| !verdict.passed)?;
+        let violations: Vec<String> = verdict
+            .violations
+            .iter()
+            .map(|violation| match (violation.value, violation.excess) {
+                (Some(value), Some(excess)) => format!(
+                    "{} is {} for a maximum of {} (+{})",
+                    violation.metric, value, violation.threshold, excess
+                ),
+                _ => format!(
+                    "{} is not available for a maximum of {}",
+                    violation.metric, violation.threshold
+                ),
+            })
+            .collect();
+        Some(format!("{}: {}", self.target(), violations.join(", ")))
+    }
+
+    /// Describes the regressions of the session from its baseline, if any.
+    pub fn regressions(&self) -> Option<String> {
+        let comparison = self
+            .baseline
+            .as_ref()
+            .filter(|comparison| comparison.regressed)?;
+        let mut regressions = Vec::new();
+        if let Some(rtt) = comparison.rtt.as_ref().filter(|rtt| rtt.regressed) {
+            regressions.push(format!(
+                "median RTT from {} to {} ns (p = {:.2e})",
+                rtt.baseline_median, rtt.current_median, rtt.test.p_value
+            ));
+        }
+        if let Some(loss) = comparison.loss.as_ref().filter(|loss| loss.regressed) {
+            regressions.push(format!(
+                "loss from {:.4}% to {:.4}% (p = {:.2e})",
+                100.0 * loss.baseline_loss_ratio,
+                100.0 * loss.current_loss_ratio,
+                loss.test.p_value
+            ));
+        }
+        Some(format!("{}: {}", self.target(), regressions.join(", ")))
+    }
 }
 
 fn round_f64_with_precision<S>(num: &f64, serializer: S) -> Result<S::Ok, S::Error>
@@ -205,6 +706,30 @@ where
     round_f64_with_precision(&num, serializer)
 }
 
+fn round_f64_to_hundredths<S>(num: &f64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_f64((num * 100.0).round() / 100.0)
+}
+
+fn round_option_interval_with_precision<S>(
+    interval: &Option<ConfidenceInterval>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let factor = 10f64.powi(NETWORK_PRECISION);
+    let round = |num: f64| (num * factor).round() / factor;
+    interval
+        .map(|interval| ConfidenceInterval {
+            lower: round(interval.lower),
+            upper: round(interval.upper),
+        })
+        .serialize(serializer)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TwampResult {
     pub session_results: Vec<SessionResult>,
@@ -212,4 +737,32 @@ pub struct TwampResult {
     pub error: Option<String>,
 }
 
-impl TestResult for TwampResult {}
+impl TestResult for TwampResult {
+    /// Fails when a session violated its SLA thresholds, or regressed from a baseline that
+    /// fails the test on regressions.
+    fn status(&self) -> Result<(), CommonError> {
+        let violations: Vec<String> = self
+            .session_results
+            .iter()
+            .filter_map(SessionResult::sla_violations)
+            .collect();
+        if !violations.is_empty() {
+            return Err(CommonError::ThresholdViolation(violations.join("; ")));
+        }
+        let regressions: Vec<String> = self
+            .session_results
+            .iter()
+            .filter(|session_result| {
+                session_result
+                    .baseline
+                    .as_ref()
+                    .is_some_and(|comparison| comparison.fail_on_regression)
+            })
+            .filter_map(SessionResult::regressions)
+            .collect();
+        if !regressions.is_empty() {
+            return Err(CommonError::Regression(regressions.join("; ")));
+        }
+        Ok(())
+    }
+}
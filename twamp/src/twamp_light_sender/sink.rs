@@ -0,0 +1,88 @@
+use std::{
+    path::PathBuf,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+use network_commons::{
+    error::CommonError,
+    sink::{JsonLinesSink, ResultSink, SharedSink, Sinks},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{packet_export::PacketRecord, report::IntervalReport, result::TwampResult};
+
+/// A sink of the outputs of a TWAMP test.
+pub type TwampSink = SharedSink<TwampResult, IntervalReport, PacketRecord>;
+
+/// The sinks of a TWAMP test.
+pub type TwampSinks = Sinks<TwampResult, IntervalReport, PacketRecord>;
+
+/// Settings of a built-in sink, writing every output of the test as a line of JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SinkConfiguration {
+    Stdout {
+        /// Whether every packet is written too
+        #[serde(default)]
+        packets: bool,
+    },
+    File {
+        /// File the outputs are written to, replaced if it exists
+        path: PathBuf,
+        /// Whether every packet is written too
+        #[serde(default)]
+        packets: bool,
+        /// Whether the outputs are added to the end of the file instead of replacing it.
+        /// The runs of scheduled jobs append by default, and other tests replace the file.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        append: Option<bool>,
+    },
+}
+
+impl SinkConfiguration {
+    pub fn create(&self) -> Result<TwampSink, CommonError> {
+        Ok(match self {
+            SinkConfiguration::Stdout { packets } => {
+                Arc::new(Mutex::new(JsonLinesSink::stdout(*packets)))
+            }
+            SinkConfiguration::File {
+                path,
+                packets,
+                append: Some(true),
+            } => Arc::new(Mutex::new(JsonLinesSink::append(path, *packets)?)),
+            SinkConfiguration::File { path, packets, .. } => {
+                Arc::new(Mutex::new(JsonLinesSink::create(path, *packets)?))
+            }
+        })
+    }
+
+    /// Makes a `FILE` sink append to its file unless it is set to replace it, so that the
+    /// runs of a scheduled job don't erase the outputs of the previous ones.
+    pub(crate) fn append_by_default(&mut self) {
+        if let SinkConfiguration::File { append, .. } = self {
+            append.get_or_insert(true);
+        }
+    }
+}
+
+/// Delivers the interval reports through a channel.
+/// A receiver that went away must not stop the test.
+#[derive(Debug)]
+pub struct ReportChannel {
+    sender: Sender<IntervalReport>,
+}
+
+impl ReportChannel {
+    pub fn new(sender: Sender<IntervalReport>) -> Self {
+        Self { sender }
+    }
+}
+
+impl ResultSink<TwampResult, IntervalReport, PacketRecord> for ReportChannel {
+    fn on_interval(&mut self, interval: &IntervalReport) -> Result<(), CommonError> {
+        if let Err(e) = self.sender.send(interval.clone()) {
+            log::warn!("Failed to deliver interval report: {}", e);
+        }
+        Ok(())
+    }
+}
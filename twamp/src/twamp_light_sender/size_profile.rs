@@ -0,0 +1,190 @@
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+use validator::ValidationError;
+
+use crate::twamp_common::MIN_UNAUTH_PADDING;
+
+/// Largest packet size that can be probed, bound by the receive buffers of the reflector.
+pub const MAX_PACKET_SIZE: usize = 4096;
+
+/// Size of the TWAMP test packet without its padding
+const TEST_PACKET_HEADER_SIZE: usize = 14 + MIN_UNAUTH_PADDING;
+const UDP_HEADER_SIZE: usize = 8;
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+
+/// A packet size and its share of the packets of an `Imix` profile.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SizeClass {
+    /// Size of the IP packet in bytes
+    pub size: usize,
+    pub weight: u32,
+}
+
+/// Sizes of the packets sent during a test.
+///
+/// Sizes are IP packet sizes, headers included. Sizes below the smallest TWAMP test packet
+/// are sent with no padding, but their results are still reported under the configured size.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SizeProfile {
+    /// Sends `packets_per_step` packets of every size from `min` to `max`, increasing by `step`.
+    /// The sweep starts over once `max` is reached.
+    Sweep {
+        min: usize,
+        max: usize,
+        step: usize,
+        packets_per_step: u32,
+    },
+    /// Mixes the sizes of the `classes` according to their weight.
+    /// Without classes, the simple IMIX of 7:4:1 packets of 64, 576 and 1500 bytes is used.
+    Imix {
+        #[serde(default = "simple_imix")]
+        classes: Vec<SizeClass>,
+    },
+}
+
+fn simple_imix() -> Vec<SizeClass> {
+    vec![
+        SizeClass {
+            size: 64,
+            weight: 7,
+        },
+        SizeClass {
+            size: 576,
+            weight: 4,
+        },
+        SizeClass {
+            size: 1500,
+            weight: 1,
+        },
+    ]
+}
+
+impl SizeProfile {
+    /// Returns every size sent with this profile, in ascending order.
+    pub fn sizes(&self) -> Vec<usize> {
+        let mut sizes: Vec<usize> = match self {
+            SizeProfile::Sweep { min, max, step, .. } => {
+                (*min..=*max).step_by((*step).max(1)).collect()
+            }
+            SizeProfile::Imix { classes } => classes.iter().map(|class| class.size).collect(),
+        };
+        sizes.sort_unstable();
+        sizes.dedup();
+        sizes
+    }
+}
+
+/// Validates the bounds of a `SizeProfile`.
+pub fn validate_size_profile(profile: &SizeProfile) -> Result<(), ValidationError> {
+    let valid_size = |size: &usize| (1..=MAX_PACKET_SIZE).contains(size);
+    match profile {
+        SizeProfile::Sweep {
+            min,
+            max,
+            step,
+            packets_per_step,
+        } => {
+            if !valid_size(min) || !valid_size(max) || min > max {
+                return Err(ValidationError::new(
+                    "sweep sizes must be between 1 and 4096 with min not above max",
+                ));
+            }
+            if *step == 0 || *packets_per_step == 0 {
+                return Err(ValidationError::new(
+                    "sweep step and packets_per_step must be at least 1",
+                ));
+            }
+        }
+        SizeProfile::Imix { classes } => {
+            if classes.is_empty() {
+                return Err(ValidationError::new("imix requires at least one class"));
+            }
+            if !classes
+                .iter()
+                .all(|class| valid_size(&class.size) && class.weight > 0)
+            {
+                return Err(ValidationError::new(
+                    "imix sizes must be between 1 and 4096 with a weight of at least 1",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Picks the size of every packet sent with a `SizeProfile`.
+///
+/// The `Imix` classes are interleaved with a smooth weighted round robin, so every
+/// window of packets follows the distribution as closely as possible.
+#[derive(Debug, Clone)]
+pub struct SizeSchedule {
+    profile: SizeProfile,
+    sent: u64,
+    current_weights: Vec<i64>,
+}
+
+impl SizeSchedule {
+    pub fn new(profile: SizeProfile) -> Self {
+        let classes = match &profile {
+            SizeProfile::Imix { classes } => classes.len(),
+            SizeProfile::Sweep { .. } => 0,
+        };
+        Self {
+            profile,
+            sent: 0,
+            current_weights: vec![0; classes],
+        }
+    }
+
+    /// Returns the size of the next packet.
+    pub fn next_size(&mut self) -> usize {
+        let size = match &self.profile {
+            SizeProfile::Sweep {
+                min,
+                max,
+                step,
+                packets_per_step,
+            } => {
+                let steps = (max - min) / step + 1;
+                let current_step = (self.sent / u64::from(*packets_per_step)) % steps as u64;
+                min + current_step as usize * step
+            }
+            SizeProfile::Imix { classes } => {
+                let total: i64 = classes.iter().map(|class| i64::from(class.weight)).sum();
+                self.current_weights
+                    .iter_mut()
+                    .zip(classes.iter())
+                    .for_each(|(current, class)| *current += i64::from(class.weight));
+                let selected = self
+                    .current_weights
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(index, weight)| (**weight, std::cmp::Reverse(*index)))
+                    .map(|(index, _)| index)
+                    .unwrap_or_default();
+                self.current_weights[selected] -= total;
+                classes[selected].size
+            }
+        };
+        self.sent += 1;
+        size
+    }
+}
+
+/// Returns the padding to add to a TWAMP test packet sent to `host` for the IP packet
+/// to be `size` bytes long.
+pub fn padding_for_size(size: usize, host: &SocketAddr) -> usize {
+    size.saturating_sub(min_packet_size(host))
+}
+
+/// Size of the smallest IP packet carrying a TWAMP test packet to `host`.
+pub fn min_packet_size(host: &SocketAddr) -> usize {
+    let ip_header_size = match host {
+        SocketAddr::V4(_) => IPV4_HEADER_SIZE,
+        SocketAddr::V6(_) => IPV6_HEADER_SIZE,
+    };
+    ip_header_size + UDP_HEADER_SIZE + TEST_PACKET_HEADER_SIZE
+}
@@ -0,0 +1,137 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::result::{NetworkStatistics, NANOS_PER_MILLI};
+
+/// Metric of a session that can be bound by an `SlaThreshold`.
+/// Delays and jitters are in milliseconds, and losses in percent of the sent packets.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SlaMetric {
+    AvgRtt,
+    MedianRtt,
+    P95Rtt,
+    P99Rtt,
+    MaxRtt,
+    AvgForwardOwd,
+    AvgBackwardOwd,
+    /// Mean jitter of the worst direction
+    Jitter,
+    ForwardJitter,
+    BackwardJitter,
+    Loss,
+    ForwardLoss,
+    BackwardLoss,
+}
+
+impl Display for SlaMetric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let name = match self {
+            SlaMetric::AvgRtt => "AVG_RTT",
+            SlaMetric::MedianRtt => "MEDIAN_RTT",
+            SlaMetric::P95Rtt => "P95_RTT",
+            SlaMetric::P99Rtt => "P99_RTT",
+            SlaMetric::MaxRtt => "MAX_RTT",
+            SlaMetric::AvgForwardOwd => "AVG_FORWARD_OWD",
+            SlaMetric::AvgBackwardOwd => "AVG_BACKWARD_OWD",
+            SlaMetric::Jitter => "JITTER",
+            SlaMetric::ForwardJitter => "FORWARD_JITTER",
+            SlaMetric::BackwardJitter => "BACKWARD_JITTER",
+            SlaMetric::Loss => "LOSS",
+            SlaMetric::ForwardLoss => "FORWARD_LOSS",
+            SlaMetric::BackwardLoss => "BACKWARD_LOSS",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl SlaMetric {
+    /// Returns the value of the metric, if the statistics have it.
+    pub fn value(&self, statistics: &NetworkStatistics) -> Option<f64> {
+        let millis = |nanos: Option<f64>| nanos.map(|nanos| nanos / NANOS_PER_MILLI);
+        let sent_packets = statistics.total_packets as f64 + f64::from(statistics.total_loss);
+        let percent =
+            |lost: u32| (sent_packets > 0.0).then(|| 100.0 * f64::from(lost) / sent_packets);
+        match self {
+            SlaMetric::AvgRtt => millis(statistics.avg_rtt),
+            SlaMetric::MedianRtt => millis(statistics.median_rtt),
+            SlaMetric::P95Rtt => millis(statistics.p95_rtt),
+            SlaMetric::P99Rtt => millis(statistics.p99_rtt),
+            SlaMetric::MaxRtt => millis(statistics.max_rtt),
+            SlaMetric::AvgForwardOwd => millis(statistics.avg_forward_owd),
+            SlaMetric::AvgBackwardOwd => millis(statistics.avg_backward_owd),
+            SlaMetric::Jitter => millis(
+                statistics
+                    .avg_forward_jitter
+                    .into_iter()
+                    .chain(statistics.avg_backward_jitter)
+                    .reduce(f64::max),
+            ),
+            SlaMetric::ForwardJitter => millis(statistics.avg_forward_jitter),
+            SlaMetric::BackwardJitter => millis(statistics.avg_backward_jitter),
+            SlaMetric::Loss => percent(statistics.total_loss),
+            SlaMetric::ForwardLoss => percent(statistics.forward_loss),
+            SlaMetric::BackwardLoss => percent(statistics.backward_loss),
+        }
+    }
+}
+
+/// Upper bound of a metric of a session.
+#[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub struct SlaThreshold {
+    pub metric: SlaMetric,
+    #[validate(range(min = 0.0))]
+    pub max: f64,
+}
+
+/// A threshold that was not met.
+/// The value is missing when the statistics don't have it, as when no packet came back.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct SlaViolation {
+    pub metric: SlaMetric,
+    pub threshold: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    /// Amount by which the value exceeds the threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excess: Option<f64>,
+}
+
+/// Outcome of the evaluation of the thresholds of a session.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct SlaVerdict {
+    pub passed: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<SlaViolation>,
+}
+
+impl SlaVerdict {
+    /// Evaluates every threshold against the statistics of a session.
+    pub fn evaluate(thresholds: &[SlaThreshold], statistics: &NetworkStatistics) -> Self {
+        let violations: Vec<SlaViolation> = thresholds
+            .iter()
+            .filter_map(|threshold| {
+                let value = threshold.metric.value(statistics);
+                match value {
+                    Some(value) if value <= threshold.max => None,
+                    _ => Some(SlaViolation {
+                        metric: threshold.metric,
+                        threshold: threshold.max,
+                        value: value.map(round_to_thousandths),
+                        excess: value.map(|value| round_to_thousandths(value - threshold.max)),
+                    }),
+                }
+            })
+            .collect();
+        Self {
+            passed: violations.is_empty(),
+            violations,
+        }
+    }
+}
+
+fn round_to_thousandths(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
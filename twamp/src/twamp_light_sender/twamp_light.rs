@@ -4,8 +4,15 @@ use network_commons::epoll_loop::LinuxEventLoop as EventLoop;
 use network_commons::{
     error::CommonError,
     event_loop::{EventLoopTrait, Itimerspec, Token},
+    metrics::MetricsServer,
+    pcap::CaptureConfiguration,
     socket::{Socket, DEFAULT_BUFFER_SIZE},
-    time::NtpTimestamp,
+    stats::{
+        confidence::ConfidenceConfiguration, offset_estimator::GamlrConfiguration,
+        skew_estimator::SkewEstimator, voice_quality::Codec,
+    },
+    stop_handle::StopHandle,
+    time::{DateTime, NtpTimestamp},
     udp_socket::TimestampedUdpSocket,
     Strategy,
 };
@@ -13,23 +20,126 @@ use network_commons::{
 use bebytes::BeBytes;
 
 use crate::twamp_common::{
-    data_model::ErrorEstimate,
+    capture::{CaptureHandle, CaptureThread},
+    data_model::{ErrorEstimate, PacketResults},
     message::{ReflectedMessage, SenderMessage},
+    metrics::{SenderMetrics, METRICS_REFRESH_INTERVAL},
+    session::analyze_packet_loss,
+    statistics::{ClockSkew, CorrectedOwd, OffsetEstimator, OwdSeries, SessionStatistics},
 };
 use crate::twamp_common::{session::Session, MIN_UNAUTH_PADDING};
-use crate::twamp_light_sender::Configuration as TwampLightConfiguration;
+use crate::twamp_light_sender::{
+    Configuration as TwampLightConfiguration, TargetConfiguration, DEFAULT_RETENTION_WINDOW,
+};
 use core::time::Duration;
 use std::{
-    borrow::BorrowMut,
+    collections::{BTreeMap, VecDeque},
     net::SocketAddr,
-    sync::{atomic::Ordering, Arc, RwLock},
+    sync::{atomic::Ordering, Arc, Mutex, RwLock},
+};
+
+use super::baseline::{Baseline, BaselineConfiguration};
+use super::packet_export::{PacketExportConfiguration, PacketExporter, PacketFeed};
+use super::push::{PushConfiguration, ResultPusher};
+use super::report::IntervalReporter;
+use super::result::{
+    ConfidenceIntervals, CorrectedOwdStatistics, NetworkStatistics, SessionResult, SizeResult,
+    TwampResult, VoiceQuality,
 };
+use super::sink::{TwampSink, TwampSinks};
+use super::size_profile::{padding_for_size, SizeProfile, SizeSchedule};
+use super::sla::SlaThreshold;
+
+/// Interval at which the complete packets are delivered to the sinks
+pub const PACKET_DELIVERY_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval at which the packets that left the retention window are removed in continuous mode
+const RETENTION_PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Settings used to send the packets of a single target of the test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub host: SocketAddr,
+    pub padding: usize,
+    pub size_profile: Option<SizeProfile>,
+    pub packet_interval: Duration,
+    pub dscp: Option<u8>,
+    /// Delay between the start of the test and the first packet
+    pub start_offset: Duration,
+    /// Time during which packets are sent once the target started
+    pub duration: Option<Duration>,
+    pub label: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub sla: Vec<SlaThreshold>,
+}
+
+impl Target {
+    /// Resolves the settings of a target, falling back to the test settings.
+    pub fn new(target: &TargetConfiguration, configuration: &TwampLightConfiguration) -> Self {
+        Self {
+            host: target.host,
+            padding: target.padding.unwrap_or(configuration.padding),
+            size_profile: target
+                .size_profile
+                .clone()
+                .or_else(|| configuration.size_profile.clone()),
+            packet_interval: Duration::from_millis(
+                target
+                    .packet_interval
+                    .unwrap_or(configuration.packet_interval),
+            ),
+            dscp: target.dscp,
+            start_offset: Duration::from_millis(target.start_offset.unwrap_or_default()),
+            duration: target.duration.map(Duration::from_secs),
+            label: target.label.clone(),
+            tags: target.tags.clone(),
+            sla: target
+                .sla
+                .clone()
+                .unwrap_or_else(|| configuration.sla.clone()),
+        }
+    }
+}
+
+/// Keeps the session and sequence number of the packets whose transmit timestamp was not
+/// retrieved yet, along with the identifier the kernel gave to each of them.
+///
+/// Every target has its own timer, so the transmit timestamps in the error queue don't follow
+/// the order of the sessions and have to be matched through these identifiers.
+#[derive(Debug, Default)]
+pub struct TxTracker {
+    next_id: u32,
+    pending: VecDeque<(u32, usize, u32)>,
+}
+
+impl TxTracker {
+    /// Records a packet that was just sent for the session at `session_index`.
+    pub fn track(&mut self, session_index: usize, sender_seq: u32) {
+        self.pending
+            .push_back((self.next_id, session_index, sender_seq));
+        self.next_id = self.next_id.wrapping_add(1);
+    }
 
-use super::result::{NetworkStatistics, SessionResult, TwampResult};
+    /// Returns the session index and sequence number of the packet identified by `id`.
+    /// Timestamps are delivered in sending order, so the pending packets sent before
+    /// that one will never get their timestamp and are forgotten.
+    pub fn resolve(&mut self, id: u32) -> Option<(usize, u32)> {
+        while let Some(&(pending_id, session_index, sender_seq)) = self.pending.front() {
+            // The identifier belongs to a packet that was already resolved
+            if (pending_id.wrapping_sub(id) as i32) > 0 {
+                return None;
+            }
+            self.pending.pop_front();
+            if pending_id == id {
+                return Some((session_index, sender_seq));
+            }
+        }
+        None
+    }
+}
 
 pub struct SessionSender {
-    /// List of host on which runs a reflecctors to perform the test
-    pub targets: Vec<SocketAddr>,
+    /// List of targets on which runs a reflector to perform the test
+    pub targets: Vec<Target>,
     /// IP address of the interface on which to bind
     pub source_ip_address: SocketAddr,
     /// Interval at which the packets are sent
@@ -40,18 +150,78 @@ pub struct SessionSender {
     pub padding: usize,
     /// Duration of the test session
     pub duration: Duration,
+    /// Retention window of the packet results when the test runs until stopped
+    pub continuous: Option<Duration>,
+    /// Interval at which reports of the running test are produced
+    pub report_interval: Option<Duration>,
+    /// Settings of the analysis of the packets once the test is over
+    pub analysis: ResultAnalysis,
+    /// File to which every packet is written while the test runs
+    pub packet_export: Option<PacketExportConfiguration>,
+    /// Address on which the metrics of the running test are served
+    pub metrics_address: Option<SocketAddr>,
+    /// Metrics databases the interval reports are pushed to
+    pub push: Vec<PushConfiguration>,
+    /// File to which every sent and received packet is written
+    pub capture: Option<CaptureConfiguration>,
+    /// Sinks the outputs of the running test are delivered to
+    sinks: TwampSinks,
+    /// Handle used to stop the test before its deadline
+    stop_handle: Option<StopHandle>,
 }
 
 impl SessionSender {
     pub fn new(configuration: &TwampLightConfiguration) -> Self {
         Self {
-            targets: configuration.hosts.to_owned(),
+            targets: configuration
+                .all_targets()
+                .iter()
+                .map(|target| Target::new(target, configuration))
+                .collect(),
             source_ip_address: configuration.source_ip_address.to_owned(),
             duration: Duration::from_secs(configuration.duration),
             packet_interval: Duration::from_millis(configuration.packet_interval),
             padding: configuration.padding,
             last_message_timeout: Duration::from_secs(configuration.last_message_timeout),
+            report_interval: configuration.report_interval.map(Duration::from_secs),
+            continuous: configuration.continuous.then(|| {
+                Duration::from_secs(
+                    configuration
+                        .retention_window
+                        .unwrap_or(DEFAULT_RETENTION_WINDOW),
+                )
+            }),
+            analysis: ResultAnalysis::new(configuration),
+            packet_export: configuration.packet_export.clone(),
+            metrics_address: configuration.metrics_address,
+            push: configuration.push.clone(),
+            capture: configuration.capture.clone(),
+            sinks: TwampSinks::new(),
+            stop_handle: None,
+        }
+    }
+
+    /// Sets the sinks the outputs of the running test are delivered to, which include those
+    /// of `create_sinks`. Interval reports are only produced if a report interval is configured.
+    pub fn set_sinks(&mut self, sinks: TwampSinks) {
+        self.sinks = sinks;
+    }
+
+    /// Creates the sinks of the packet export and of the metrics databases of the
+    /// configuration.
+    pub fn create_sinks(&self) -> Result<Vec<TwampSink>, CommonError> {
+        let mut sinks: Vec<TwampSink> = Vec::new();
+        if let Some(configuration) = &self.packet_export {
+            sinks.push(Arc::new(Mutex::new(PacketExporter::create(configuration)?)));
+        }
+        for configuration in &self.push {
+            sinks.push(Arc::new(Mutex::new(ResultPusher::new(
+                configuration,
+                self.source_ip_address,
+                &self.targets,
+            )?)));
         }
+        Ok(sinks)
     }
 
     pub fn create_udp_socket(&mut self) -> Result<TimestampedUdpSocket, CommonError> {
@@ -60,24 +230,49 @@ impl SessionSender {
         my_socket.set_fcntl_options()?;
         my_socket.set_socket_options(libc::SOL_IP, libc::IP_RECVERR, Some(1))?;
         my_socket.set_socket_options(libc::IPPROTO_IP, libc::IP_TOS, Some(0))?;
+        if self.capture.is_some() {
+            // The capture needs the local address of the packets received on an
+            // unspecified address
+            my_socket.set_packet_info_options()?;
+        }
 
-        my_socket.set_timestamping_options()?;
+        my_socket.set_identified_timestamping_options()?;
 
         Ok(my_socket)
     }
 }
 impl Strategy<TwampResult, CommonError> for SessionSender {
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+
     fn execute(&mut self) -> Result<TwampResult, CommonError> {
+        let baseline = self.analysis.load_baseline()?;
         // Create the sessions vector
         let sessions = self
             .targets
             .iter()
-            .map(|host| Session::new(self.source_ip_address, *host))
+            .map(|target| {
+                let mut session = Session::new(self.source_ip_address, target.host);
+                session.label = target.label.clone();
+                session.tags = target.tags.clone();
+                session.sla = target.sla.clone();
+                session
+            })
             .collect::<Vec<Session>>();
         let rc_sessions = Arc::new(RwLock::new(sessions));
+        let sinks = self.sinks.clone();
 
         // Create the socket
         let my_socket = self.create_udp_socket()?;
+        let capture = match &self.capture {
+            Some(configuration) => Some(CaptureThread::spawn(
+                configuration,
+                my_socket.local_address()?,
+            )?),
+            None => None,
+        };
+        let capture_handle = capture.as_ref().map(CaptureThread::handle);
 
         // Creates the event loop with a default socket
         let mut event_loop = EventLoop::new(1024)?;
@@ -86,22 +281,39 @@ impl Strategy<TwampResult, CommonError> for SessionSender {
             it_value: self.last_message_timeout,
         });
         // Register the socket into the event loop
-        let rx_token = event_loop
-            .register_event_source(my_socket, Box::new(create_rx_callback(rc_sessions.clone())))?;
-
-        // This configures the tx socket timer.
-        let timer_spec = Itimerspec {
-            it_interval: self.packet_interval,
-            it_value: Duration::from_nanos(10),
-        };
-
-        // Create the Tx timed event to send twamp messages
-        let _tx_token = event_loop.register_timer(
-            &timer_spec,
-            &rx_token,
-            Box::new(create_tx_callback(rc_sessions.clone(), self.padding)),
+        let rx_token = event_loop.register_event_source(
+            my_socket,
+            Box::new(create_rx_callback(
+                rc_sessions.clone(),
+                capture_handle.clone(),
+            )),
         )?;
 
+        // Create a Tx timed event per target to send twamp messages, they all share the socket
+        let tracker = Arc::new(Mutex::new(TxTracker::default()));
+        let start = DateTime::utc_now();
+        for (index, target) in self.targets.iter().enumerate() {
+            let timer_spec = Itimerspec {
+                it_interval: target.packet_interval,
+                it_value: target.start_offset.max(Duration::from_nanos(10)),
+            };
+            let end = target
+                .duration
+                .map(|duration| start + target.start_offset + duration);
+            let _tx_token = event_loop.register_timer(
+                &timer_spec,
+                &rx_token,
+                Box::new(create_target_tx_callback(
+                    rc_sessions.clone(),
+                    tracker.clone(),
+                    index,
+                    target.clone(),
+                    end,
+                    capture_handle.clone(),
+                )),
+            )?;
+        }
+
         // // This configures the tx timestamp correction socket timer.
         let tx_correction_timer_spec = Itimerspec {
             it_interval: Duration::from_millis(150),
@@ -111,23 +323,137 @@ impl Strategy<TwampResult, CommonError> for SessionSender {
         let tx_correct_token = event_loop.register_timer(
             &tx_correction_timer_spec,
             &rx_token,
-            Box::new(create_tx_correct_callback(rc_sessions.clone())),
+            Box::new(create_tx_correct_callback(
+                rc_sessions.clone(),
+                tracker.clone(),
+                capture_handle.clone(),
+                self.last_message_timeout,
+            )),
         )?;
         event_loop.add_overtime_exception(tx_correct_token);
-        // Create the deadline event
-        let duration_spec = Itimerspec {
-            it_interval: Duration::ZERO,
-            it_value: self.duration,
+
+        // Create the interval reports timed event
+        let reporter = match self.report_interval {
+            Some(report_interval) if sinks.wants_intervals() => {
+                let mut reporter = IntervalReporter::new(sinks.clone(), self.last_message_timeout);
+                if let Some(retention_window) = self.continuous {
+                    reporter.set_retention_window(retention_window);
+                }
+                let reporter = Arc::new(Mutex::new(reporter));
+                let report_timer_spec = Itimerspec {
+                    it_interval: report_interval,
+                    it_value: report_interval,
+                };
+                event_loop.register_timer(
+                    &report_timer_spec,
+                    &rx_token,
+                    Box::new(create_report_callback(
+                        rc_sessions.clone(),
+                        reporter.clone(),
+                    )),
+                )?;
+                Some(reporter)
+            }
+            _ => None,
+        };
+        // Create the retention timed event, so the memory of a continuous test stays bounded
+        // whatever its sinks are
+        if let Some(retention_window) = self.continuous {
+            let prune_timer_spec = Itimerspec {
+                it_interval: RETENTION_PRUNE_INTERVAL,
+                it_value: RETENTION_PRUNE_INTERVAL,
+            };
+            event_loop.register_timer(
+                &prune_timer_spec,
+                &rx_token,
+                Box::new(create_prune_callback(
+                    rc_sessions.clone(),
+                    retention_window,
+                    self.last_message_timeout,
+                )),
+            )?;
+        }
+        // Create the packet delivery timed event
+        let packet_feed = if sinks.wants_packets() {
+            let packet_feed = Arc::new(Mutex::new(PacketFeed::new(self.targets.len())));
+            let packet_timer_spec = Itimerspec {
+                it_interval: PACKET_DELIVERY_INTERVAL,
+                it_value: PACKET_DELIVERY_INTERVAL,
+            };
+            event_loop.register_timer(
+                &packet_timer_spec,
+                &rx_token,
+                Box::new(create_packet_callback(
+                    rc_sessions.clone(),
+                    packet_feed.clone(),
+                    sinks.clone(),
+                    self.last_message_timeout,
+                )),
+            )?;
+            Some(packet_feed)
+        } else {
+            None
         };
+        // Serve the metrics, refreshed by a timer of the test loop so the sessions are never
+        // locked by a scrape
+        let metrics_server = match self.metrics_address {
+            Some(metrics_address) => {
+                let mut metrics = SenderMetrics::new(self.targets.len(), self.last_message_timeout);
+                let snapshot = Arc::new(RwLock::new(metrics.encode(&rc_sessions.try_read()?)?));
+                let metrics_timer_spec = Itimerspec {
+                    it_interval: METRICS_REFRESH_INTERVAL,
+                    it_value: METRICS_REFRESH_INTERVAL,
+                };
+                event_loop.register_timer(
+                    &metrics_timer_spec,
+                    &rx_token,
+                    Box::new(create_metrics_callback(
+                        rc_sessions.clone(),
+                        metrics,
+                        snapshot.clone(),
+                    )),
+                )?;
+                Some(MetricsServer::spawn(metrics_address, snapshot)?)
+            }
+            None => None,
+        };
+        if let Some(stop_handle) = &self.stop_handle {
+            event_loop.register_stop_handle(stop_handle)?;
+        }
 
-        // Add a deadline event
-        let _termination_token = event_loop.add_duration(&duration_spec)?;
+        // Add a deadline event, a continuous test runs until it is stopped
+        if self.continuous.is_none() {
+            let duration_spec = Itimerspec {
+                it_interval: Duration::ZERO,
+                it_value: self.duration,
+            };
+            let _termination_token = event_loop.add_duration(&duration_spec)?;
+        }
         log::info!("Starting test");
         // Run the event loop
         event_loop.run()?;
         log::info!("Test finished");
+        if let Some(metrics_server) = metrics_server {
+            metrics_server.stop()?;
+        }
+        if let Some(reporter) = reporter {
+            if let Err(e) = reporter.lock()?.flush(&rc_sessions) {
+                log::error!("Failed to report the last interval: {}", e);
+            }
+        }
+        if let Some(capture) = capture {
+            capture.finish()?;
+        }
+        if let Some(packet_feed) = packet_feed {
+            sinks.packets(
+                &packet_feed
+                    .lock()?
+                    .next_packets(&rc_sessions.try_read()?, None)?,
+            );
+        }
         log::info!("Calculating results");
-        let session_results = calculate_session_results(rc_sessions)?;
+        let session_results =
+            calculate_session_results(rc_sessions, &self.analysis, baseline.as_ref())?;
         let test_result = TwampResult {
             session_results,
             error: None,
@@ -137,301 +463,450 @@ impl Strategy<TwampResult, CommonError> for SessionSender {
     }
 }
 
+/// Settings of the analysis of the packets of a test once it is over.
+#[derive(Debug, Clone, Default)]
+pub struct ResultAnalysis {
+    /// Method used to remove the drift between the sender and reflector clocks from the
+    /// one-way delays before the clock offset is estimated
+    pub skew_estimator: Option<SkewEstimator>,
+    /// Settings of the clock offset estimator
+    pub offset_estimation: GamlrConfiguration,
+    /// Settings of the confidence intervals of the main statistics
+    pub confidence: Option<ConfidenceConfiguration>,
+    /// Codecs whose voice quality is estimated
+    pub voice_codecs: Vec<Codec>,
+    /// Whether the round trip time of every packet is added to the results
+    pub rtt_samples: bool,
+    /// Previous run the results are compared with
+    pub baseline: Option<BaselineConfiguration>,
+    /// Whether the packets were pruned to a retention window, in which case the medians and
+    /// percentiles are the streaming estimates instead of the exact quantiles of the packets
+    pub continuous: bool,
+}
+
+impl ResultAnalysis {
+    pub fn new(configuration: &TwampLightConfiguration) -> Self {
+        Self {
+            skew_estimator: configuration.skew_estimator,
+            offset_estimation: configuration.offset_estimation.clone(),
+            confidence: configuration.confidence.clone(),
+            voice_codecs: configuration.voice_codecs.clone(),
+            rtt_samples: configuration.rtt_samples || configuration.baseline.is_some(),
+            baseline: configuration.baseline.clone(),
+            continuous: configuration.continuous,
+        }
+    }
+
+    /// Reads the configured baseline, so a missing or invalid file fails the test before
+    /// it starts.
+    pub fn load_baseline(&self) -> Result<Option<Baseline>, CommonError> {
+        self.baseline.as_ref().map(Baseline::load).transpose()
+    }
+}
+
+/// Calculates the final results of every session, compared with the `baseline` if any.
+///
+/// The loss, delay, jitter and voice quality statistics cover every packet of the test, as
+/// they come from the streaming statistics of the sessions. The clock offset and skew, the
+/// corrected one-way delays, the confidence intervals, the size results and the RTT samples
+/// need the packets themselves, so in continuous mode they only cover the retention window.
 pub fn calculate_session_results(
     rc_sessions: Arc<RwLock<Vec<Session>>>,
+    analysis: &ResultAnalysis,
+    baseline: Option<&Baseline>,
 ) -> Result<Vec<SessionResult>, CommonError> {
     rc_sessions
-        .try_read()?
-        .borrow_mut()
-        .iter()
+        .try_write()?
+        .iter_mut()
         .map(|session| -> Result<SessionResult, CommonError> {
-            let packets = session.results.try_read()?;
-            let total_packets = packets
-                .iter()
-                .filter(|packet_results| packet_results.t2.is_some() && packet_results.t3.is_some())
-                .count();
-            let (forward_loss, backward_loss, total_loss) =
-                session.analyze_packet_loss().unwrap_or_default();
-
-            let mut rtt_vec = Vec::new();
-            let mut f_owd_vec = Vec::new();
-            let mut b_owd_vec = Vec::new();
-            let mut rpd_vec = Vec::new();
-            let mut forward_jitter_vec = Vec::new();
-            let mut backward_jitter_vec = Vec::new();
-
-            let mut rtt_sum = 0.0;
-            let mut f_owd_sum = 0.0;
-            let mut b_owd_sum = 0.0;
-            let mut rpd_sum = 0.0;
-
-            let mut prev_forward_owd: Option<f64> = None;
-            let mut prev_backward_owd: Option<f64> = None;
-            for packet in packets
-                .iter()
-                .filter(|packet_results| packet_results.t2.is_some() && packet_results.t3.is_some())
-            {
-                if let Some(rtt) = packet.calculate_rtt() {
-                    let rtt = rtt.as_nanos() as f64;
-                    rtt_vec.push(rtt);
-                    rtt_sum += rtt;
-                }
-
-                if let Some(owd) = packet.calculate_owd_forward() {
-                    let owd = owd.as_nanos() as f64;
-                    f_owd_vec.push(owd);
-                    f_owd_sum += owd;
-
-                    // Calculate forward jitter
-                    if let Some(prev_fwd) = prev_forward_owd {
-                        let fwd_jitter = (owd - prev_fwd).abs();
-                        forward_jitter_vec.push(fwd_jitter);
-                    }
-                    prev_forward_owd = Some(owd);
-                }
-
-                if let Some(owd) = packet.calculate_owd_backward() {
-                    let owd = owd.as_nanos() as f64;
-                    b_owd_vec.push(owd);
-                    b_owd_sum += owd;
-
-                    // Calculate backward jitter
-                    if let Some(prev_bwd) = prev_backward_owd {
-                        let bwd_jitter = (owd - prev_bwd).abs();
-                        backward_jitter_vec.push(bwd_jitter);
-                    }
-                    prev_backward_owd = Some(owd);
-                }
+            session.finalize_statistics()?;
+            let statistics = session.statistics_snapshot()?;
 
-                if let Some(rpd) = packet.calculate_rpd() {
-                    let rpd = rpd.as_nanos() as f64;
-                    rpd_vec.push(rpd);
-                    rpd_sum += rpd;
-                }
-            }
-
-            // Sort the vectors for median and percentile calculations
-            rtt_vec.sort_by(|a, b| a.total_cmp(b));
-            f_owd_vec.sort_by(|a, b| a.total_cmp(b));
-            b_owd_vec.sort_by(|a, b| a.total_cmp(b));
-            rpd_vec.sort_by(|a, b| a.total_cmp(b));
-            forward_jitter_vec.sort_by(|a, b| a.total_cmp(b));
-            backward_jitter_vec.sort_by(|a, b| a.total_cmp(b));
-
-            let gamlr_offset = session.calculate_gamlr_offset(&f_owd_vec, &b_owd_vec);
-            let avg_rtt = if total_packets > 0 {
-                Some(rtt_sum / (total_packets as f64))
-            } else {
-                None
-            };
-            let avg_backward_owd = if total_packets > 0 {
-                Some(b_owd_sum / (total_packets as f64))
-            } else {
-                None
-            };
-            let avg_forward_owd = if total_packets > 0 {
-                Some(f_owd_sum / (total_packets as f64))
-            } else {
-                None
-            };
-            let avg_process_time = if total_packets > 0 {
-                Some(rpd_sum / (total_packets as f64))
-            } else {
-                None
-            };
-            let avg_forward_jitter = if !forward_jitter_vec.is_empty() {
-                Some(forward_jitter_vec.iter().sum::<f64>() / forward_jitter_vec.len() as f64)
+            let packets = session.results.try_read()?;
+            let owd_series = OwdSeries::new(&packets);
+            let clock_skew = analysis
+                .skew_estimator
+                .and_then(|estimator| ClockSkew::estimate(estimator, &owd_series));
+            let (f_owd_vec, b_owd_vec) = owd_series.detrended(clock_skew.as_ref());
+            let clock_offset =
+                session.estimate_clock_offset(&f_owd_vec, &b_owd_vec, &analysis.offset_estimation);
+            let gamlr_offset = clock_offset
+                .filter(|clock_offset| clock_offset.estimator == OffsetEstimator::Gamlr)
+                .map(|clock_offset| clock_offset.offset());
+            let corrected_owd = clock_offset.map(|clock_offset| {
+                CorrectedOwdStatistics::new(
+                    &clock_offset,
+                    clock_skew.as_ref(),
+                    &CorrectedOwd::new(&f_owd_vec, &b_owd_vec, &clock_offset),
+                )
+            });
+            // The round trip times are only needed by the intervals and the samples, which the
+            // baseline comparison uses
+            let rtt: Vec<f64> = if analysis.confidence.is_some() || analysis.rtt_samples {
+                packets
+                    .iter()
+                    .filter(|packet| packet.t2.is_some() && packet.t3.is_some())
+                    .filter_map(|packet| packet.calculate_rtt())
+                    .map(|rtt| rtt.as_nanos() as f64)
+                    .collect()
             } else {
-                None
+                Vec::new()
             };
-            let avg_backward_jitter = if !backward_jitter_vec.is_empty() {
-                Some(backward_jitter_vec.iter().sum::<f64>() / backward_jitter_vec.len() as f64)
-            } else {
-                None
+            let confidence_intervals = analysis.confidence.as_ref().map(|confidence| {
+                let (_, _, total_loss) = analyze_packet_loss(&packets);
+                ConfidenceIntervals::new(
+                    confidence,
+                    &rtt,
+                    &owd_series,
+                    total_loss as usize,
+                    packets.len(),
+                )
+            });
+
+            let mut network_results = NetworkStatistics::from(&statistics);
+            if !analysis.continuous {
+                network_results = network_results.with_exact_quantiles(&packets);
+            }
+            let network_results = NetworkStatistics {
+                gamlr_offset,
+                corrected_owd,
+                confidence_intervals,
+                ..network_results
             };
-            let std_dev_forward_jitter = calculate_std_dev(
-                &forward_jitter_vec,
-                forward_jitter_vec.iter().sum::<f64>() / forward_jitter_vec.len() as f64,
-            );
-            let std_dev_backward_jitter = calculate_std_dev(
-                &backward_jitter_vec,
-                backward_jitter_vec.iter().sum::<f64>() / backward_jitter_vec.len() as f64,
+            let impairments = network_results.path_impairments(
+                statistics.loss.loss_ratio(),
+                statistics.loss.bursts.burst_ratio(),
             );
             let network_results = NetworkStatistics {
-                avg_rtt,
-                min_rtt: rtt_vec.iter().min_by(|a, b| a.total_cmp(b)).copied(),
-                max_rtt: rtt_vec.iter().max_by(|a, b| a.total_cmp(b)).copied(),
-                std_dev_rtt: calculate_std_dev(&rtt_vec, rtt_sum / total_packets as f64),
-                median_rtt: median(&rtt_vec),
-                low_percentile_rtt: percentile(&rtt_vec, 25.0),
-                high_percentile_rtt: percentile(&rtt_vec, 75.0),
-                avg_forward_owd,
-                min_forward_owd: f_owd_vec.iter().min_by(|a, b| a.total_cmp(b)).copied(),
-                max_forward_owd: f_owd_vec.iter().max_by(|a, b| a.total_cmp(b)).copied(),
-                std_dev_forward_owd: calculate_std_dev(
-                    &f_owd_vec,
-                    f_owd_sum / total_packets as f64,
-                ),
-                median_forward_owd: median(&f_owd_vec),
-                low_percentile_forward_owd: percentile(&f_owd_vec, 25.0),
-                high_percentile_forward_owd: percentile(&f_owd_vec, 75.0),
-                avg_backward_owd,
-                min_backward_owd: b_owd_vec.iter().min_by(|a, b| a.total_cmp(b)).copied(),
-                max_backward_owd: b_owd_vec.iter().max_by(|a, b| a.total_cmp(b)).copied(),
-                std_dev_backward_owd: calculate_std_dev(
-                    &b_owd_vec,
-                    b_owd_sum / total_packets as f64,
-                ),
-                median_backward_owd: median(&b_owd_vec),
-                low_percentile_backward_owd: percentile(&b_owd_vec, 25.0),
-                high_percentile_backward_owd: percentile(&b_owd_vec, 75.0),
-                avg_process_time,
-                min_process_time: rpd_vec.iter().min_by(|a, b| a.total_cmp(b)).copied(),
-                max_process_time: rpd_vec.iter().max_by(|a, b| a.total_cmp(b)).copied(),
-                std_dev_process_time: calculate_std_dev(&rpd_vec, rpd_sum / total_packets as f64),
-                median_process_time: median(&rpd_vec),
-                low_percentile_process_time: percentile(&rpd_vec, 25.0),
-                high_percentile_process_time: percentile(&rpd_vec, 75.0),
-                avg_forward_jitter,
-                avg_backward_jitter,
-                std_dev_forward_jitter,
-                std_dev_backward_jitter,
-                forward_loss,
-                backward_loss,
-                total_loss,
-                total_packets,
-                gamlr_offset,
+                voice_quality: analysis
+                    .voice_codecs
+                    .iter()
+                    .filter_map(|codec| {
+                        impairments
+                            .as_ref()
+                            .map(|impairments| VoiceQuality::new(*codec, impairments))
+                    })
+                    .collect(),
+                ..network_results
             };
 
+            let mut session_result = SessionResult {
+                size_results: calculate_size_results(&packets),
+                ..SessionResult::new(session, network_results)
+            };
+            if analysis.rtt_samples {
+                session_result.rtt_samples = rtt;
+            }
+            session_result.baseline = baseline.and_then(|baseline| {
+                let comparison = baseline.compare(&session_result);
+                if comparison.is_none() {
+                    log::warn!("No baseline session for {}", session_result.target());
+                }
+                comparison
+            });
+            Ok(session_result)
+        })
+        .collect()
+}
+
+/// Calculates the results of the packets sent since the previous report window up to `deadline`.
+pub fn calculate_interval_results(
+    rc_sessions: &Arc<RwLock<Vec<Session>>>,
+    deadline: Option<DateTime>,
+) -> Result<Vec<SessionResult>, CommonError> {
+    rc_sessions
+        .try_write()?
+        .iter_mut()
+        .map(|session| -> Result<SessionResult, CommonError> {
+            let packets = session.next_report_window(deadline)?;
+            Ok(SessionResult {
+                size_results: calculate_size_results(&packets),
+                ..SessionResult::new(session, calculate_network_statistics(&packets))
+            })
+        })
+        .collect()
+}
+
+/// Calculates the results of the retained packets sent from `start` up to `deadline`.
+pub fn calculate_rolling_results(
+    rc_sessions: &Arc<RwLock<Vec<Session>>>,
+    start: DateTime,
+    deadline: DateTime,
+) -> Result<Vec<SessionResult>, CommonError> {
+    rc_sessions
+        .try_read()?
+        .iter()
+        .map(|session| -> Result<SessionResult, CommonError> {
+            let results = session.results.try_read()?;
+            let packets: Vec<PacketResults> = results
+                .iter()
+                .skip_while(|result| (result.t1 - start).as_nanos() < 0)
+                .take_while(|result| (result.t1 - deadline).as_nanos() <= 0)
+                .cloned()
+                .collect();
             Ok(SessionResult {
-                address: session.tx_socket_address,
-                status: Some("Success".to_string()),
-                network_statistics: Some(network_results),
+                size_results: calculate_size_results(&packets),
+                ..SessionResult::new(session, calculate_network_statistics(&packets))
             })
         })
         .collect()
 }
 
-fn median(v: &[f64]) -> Option<f64> {
-    if v.is_empty() {
-        return None;
+/// Calculates the network statistics of a set of packets.
+pub fn calculate_network_statistics(packets: &[PacketResults]) -> NetworkStatistics {
+    let mut statistics = SessionStatistics::default();
+    packets.iter().for_each(|packet| statistics.update(packet));
+    NetworkStatistics::from(&statistics).with_exact_quantiles(packets)
+}
+
+/// Calculates the network statistics of every packet size of a set of packets.
+/// Packets sent without a size profile are ignored.
+pub fn calculate_size_results(packets: &[PacketResults]) -> Vec<SizeResult> {
+    let mut sizes: Vec<usize> = packets.iter().filter_map(|packet| packet.size).collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+        .into_iter()
+        .map(|size| {
+            let size_packets: Vec<PacketResults> = packets
+                .iter()
+                .filter(|packet| packet.size == Some(size))
+                .copied()
+                .collect();
+            SizeResult {
+                size,
+                network_statistics: calculate_network_statistics(&size_packets),
+            }
+        })
+        .collect()
+}
+
+/// Produces the interval reports of the running test.
+/// Failures are logged so they don't stop the test, and the packets of a report that failed
+/// are part of the next one.
+pub fn create_report_callback(
+    tx_sessions: Arc<RwLock<Vec<Session>>>,
+    reporter: Arc<Mutex<IntervalReporter>>,
+) -> impl Fn(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    move |_inner_socket: &mut TimestampedUdpSocket, _| {
+        let reported = reporter
+            .lock()
+            .map_err(CommonError::from)
+            .and_then(|mut reporter| reporter.report(&tx_sessions));
+        if let Err(e) = reported {
+            log::error!("Failed to report the interval: {}", e);
+        }
+        Ok(0)
     }
-    let mid = v.len() / 2;
-    if v.len() % 2 == 0 {
-        Some((v[mid - 1] + v[mid]) / 2.0)
-    } else {
-        Some(v[mid])
+}
+
+/// Removes the packets sent before the `retention_window` that ended `last_message_timeout`
+/// ago, once they were added to the statistics.
+/// Failures are logged so they don't stop the test, and the packets are removed by the next
+/// call instead.
+pub fn create_prune_callback(
+    tx_sessions: Arc<RwLock<Vec<Session>>>,
+    retention_window: Duration,
+    last_message_timeout: Duration,
+) -> impl Fn(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    move |_inner_socket: &mut TimestampedUdpSocket, _| {
+        let retention_start = DateTime::utc_now() - last_message_timeout - retention_window;
+        let pruned = tx_sessions
+            .try_write()
+            .map_err(CommonError::from)
+            .and_then(|mut sessions| {
+                sessions.iter_mut().try_fold(0, |pruned, session| {
+                    Ok(pruned + session.prune_results(retention_start)?)
+                })
+            });
+        match pruned {
+            Ok(pruned) => log::debug!("Removed {} packets out of the retention window", pruned),
+            Err(e) => log::error!(
+                "Failed to remove the packets out of the retention window: {}",
+                e
+            ),
+        }
+        Ok(0)
     }
 }
 
-fn percentile(v: &[f64], percentile: f64) -> Option<f64> {
-    if v.is_empty() {
-        return None;
+/// Delivers the packets that are complete or lost to the sinks.
+/// Failures are logged so they don't stop the test.
+pub fn create_packet_callback(
+    tx_sessions: Arc<RwLock<Vec<Session>>>,
+    packet_feed: Arc<Mutex<PacketFeed>>,
+    sinks: TwampSinks,
+    last_message_timeout: Duration,
+) -> impl Fn(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    move |_inner_socket: &mut TimestampedUdpSocket, _| {
+        let deadline = DateTime::utc_now() - last_message_timeout;
+        let packets = tx_sessions
+            .try_read()
+            .map_err(CommonError::from)
+            .and_then(|sessions| packet_feed.lock()?.next_packets(&sessions, Some(deadline)));
+        match packets {
+            Ok(packets) => {
+                log::debug!("Delivering {} packets", packets.len());
+                sinks.packets(&packets);
+            }
+            Err(e) => log::error!("Failed to collect packets: {}", e),
+        }
+        Ok(0)
     }
-    let idx = (percentile / 100.0 * (v.len() - 1) as f64).round() as usize;
-    Some(v[idx])
 }
 
-fn calculate_std_dev(v: &[f64], mean: f64) -> Option<f64> {
-    if v.is_empty() {
-        return None;
+/// Renders the metrics of the sessions into the `snapshot` served by the metrics server.
+/// Failures are logged so they don't stop the test.
+pub fn create_metrics_callback(
+    tx_sessions: Arc<RwLock<Vec<Session>>>,
+    mut metrics: SenderMetrics,
+    snapshot: Arc<RwLock<String>>,
+) -> impl FnMut(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    move |_inner_socket: &mut TimestampedUdpSocket, _| {
+        let updated = tx_sessions
+            .try_read()
+            .map_err(CommonError::from)
+            .and_then(|sessions| metrics.encode(&sessions))
+            .and_then(|encoded| {
+                *snapshot.write()? = encoded;
+                Ok(())
+            });
+        if let Err(e) = updated {
+            log::error!("Failed to update the metrics: {}", e);
+        }
+        Ok(0)
     }
-    let variance = v.iter().map(|&value| (value - mean).powi(2)).sum::<f64>() / v.len() as f64;
-    Some(variance.sqrt())
 }
 
-pub fn create_tx_callback(
+/// Creates the callback that sends the packets of the target at `session_index`.
+/// Packets are only sent until `end`, when the target has a limited duration.
+/// The socket is shared by every target, so the DSCP of the target is carried by each packet
+/// rather than set on the socket.
+pub fn create_target_tx_callback(
     tx_sessions: Arc<RwLock<Vec<Session>>>,
-    padding: usize,
-) -> impl Fn(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    tracker: Arc<Mutex<TxTracker>>,
+    session_index: usize,
+    target: Target,
+    end: Option<DateTime>,
+    capture: Option<CaptureHandle>,
+) -> impl FnMut(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    let tos = i32::from(target.dscp.unwrap_or_default()) << 2;
+    let mut size_schedule = target.size_profile.clone().map(SizeSchedule::new);
     move |inner_socket: &mut TimestampedUdpSocket, _| {
-        let mut sent_bytes = vec![];
-        let mut timestamps = vec![];
-        tx_sessions.try_read()?.iter().for_each(|session| {
-            let twamp_test_message = SenderMessage::new(
-                session.seq_number.load(Ordering::SeqCst),
-                NtpTimestamp::now(),
-                ErrorEstimate::new(1, 0, 1, 1),
-                vec![0u8; MIN_UNAUTH_PADDING + padding],
-            );
-
-            log::trace!("Sending to {}", session.tx_socket_address);
-            if let Ok((sent, timestamp)) =
-                inner_socket.send_to(&session.tx_socket_address, twamp_test_message)
-            {
-                sent_bytes.push(sent);
-                timestamps.push(timestamp);
-                log::trace!("Timestamps {:?}", timestamps);
-            } else {
+        if end.is_some_and(|end| (DateTime::utc_now() - end).as_nanos() > 0) {
+            return Ok(0);
+        }
+        let sessions = tx_sessions.try_read()?;
+        let Some(session) = sessions.get(session_index) else {
+            return Ok(0);
+        };
+        let size = size_schedule.as_mut().map(SizeSchedule::next_size);
+        let padding = size
+            .map(|size| padding_for_size(size, &target.host))
+            .unwrap_or(target.padding);
+        let sender_seq = session.seq_number.load(Ordering::SeqCst);
+        let twamp_test_message = SenderMessage::new(
+            sender_seq,
+            NtpTimestamp::now(),
+            ErrorEstimate::new(1, 0, 1, 1),
+            vec![0u8; MIN_UNAUTH_PADDING + padding],
+        );
+        let payload = capture.as_ref().map(|_| twamp_test_message.to_be_bytes());
+
+        log::trace!("Sending to {}", session.tx_socket_address);
+        match inner_socket.send_to_with_traffic_class(
+            &session.tx_socket_address,
+            twamp_test_message,
+            tos,
+        ) {
+            Ok((sent, timestamp)) if sent >= 0 => {
+                tracker.lock()?.track(session_index, sender_seq);
+                if let (Some(capture), Some(payload)) = (&capture, payload) {
+                    capture.sent(session_index, sender_seq, target.host, tos as u8, payload);
+                }
+                let sent_message = SenderMessage {
+                    sequence_number: sender_seq,
+                    timestamp: NtpTimestamp::from(timestamp),
+                    error_estimate: ErrorEstimate::new(1, 0, 1, 1),
+                    padding: Vec::new(),
+                };
+                match size {
+                    Some(size) => session.add_sized_to_sent(sent_message, size)?,
+                    None => session.add_to_sent(sent_message)?,
+                }
+                Ok(sent)
+            }
+            _ => {
                 let error = std::io::Error::last_os_error();
                 log::error!(
                     "Error {:#?} sending to {}",
                     error,
                     session.tx_socket_address
                 );
+                Ok(0)
             }
-        });
-
-        tx_sessions
-            .try_read()?
-            .iter()
-            .zip(timestamps.iter())
-            .try_for_each(|(session, timestamp)| {
-                let twamp_test_message = SenderMessage {
-                    sequence_number: session.seq_number.load(Ordering::SeqCst),
-                    timestamp: NtpTimestamp::from(*timestamp),
-                    error_estimate: ErrorEstimate::new(1, 0, 1, 1),
-                    padding: Vec::new(),
-                };
-                session.add_to_sent(twamp_test_message)
-            })?;
-        Ok(0)
+        }
     }
 }
 
+/// Creates the callback that corrects the transmit timestamps of the sent packets and adds
+/// the packets that are complete, or older than `last_message_timeout`, to the statistics.
 pub fn create_tx_correct_callback(
     tx_sessions: Arc<RwLock<Vec<Session>>>,
+    tracker: Arc<Mutex<TxTracker>>,
+    capture: Option<CaptureHandle>,
+    last_message_timeout: Duration,
 ) -> impl Fn(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
     move |inner_socket: &mut TimestampedUdpSocket, _| {
-        let mut tx_timestamps = vec![];
-        let addresses_lock = tx_sessions.try_read()?;
-        let mut addresses: Vec<SocketAddr> = addresses_lock
-            .iter()
-            .map(|session| session.tx_socket_address)
-            .collect();
-        drop(addresses_lock);
-        while let Ok(error_messages) = inner_socket.retrieve_tx_timestamps(&mut addresses) {
-            log::trace!("Received error messages {}", error_messages.len());
-            error_messages.iter().for_each(|tx_timestamp| {
-                log::trace!("Received timestamp {:?}", tx_timestamp);
-                tx_timestamps.push(tx_timestamp.to_owned());
-            });
-        }
-        let mut write_lock = tx_sessions.try_write()?;
-        let length = write_lock.len();
-
-        // mutably iterate through the sessions. Timestamps are ordered by target and then by sequence number
-        // so to update the correct ones, we need to iterate through the sessions in the same order
-        for i in 0..length {
-            let session_timestamps = tx_timestamps
-                .iter()
-                .skip(i)
-                .step_by(length)
-                .map(|date_time| date_time.to_owned());
+        let tx_timestamps = match inner_socket.retrieve_identified_tx_timestamps() {
+            Ok(tx_timestamps) => tx_timestamps,
+            Err(e) => {
+                log::error!("Error retrieving tx timestamps {:?}", e);
+                return Ok(0);
+            }
+        };
+        log::trace!("Received tx timestamps {}", tx_timestamps.len());
 
-            write_lock.borrow_mut()[i].update_tx_timestamps(session_timestamps)?;
+        // Group the timestamps by session, keeping the sending order
+        let mut write_lock = tx_sessions.try_write()?;
+        let mut session_timestamps = vec![Vec::new(); write_lock.len()];
+        let mut tracker = tracker.lock()?;
+        for (id, timestamp) in tx_timestamps {
+            if let Some((session_index, sender_seq)) = tracker.resolve(id) {
+                session_timestamps[session_index].push((sender_seq, timestamp));
+                if let Some(capture) = &capture {
+                    capture.transmitted(session_index, sender_seq, timestamp);
+                }
+            }
         }
+        drop(tracker);
+
+        write_lock
+            .iter_mut()
+            .zip(session_timestamps)
+            .try_for_each(|(session, timestamps)| {
+                session.update_tx_timestamps(timestamps.into_iter())?;
+                session.fold_statistics(Some(DateTime::utc_now() - last_message_timeout))
+            })?;
         Ok(0)
     }
 }
 
+/// Creates the callback that matches the reflected packets with their session, writing them
+/// to the `capture` if any.
 pub fn create_rx_callback(
     rx_sessions: Arc<RwLock<Vec<Session>>>,
+    capture: Option<CaptureHandle>,
 ) -> impl Fn(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
     move |inner_socket, _| {
         let buffer = &mut [0u8; DEFAULT_BUFFER_SIZE];
-        while let Ok((result, socket_address, datetime)) = inner_socket.receive_from(buffer) {
+        while let Ok((result, socket_address, destination, datetime)) =
+            inner_socket.receive_from_to(buffer)
+        {
             let received_bytes = &buffer[..result as usize];
+            if let Some(capture) = &capture {
+                capture.received(socket_address, destination, datetime, received_bytes);
+            }
             let twamp_test_message: &Result<(ReflectedMessage, usize), CommonError> =
                 &ReflectedMessage::try_from_be_bytes(received_bytes).map_err(|e| e.into());
             log::trace!("Twamp Response Message {:?}", twamp_test_message);
@@ -448,11 +923,6 @@ pub fn create_rx_callback(
                     if let Some(session) = session_option {
                         log::debug!("Received from session {}", session.tx_socket_address);
                         let _ = session.add_to_received(twamp_message.0.to_owned(), datetime);
-                        // let latest_result = session.get_latest_result();
-
-                        // if let Ok(json_result) = serde_json::to_string_pretty(&latest_result) {
-                        //     log::info!("Latest {}", json_result);
-                        // }
                     }
                 }
             }
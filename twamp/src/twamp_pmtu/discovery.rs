@@ -0,0 +1,343 @@
+#[cfg(target_os = "linux")]
+use network_commons::epoll_loop::LinuxEventLoop as EventLoop;
+
+use core::time::Duration;
+use std::{
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc, Mutex, RwLock},
+};
+
+use network_commons::{
+    error::CommonError,
+    event_loop::{EventLoopTrait, Itimerspec, Token},
+    socket::Socket,
+    stop_handle::StopHandle,
+    time::NtpTimestamp,
+    udp_socket::TimestampedUdpSocket,
+    Strategy,
+};
+
+use crate::twamp_common::{
+    data_model::ErrorEstimate, message::SenderMessage, session::Session, MIN_UNAUTH_PADDING,
+};
+use crate::twamp_light_sender::{
+    result::{SessionResult, TwampResult},
+    size_profile::{min_packet_size, padding_for_size},
+    twamp_light::create_rx_callback,
+};
+
+use super::Configuration;
+
+/// Number of probes of a size that must go unanswered before the size is considered too big
+const PROBE_ATTEMPTS: u32 = 3;
+
+/// A probe waiting for its reflection
+#[derive(Debug, Clone, Copy)]
+struct Probe {
+    sender_seq: u32,
+    size: usize,
+    attempts: u32,
+}
+
+/// Binary search of the largest IP packet size that goes to a reflector and back.
+#[derive(Debug, Clone)]
+struct MtuSearch {
+    /// Largest size that was reflected
+    low: Option<usize>,
+    /// Largest size that might still be reflected
+    high: usize,
+    probe: Option<Probe>,
+    done: bool,
+}
+
+impl MtuSearch {
+    fn new(max_packet_size: usize) -> Self {
+        Self {
+            low: None,
+            high: max_packet_size,
+            probe: None,
+            done: false,
+        }
+    }
+
+    /// Size of the next probe, starting with the smallest packet to make sure the
+    /// reflector is reachable.
+    fn next_size(&self, host: &SocketAddr) -> usize {
+        match self.low {
+            Some(low) => (low + self.high).div_ceil(2),
+            None => min_packet_size(host),
+        }
+    }
+
+    fn on_success(&mut self, size: usize) {
+        self.probe = None;
+        self.low = Some(size);
+        self.done = size >= self.high;
+    }
+
+    fn on_failure(&mut self, size: usize) {
+        self.probe = None;
+        match self.low {
+            Some(low) => {
+                self.high = size.saturating_sub(1).max(low);
+                self.done = low >= self.high;
+            }
+            // The smallest packet was never reflected
+            None => self.done = true,
+        }
+    }
+
+    /// Lowers the upper bound to the MTU reported by the path.
+    fn on_path_mtu(&mut self, mtu: usize) {
+        self.high = self.high.min(mtu.max(self.low.unwrap_or_default()));
+        if let Some(probe) = self.probe {
+            if probe.size > self.high {
+                self.on_failure(probe.size);
+            }
+        }
+        self.done = self.done || self.low.is_some_and(|low| low >= self.high);
+    }
+}
+
+/// `PathMtuDiscovery` finds the largest IP packet size that reaches every reflector and
+/// comes back, by sending TWAMP test packets that must not be fragmented.
+///
+/// The size is binary searched between the smallest test packet and `max_packet_size`.
+/// A size is too big once `PROBE_ATTEMPTS` probes of that size went unanswered, or as soon as
+/// the local interface or a router on the path reports a smaller MTU.
+pub struct PathMtuDiscovery {
+    pub configuration: Configuration,
+    stop_handle: Option<StopHandle>,
+}
+
+impl PathMtuDiscovery {
+    pub fn new(configuration: Configuration) -> Self {
+        Self {
+            configuration,
+            stop_handle: None,
+        }
+    }
+
+    pub fn create_udp_socket(&mut self) -> Result<TimestampedUdpSocket, CommonError> {
+        let mut my_socket = TimestampedUdpSocket::bind(&self.configuration.source_ip_address)?;
+
+        my_socket.set_fcntl_options()?;
+        // Probes are sent with the DF bit, regardless of the MTU the kernel knows for the path
+        match self.configuration.source_ip_address {
+            SocketAddr::V4(_) => {
+                my_socket.set_socket_options(libc::SOL_IP, libc::IP_RECVERR, Some(1))?;
+                my_socket.set_socket_options(
+                    libc::IPPROTO_IP,
+                    libc::IP_MTU_DISCOVER,
+                    Some(libc::IP_PMTUDISC_PROBE),
+                )?;
+            }
+            SocketAddr::V6(_) => {
+                my_socket.set_socket_options(libc::SOL_IPV6, libc::IPV6_RECVERR, Some(1))?;
+                my_socket.set_socket_options(
+                    libc::IPPROTO_IPV6,
+                    libc::IPV6_MTU_DISCOVER,
+                    Some(libc::IPV6_PMTUDISC_PROBE),
+                )?;
+            }
+        }
+        my_socket.set_rx_timestamping_options()?;
+
+        Ok(my_socket)
+    }
+}
+
+impl Strategy<TwampResult, CommonError> for PathMtuDiscovery {
+    fn set_stop_handle(&mut self, stop_handle: StopHandle) {
+        self.stop_handle = Some(stop_handle);
+    }
+
+    fn execute(&mut self) -> Result<TwampResult, CommonError> {
+        let sessions = self
+            .configuration
+            .hosts
+            .iter()
+            .map(|host| Session::new(self.configuration.source_ip_address, *host))
+            .collect::<Vec<Session>>();
+        let rc_sessions = Arc::new(RwLock::new(sessions));
+        let searches = Arc::new(Mutex::new(vec![
+            MtuSearch::new(
+                self.configuration.max_packet_size
+            );
+            self.configuration.hosts.len()
+        ]));
+
+        let my_socket = self.create_udp_socket()?;
+
+        let mut event_loop = EventLoop::new(1024)?;
+        let rx_token = event_loop.register_event_source(
+            my_socket,
+            Box::new(create_rx_callback(rc_sessions.clone(), None)),
+        )?;
+
+        // The discovery stops by itself once every search is over
+        let done_handle = StopHandle::new()?;
+        event_loop.register_stop_handle(&done_handle)?;
+        if let Some(stop_handle) = &self.stop_handle {
+            event_loop.register_stop_handle(stop_handle)?;
+        }
+
+        let probe_timer_spec = Itimerspec {
+            it_interval: Duration::from_millis(self.configuration.probe_timeout),
+            it_value: Duration::from_nanos(10),
+        };
+        event_loop.register_timer(
+            &probe_timer_spec,
+            &rx_token,
+            Box::new(create_probe_callback(
+                rc_sessions.clone(),
+                searches.clone(),
+                done_handle,
+            )),
+        )?;
+
+        let duration_spec = Itimerspec {
+            it_interval: Duration::ZERO,
+            it_value: Duration::from_secs(self.configuration.duration),
+        };
+        event_loop.add_duration(&duration_spec)?;
+
+        log::info!("Starting path MTU discovery");
+        event_loop.run()?;
+        log::info!("Path MTU discovery finished");
+
+        let searches = searches.lock()?;
+        let session_results = rc_sessions
+            .try_read()?
+            .iter()
+            .zip(searches.iter())
+            .map(|(session, search)| SessionResult {
+                status: Some(
+                    match (search.done, search.low) {
+                        (_, None) => "Unreachable",
+                        (false, Some(_)) => "Incomplete",
+                        (true, Some(_)) => "Success",
+                    }
+                    .to_string(),
+                ),
+                network_statistics: None,
+                path_mtu: search.low,
+                ..SessionResult::new(session, Default::default())
+            })
+            .collect();
+
+        Ok(TwampResult {
+            session_results,
+            error: None,
+        })
+    }
+}
+
+/// Creates the callback that checks the outcome of the pending probes and sends the next ones.
+fn create_probe_callback(
+    tx_sessions: Arc<RwLock<Vec<Session>>>,
+    searches: Arc<Mutex<Vec<MtuSearch>>>,
+    stop_handle: StopHandle,
+) -> impl FnMut(&mut TimestampedUdpSocket, Token) -> Result<isize, CommonError> {
+    move |inner_socket: &mut TimestampedUdpSocket, _| {
+        let sessions = tx_sessions.try_read()?;
+        let mut searches = searches.lock()?;
+
+        match inner_socket.retrieve_path_mtu_errors() {
+            Ok(errors) => errors.iter().for_each(|(destination, mtu)| {
+                log::debug!("Path MTU {} reported for {}", mtu, destination);
+                sessions
+                    .iter()
+                    .zip(searches.iter_mut())
+                    .filter(|(session, _)| session.tx_socket_address.ip() == destination.ip())
+                    .for_each(|(_, search)| search.on_path_mtu(*mtu as usize));
+            }),
+            Err(e) => log::error!("Error retrieving path MTU errors {:?}", e),
+        }
+
+        for (session, search) in sessions.iter().zip(searches.iter_mut()) {
+            if search.done {
+                continue;
+            }
+            let host = session.tx_socket_address;
+            let size = match search.probe {
+                Some(probe) if is_reflected(session, probe.sender_seq)? => {
+                    search.on_success(probe.size);
+                    search.next_size(&host)
+                }
+                Some(probe) if probe.attempts >= PROBE_ATTEMPTS => {
+                    search.on_failure(probe.size);
+                    search.next_size(&host)
+                }
+                Some(probe) => probe.size,
+                None => search.next_size(&host),
+            };
+            if search.done {
+                log::info!("Path MTU to {}: {:?}", host, search.low);
+                continue;
+            }
+
+            let sender_seq = session.seq_number.load(Ordering::SeqCst);
+            let twamp_test_message = SenderMessage::new(
+                sender_seq,
+                NtpTimestamp::now(),
+                ErrorEstimate::new(1, 0, 1, 1),
+                vec![0u8; MIN_UNAUTH_PADDING + padding_for_size(size, &host)],
+            );
+            log::debug!("Probing {} with {} bytes", host, size);
+            let (sent, timestamp) = inner_socket.send_to(&host, twamp_test_message)?;
+            if sent < 0 {
+                let error = std::io::Error::last_os_error();
+                log::debug!("Error {:?} probing {} with {} bytes", error, host, size);
+                // A packet bigger than the interface MTU is refused right away
+                if error.raw_os_error() == Some(libc::EMSGSIZE) {
+                    search.on_failure(size);
+                }
+                continue;
+            }
+            session.add_sized_to_sent(
+                SenderMessage {
+                    sequence_number: sender_seq,
+                    timestamp: NtpTimestamp::from(timestamp),
+                    error_estimate: ErrorEstimate::new(1, 0, 1, 1),
+                    padding: Vec::new(),
+                },
+                size,
+            )?;
+            let attempts = match search.probe {
+                Some(probe) if probe.size == size => probe.attempts + 1,
+                _ => 1,
+            };
+            search.probe = Some(Probe {
+                sender_seq,
+                size,
+                attempts,
+            });
+        }
+
+        if searches.iter().all(|search| search.done) {
+            stop_handle.stop()?;
+        }
+        Ok(0)
+    }
+}
+
+/// Returns whether any probe of the size of the probe `sender_seq` was reflected.
+/// Retries are sent with new sequence numbers, so a late reflection of an earlier
+/// attempt still counts.
+fn is_reflected(session: &Session, sender_seq: u32) -> Result<bool, CommonError> {
+    let results = session.results.read()?;
+    let Some(size) = results
+        .iter()
+        .rev()
+        .find(|result| result.sender_seq == sender_seq)
+        .and_then(|result| result.size)
+    else {
+        return Ok(false);
+    };
+    Ok(results
+        .iter()
+        .rev()
+        .take_while(|result| result.size == Some(size))
+        .any(|result| result.t4.is_some()))
+}
@@ -0,0 +1,81 @@
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    twamp_common::default_source_ip_address, twamp_light_sender::size_profile::MAX_PACKET_SIZE,
+};
+
+pub mod discovery;
+
+/// Default largest IP packet size probed
+pub(crate) const DEFAULT_MAX_PACKET_SIZE: usize = 1500;
+/// Default time in milliseconds after which an unanswered probe is considered lost
+pub(crate) const DEFAULT_PROBE_TIMEOUT: u64 = 500;
+/// Default time in seconds after which the discovery is stopped
+pub(crate) const DEFAULT_DURATION: u64 = 60;
+
+/// Settings of a `LIGHT_PMTU` discovery.
+#[derive(Validate, Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Configuration {
+    #[serde(rename = "test_session_hosts", default)]
+    #[validate(length(min = 1))]
+    pub hosts: Vec<SocketAddr>,
+    #[serde(default = "default_source_ip_address")]
+    pub source_ip_address: SocketAddr,
+    /// Time in milliseconds after which an unanswered probe is considered lost
+    #[serde(alias = "packet_interval", default = "default_probe_timeout")]
+    #[validate(range(min = 1, max = 10000))]
+    pub probe_timeout: u64,
+    /// Largest IP packet size probed
+    #[serde(default = "default_max_packet_size")]
+    #[validate(range(min = 1, max = "MAX_PACKET_SIZE"))]
+    pub max_packet_size: usize,
+    /// Time in seconds after which the discovery is stopped, even if it is not over
+    #[serde(rename = "collection_period", default = "default_duration")]
+    #[validate(range(min = 1, max = 3600))]
+    pub duration: u64,
+}
+
+fn default_probe_timeout() -> u64 {
+    DEFAULT_PROBE_TIMEOUT
+}
+
+fn default_max_packet_size() -> usize {
+    DEFAULT_MAX_PACKET_SIZE
+}
+
+fn default_duration() -> u64 {
+    DEFAULT_DURATION
+}
+
+impl Configuration {
+    pub fn new(
+        hosts: &[SocketAddr],
+        source_ip_address: &SocketAddr,
+        probe_timeout: u64,
+        max_packet_size: usize,
+        duration: u64,
+    ) -> Self {
+        Self {
+            hosts: hosts.to_owned(),
+            source_ip_address: *source_ip_address,
+            probe_timeout,
+            max_packet_size,
+            duration,
+        }
+    }
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self::new(
+            &[],
+            &default_source_ip_address(),
+            DEFAULT_PROBE_TIMEOUT,
+            DEFAULT_MAX_PACKET_SIZE,
+            DEFAULT_DURATION,
+        )
+    }
+}
@@ -0,0 +1,68 @@
+use network_commons::management::Request;
+use serde_json::{json, Value};
+use twamp::{Agent, SchedulerConfiguration};
+
+fn request(method: &str, path: &str, body: Value) -> Request {
+    Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        body: body.to_string().into_bytes(),
+    }
+}
+
+#[test]
+fn agent_manages_jobs() {
+    let configuration: SchedulerConfiguration = serde_json::from_value(json!({})).unwrap();
+    let (agent, _scheduler) = Agent::new(&configuration).unwrap();
+    let job = json!({
+        "cron": "0 0 1 1 *",
+        "configuration": { "mode": "LIGHT_PMTU", "test_session_hosts": ["127.0.0.1:862"] },
+    });
+
+    let response = agent.handle(&request("PUT", "/jobs/yearly", job.clone()));
+    assert_eq!(response.status, 201, "{}", response.body);
+    assert_eq!(
+        agent.handle(&request("PUT", "/jobs/yearly", job)).status,
+        200
+    );
+    let jobs: Value =
+        serde_json::from_str(&agent.handle(&request("GET", "/jobs", json!(null))).body).unwrap();
+    assert_eq!(jobs[0]["name"], "yearly");
+    assert!(jobs[0]["next_run"].is_string());
+
+    // Scheduled runs must end on their own
+    let reflector = json!({
+        "every": 60,
+        "configuration": { "mode": "LIGHT_REFLECTOR", "source_ip_address": "127.0.0.1:0" },
+    });
+    assert_eq!(
+        agent
+            .handle(&request("PUT", "/jobs/reflector", reflector))
+            .status,
+        422
+    );
+    assert_eq!(
+        agent
+            .handle(&request("POST", "/jobs/yearly/stop", json!(null)))
+            .status,
+        409
+    );
+    assert_eq!(
+        agent
+            .handle(&request("GET", "/jobs/yearly/result", json!(null)))
+            .status,
+        404
+    );
+    assert_eq!(
+        agent
+            .handle(&request("DELETE", "/jobs/yearly", json!(null)))
+            .status,
+        200
+    );
+    assert_eq!(
+        agent
+            .handle(&request("GET", "/jobs/yearly", json!(null)))
+            .status,
+        404
+    );
+}
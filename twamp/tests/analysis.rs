@@ -0,0 +1,396 @@
+use std::{
+    io::Write,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+};
+
+use network_commons::stats::{offset_estimator::LcgRng, skew_estimator::SkewEstimator};
+use twamp::{
+    calculate_session_results, load_sessions, CorrectedOwdStatistics, ExportFormat, PacketRecord,
+    ResultAnalysis, SessionResult,
+};
+
+/// Time at which the simulated tests start, in nanoseconds since the Unix epoch
+const START: u64 = 1_700_000_000_000_000_000;
+/// Interval between the simulated packets in nanoseconds
+const PACKET_INTERVAL: u64 = 10_000_000;
+/// Minimum delay of the simulated path in nanoseconds
+const PROPAGATION_DELAY: f64 = 1_000_000.0;
+/// Mean of the exponentially distributed queuing delay in nanoseconds
+const QUEUING_SCALE: f64 = 100_000.0;
+/// Time the reflector holds every packet in nanoseconds
+const PROCESS_TIME: u64 = 50_000;
+
+/// Simulates a session of `packets` packets over a path of the same propagation delay in both
+/// directions, with the reflector clock `offset` nanoseconds ahead of the sender clock at the
+/// first packet and drifting `skew` nanoseconds per nanosecond.
+/// `forward_queuing` and `backward_queuing` give the queuing delay of every packet.
+fn simulate(
+    packets: u32,
+    offset: f64,
+    skew: f64,
+    mut forward_queuing: impl FnMut() -> f64,
+    mut backward_queuing: impl FnMut() -> f64,
+) -> Vec<(f64, f64, PacketRecord)> {
+    let address: SocketAddr = "192.0.2.1:862".parse().unwrap();
+    (0..packets)
+        .map(|sender_seq| {
+            let forward = PROPAGATION_DELAY + forward_queuing();
+            let backward = PROPAGATION_DELAY + backward_queuing();
+            let elapsed = u64::from(sender_seq) * PACKET_INTERVAL;
+            let offset = offset + skew * elapsed as f64;
+            let t1 = START + elapsed;
+            let t2 = t1
+                .checked_add_signed((forward + offset).round() as i64)
+                .unwrap();
+            let t3 = t2 + PROCESS_TIME;
+            let t4 = t3
+                .checked_add_signed((backward - offset).round() as i64)
+                .unwrap();
+            let record = PacketRecord {
+                address,
+                sender_seq,
+                reflector_seq: Some(sender_seq),
+                t1,
+                t2: Some(t2),
+                t3: Some(t3),
+                t4: Some(t4),
+                size: None,
+                sender_ttl: Some(255),
+                rtt: None,
+                forward_owd: None,
+                backward_owd: None,
+            };
+            (forward, backward, record)
+        })
+        .collect()
+}
+
+/// Draws exponentially distributed queuing delays of mean `scale`.
+fn exponential_queuing(seed: u64, scale: f64) -> impl FnMut() -> f64 {
+    let mut rng = LcgRng::new(seed);
+    move || -(1.0 - rng.gen_range(0.0..1.0)).ln() * scale
+}
+
+/// Calculates the results of the simulated packets as the final results of a test.
+fn analyze(name: &str, records: &[PacketRecord], analysis: &ResultAnalysis) -> SessionResult {
+    let path = std::env::temp_dir().join(format!("twamp-analysis-{}-{}", std::process::id(), name));
+    let mut file = std::fs::File::create(&path).unwrap();
+    for record in records {
+        writeln!(file, "{}", serde_json::to_string(record).unwrap()).unwrap();
+    }
+    drop(file);
+    let sessions = load_sessions(&path, ExportFormat::JsonLines).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    calculate_session_results(Arc::new(RwLock::new(sessions)), analysis, None)
+        .unwrap()
+        .remove(0)
+}
+
+#[test]
+fn clock_offset_is_estimated_from_the_delays_in_sending_order() {
+    let offset = 250_000.0;
+    // The backward path queues more, which biases any estimate taken from the whole
+    // distribution of the delays rather than from their floor
+    let packets = simulate(
+        1000,
+        offset,
+        0.0,
+        exponential_queuing(7, QUEUING_SCALE),
+        exponential_queuing(11, 4.0 * QUEUING_SCALE),
+    );
+    let records: Vec<PacketRecord> = packets.into_iter().map(|(_, _, record)| record).collect();
+    let result = analyze("gamlr", &records, &ResultAnalysis::default());
+
+    let corrected = result
+        .network_statistics
+        .unwrap()
+        .corrected_owd
+        .expect("the offset is estimated");
+    assert_eq!(
+        serde_json::to_value(corrected.estimator).unwrap(),
+        serde_json::json!("GAMLR")
+    );
+    // Chunks of sorted delays would be quantile bands, estimated about 140 µs off
+    assert!(
+        (corrected.offset - offset).abs() < 0.5 * QUEUING_SCALE,
+        "offset {} estimated as {}",
+        offset,
+        corrected.offset
+    );
+    let standard_error = corrected.offset_standard_error.unwrap();
+    assert!(
+        standard_error < 0.25 * QUEUING_SCALE,
+        "standard error {}",
+        standard_error
+    );
+    let (lower, upper) = (
+        corrected.offset_lower_bound.unwrap(),
+        corrected.offset_upper_bound.unwrap(),
+    );
+    assert!(lower < corrected.offset && corrected.offset < upper);
+}
+
+#[test]
+fn clock_skew_is_removed_before_the_offset_is_estimated() {
+    let (offset, skew) = (250_000.0, 20e-6);
+    let packets = simulate(
+        1000,
+        offset,
+        skew,
+        exponential_queuing(3, QUEUING_SCALE),
+        exponential_queuing(5, QUEUING_SCALE),
+    );
+    let records: Vec<PacketRecord> = packets.into_iter().map(|(_, _, record)| record).collect();
+
+    for estimator in [SkewEstimator::LinearProgramming, SkewEstimator::Paxson] {
+        let analysis = ResultAnalysis {
+            skew_estimator: Some(estimator),
+            ..Default::default()
+        };
+        let corrected = analyze("skew", &records, &analysis)
+            .network_statistics
+            .unwrap()
+            .corrected_owd
+            .unwrap();
+        assert_eq!(corrected.skew_estimator, Some(estimator));
+        // Reported in nanoseconds per second
+        let estimated_skew = corrected.skew.unwrap() / 1e9;
+        assert!(
+            (estimated_skew - skew).abs() < 2e-6,
+            "skew {} estimated as {} by {:?}",
+            skew,
+            estimated_skew,
+            estimator
+        );
+        // The offset is the one of the first packet once the drift is removed
+        assert!(
+            (corrected.offset - offset).abs() < 0.5 * QUEUING_SCALE,
+            "offset {} estimated as {} by {:?}",
+            offset,
+            corrected.offset,
+            estimator
+        );
+    }
+
+    // The drift of 200 µs over the test pulls the offset towards its mean otherwise
+    let corrected = analyze("drifting", &records, &ResultAnalysis::default())
+        .network_statistics
+        .unwrap()
+        .corrected_owd
+        .unwrap();
+    assert_eq!(corrected.skew, None);
+    assert!((corrected.offset - offset).abs() > 0.5 * QUEUING_SCALE);
+}
+
+/// Returns the forward and backward delays measured by the timestamps of the records.
+fn measured_delays(records: &[PacketRecord]) -> (Vec<f64>, Vec<f64>) {
+    records
+        .iter()
+        .map(|record| {
+            (
+                (record.t2.unwrap() as i64 - record.t1 as i64) as f64,
+                (record.t4.unwrap() as i64 - record.t3.unwrap() as i64) as f64,
+            )
+        })
+        .unzip()
+}
+
+fn assert_close(actual: Option<f64>, expected: f64, what: &str) {
+    let actual = actual.unwrap_or_else(|| panic!("no {}", what));
+    assert!(
+        (actual - expected).abs() < 1e-6 * expected.abs().max(1.0),
+        "{} is {} instead of {}",
+        what,
+        actual,
+        expected
+    );
+}
+
+/// Asserts that the corrected statistics are those of the measured delays with the estimated
+/// offset removed from the forward ones and added to the backward ones.
+fn assert_corrected_delays(corrected: &CorrectedOwdStatistics, records: &[PacketRecord]) {
+    let (forward, backward) = measured_delays(records);
+    let mean = |owd: &[f64]| owd.iter().sum::<f64>() / owd.len() as f64;
+    let min = |owd: &[f64]| owd.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = |owd: &[f64]| owd.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let offset = corrected.offset;
+    assert_close(
+        corrected.avg_forward_owd,
+        mean(&forward) - offset,
+        "mean forward delay",
+    );
+    assert_close(
+        corrected.min_forward_owd,
+        min(&forward) - offset,
+        "min forward delay",
+    );
+    assert_close(
+        corrected.max_forward_owd,
+        max(&forward) - offset,
+        "max forward delay",
+    );
+    assert_close(
+        corrected.avg_backward_owd,
+        mean(&backward) + offset,
+        "mean backward delay",
+    );
+    assert_close(
+        corrected.min_backward_owd,
+        min(&backward) + offset,
+        "min backward delay",
+    );
+    assert_close(
+        corrected.max_backward_owd,
+        max(&backward) + offset,
+        "max backward delay",
+    );
+}
+
+#[test]
+fn one_way_delays_are_corrected_with_the_estimated_offset() {
+    let offset = -400_000.0;
+    let packets = simulate(
+        1000,
+        offset,
+        0.0,
+        exponential_queuing(13, QUEUING_SCALE),
+        exponential_queuing(17, QUEUING_SCALE),
+    );
+    let (forward, backward): (Vec<f64>, Vec<f64>) = packets
+        .iter()
+        .map(|(forward, backward, _)| (*forward, *backward))
+        .unzip();
+    let records: Vec<PacketRecord> = packets.into_iter().map(|(_, _, record)| record).collect();
+    let corrected = analyze("corrected", &records, &ResultAnalysis::default())
+        .network_statistics
+        .unwrap()
+        .corrected_owd
+        .unwrap();
+
+    assert_eq!(
+        serde_json::to_value(corrected.estimator).unwrap(),
+        serde_json::json!("GAMLR")
+    );
+    assert_corrected_delays(&corrected, &records);
+    // Measured with a single clock, the corrected delays are the simulated ones
+    let mean = |owd: &[f64]| owd.iter().sum::<f64>() / owd.len() as f64;
+    let tolerance = 0.5 * QUEUING_SCALE;
+    assert!((corrected.avg_forward_owd.unwrap() - mean(&forward)).abs() < tolerance);
+    assert!((corrected.avg_backward_owd.unwrap() - mean(&backward)).abs() < tolerance);
+    assert!(corrected.min_forward_owd.unwrap() > PROPAGATION_DELAY - tolerance);
+    assert!(corrected.min_backward_owd.unwrap() > PROPAGATION_DELAY - tolerance);
+}
+
+#[test]
+fn offset_of_too_few_packets_is_derived_from_the_minimum_delays() {
+    let offset = 300_000.0;
+    // Fewer packets than a chunk of the GAMLR estimator
+    let packets = simulate(
+        4,
+        offset,
+        0.0,
+        exponential_queuing(19, QUEUING_SCALE),
+        exponential_queuing(23, QUEUING_SCALE),
+    );
+    let records: Vec<PacketRecord> = packets.into_iter().map(|(_, _, record)| record).collect();
+    let corrected = analyze("minimum", &records, &ResultAnalysis::default())
+        .network_statistics
+        .unwrap()
+        .corrected_owd
+        .unwrap();
+
+    assert_eq!(
+        serde_json::to_value(corrected.estimator).unwrap(),
+        serde_json::json!("MINIMUM_DELAY")
+    );
+    let (forward, backward) = measured_delays(&records);
+    let min = |owd: &[f64]| owd.iter().copied().fold(f64::INFINITY, f64::min);
+    assert_close(
+        Some(corrected.offset),
+        (min(&forward) - min(&backward)) / 2.0,
+        "offset",
+    );
+    assert_eq!(corrected.offset_standard_error, None);
+    assert_eq!(corrected.offset_lower_bound, None);
+    assert_eq!(corrected.offset_upper_bound, None);
+    assert_corrected_delays(&corrected, &records);
+    // The smallest corrected delays are equal in both directions
+    assert_close(
+        corrected.min_forward_owd,
+        corrected.min_backward_owd.unwrap(),
+        "min forward delay",
+    );
+}
+
+/// Quantile `p` of `values`, interpolated between the two closest ranks.
+fn exact_quantile(values: &[f64], p: f64) -> f64 {
+    let mut sorted = values.to_owned();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = p * (sorted.len() - 1) as f64;
+    let (lower, upper) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+    lower + (upper - lower) * rank.fract()
+}
+
+#[test]
+fn bounded_tests_report_the_exact_quantiles() {
+    let packets = simulate(
+        201,
+        0.0,
+        0.0,
+        exponential_queuing(3, QUEUING_SCALE),
+        exponential_queuing(5, QUEUING_SCALE),
+    );
+    let records: Vec<PacketRecord> = packets.into_iter().map(|(_, _, record)| record).collect();
+    let rtt: Vec<f64> = records
+        .iter()
+        .map(|record| (record.t4.unwrap() - record.t1) as f64)
+        .collect();
+    let (forward, backward) = measured_delays(&records);
+
+    let statistics = analyze("quantiles", &records, &ResultAnalysis::default())
+        .network_statistics
+        .unwrap();
+    assert_close(
+        statistics.median_rtt,
+        exact_quantile(&rtt, 0.5),
+        "median rtt",
+    );
+    assert_close(
+        statistics.low_percentile_rtt,
+        exact_quantile(&rtt, 0.25),
+        "low percentile rtt",
+    );
+    assert_close(
+        statistics.high_percentile_rtt,
+        exact_quantile(&rtt, 0.75),
+        "high percentile rtt",
+    );
+    assert_close(statistics.p95_rtt, exact_quantile(&rtt, 0.95), "p95 rtt");
+    assert_close(statistics.p99_rtt, exact_quantile(&rtt, 0.99), "p99 rtt");
+    assert_close(
+        statistics.median_forward_owd,
+        exact_quantile(&forward, 0.5),
+        "median forward delay",
+    );
+    assert_close(
+        statistics.median_backward_owd,
+        exact_quantile(&backward, 0.5),
+        "median backward delay",
+    );
+    assert_close(
+        statistics.median_process_time,
+        PROCESS_TIME as f64,
+        "median process time",
+    );
+
+    // A continuous test keeps the streaming estimates, as its older packets are gone
+    let analysis = ResultAnalysis {
+        continuous: true,
+        ..Default::default()
+    };
+    let statistics = analyze("estimates", &records, &analysis)
+        .network_statistics
+        .unwrap();
+    assert_ne!(statistics.p99_rtt, Some(exact_quantile(&rtt, 0.99)));
+}
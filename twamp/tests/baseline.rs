@@ -0,0 +1,217 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use network_commons::{error::CommonError, stats::offset_estimator::LcgRng, TestResult};
+use twamp::{Baseline, NetworkStatistics, SessionResult, TwampResult};
+
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+const PACKETS: usize = 200;
+
+/// Draws `PACKETS` round trip times of `base` nanoseconds plus up to 100 µs of jitter.
+fn rtt_samples(base: f64, seed: u64) -> Vec<f64> {
+    let mut rng = LcgRng::new(seed);
+    (0..PACKETS)
+        .map(|_| base + rng.gen_range(0.0..100_000.0))
+        .collect()
+}
+
+fn session_result(
+    address: &str,
+    label: Option<&str>,
+    rtt_samples: Vec<f64>,
+    lost: u32,
+) -> SessionResult {
+    SessionResult {
+        address: address.parse().unwrap(),
+        status: None,
+        network_statistics: Some(NetworkStatistics {
+            total_loss: lost,
+            total_packets: PACKETS - lost as usize,
+            ..Default::default()
+        }),
+        label: label.map(str::to_owned),
+        tags: BTreeMap::new(),
+        size_results: Vec::new(),
+        path_mtu: None,
+        sla: None,
+        rtt_samples,
+        baseline: None,
+    }
+}
+
+fn baseline(session_results: Vec<SessionResult>) -> Baseline {
+    Baseline::new(
+        TwampResult {
+            session_results,
+            error: None,
+        },
+        SIGNIFICANCE_LEVEL,
+    )
+}
+
+#[test]
+fn sessions_are_matched_by_label_before_address() {
+    let baseline = baseline(vec![
+        session_result("192.0.2.1:862", Some("voice"), rtt_samples(1e6, 1), 0),
+        session_result("192.0.2.2:862", Some("video"), rtt_samples(1e6, 2), 0),
+    ]);
+    // The target moved to another address but kept its label
+    let current = session_result("192.0.2.9:862", Some("video"), rtt_samples(1e6, 3), 0);
+    let expected: SocketAddr = "192.0.2.2:862".parse().unwrap();
+    assert_eq!(
+        baseline.compare(&current).unwrap().baseline_address,
+        expected
+    );
+    // A label takes precedence over a matching address
+    let current = session_result("192.0.2.1:862", Some("video"), rtt_samples(1e6, 3), 0);
+    assert_eq!(
+        baseline.compare(&current).unwrap().baseline_address,
+        expected
+    );
+}
+
+#[test]
+fn sessions_without_a_label_are_matched_by_address() {
+    let baseline = baseline(vec![
+        session_result("192.0.2.1:862", None, rtt_samples(1e6, 1), 0),
+        session_result("192.0.2.2:862", Some("video"), rtt_samples(1e6, 2), 0),
+    ]);
+    for (address, label) in [("192.0.2.1:862", Some("voice")), ("192.0.2.2:862", None)] {
+        let current = session_result(address, label, rtt_samples(1e6, 3), 0);
+        let expected: SocketAddr = address.parse().unwrap();
+        assert_eq!(
+            baseline.compare(&current).unwrap().baseline_address,
+            expected
+        );
+    }
+}
+
+#[test]
+fn sessions_without_a_match_are_not_compared() {
+    let baseline = baseline(vec![session_result(
+        "192.0.2.1:862",
+        Some("voice"),
+        rtt_samples(1e6, 1),
+        0,
+    )]);
+    let other_label = session_result("192.0.2.1:862", Some("video"), rtt_samples(1e6, 2), 0);
+    let other_address = session_result("192.0.2.2:862", None, rtt_samples(1e6, 2), 0);
+    assert!(baseline.compare(&other_label).is_none());
+    assert!(baseline.compare(&other_address).is_none());
+}
+
+#[test]
+fn slower_sessions_are_flagged_as_regressions() {
+    let baseline = baseline(vec![session_result(
+        "192.0.2.1:862",
+        None,
+        rtt_samples(1e6, 1),
+        1,
+    )]);
+    let current = session_result("192.0.2.1:862", None, rtt_samples(1.05e6, 2), 1);
+    let comparison = baseline.compare(&current).unwrap();
+    let rtt = comparison.rtt.unwrap();
+    assert!(rtt.regressed);
+    assert!(rtt.test.p_value < SIGNIFICANCE_LEVEL);
+    assert!(rtt.current_median > rtt.baseline_median);
+    assert!(rtt.probability_longer > 0.5);
+    assert!(comparison.regressed);
+    assert!(!comparison.loss.unwrap().regressed);
+}
+
+#[test]
+fn faster_or_unchanged_sessions_are_not_flagged() {
+    let baseline = baseline(vec![session_result(
+        "192.0.2.1:862",
+        None,
+        rtt_samples(1e6, 1),
+        2,
+    )]);
+    for base in [0.95e6, 1e6] {
+        let current = session_result("192.0.2.1:862", None, rtt_samples(base, 2), 0);
+        let comparison = baseline.compare(&current).unwrap();
+        let rtt = comparison.rtt.unwrap();
+        assert!(!rtt.regressed, "{:?}", rtt);
+        assert!(rtt.test.p_value >= SIGNIFICANCE_LEVEL);
+        assert!(!comparison.loss.unwrap().regressed);
+        assert!(!comparison.regressed);
+    }
+}
+
+#[test]
+fn sessions_losing_more_packets_are_flagged_as_regressions() {
+    let baseline = baseline(vec![session_result(
+        "192.0.2.1:862",
+        None,
+        rtt_samples(1e6, 1),
+        1,
+    )]);
+    let current = session_result("192.0.2.1:862", None, rtt_samples(1e6, 2), 20);
+    let comparison = baseline.compare(&current).unwrap();
+    let loss = comparison.loss.unwrap();
+    assert_eq!(loss.baseline_loss_ratio, 1.0 / PACKETS as f64);
+    assert_eq!(loss.current_loss_ratio, 20.0 / PACKETS as f64);
+    assert!(loss.regressed);
+    assert!(!comparison.rtt.unwrap().regressed);
+    assert!(comparison.regressed);
+}
+
+#[test]
+fn round_trip_times_are_not_compared_without_samples() {
+    let baseline = baseline(vec![session_result("192.0.2.1:862", None, Vec::new(), 1)]);
+    let current = session_result("192.0.2.1:862", None, rtt_samples(2e6, 2), 1);
+    let comparison = baseline.compare(&current).unwrap();
+    assert!(comparison.rtt.is_none());
+    assert!(comparison.loss.is_some());
+    assert!(!comparison.regressed);
+}
+
+#[test]
+fn losses_are_not_compared_without_any_lost_packet() {
+    let baseline = baseline(vec![session_result(
+        "192.0.2.1:862",
+        None,
+        rtt_samples(1e6, 1),
+        0,
+    )]);
+    let current = session_result("192.0.2.1:862", None, rtt_samples(1e6, 2), 0);
+    let comparison = baseline.compare(&current).unwrap();
+    assert!(comparison.loss.is_none());
+    assert!(comparison.rtt.is_some());
+}
+
+#[test]
+fn regressions_only_fail_the_test_when_asked_to() {
+    let baseline_result = || {
+        vec![session_result(
+            "192.0.2.1:862",
+            None,
+            rtt_samples(1e6, 1),
+            1,
+        )]
+    };
+    let result = |baseline: &Baseline| {
+        let mut current = session_result("192.0.2.1:862", None, rtt_samples(1.05e6, 2), 1);
+        current.baseline = baseline.compare(&current);
+        TwampResult {
+            session_results: vec![current],
+            error: None,
+        }
+    };
+
+    let reported = result(&baseline(baseline_result()));
+    let comparison = reported.session_results[0].baseline.as_ref().unwrap();
+    assert!(comparison.regressed);
+    assert!(!comparison.fail_on_regression);
+    assert!(reported.session_results[0].regressions().is_some());
+    assert!(reported.status().is_ok());
+
+    let failed = result(&baseline(baseline_result()).fail_on_regression());
+    assert!(
+        failed.session_results[0]
+            .baseline
+            .as_ref()
+            .unwrap()
+            .fail_on_regression
+    );
+    assert!(matches!(failed.status(), Err(CommonError::Regression(_))));
+}
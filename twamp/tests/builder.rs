@@ -0,0 +1,162 @@
+use std::net::SocketAddr;
+
+use network_commons::error::CommonError;
+use serde_json::json;
+use twamp::{SinkConfiguration, SlaMetric, SlaThreshold, TwampConfiguration, TwampMode};
+
+fn address(address: &str) -> SocketAddr {
+    address.parse().unwrap()
+}
+
+/// Checks that `configuration` reads back from its JSON and YAML forms.
+fn assert_round_trips(configuration: &TwampConfiguration) {
+    let json = serde_json::to_string(configuration).unwrap();
+    assert_eq!(
+        &serde_json::from_str::<TwampConfiguration>(&json).unwrap(),
+        configuration,
+        "{}",
+        json
+    );
+    let yaml = serde_yaml::to_string(configuration).unwrap();
+    assert_eq!(
+        &serde_yaml::from_str::<TwampConfiguration>(&yaml).unwrap(),
+        configuration,
+        "{}",
+        yaml
+    );
+}
+
+fn assert_invalid(configuration: Result<TwampConfiguration, CommonError>) {
+    match configuration {
+        Err(CommonError::ValidationError(_)) => {}
+        other => panic!("expected a validation error, got {:?}", other),
+    }
+}
+
+#[test]
+fn sender_builders_validate_and_round_trip() {
+    let configuration = TwampConfiguration::light_sender()
+        .host(address("192.0.2.1:862"))
+        .source_ip_address(address("0.0.0.0:45000"))
+        .collection_period(30)
+        .packet_interval(20)
+        .padding(100)
+        .last_message_timeout(2)
+        .report_interval(5)
+        .sla(SlaThreshold {
+            metric: SlaMetric::P95Rtt,
+            max: 20.0,
+        })
+        .rtt_samples()
+        .build()
+        .unwrap()
+        .with_sink(SinkConfiguration::Stdout { packets: true });
+    let TwampMode::LightSender(sender) = &configuration.mode else {
+        panic!("not a light sender: {:?}", configuration.mode);
+    };
+    assert_eq!(sender.hosts, [address("192.0.2.1:862")]);
+    assert_eq!(sender.duration, 30);
+    assert_eq!(sender.packet_interval, 20);
+    assert_round_trips(&configuration);
+
+    let configuration = TwampConfiguration::full_sender(address("192.0.2.1:862"))
+        .host(address("192.0.2.1:40000"))
+        .collection_period(10)
+        .build()
+        .unwrap();
+    let TwampMode::FullSender(sender) = &configuration.mode else {
+        panic!("not a full sender: {:?}", configuration.mode);
+    };
+    assert_eq!(sender.control_host, address("192.0.2.1:862"));
+    assert_round_trips(&configuration);
+
+    assert_invalid(
+        TwampConfiguration::light_sender()
+            .host(address("192.0.2.1:862"))
+            .packet_interval(0)
+            .build(),
+    );
+    assert_invalid(
+        TwampConfiguration::full_sender(address("192.0.2.1:862"))
+            .host(address("192.0.2.1:40000"))
+            .collection_period(0)
+            .build(),
+    );
+}
+
+#[test]
+fn reflector_builders_validate_and_round_trip() {
+    let configuration = TwampConfiguration::light_reflector()
+        .source_ip_address(address("0.0.0.0:862"))
+        .listen_address(address("[::]:862"))
+        .allowed_sender("10.0.0.0/8".parse().unwrap())
+        .ref_wait(60)
+        .max_sessions(10)
+        .build()
+        .unwrap();
+    let TwampMode::LightReflector(reflector) = &configuration.mode else {
+        panic!("not a light reflector: {:?}", configuration.mode);
+    };
+    assert_eq!(reflector.ref_wait, 60);
+    assert_eq!(reflector.max_sessions, Some(10));
+    assert_round_trips(&configuration);
+
+    let configuration = TwampConfiguration::full_reflector()
+        .source_ip_address(address("0.0.0.0:862"))
+        .allowed_sender("2001:db8::/32".parse().unwrap())
+        .max_connections(5)
+        .build()
+        .unwrap();
+    assert_eq!(configuration.mode.name(), "FULL_REFLECTOR");
+    assert_round_trips(&configuration);
+
+    assert_invalid(TwampConfiguration::light_reflector().ref_wait(0).build());
+    assert_invalid(
+        TwampConfiguration::light_reflector()
+            .max_sessions(0)
+            .build(),
+    );
+    assert_invalid(
+        TwampConfiguration::full_reflector()
+            .ref_wait(100_000)
+            .build(),
+    );
+}
+
+#[test]
+fn pmtu_builder_validates_and_round_trips() {
+    let configuration = TwampConfiguration::light_pmtu()
+        .host(address("192.0.2.1:862"))
+        .probe_timeout(200)
+        .max_packet_size(1400)
+        .collection_period(20)
+        .build()
+        .unwrap();
+    let TwampMode::LightPmtu(pmtu) = &configuration.mode else {
+        panic!("not a path MTU discovery: {:?}", configuration.mode);
+    };
+    assert_eq!(pmtu.probe_timeout, 200);
+    assert_round_trips(&configuration);
+    let json = serde_json::to_value(&configuration).unwrap();
+    assert_eq!(json["probe_timeout"], 200);
+
+    // Configuration files written before the setting was named keep working
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_PMTU",
+        "test_session_hosts": ["192.0.2.1:862"],
+        "packet_interval": 300,
+    }))
+    .unwrap();
+    let TwampMode::LightPmtu(pmtu) = &configuration.mode else {
+        panic!("not a path MTU discovery: {:?}", configuration.mode);
+    };
+    assert_eq!(pmtu.probe_timeout, 300);
+
+    assert_invalid(TwampConfiguration::light_pmtu().build());
+    assert_invalid(
+        TwampConfiguration::light_pmtu()
+            .host(address("192.0.2.1:862"))
+            .max_packet_size(10_000)
+            .build(),
+    );
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
+
+use common::{free_address, wait_for_reflection, TestReflector};
+use network_commons::{pcap::read_udp_datagrams, stop_handle::StopHandle};
+use serde_json::json;
+use twamp::{Twamp, TwampConfiguration};
+
+fn capture_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("twamp-{}-{}", std::process::id(), name))
+}
+
+/// Runs a 2 seconds test from an unspecified address against `reflector`, capturing its
+/// packets to `path`, and returns the number of sent packets.
+fn run_sender(reflector: SocketAddr, path: &PathBuf, format: &str) -> usize {
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_SENDER",
+        "source_ip_address": "0.0.0.0:0",
+        "targets": [{ "host": reflector.to_string() }],
+        "collection_period": 2,
+        "packet_interval": 50,
+        "padding": 41,
+        "last_message_timeout": 1,
+        "capture": { "path": path, "format": format },
+    }))
+    .unwrap();
+    let result = Twamp::new(configuration)
+        .generate()
+        .unwrap()
+        .execute()
+        .unwrap();
+    let statistics = result.session_results[0].network_statistics.as_ref();
+    statistics.unwrap().total_packets
+}
+
+#[test]
+fn sender_captures_read_back_with_the_local_address() {
+    let reflector = TestReflector::start();
+    let localhost: IpAddr = "127.0.0.1".parse().unwrap();
+    for format in ["PCAP", "PCAPNG"] {
+        let path = capture_path(&format!("sender.{}", format.to_lowercase()));
+        let sent = run_sender(reflector.address, &path, format);
+        let datagrams = read_udp_datagrams(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (outbound, inbound): (Vec<_>, Vec<_>) = datagrams
+            .iter()
+            .partition(|datagram| datagram.destination == reflector.address);
+        assert_eq!(outbound.len(), sent);
+        assert_eq!(inbound.len(), sent);
+        for datagram in &outbound {
+            assert_eq!(datagram.source.ip(), localhost);
+            assert_ne!(datagram.source.port(), 0);
+        }
+        for datagram in &inbound {
+            assert_eq!(datagram.source, reflector.address);
+            assert_eq!(datagram.destination, outbound[0].source);
+        }
+        // The sent packets carry their sequence number
+        for (seq, datagram) in outbound.iter().enumerate() {
+            assert_eq!(datagram.payload[..4], (seq as u32).to_be_bytes());
+        }
+    }
+    reflector.stop();
+}
+
+#[test]
+fn reflector_captures_read_back_with_the_local_address() {
+    let port = free_address().port();
+    let path = capture_path("reflector.pcapng");
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_REFLECTOR",
+        "source_ip_address": format!("0.0.0.0:{}", port),
+        "ref_wait": 5,
+        "capture": { "path": path },
+    }))
+    .unwrap();
+    let stop_handle = StopHandle::new().unwrap();
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_stop_handle(stop_handle.clone());
+    let thread = std::thread::spawn(move || {
+        twamp.generate().unwrap().execute().unwrap();
+    });
+    let reflector: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+    wait_for_reflection(reflector);
+    stop_handle.stop().unwrap();
+    thread.join().unwrap();
+
+    let datagrams = read_udp_datagrams(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(!datagrams.is_empty());
+    assert_eq!(datagrams.len() % 2, 0);
+    for pair in datagrams.chunks(2) {
+        // Every received packet is followed by its reflection
+        assert_eq!(pair[0].destination, reflector);
+        assert_eq!(pair[1].source, reflector);
+        assert_eq!(pair[1].destination, pair[0].source);
+    }
+}
@@ -0,0 +1,75 @@
+// Every test uses its own part of the helpers
+#![allow(dead_code)]
+
+use std::{
+    net::{SocketAddr, UdpSocket},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use network_commons::stop_handle::StopHandle;
+use serde_json::json;
+use twamp::{Twamp, TwampConfiguration};
+
+/// Size of the smallest TWAMP test packet
+const TEST_PACKET_SIZE: usize = 41;
+
+/// Returns a local address whose port was free when the function returned.
+pub fn free_address() -> SocketAddr {
+    UdpSocket::bind("127.0.0.1:0")
+        .and_then(|socket| socket.local_addr())
+        .unwrap()
+}
+
+/// Reflector running on a thread until it is stopped.
+pub struct TestReflector {
+    pub address: SocketAddr,
+    stop_handle: StopHandle,
+    thread: JoinHandle<()>,
+}
+
+impl TestReflector {
+    /// Starts a `LIGHT_REFLECTOR` on a free port and returns once it reflects packets.
+    pub fn start() -> Self {
+        let address = free_address();
+        let configuration: TwampConfiguration = serde_json::from_value(json!({
+            "mode": "LIGHT_REFLECTOR",
+            "source_ip_address": address.to_string(),
+            "ref_wait": 5,
+        }))
+        .unwrap();
+        let stop_handle = StopHandle::new().unwrap();
+        let mut twamp = Twamp::new(configuration);
+        twamp.set_stop_handle(stop_handle.clone());
+        let thread = std::thread::spawn(move || {
+            twamp.generate().unwrap().execute().unwrap();
+        });
+        wait_for_reflection(address);
+        Self {
+            address,
+            stop_handle,
+            thread,
+        }
+    }
+
+    pub fn stop(self) {
+        self.stop_handle.stop().unwrap();
+        self.thread.join().unwrap();
+    }
+}
+
+/// Sends test packets to `address` until one is reflected.
+pub fn wait_for_reflection(address: SocketAddr) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let mut buffer = [0u8; 2048];
+    for _ in 0..250 {
+        socket.send_to(&[0u8; TEST_PACKET_SIZE], address).unwrap();
+        if socket.recv(&mut buffer).is_ok() {
+            return;
+        }
+    }
+    panic!("{} never reflected a packet", address);
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::{net::SocketAddr, sync::mpsc, thread, time::Duration};
+
+use common::TestReflector;
+use network_commons::stop_handle::StopHandle;
+use serde_json::{json, Value};
+use twamp::{IntervalReport, Twamp, TwampConfiguration, TwampResult};
+
+/// Packets sent in a second at the packet interval of the tests
+const PACKETS_PER_SECOND: usize = 20;
+
+/// Runs a continuous test against `reflector` with the `extra` settings, stopped after
+/// `run_time`.
+fn run_continuous(
+    reflector: SocketAddr,
+    extra: Value,
+    run_time: Duration,
+    report_sender: Option<mpsc::Sender<IntervalReport>>,
+) -> TwampResult {
+    let mut configuration = json!({
+        "mode": "LIGHT_SENDER",
+        "source_ip_address": "127.0.0.1:0",
+        "targets": [{ "host": reflector.to_string() }],
+        "packet_interval": 50,
+        "padding": 41,
+        "last_message_timeout": 1,
+        "continuous": true,
+        "report_interval": 1,
+        "retention_window": 2,
+    });
+    configuration
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    let configuration: TwampConfiguration = serde_json::from_value(configuration).unwrap();
+    let stop_handle = StopHandle::new().unwrap();
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_stop_handle(stop_handle.clone());
+    if let Some(report_sender) = report_sender {
+        twamp.set_report_channel(report_sender);
+    }
+    let stopper = thread::spawn(move || {
+        thread::sleep(run_time);
+        stop_handle.stop().unwrap();
+    });
+    let result = twamp.generate().unwrap().execute().unwrap();
+    stopper.join().unwrap();
+    result
+}
+
+#[test]
+fn packets_out_of_the_retention_window_are_removed_without_interval_sinks() {
+    let reflector = TestReflector::start();
+    // The round trip times are taken from the retained packets only
+    let result = run_continuous(
+        reflector.address,
+        json!({ "rtt_samples": true }),
+        Duration::from_secs(8),
+        None,
+    );
+    reflector.stop();
+
+    let session_result = &result.session_results[0];
+    let statistics = session_result.network_statistics.as_ref().unwrap();
+    assert!(statistics.total_packets >= 6 * PACKETS_PER_SECOND);
+    // The retained packets cover the window, the last message timeout and at most one
+    // prune interval
+    assert!(!session_result.rtt_samples.is_empty());
+    assert!(session_result.rtt_samples.len() <= 4 * PACKETS_PER_SECOND + 2);
+    assert!(session_result.rtt_samples.len() < statistics.total_packets);
+}
+
+#[test]
+fn rolling_results_cover_the_retention_window_and_final_results_the_whole_test() {
+    let reflector = TestReflector::start();
+    let (report_sender, report_receiver) = mpsc::channel::<IntervalReport>();
+    let result = run_continuous(
+        reflector.address,
+        json!({ "retention_window": 3 }),
+        Duration::from_secs(7),
+        Some(report_sender),
+    );
+    reflector.stop();
+
+    let reports: Vec<IntervalReport> = report_receiver.try_iter().collect();
+    assert!(reports.len() >= 3);
+    let cumulative = result.session_results[0]
+        .network_statistics
+        .as_ref()
+        .unwrap()
+        .total_packets;
+    let mut windowed_packets = 0;
+    for report in &reports {
+        let rolling = report.rolling_session_results.as_ref().unwrap()[0]
+            .network_statistics
+            .as_ref()
+            .unwrap()
+            .total_packets;
+        let interval = report.session_results[0]
+            .network_statistics
+            .as_ref()
+            .unwrap()
+            .total_packets;
+        // A window of 3 seconds holds about 60 packets
+        assert!(rolling <= 3 * PACKETS_PER_SECOND + 2);
+        assert!(interval <= rolling);
+        windowed_packets = windowed_packets.max(rolling);
+    }
+    assert!(windowed_packets >= PACKETS_PER_SECOND);
+    assert!(cumulative > windowed_packets + PACKETS_PER_SECOND);
+}
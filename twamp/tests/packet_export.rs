@@ -0,0 +1,170 @@
+mod common;
+
+use std::{
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{free_address, TestReflector};
+use network_commons::stop_handle::StopHandle;
+use serde_json::json;
+use twamp::{
+    calculate_session_results, load_sessions, read_packet_records, ExportFormat, PacketRecord,
+    ResultAnalysis, Twamp, TwampConfiguration, TwampResult,
+};
+
+/// Runs a 2 seconds test against `reflector`, exporting its packets to `path`.
+fn run_sender(reflector: SocketAddr, path: &PathBuf, format: &str) -> TwampResult {
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_SENDER",
+        "source_ip_address": "127.0.0.1:0",
+        "targets": [{ "host": reflector.to_string() }],
+        "collection_period": 2,
+        "packet_interval": 50,
+        "padding": 41,
+        "last_message_timeout": 1,
+        "packet_export": { "path": path, "format": format },
+    }))
+    .unwrap();
+    Twamp::new(configuration)
+        .generate()
+        .unwrap()
+        .execute()
+        .unwrap()
+}
+
+fn export_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("twamp-{}-{}", std::process::id(), name))
+}
+
+fn assert_records_match(records: &[PacketRecord], reflector: SocketAddr) {
+    assert!(!records.is_empty());
+    for (seq, record) in records.iter().enumerate() {
+        assert_eq!(record.address, reflector);
+        assert_eq!(record.sender_seq, seq as u32);
+        let (t1, t2, t3, t4) = (
+            record.t1 as i64,
+            record.t2.unwrap() as i64,
+            record.t3.unwrap() as i64,
+            record.t4.unwrap() as i64,
+        );
+        // The reflector time is left out of the round trip time
+        assert_eq!(record.rtt, Some((t4 - t1) - (t3 - t2)));
+        assert_eq!(record.forward_owd, Some(t2 - t1));
+        assert_eq!(record.backward_owd, Some(t4 - t3));
+    }
+}
+
+#[test]
+fn exported_packets_read_back_into_the_same_results() {
+    let reflector = TestReflector::start();
+    for (format, export_format) in [
+        ("CSV", ExportFormat::Csv),
+        ("JSON_LINES", ExportFormat::JsonLines),
+    ] {
+        let path = export_path(format);
+        let result = run_sender(reflector.address, &path, format);
+
+        let records = read_packet_records(&path, export_format).unwrap();
+        assert_records_match(&records, reflector.address);
+
+        let sessions = load_sessions(&path, export_format).unwrap();
+        let reloaded = calculate_session_results(
+            Arc::new(RwLock::new(sessions)),
+            &ResultAnalysis::default(),
+            None,
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let live = result.session_results[0].network_statistics.as_ref();
+        let reloaded = reloaded[0].network_statistics.as_ref();
+        let (live, reloaded) = (live.unwrap(), reloaded.unwrap());
+        assert_eq!(reloaded.total_packets, records.len());
+        assert_eq!(reloaded.total_packets, live.total_packets);
+        assert_eq!(reloaded.total_loss, live.total_loss);
+        assert_eq!(reloaded.min_rtt, live.min_rtt);
+        assert_eq!(reloaded.max_rtt, live.max_rtt);
+        // The statistics keep the time the packets spent on the reflector
+        let max_rtt = records
+            .iter()
+            .map(|record| (record.t4.unwrap() - record.t1) as f64)
+            .fold(f64::MIN, f64::max);
+        assert_eq!(live.max_rtt, Some(max_rtt));
+    }
+    reflector.stop();
+}
+
+#[test]
+fn full_sender_writes_its_packets_while_the_test_runs() {
+    let server = free_address();
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "FULL_REFLECTOR",
+        "source_ip_address": server.to_string(),
+        "ref_wait": 5,
+    }))
+    .unwrap();
+    let stop_handle = StopHandle::new().unwrap();
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_stop_handle(stop_handle.clone());
+    let server_thread = thread::spawn(move || {
+        twamp.generate().unwrap().execute().unwrap();
+    });
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while TcpStream::connect(server).is_err() {
+        assert!(Instant::now() < deadline, "{} never listened", server);
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let path = export_path("full-sender");
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "FULL_SENDER",
+        "control_host": server.to_string(),
+        "source_ip_address": free_address().to_string(),
+        "test_session_hosts": [free_address().to_string()],
+        "collection_period": 6,
+        "packet_interval": 50,
+        "padding": 41,
+        "last_message_timeout": 1,
+        "packet_export": { "path": path, "format": "JSON_LINES" },
+    }))
+    .unwrap();
+    let start = Instant::now();
+    let sender_thread = thread::spawn(move || {
+        Twamp::new(configuration)
+            .generate()
+            .unwrap()
+            .execute()
+            .unwrap()
+    });
+    // Packets are written about a second after they were sent
+    let deadline = start + Duration::from_secs(4);
+    let mut records = Vec::new();
+    while records.is_empty() {
+        assert!(
+            Instant::now() < deadline,
+            "no packet was written during the test"
+        );
+        thread::sleep(Duration::from_millis(100));
+        // The file is created by the sender, and its last line may still be written
+        records = read_packet_records(&path, ExportFormat::JsonLines).unwrap_or_default();
+    }
+    assert!(!sender_thread.is_finished());
+    let result = sender_thread.join().unwrap();
+    stop_handle.stop().unwrap();
+    server_thread.join().unwrap();
+
+    let records = read_packet_records(&path, ExportFormat::JsonLines).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let statistics = result.session_results[0]
+        .network_statistics
+        .as_ref()
+        .unwrap();
+    assert_eq!(
+        records.len(),
+        statistics.total_packets + statistics.total_loss as usize
+    );
+}
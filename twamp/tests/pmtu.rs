@@ -0,0 +1,42 @@
+mod common;
+
+use common::{free_address, TestReflector};
+use serde_json::json;
+use twamp::{Twamp, TwampConfiguration, TwampResult};
+
+fn discover(hosts: &[String], max_packet_size: usize) -> TwampResult {
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_PMTU",
+        "source_ip_address": "127.0.0.1:0",
+        "test_session_hosts": hosts,
+        "probe_timeout": 50,
+        "max_packet_size": max_packet_size,
+        "collection_period": 10,
+    }))
+    .unwrap();
+    Twamp::new(configuration)
+        .generate()
+        .unwrap()
+        .execute()
+        .unwrap()
+}
+
+#[test]
+fn finds_the_largest_reflected_size() {
+    let reflector = TestReflector::start();
+    let result = discover(&[reflector.address.to_string()], 1500);
+    reflector.stop();
+
+    let session = &result.session_results[0];
+    assert_eq!(session.status.as_deref(), Some("Success"));
+    // Loopback takes every size up to the probed maximum
+    assert_eq!(session.path_mtu, Some(1500));
+}
+
+#[test]
+fn reports_a_silent_host_as_unreachable() {
+    let result = discover(&[free_address().to_string()], 1500);
+    let session = &result.session_results[0];
+    assert_eq!(session.status.as_deref(), Some("Unreachable"));
+    assert_eq!(session.path_mtu, None);
+}
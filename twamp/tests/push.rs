@@ -0,0 +1,101 @@
+mod common;
+
+use std::{
+    io::Read,
+    net::{SocketAddr, TcpListener, UdpSocket},
+    time::Duration,
+};
+
+use common::TestReflector;
+use serde_json::json;
+use twamp::{Twamp, TwampConfiguration};
+
+/// Runs a 3 seconds test against `reflector`, reporting every second to the `push` sink.
+fn run_sender(reflector: SocketAddr, push: serde_json::Value) {
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_SENDER",
+        "source_ip_address": "127.0.0.1:0",
+        "targets": [{ "host": reflector.to_string(), "label": "core link", "dscp": 46 }],
+        "collection_period": 3,
+        "packet_interval": 50,
+        "padding": 41,
+        "last_message_timeout": 1,
+        "report_interval": 1,
+        "push": [push],
+    }))
+    .unwrap();
+    let mut strategy = Twamp::new(configuration).generate().unwrap();
+    let result = strategy.execute().unwrap();
+    assert_eq!(result.session_results.len(), 1);
+}
+
+#[test]
+fn pushes_influx_lines_over_udp() {
+    let reflector = TestReflector::start();
+    let database = UdpSocket::bind("127.0.0.1:0").unwrap();
+    database
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let database_address = database.local_addr().unwrap();
+
+    run_sender(
+        reflector.address,
+        json!({ "address": database_address.to_string(), "format": "INFLUX_LINE" }),
+    );
+    let address = reflector.address;
+    reflector.stop();
+
+    // The pushed datagrams wait in the socket buffer
+    let mut lines = Vec::new();
+    let mut buffer = [0u8; 2048];
+    while let Ok(length) = database.recv(&mut buffer) {
+        let datagram = String::from_utf8_lossy(&buffer[..length]).to_string();
+        lines.extend(datagram.lines().map(str::to_string));
+    }
+    assert!(!lines.is_empty(), "no point was pushed");
+    let tags = format!(
+        "twamp,source=127.0.0.1,target={},dscp=46,label=core\\ link ",
+        address
+    );
+    for line in &lines {
+        assert!(line.starts_with(&tags), "{}", line);
+        assert!(line.contains("total_loss=0i"), "{}", line);
+    }
+    assert!(lines.iter().any(|line| line.contains("avg_rtt=")));
+}
+
+#[test]
+fn pushes_statsd_metrics_over_tcp() {
+    let reflector = TestReflector::start();
+    let database = TcpListener::bind("127.0.0.1:0").unwrap();
+    let database_address = database.local_addr().unwrap();
+
+    // The connection is closed once the sender is done
+    let receiver = std::thread::spawn(move || {
+        let (mut stream, _) = database.accept().unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        received
+    });
+    run_sender(
+        reflector.address,
+        json!({
+            "address": database_address.to_string(),
+            "format": "STATSD",
+            "transport": "TCP",
+            "prefix": "probe",
+            "tags": ["TARGET", "LABEL"],
+        }),
+    );
+    let address = reflector.address;
+    reflector.stop();
+
+    let received = receiver.join().unwrap();
+    let tags = format!("|#target:{},label:core link", address);
+    assert!(received
+        .lines()
+        .any(|line| line.starts_with("probe.avg_rtt:") && line.ends_with(&format!("|g{}", tags))));
+    assert!(received
+        .lines()
+        .any(|line| line == format!("probe.total_loss:0|c{}", tags)));
+}
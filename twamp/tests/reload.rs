@@ -0,0 +1,230 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use common::{free_address, wait_for_reflection};
+use network_commons::{stop_handle::StopHandle, time::NtpTimestamp};
+use serde_json::json;
+use twamp::{ReloadHandle, SessionMonitor, Subnet, Twamp, TwampConfiguration};
+
+fn reflector_configuration(value: serde_json::Value) -> TwampConfiguration {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn subnets_contain_their_addresses() {
+    let subnet: Subnet = "10.0.0.0/8".parse().unwrap();
+    assert!(subnet.contains("10.1.2.3".parse().unwrap()));
+    assert!(!subnet.contains("11.0.0.1".parse().unwrap()));
+    // Dual-stack sockets report their IPv4 senders as IPv4-mapped addresses
+    assert!(subnet.contains("::ffff:10.0.0.1".parse().unwrap()));
+    assert!(!subnet.contains("::ffff:11.0.0.1".parse().unwrap()));
+    let mapped: Subnet = "::ffff:10.0.0.0/104".parse().unwrap();
+    assert_eq!(mapped, subnet);
+    assert!(mapped.contains("10.1.2.3".parse().unwrap()));
+    let address: Subnet = "2001:db8::1".parse().unwrap();
+    assert_eq!(address.to_string(), "2001:db8::1/128");
+    assert!(!address.contains("2001:db8::2".parse().unwrap()));
+    assert!("10.0.0.0/33".parse::<Subnet>().is_err());
+}
+
+/// Starts a reflector with `configuration` on a thread, which runs until it is stopped.
+fn start_reflector(
+    configuration: TwampConfiguration,
+) -> (StopHandle, ReloadHandle, std::thread::JoinHandle<()>) {
+    start_monitored_reflector(configuration, SessionMonitor::default())
+}
+
+fn start_monitored_reflector(
+    configuration: TwampConfiguration,
+    monitor: SessionMonitor,
+) -> (StopHandle, ReloadHandle, std::thread::JoinHandle<()>) {
+    let stop_handle = StopHandle::new().unwrap();
+    let reload_handle = ReloadHandle::default();
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_stop_handle(stop_handle.clone());
+    twamp.set_reload_handle(reload_handle.clone());
+    twamp.set_session_monitor(monitor);
+    let thread = std::thread::spawn(move || {
+        twamp.generate().unwrap().execute().unwrap();
+    });
+    (stop_handle, reload_handle, thread)
+}
+
+#[test]
+fn reflects_ipv4_senders_of_dual_stack_sockets() {
+    let port = free_address().port();
+    let (stop_handle, _, thread) = start_reflector(reflector_configuration(json!({
+        "mode": "LIGHT_REFLECTOR",
+        "source_ip_address": format!("[::]:{}", port),
+        "allowed_senders": ["127.0.0.0/8"],
+        "ref_wait": 5,
+    })));
+    wait_for_reflection(format!("127.0.0.1:{}", port).parse().unwrap());
+    stop_handle.stop().unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+fn reloads_the_listen_addresses_of_a_running_reflector() {
+    let source = free_address();
+    let extra = free_address();
+    let (stop_handle, reload_handle, thread) = start_reflector(reflector_configuration(json!({
+        "mode": "LIGHT_REFLECTOR",
+        "source_ip_address": source.to_string(),
+        "ref_wait": 5,
+    })));
+    wait_for_reflection(source);
+
+    let reloaded = reflector_configuration(json!({
+        "mode": "LIGHT_REFLECTOR",
+        "source_ip_address": source.to_string(),
+        "listen_addresses": [extra.to_string()],
+        "ref_wait": 5,
+    }));
+    assert!(reload_handle.reload(&reloaded.mode).unwrap());
+    wait_for_reflection(extra);
+
+    let moved = reflector_configuration(json!({
+        "mode": "LIGHT_REFLECTOR",
+        "source_ip_address": free_address().to_string(),
+    }));
+    assert!(reload_handle.reload(&moved.mode).is_err());
+
+    let initial = reflector_configuration(json!({
+        "mode": "LIGHT_REFLECTOR",
+        "source_ip_address": source.to_string(),
+        "ref_wait": 5,
+    }));
+    assert!(reload_handle.reload(&initial.mode).unwrap());
+    // The event loop closes the removed listener on its next iteration
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while UdpSocket::bind(extra).is_err() {
+        assert!(Instant::now() < deadline, "{} is still listened on", extra);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    stop_handle.stop().unwrap();
+    thread.join().unwrap();
+    assert!(!reload_handle.reload(&initial.mode).unwrap());
+}
+
+/// Connects to the control server at `server` and requests a test session reflected from
+/// `reflector` for packets sent from `sender`, keeping the control connection open.
+fn request_test_session(
+    server: SocketAddr,
+    sender: SocketAddr,
+    reflector: SocketAddr,
+) -> TcpStream {
+    let mut control = TcpStream::connect(server).unwrap();
+    control
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut buffer = [0u8; 1024];
+    // Server Greeting, then the unauthenticated Set-Up-Response and the Server-Start
+    assert!(control.read(&mut buffer).unwrap() > 0);
+    control.write_all(&[0u8; 161]).unwrap();
+    assert!(control.read(&mut buffer).unwrap() > 0);
+
+    let ipv4 = |address: SocketAddr| match address.ip() {
+        IpAddr::V4(ip) => ip.octets(),
+        IpAddr::V6(_) => panic!("{} is not an IPv4 address", address),
+    };
+    let mut request = vec![5u8, 4, 0, 0];
+    request.extend_from_slice(&[0u8; 8]);
+    request.extend_from_slice(&sender.port().to_be_bytes());
+    request.extend_from_slice(&reflector.port().to_be_bytes());
+    request.extend_from_slice(&ipv4(sender));
+    request.extend_from_slice(&[0u8; 12]);
+    request.extend_from_slice(&ipv4(reflector));
+    request.extend_from_slice(&[0u8; 12]);
+    request.resize(112, 0);
+    control.write_all(&request).unwrap();
+    // Accept-Session
+    assert!(control.read(&mut buffer).unwrap() > 0);
+    control
+}
+
+/// Sends a test packet from `sender` to `reflector` and waits for its reflection.
+fn send_test_packet(sender: &UdpSocket, reflector: SocketAddr, sequence_number: u32) {
+    let timestamp = NtpTimestamp::now();
+    let mut packet = sequence_number.to_be_bytes().to_vec();
+    packet.extend_from_slice(&timestamp.seconds.to_be_bytes());
+    packet.extend_from_slice(&timestamp.fraction.to_be_bytes());
+    packet.extend_from_slice(&[0u8; 2]);
+    packet.resize(41, 0);
+    let mut buffer = [0u8; 1024];
+    for _ in 0..100 {
+        sender.send_to(&packet, reflector).unwrap();
+        if sender.recv(&mut buffer).is_ok() {
+            return;
+        }
+    }
+    panic!("{} never reflected a packet", reflector);
+}
+
+#[test]
+fn reloaded_ref_wait_closes_the_idle_test_sessions_of_a_running_server() {
+    let server = free_address();
+    let reflector = free_address();
+    let configuration = |ref_wait: u64| {
+        reflector_configuration(json!({
+            "mode": "FULL_REFLECTOR",
+            "source_ip_address": server.to_string(),
+            "ref_wait": ref_wait,
+        }))
+    };
+    let monitor = SessionMonitor::default();
+    let (stop_handle, reload_handle, thread) =
+        start_monitored_reflector(configuration(60), monitor.clone());
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while TcpStream::connect(server).is_err() {
+        assert!(Instant::now() < deadline, "{} never listened", server);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sender
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let _control = request_test_session(server, sender.local_addr().unwrap(), reflector);
+    for sequence_number in 0..3 {
+        send_test_packet(&sender, reflector, sequence_number);
+    }
+    std::thread::sleep(Duration::from_millis(1500));
+    // The session is idle for less than the initial ref_wait
+    assert!(monitor
+        .sessions()
+        .iter()
+        .any(|session| session.reflector == reflector));
+
+    assert!(reload_handle.reload(&configuration(1).mode).unwrap());
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while monitor
+        .sessions()
+        .iter()
+        .any(|session| session.reflector == reflector)
+    {
+        assert!(
+            Instant::now() < deadline,
+            "the test session was never closed"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    // The socket of the closed session is released on the next iteration of the worker loop
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while UdpSocket::bind(reflector).is_err() {
+        assert!(
+            Instant::now() < deadline,
+            "{} is still listened on",
+            reflector
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    stop_handle.stop().unwrap();
+    thread.join().unwrap();
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use common::TestReflector;
+use network_commons::stop_handle::StopHandle;
+use serde_json::{json, Value};
+use twamp::SchedulerConfiguration;
+
+fn sink_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("twamp-{}-{}", std::process::id(), name))
+}
+
+/// Number of test results written to the file sink at `path`.
+fn completed_runs(path: &PathBuf) -> usize {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.contains("\"event\":\"complete\""))
+        .count()
+}
+
+#[test]
+fn scheduled_runs_append_to_file_sinks() {
+    let reflector = TestReflector::start();
+    let appended = sink_path("appended.jsonl");
+    let replaced = sink_path("replaced.jsonl");
+    let job = |name: &str, sink: Value| {
+        json!({
+            "name": name,
+            "every": 1,
+            "configuration": {
+                "mode": "LIGHT_PMTU",
+                "source_ip_address": "127.0.0.1:0",
+                "test_session_hosts": [reflector.address.to_string()],
+                "probe_timeout": 50,
+                "max_packet_size": 100,
+                "sinks": [sink],
+            },
+        })
+    };
+    let configuration: SchedulerConfiguration = serde_json::from_value(json!({
+        "jobs": [
+            job("appended", json!({ "type": "FILE", "path": appended })),
+            job("replaced", json!({ "type": "FILE", "path": replaced, "append": false })),
+        ],
+    }))
+    .unwrap();
+    let mut scheduler = configuration.scheduler().unwrap();
+    let stop_handle = StopHandle::new().unwrap();
+    scheduler.set_stop_handle(stop_handle.clone());
+    let thread = std::thread::spawn(move || scheduler.run().unwrap());
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while completed_runs(&appended) < 2 {
+        assert!(Instant::now() < deadline, "the job didn't run twice");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    stop_handle.stop().unwrap();
+    thread.join().unwrap();
+    reflector.stop();
+
+    // Every run starts its own file unless the sink appends
+    assert!(completed_runs(&appended) >= 2);
+    assert_eq!(completed_runs(&replaced), 1);
+    std::fs::remove_file(&appended).unwrap();
+    std::fs::remove_file(&replaced).unwrap();
+}
@@ -0,0 +1,49 @@
+use twamp::{SizeClass, SizeProfile, SizeSchedule};
+
+fn next_sizes(profile: SizeProfile, count: usize) -> Vec<usize> {
+    let mut schedule = SizeSchedule::new(profile);
+    (0..count).map(|_| schedule.next_size()).collect()
+}
+
+#[test]
+fn sweep_repeats_every_step_and_starts_over() {
+    let sweep = SizeProfile::Sweep {
+        min: 100,
+        max: 250,
+        step: 100,
+        packets_per_step: 2,
+    };
+    assert_eq!(sweep.sizes(), vec![100, 200]);
+    assert_eq!(next_sizes(sweep, 6), vec![100, 100, 200, 200, 100, 100]);
+}
+
+#[test]
+fn imix_interleaves_the_classes_by_weight() {
+    let imix = SizeProfile::Imix {
+        classes: vec![
+            SizeClass {
+                size: 64,
+                weight: 2,
+            },
+            SizeClass {
+                size: 1500,
+                weight: 1,
+            },
+        ],
+    };
+    assert_eq!(
+        next_sizes(imix, 6),
+        vec![64, 1500, 64, 64, 1500, 64],
+        "every window of 3 packets holds 2 small ones"
+    );
+}
+
+#[test]
+fn simple_imix_is_the_default() {
+    let imix: SizeProfile = serde_json::from_str(r#"{ "type": "IMIX" }"#).unwrap();
+    assert_eq!(imix.sizes(), vec![64, 576, 1500]);
+    let sizes = next_sizes(imix, 120);
+    for (size, count) in [(64, 70), (576, 40), (1500, 10)] {
+        assert_eq!(sizes.iter().filter(|s| **s == size).count(), count);
+    }
+}
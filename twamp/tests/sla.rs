@@ -0,0 +1,122 @@
+mod common;
+
+use std::sync::mpsc;
+
+use common::TestReflector;
+use network_commons::assert_approx_eq;
+use serde_json::json;
+use twamp::{
+    IntervalReport, NetworkStatistics, SlaMetric, SlaThreshold, SlaVerdict, Twamp,
+    TwampConfiguration,
+};
+
+fn statistics() -> NetworkStatistics {
+    NetworkStatistics {
+        avg_rtt: Some(12_500_000.0),
+        median_rtt: Some(12_000_000.0),
+        p95_rtt: Some(18_000_000.0),
+        p99_rtt: Some(21_000_000.0),
+        max_rtt: Some(30_000_000.0),
+        avg_forward_owd: Some(6_000_000.0),
+        avg_backward_owd: Some(6_500_000.0),
+        avg_forward_jitter: Some(1_000_000.0),
+        avg_backward_jitter: Some(2_500_000.0),
+        forward_loss: 2,
+        backward_loss: 3,
+        total_loss: 5,
+        total_packets: 95,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn metrics_convert_delays_to_milliseconds_and_losses_to_percents() {
+    let statistics = statistics();
+    let expected = [
+        (SlaMetric::AvgRtt, 12.5),
+        (SlaMetric::MedianRtt, 12.0),
+        (SlaMetric::P95Rtt, 18.0),
+        (SlaMetric::P99Rtt, 21.0),
+        (SlaMetric::MaxRtt, 30.0),
+        (SlaMetric::AvgForwardOwd, 6.0),
+        (SlaMetric::AvgBackwardOwd, 6.5),
+        (SlaMetric::Jitter, 2.5),
+        (SlaMetric::ForwardJitter, 1.0),
+        (SlaMetric::BackwardJitter, 2.5),
+        // 5 of the 100 sent packets were lost
+        (SlaMetric::Loss, 5.0),
+        (SlaMetric::ForwardLoss, 2.0),
+        (SlaMetric::BackwardLoss, 3.0),
+    ];
+    for (metric, value) in expected {
+        assert_approx_eq!(metric.value(&statistics).unwrap(), value, 1e-12);
+    }
+}
+
+#[test]
+fn metrics_are_missing_without_packets() {
+    let statistics = NetworkStatistics::default();
+    assert_eq!(SlaMetric::AvgRtt.value(&statistics), None);
+    assert_eq!(SlaMetric::Jitter.value(&statistics), None);
+    assert_eq!(SlaMetric::Loss.value(&statistics), None);
+}
+
+#[test]
+fn verdicts_list_the_thresholds_that_are_not_met() {
+    let thresholds = [
+        SlaThreshold {
+            metric: SlaMetric::P99Rtt,
+            max: 20.0,
+        },
+        SlaThreshold {
+            metric: SlaMetric::Loss,
+            max: 5.0,
+        },
+    ];
+    let verdict = SlaVerdict::evaluate(&thresholds, &statistics());
+    assert!(!verdict.passed);
+    assert_eq!(verdict.violations.len(), 1);
+    assert_eq!(verdict.violations[0].metric, SlaMetric::P99Rtt);
+    assert_eq!(verdict.violations[0].value, Some(21.0));
+    assert_eq!(verdict.violations[0].excess, Some(1.0));
+
+    let verdict = SlaVerdict::evaluate(&thresholds, &NetworkStatistics::default());
+    assert_eq!(verdict.violations.len(), 2);
+    assert!(verdict.violations.iter().all(|v| v.value.is_none()));
+}
+
+#[test]
+fn interval_reports_carry_the_verdicts() {
+    let reflector = TestReflector::start();
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_SENDER",
+        "source_ip_address": "127.0.0.1:0",
+        "targets": [{ "host": reflector.address.to_string() }],
+        "collection_period": 3,
+        "packet_interval": 50,
+        "padding": 41,
+        "last_message_timeout": 1,
+        "report_interval": 1,
+        "sla": [{ "metric": "MAX_RTT", "max": 0.0 }, { "metric": "LOSS", "max": 100.0 }],
+    }))
+    .unwrap();
+    let (report_sender, report_receiver) = mpsc::channel::<IntervalReport>();
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_report_channel(report_sender);
+    let result = twamp.generate().unwrap().execute();
+    drop(twamp);
+    reflector.stop();
+    assert!(result.is_ok());
+
+    let reports: Vec<IntervalReport> = report_receiver.iter().collect();
+    assert!(!reports.is_empty());
+    for report in reports {
+        assert_eq!(report.sla_passed, Some(false));
+        let verdict = report.session_results[0].sla.as_ref().unwrap();
+        // Intervals without packets violate both thresholds
+        assert!(verdict
+            .violations
+            .iter()
+            .any(|violation| violation.metric == SlaMetric::MaxRtt));
+    }
+}
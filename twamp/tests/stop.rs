@@ -0,0 +1,93 @@
+mod common;
+
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::TestReflector;
+use network_commons::stop_handle::StopHandle;
+use serde_json::json;
+use twamp::{Twamp, TwampConfiguration, TwampResult};
+
+/// Packets sent in a second at the packet interval of the tests
+const PACKETS_PER_SECOND: usize = 20;
+
+/// Runs a test of a minute against `reflector`, stopped through `stop_handle`.
+fn run_sender(reflector: SocketAddr, stop_handle: StopHandle) -> (TwampResult, Duration) {
+    let configuration: TwampConfiguration = serde_json::from_value(json!({
+        "mode": "LIGHT_SENDER",
+        "source_ip_address": "127.0.0.1:0",
+        "targets": [{ "host": reflector.to_string() }],
+        "collection_period": 60,
+        "packet_interval": 50,
+        "padding": 41,
+        "last_message_timeout": 1,
+    }))
+    .unwrap();
+    let mut twamp = Twamp::new(configuration);
+    twamp.set_stop_handle(stop_handle);
+    let start = Instant::now();
+    let result = twamp.generate().unwrap().execute().unwrap();
+    (result, start.elapsed())
+}
+
+/// Asserts that a test stopped after about a second returned in time with the packets
+/// it sent until then.
+fn assert_partial_results(result: &TwampResult, elapsed: Duration) {
+    // The stop is followed by the last message timeout
+    assert!(
+        elapsed < Duration::from_secs(5),
+        "returned after {:?}",
+        elapsed
+    );
+    let statistics = result.session_results[0]
+        .network_statistics
+        .as_ref()
+        .unwrap();
+    assert!(statistics.total_packets >= PACKETS_PER_SECOND / 2);
+    assert!(statistics.total_packets <= 4 * PACKETS_PER_SECOND);
+    assert_eq!(statistics.total_loss, 0);
+}
+
+#[test]
+fn stopped_sender_returns_its_partial_results() {
+    let reflector = TestReflector::start();
+    let stop_handle = StopHandle::new().unwrap();
+    let stopper = {
+        let stop_handle = stop_handle.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(1));
+            stop_handle.stop().unwrap();
+        })
+    };
+    let (result, elapsed) = run_sender(reflector.address, stop_handle.clone());
+    stopper.join().unwrap();
+    reflector.stop();
+
+    assert!(stop_handle.is_stopped());
+    assert_partial_results(&result, elapsed);
+}
+
+#[test]
+fn termination_signal_stops_the_sender() {
+    // The signals are blocked for this thread and the threads it spawns, so the signal
+    // sent to this thread can only be read from the signalfd of the handle
+    let stop_handle = StopHandle::with_termination_signals().unwrap();
+    let reflector = TestReflector::start();
+    let sender_thread = unsafe { libc::pthread_self() };
+    let signaler = thread::spawn(move || {
+        thread::sleep(Duration::from_secs(1));
+        assert_eq!(
+            unsafe { libc::pthread_kill(sender_thread, libc::SIGTERM) },
+            0
+        );
+    });
+    let (result, elapsed) = run_sender(reflector.address, stop_handle.clone());
+    signaler.join().unwrap();
+    reflector.stop();
+
+    assert!(stop_handle.is_stopped());
+    assert_partial_results(&result, elapsed);
+}
@@ -0,0 +1,229 @@
+mod common;
+
+use std::{
+    net::{SocketAddr, UdpSocket},
+    os::fd::AsRawFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use network_commons::error::CommonError;
+use serde_json::{json, Value};
+use twamp::{Twamp, TwampConfiguration};
+
+/// Size of a test packet without padding
+const TEST_PACKET_SIZE: usize = 41;
+
+/// Packet received by a `RecordingTarget`.
+struct ReceivedPacket {
+    arrival: Instant,
+    size: usize,
+    tos: Option<u8>,
+}
+
+/// Target that records the packets it receives without reflecting them.
+struct RecordingTarget {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: JoinHandle<Vec<ReceivedPacket>>,
+}
+
+impl RecordingTarget {
+    fn start() -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        let enable: libc::c_int = 1;
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IP,
+                libc::IP_RECVTOS,
+                &enable as *const _ as *const libc::c_void,
+                std::mem::size_of_val(&enable) as libc::socklen_t,
+            )
+        };
+        assert_eq!(result, 0);
+        let address = socket.local_addr().unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::spawn(move || {
+                let mut packets = Vec::new();
+                while running.load(Ordering::SeqCst) {
+                    if let Some(packet) = receive(&socket) {
+                        packets.push(packet);
+                    }
+                }
+                packets
+            })
+        };
+        Self {
+            address,
+            running,
+            thread,
+        }
+    }
+
+    fn stop(self) -> Vec<ReceivedPacket> {
+        self.running.store(false, Ordering::SeqCst);
+        self.thread.join().unwrap()
+    }
+}
+
+/// Receives a packet along with the type of service it was sent with.
+fn receive(socket: &UdpSocket) -> Option<ReceivedPacket> {
+    let mut buffer = [0u8; 2048];
+    let mut control = [0u8; 64];
+    let mut iov = libc::iovec {
+        iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buffer.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len();
+    let size = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if size < 0 {
+        return None;
+    }
+    let arrival = Instant::now();
+    let mut tos = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_TOS {
+                tos = Some(*libc::CMSG_DATA(cmsg));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Some(ReceivedPacket {
+        arrival,
+        size: size as usize,
+        tos,
+    })
+}
+
+fn sender_configuration(targets: Value) -> TwampConfiguration {
+    serde_json::from_value(json!({
+        "mode": "LIGHT_SENDER",
+        "source_ip_address": "127.0.0.1:0",
+        "targets": targets,
+        "collection_period": 2,
+        "packet_interval": 100,
+        "padding": 0,
+        "last_message_timeout": 1,
+    }))
+    .unwrap()
+}
+
+/// Asserts that `packets` were sent every `interval` for `duration`, starting `offset`
+/// after `start`.
+fn assert_timing(
+    packets: &[ReceivedPacket],
+    start: Instant,
+    offset: Duration,
+    interval: Duration,
+    duration: Duration,
+) {
+    let expected = (duration.as_millis() / interval.as_millis()) as usize;
+    assert!(
+        packets.len().abs_diff(expected) <= 1,
+        "{} packets received instead of {}",
+        packets.len(),
+        expected
+    );
+    let first = packets[0].arrival - start;
+    assert!(
+        first >= offset && first < offset + Duration::from_millis(150),
+        "first packet received after {:?} instead of {:?}",
+        first,
+        offset
+    );
+    let span = packets[packets.len() - 1].arrival - packets[0].arrival;
+    assert!(
+        span <= duration && span + 2 * interval >= duration,
+        "packets received over {:?} instead of {:?}",
+        span,
+        duration
+    );
+}
+
+#[test]
+fn every_target_is_sent_its_own_packets() {
+    let (first, second) = (RecordingTarget::start(), RecordingTarget::start());
+    let configuration = sender_configuration(json!([
+        {
+            "host": first.address.to_string(),
+            "padding": 10,
+            "packet_interval": 20,
+            "dscp": 46,
+            "duration": 1,
+        },
+        {
+            "host": second.address.to_string(),
+            "padding": 100,
+            "packet_interval": 50,
+            "dscp": 10,
+            "start_offset": 500,
+            "duration": 1,
+        },
+    ]));
+    let start = Instant::now();
+    let result = Twamp::new(configuration)
+        .generate()
+        .unwrap()
+        .execute()
+        .unwrap();
+    let (first_packets, second_packets) = (first.stop(), second.stop());
+
+    assert_timing(
+        &first_packets,
+        start,
+        Duration::ZERO,
+        Duration::from_millis(20),
+        Duration::from_secs(1),
+    );
+    assert_timing(
+        &second_packets,
+        start,
+        Duration::from_millis(500),
+        Duration::from_millis(50),
+        Duration::from_secs(1),
+    );
+    for (packets, padding, dscp) in [(&first_packets, 10, 46u8), (&second_packets, 100, 10)] {
+        assert!(packets.iter().all(
+            |packet| packet.size == TEST_PACKET_SIZE + padding && packet.tos == Some(dscp << 2)
+        ));
+    }
+
+    // Nothing was reflected, so every packet sent to a target was lost
+    for (session_result, packets) in result
+        .session_results
+        .iter()
+        .zip([&first_packets, &second_packets])
+    {
+        let statistics = session_result.network_statistics.as_ref().unwrap();
+        assert_eq!(statistics.total_packets, 0);
+        assert_eq!(statistics.total_loss as usize, packets.len());
+    }
+}
+
+#[test]
+fn targets_of_another_address_family_than_the_source_are_rejected() {
+    let configuration = sender_configuration(json!([
+        { "host": "127.0.0.1:862" },
+        { "host": "[::1]:862" },
+    ]));
+    assert!(matches!(
+        Twamp::new(configuration).generate(),
+        Err(CommonError::ValidationError(_))
+    ));
+}